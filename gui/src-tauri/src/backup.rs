@@ -0,0 +1,162 @@
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::num::NonZeroU32;
+use std::path::Path;
+
+use crate::credentials::SiteCredential;
+use crate::history::HistoryItem;
+use crate::queue::QueueSnapshot;
+use crate::AppSettings;
+
+// Archive layout: MAGIC, salt, nonce, then the AES-256-GCM sealed JSON
+const MAGIC: &[u8; 8] = b"TVDBAK01";
+const SALT_LEN: usize = 16;
+const PBKDF2_ITERATIONS: u32 = 600_000;
+const MIN_PASSPHRASE_LEN: usize = 8;
+const BACKUP_VERSION: u32 = 1;
+
+/// Everything needed to set the app up on another machine. Paths inside
+/// settings and history are kept as they were; the user can fix them after.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupContents {
+    pub version: u32,
+    pub created_at: String,
+    pub settings: Option<AppSettings>,
+    pub history: Vec<HistoryItem>,
+    pub queue: QueueSnapshot,
+    /// Extractor rule files by name
+    pub rules: BTreeMap<String, String>,
+    /// Site hook scripts by name
+    pub scripts: BTreeMap<String, String>,
+    pub ad_patterns: Option<Vec<String>>,
+    /// Only when the user opted in; re-encrypted with the new machine's key
+    pub credentials: Option<Vec<SiteCredential>>,
+    /// Upload passwords by the reference their destination keeps, likewise
+    pub upload_secrets: Option<BTreeMap<String, String>>,
+}
+
+/// What an archive holds, shown after export and import
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BackupSummary {
+    pub created_at: String,
+    pub has_settings: bool,
+    pub history_items: usize,
+    pub queue_items: usize,
+    pub rules: usize,
+    pub scripts: usize,
+    pub credentials: usize,
+}
+
+impl BackupContents {
+    pub fn new() -> Self {
+        Self {
+            version: BACKUP_VERSION,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            ..Default::default()
+        }
+    }
+
+    pub fn summary(&self) -> BackupSummary {
+        BackupSummary {
+            created_at: self.created_at.clone(),
+            has_settings: self.settings.is_some(),
+            history_items: self.history.len(),
+            queue_items: self.queue.items.len(),
+            rules: self.rules.len(),
+            scripts: self.scripts.len(),
+            credentials: self.credentials.as_ref().map(Vec::len).unwrap_or(0),
+        }
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<LessSafeKey, String> {
+    let mut key = [0u8; 32];
+    let iterations = NonZeroU32::new(PBKDF2_ITERATIONS).expect("iterations are non-zero");
+    pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, passphrase.as_bytes(), &mut key);
+    let key = UnboundKey::new(&AES_256_GCM, &key).map_err(|_| "Invalid backup key".to_string())?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Encrypt the contents with a key derived from the passphrase and write
+/// the archive
+pub fn export(path: &Path, contents: &BackupContents, passphrase: &str) -> Result<(), String> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(format!("Backup passphrase needs at least {} characters", MIN_PASSPHRASE_LEN));
+    }
+
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    let rng = SystemRandom::new();
+    rng.fill(&mut salt)
+        .and_then(|_| rng.fill(&mut nonce))
+        .map_err(|_| "Failed to generate backup salt".to_string())?;
+
+    let mut buffer = serde_json::to_vec(contents).map_err(|e| format!("Failed to serialize backup: {}", e))?;
+    derive_key(passphrase, &salt)?
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(MAGIC), &mut buffer)
+        .map_err(|_| "Failed to encrypt backup".to_string())?;
+
+    let mut data = MAGIC.to_vec();
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&nonce);
+    data.extend_from_slice(&buffer);
+    std::fs::write(path, data).map_err(|e| format!("Failed to write backup: {}", e))
+}
+
+/// Read and decrypt an archive made by `export`
+pub fn import(path: &Path, passphrase: &str) -> Result<BackupContents, String> {
+    let data = std::fs::read(path).map_err(|e| format!("Failed to read backup: {}", e))?;
+    let Some(rest) = data.strip_prefix(MAGIC.as_slice()) else {
+        return Err("Not a backup file".to_string());
+    };
+    if rest.len() < SALT_LEN + NONCE_LEN {
+        return Err("Backup file is corrupted".to_string());
+    }
+
+    let (salt, rest) = rest.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| "Backup file is corrupted".to_string())?;
+    let mut buffer = ciphertext.to_vec();
+    let plaintext = derive_key(passphrase, salt)?
+        .open_in_place(nonce, Aad::from(MAGIC), &mut buffer)
+        .map_err(|_| "Wrong passphrase or damaged backup".to_string())?;
+
+    let contents: BackupContents =
+        serde_json::from_slice(plaintext).map_err(|e| format!("Failed to parse backup: {}", e))?;
+    if contents.version > BACKUP_VERSION {
+        return Err("Backup was made by a newer version of the app".to_string());
+    }
+    Ok(contents)
+}
+
+/// Text files directly inside `dir`, by name. A missing folder is empty.
+pub fn read_files(dir: &Path) -> BTreeMap<String, String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return BTreeMap::new();
+    };
+    entries
+        .flatten()
+        .filter(|e| e.path().is_file())
+        .filter_map(|e| {
+            let content = std::fs::read_to_string(e.path()).ok()?;
+            Some((e.file_name().to_string_lossy().to_string(), content))
+        })
+        .collect()
+}
+
+/// Write files back into `dir`. Names are reduced to their last component
+/// so an edited archive can't write outside the folder.
+pub fn write_files(dir: &Path, files: &BTreeMap<String, String>) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create folder: {}", e))?;
+    for (name, content) in files {
+        let Some(name) = Path::new(name).file_name() else {
+            continue;
+        };
+        std::fs::write(dir.join(name), content).map_err(|e| format!("Failed to restore {}: {}", name.to_string_lossy(), e))?;
+    }
+    Ok(())
+}
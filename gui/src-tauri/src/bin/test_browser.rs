@@ -24,7 +24,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let (mut browser, mut handler) = Browser::launch(config).await?;
 
     let handler_task = tokio::spawn(async move {
-        while let Some(_) = handler.next().await {}
+        while handler.next().await.is_some() {}
     });
 
     // Open main page first to get iframe URL
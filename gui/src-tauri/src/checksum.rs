@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+// verify_checksums outcomes
+pub const CHECKSUM_OK: &str = "ok";
+pub const CHECKSUM_MISMATCH: &str = "mismatch";
+pub const CHECKSUM_NO_SIDECAR: &str = "no_sidecar";
+pub const CHECKSUM_MISSING_FILE: &str = "missing_file";
+
+const READ_BUFFER: usize = 1024 * 1024;
+
+/// Result of re-hashing one file against its .sha256 sidecar
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChecksumReport {
+    pub file_path: String,
+    /// CHECKSUM_OK, CHECKSUM_MISMATCH, CHECKSUM_NO_SIDECAR or CHECKSUM_MISSING_FILE
+    pub status: String,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+/// `<file>.sha256` next to the file ("EP01.mp4.sha256")
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".sha256");
+    PathBuf::from(name)
+}
+
+/// SHA-256 of the whole file, hex encoded. Unlike history::content_hash
+/// this reads every byte, so it catches corruption anywhere in the file.
+pub fn sha256_file(path: &Path) -> Result<String, String> {
+    let mut file = File::open(crate::downloader::long_path(path)).map_err(|e| format!("Failed to open file: {}", e))?;
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    let mut buf = vec![0u8; READ_BUFFER];
+    loop {
+        let n = file.read(&mut buf).map_err(|e| format!("Failed to read file: {}", e))?;
+        if n == 0 {
+            break;
+        }
+        context.update(&buf[..n]);
+    }
+    Ok(hex::encode(context.finish().as_ref()))
+}
+
+/// Hash `path` and write the sidecar in `sha256sum` format, so
+/// `sha256sum -c` works on it too. Returns the hash.
+pub fn write_sidecar(path: &Path) -> Result<String, String> {
+    let hash = sha256_file(path)?;
+    save(path, &hash)?;
+    Ok(hash)
+}
+
+fn save(path: &Path, hash: &str) -> Result<(), String> {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    std::fs::write(crate::downloader::long_path(&sidecar_path(path)), format!("{}  {}\n", hash, name))
+        .map_err(|e| format!("Failed to write checksum: {}", e))
+}
+
+/// Hash stored in the sidecar, if there is a readable one
+pub fn read_sidecar(path: &Path) -> Option<String> {
+    let content = std::fs::read_to_string(crate::downloader::long_path(&sidecar_path(path))).ok()?;
+    let hash = content.split_whitespace().next()?.to_lowercase();
+    (hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit())).then_some(hash)
+}
+
+/// Re-hash `path` and compare it with its sidecar
+pub fn verify(path: &Path) -> ChecksumReport {
+    let report = |status: &str, expected: Option<String>, actual: Option<String>| ChecksumReport {
+        file_path: path.to_string_lossy().to_string(),
+        status: status.to_string(),
+        expected,
+        actual,
+    };
+
+    if !path.is_file() {
+        return report(CHECKSUM_MISSING_FILE, read_sidecar(path), None);
+    }
+    let Some(expected) = read_sidecar(path) else {
+        return report(CHECKSUM_NO_SIDECAR, None, None);
+    };
+    match sha256_file(path) {
+        Ok(actual) if actual == expected => report(CHECKSUM_OK, Some(expected), Some(actual)),
+        Ok(actual) => report(CHECKSUM_MISMATCH, Some(expected), Some(actual)),
+        Err(_) => report(CHECKSUM_MISSING_FILE, Some(expected), None),
+    }
+}
+
+/// Follow a rename: the sidecar moves along and names the new file
+pub fn rename_sidecar(old: &Path, new: &Path) {
+    if let Some(hash) = read_sidecar(old) {
+        if save(new, &hash).is_ok() {
+            std::fs::remove_file(crate::downloader::long_path(&sidecar_path(old))).ok();
+        }
+    }
+}
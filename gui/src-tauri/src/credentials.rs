@@ -0,0 +1,222 @@
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::downloader::browser::LoginCredential;
+
+const KEYRING_SERVICE: &str = "thai-video-downloader";
+const KEYRING_ACCOUNT: &str = "credentials-key";
+const KEY_FILE: &str = "credentials.key";
+
+/// Saved login for a member-only site
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SiteCredential {
+    pub id: String,
+    /// Matches the host itself and any subdomain
+    pub domain: String,
+    pub username: String,
+    pub password: String,
+    /// Page with the login form; the video page is used when empty
+    #[serde(default)]
+    pub login_url: String,
+}
+
+/// What the frontend gets back when listing: never the password
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CredentialSummary {
+    pub id: String,
+    pub domain: String,
+    pub username: String,
+    pub login_url: String,
+}
+
+impl From<&SiteCredential> for CredentialSummary {
+    fn from(c: &SiteCredential) -> Self {
+        Self {
+            id: c.id.clone(),
+            domain: c.domain.clone(),
+            username: c.username.clone(),
+            login_url: c.login_url.clone(),
+        }
+    }
+}
+
+impl From<&SiteCredential> for LoginCredential {
+    fn from(c: &SiteCredential) -> Self {
+        Self {
+            domain: c.domain.clone(),
+            username: c.username.clone(),
+            password: c.password.clone(),
+            login_url: c.login_url.clone(),
+        }
+    }
+}
+
+/// Normalize user input like "https://www.Example.com/login" to "example.com"
+pub fn normalize_domain(input: &str) -> String {
+    let trimmed = input.trim();
+    let host = url::Url::parse(trimmed)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.to_string()))
+        .unwrap_or_else(|| trimmed.split('/').next().unwrap_or("").to_string());
+    let host = host.to_lowercase();
+    host.strip_prefix("www.").map(|h| h.to_string()).unwrap_or(host)
+}
+
+/// Decrypt the credential file. A missing file is an empty store.
+pub fn load_credentials(app_dir: &Path, path: &Path) -> Result<Vec<SiteCredential>, String> {
+    load_sealed(app_dir, path)
+}
+
+/// Encrypt and write the credential file
+pub fn save_credentials(app_dir: &Path, path: &Path, credentials: &[SiteCredential]) -> Result<(), String> {
+    save_sealed(app_dir, path, credentials)
+}
+
+/// Other secrets (upload passwords, ...) by the reference settings keep in
+/// their place, sealed with the same key as the logins
+pub fn load_secrets(app_dir: &Path, path: &Path) -> Result<BTreeMap<String, String>, String> {
+    load_sealed(app_dir, path)
+}
+
+pub fn save_secrets(app_dir: &Path, path: &Path, secrets: &BTreeMap<String, String>) -> Result<(), String> {
+    save_sealed(app_dir, path, secrets)
+}
+
+fn load_sealed<T: DeserializeOwned + Default>(app_dir: &Path, path: &Path) -> Result<T, String> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(T::default()),
+        Err(e) => return Err(format!("Failed to read credentials: {}", e)),
+    };
+
+    if data.len() < NONCE_LEN {
+        return Err("Credential store is corrupted".to_string());
+    }
+
+    let key = encryption_key(app_dir, false)?;
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce)
+        .map_err(|_| "Credential store is corrupted".to_string())?;
+
+    let mut buffer = ciphertext.to_vec();
+    let plaintext = key
+        .open_in_place(nonce, Aad::empty(), &mut buffer)
+        .map_err(|_| "Failed to decrypt credentials (key changed?)".to_string())?;
+
+    serde_json::from_slice(plaintext).map_err(|e| format!("Failed to parse credentials: {}", e))
+}
+
+/// Encrypt and write with a fresh nonce
+fn save_sealed<T: Serialize + ?Sized>(app_dir: &Path, path: &Path, value: &T) -> Result<(), String> {
+    let key = encryption_key(app_dir, true)?;
+    let mut buffer = serde_json::to_vec(value)
+        .map_err(|e| format!("Failed to serialize credentials: {}", e))?;
+
+    let mut nonce_bytes = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce_bytes)
+        .map_err(|_| "Failed to generate nonce".to_string())?;
+
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce_bytes), Aad::empty(), &mut buffer)
+        .map_err(|_| "Failed to encrypt credentials".to_string())?;
+
+    let mut data = nonce_bytes.to_vec();
+    data.extend_from_slice(&buffer);
+    write_private(path, &data).map_err(|e| format!("Failed to write credentials: {}", e))
+}
+
+fn encryption_key(app_dir: &Path, create: bool) -> Result<LessSafeKey, String> {
+    let bytes = load_or_create_master_key(app_dir, create)?;
+    let key = UnboundKey::new(&AES_256_GCM, &bytes).map_err(|_| "Invalid credential key".to_string())?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// The AES key lives in the OS credential store (Keychain, Credential
+/// Manager, Secret Service). Only when none is reachable does it go to a
+/// key file next to the data, readable by the current user alone; that
+/// merely obfuscates the logins from anyone who can read the app folder.
+/// A key found in such a file moves to the store once one is available.
+/// A new key is only generated when writing, so a locked keyring can't
+/// silently orphan saved logins.
+fn load_or_create_master_key(app_dir: &Path, create: bool) -> Result<Vec<u8>, String> {
+    let key_file = app_dir.join(KEY_FILE);
+
+    if let Some(key) = keyring_get().and_then(|k| hex::decode(k.trim()).ok()) {
+        if key.len() == 32 {
+            return Ok(key);
+        }
+    }
+    if let Some(key) = legacy_keyring_get().and_then(|k| hex::decode(k.trim()).ok()) {
+        if key.len() == 32 {
+            keyring_set(&hex::encode(&key));
+            return Ok(key);
+        }
+    }
+    if let Some(key) = std::fs::read_to_string(&key_file).ok().and_then(|k| hex::decode(k.trim()).ok()) {
+        if key.len() == 32 {
+            if keyring_set(&hex::encode(&key)) {
+                std::fs::remove_file(&key_file).ok();
+            }
+            return Ok(key);
+        }
+    }
+
+    if !create {
+        return Err("Credential key is unavailable (is the keyring locked?)".to_string());
+    }
+
+    let mut key = vec![0u8; 32];
+    SystemRandom::new()
+        .fill(&mut key)
+        .map_err(|_| "Failed to generate credential key".to_string())?;
+
+    let encoded = hex::encode(&key);
+    if !keyring_set(&encoded) {
+        write_private(&key_file, encoded.as_bytes())
+            .map_err(|e| format!("Failed to write credential key: {}", e))?;
+    }
+    Ok(key)
+}
+
+fn write_private(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    std::fs::write(path, data)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+fn keyring_get() -> Option<String> {
+    let value = keyring::Entry::new(KEYRING_SERVICE, KEYRING_ACCOUNT).ok()?.get_password().ok()?;
+    (!value.trim().is_empty()).then_some(value)
+}
+
+fn keyring_set(value: &str) -> bool {
+    keyring::Entry::new(KEYRING_SERVICE, KEYRING_ACCOUNT)
+        .and_then(|entry| entry.set_password(value))
+        .is_ok()
+}
+
+// Older versions stored the key with `secret-tool` under an "account"
+// attribute the keyring crate doesn't look up; read it once to migrate
+#[cfg(target_os = "linux")]
+fn legacy_keyring_get() -> Option<String> {
+    let output = std::process::Command::new("secret-tool")
+        .args(["lookup", "service", KEYRING_SERVICE, "account", KEYRING_ACCOUNT])
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    let value = String::from_utf8(output.stdout).ok()?;
+    (output.status.success() && !value.trim().is_empty()).then_some(value)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn legacy_keyring_get() -> Option<String> {
+    None
+}
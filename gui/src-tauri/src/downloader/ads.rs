@@ -0,0 +1,188 @@
+use regex::Regex;
+use std::path::Path;
+use std::sync::RwLock;
+
+use super::AD_PATTERNS;
+
+const FILE_HEADER: &str = "\
+# Ad URL patterns, one per line. Sources whose URL matches are never downloaded.
+# Plain text matches anywhere in the URL, * is a wildcard and re: starts a
+# regular expression (re:/ads?/\\d+/). Case is ignored; lines starting with # too.
+";
+const REGEX_PREFIX: &str = "re:";
+
+// Ad servers whose streams are never the video itself, matched with subdomains
+const AD_NETWORK_DOMAINS: &[&str] = &[
+    "doubleclick.net",
+    "googlesyndication.com",
+    "imasdk.googleapis.com",
+    "adnxs.com",
+    "adsrvr.org",
+    "adform.net",
+    "pubmatic.com",
+    "rubiconproject.com",
+    "smartadserver.com",
+    "spotxchange.com",
+    "springserve.com",
+    "innovid.com",
+    "serving-sys.com",
+    "teads.tv",
+    "exoclick.com",
+    "juicyads.com",
+    "popads.net",
+    "propellerads.com",
+    "trafficjunky.net",
+];
+
+/// Streams shorter than this are prerolls, not episodes or movies
+pub const MIN_CONTENT_SECONDS: f64 = 60.0;
+/// Direct files smaller than this are ad clips
+pub const MIN_CONTENT_BYTES: u64 = 5 * 1024 * 1024;
+
+/// One line of the ad pattern file
+#[derive(Clone, Debug)]
+pub struct AdPattern {
+    /// The line as written, used to remove it again
+    pub source: String,
+    matcher: Matcher,
+}
+
+#[derive(Clone, Debug)]
+enum Matcher {
+    Contains(String),
+    Regex(Regex),
+}
+
+impl AdPattern {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Err("Ad pattern is empty".to_string());
+        }
+
+        let regex = match line.strip_prefix(REGEX_PREFIX) {
+            Some(regex) => Some(regex.to_string()),
+            None if line.contains('*') => Some(line.split('*').map(regex::escape).collect::<Vec<_>>().join(".*")),
+            None => None,
+        };
+        let matcher = match regex {
+            Some(regex) => Matcher::Regex(
+                Regex::new(&format!("(?i){}", regex)).map_err(|e| format!("Invalid ad pattern '{}': {}", line, e))?,
+            ),
+            None => Matcher::Contains(line.to_lowercase()),
+        };
+
+        Ok(Self { source: line.to_string(), matcher })
+    }
+
+    fn matches(&self, url_lower: &str) -> bool {
+        match &self.matcher {
+            Matcher::Contains(text) => url_lower.contains(text.as_str()),
+            Matcher::Regex(regex) => regex.is_match(url_lower),
+        }
+    }
+}
+
+// None until the pattern file is loaded; the built-in list applies meanwhile
+static PATTERNS: RwLock<Option<Vec<AdPattern>>> = RwLock::new(None);
+
+pub fn set_patterns(patterns: Vec<AdPattern>) {
+    if let Ok(mut current) = PATTERNS.write() {
+        *current = Some(patterns);
+    }
+}
+
+pub fn is_ad(url: &str) -> bool {
+    if is_ad_network(url) {
+        return true;
+    }
+    let url_lower = url.to_lowercase();
+    match PATTERNS.read().ok().as_ref().and_then(|p| p.as_ref()) {
+        Some(patterns) => patterns.iter().any(|p| p.matches(&url_lower)),
+        None => AD_PATTERNS.iter().any(|p| url_lower.contains(&p.to_lowercase())),
+    }
+}
+
+/// Whether the URL is served by a known ad network
+pub fn is_ad_network(url: &str) -> bool {
+    let Some(host) = url::Url::parse(url).ok().and_then(|u| u.host_str().map(str::to_lowercase)) else {
+        return false;
+    };
+    AD_NETWORK_DOMAINS
+        .iter()
+        .any(|domain| host == *domain || host.ends_with(&format!(".{}", domain)))
+}
+
+/// Pattern lines of the file, without comments. A missing file is created
+/// with the built-in patterns so users start from the defaults.
+pub fn read_lines(path: &Path) -> Result<Vec<String>, String> {
+    if !path.exists() {
+        let defaults: Vec<String> = AD_PATTERNS.iter().map(|p| p.to_string()).collect();
+        write_lines(path, &defaults)?;
+        return Ok(defaults);
+    }
+
+    let content = std::fs::read_to_string(path).map_err(|e| format!("Failed to read ad patterns: {}", e))?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+pub fn write_lines(path: &Path, lines: &[String]) -> Result<(), String> {
+    let mut content = String::from(FILE_HEADER);
+    for line in lines {
+        content.push_str(line);
+        content.push('\n');
+    }
+    std::fs::write(path, content).map_err(|e| format!("Failed to save ad patterns: {}", e))
+}
+
+/// Parse every line of the file. Invalid lines are skipped and reported in
+/// the returned error list instead of failing the whole load.
+pub fn load_patterns(path: &Path) -> (Vec<AdPattern>, Vec<String>) {
+    // An unreadable file falls back to the built-in list
+    let (lines, mut errors) = match read_lines(path) {
+        Ok(lines) => (lines, Vec::new()),
+        Err(e) => (AD_PATTERNS.iter().map(|p| p.to_string()).collect(), vec![e]),
+    };
+
+    let mut patterns = Vec::new();
+    for line in lines {
+        match AdPattern::parse(&line) {
+            Ok(pattern) => patterns.push(pattern),
+            Err(e) => errors.push(e),
+        }
+    }
+    (patterns, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::downloader::is_ad_url;
+
+    #[test]
+    fn ad_pattern_file_starts_from_defaults_and_takes_wildcards_and_regexes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ad_patterns.txt");
+
+        let mut lines = read_lines(&path).unwrap();
+        assert_eq!(lines, AD_PATTERNS.iter().map(|p| p.to_string()).collect::<Vec<_>>());
+        lines.push("*.preroll.example/*.m3u8".to_string());
+        lines.push(r"re:/vast/\d+/".to_string());
+        lines.push("re:(".to_string());
+        write_lines(&path, &lines).unwrap();
+
+        let (patterns, errors) = load_patterns(&path);
+        assert_eq!(errors.len(), 1);
+        set_patterns(patterns);
+
+        assert!(is_ad_url("https://cdn.preroll.example/15s.m3u8"));
+        assert!(is_ad_url("https://x.example/VAST/42/master.m3u8"));
+        assert!(is_ad_url("https://x.example/ad/master.m3u8"));
+        assert!(!is_ad_url("https://x.example/vast/master.m3u8"));
+    }
+}
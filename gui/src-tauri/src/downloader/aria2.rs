@@ -0,0 +1,265 @@
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::{dns, DownloaderError, USER_AGENT};
+
+// Download backends (AppSettings::download_backend)
+pub const BACKEND_BUILTIN: &str = "builtin";
+pub const BACKEND_ARIA2: &str = "aria2";
+
+pub const DEFAULT_RPC_URL: &str = "http://127.0.0.1:6800/jsonrpc";
+pub const DEFAULT_CONNECTIONS: usize = 8;
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Where to reach a running `aria2c --enable-rpc`
+#[derive(Clone, Debug)]
+pub struct Aria2Config {
+    pub rpc_url: String,
+    pub secret: String,
+    /// Connections per file (aria2 `split` / `max-connection-per-server`)
+    pub connections: usize,
+    /// Bytes per second for all of aria2's transfers, the app's own speed
+    /// limit; 0 is unlimited
+    pub max_overall_limit: u64,
+}
+
+impl Default for Aria2Config {
+    fn default() -> Self {
+        Self {
+            rpc_url: DEFAULT_RPC_URL.to_string(),
+            secret: String::new(),
+            connections: DEFAULT_CONNECTIONS,
+            max_overall_limit: 0,
+        }
+    }
+}
+
+struct Aria2Status {
+    status: String,
+    total_length: u64,
+    completed_length: u64,
+    error_message: String,
+}
+
+/// Thin JSON-RPC client. aria2 only transfers bytes; playlists, muxing and
+/// the queue stay in the app.
+#[derive(Clone)]
+pub struct Aria2Client {
+    client: Client,
+    config: Aria2Config,
+}
+
+impl Aria2Client {
+    pub fn new(config: Aria2Config) -> Self {
+        Self { client: Client::new(), config }
+    }
+
+    async fn call(&self, method: &str, params: Vec<Value>) -> Result<Value, DownloaderError> {
+        let mut all_params = Vec::with_capacity(params.len() + 1);
+        if !self.config.secret.is_empty() {
+            all_params.push(json!(format!("token:{}", self.config.secret)));
+        }
+        all_params.extend(params);
+
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": uuid::Uuid::new_v4().to_string(),
+            "method": method,
+            "params": all_params,
+        });
+
+        let response: Value = self.client
+            .post(&self.config.rpc_url)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| DownloaderError::DownloadFailed(format!("aria2 RPC unreachable: {}", e)))?
+            .json()
+            .await?;
+
+        if let Some(error) = response.get("error") {
+            let message = error.get("message").and_then(|m| m.as_str()).unwrap_or("unknown error");
+            return Err(DownloaderError::DownloadFailed(format!("aria2 {}: {}", method, message)));
+        }
+
+        Ok(response.get("result").cloned().unwrap_or(Value::Null))
+    }
+
+    /// aria2 version string, to check the RPC settings
+    pub async fn version(&self) -> Result<String, DownloaderError> {
+        let result = self.call("aria2.getVersion", vec![]).await?;
+        Ok(result.get("version").and_then(|v| v.as_str()).unwrap_or_default().to_string())
+    }
+
+    /// Check every URL before aria2 connects to it on its own, and carry
+    /// the app's speed limit over to aria2
+    async fn prepare(&self, urls: &[String]) -> Result<(), DownloaderError> {
+        for url in urls {
+            dns::check_external_url(url).await?;
+        }
+        let options = json!({ "max-overall-download-limit": self.config.max_overall_limit.to_string() });
+        self.call("aria2.changeGlobalOption", vec![options]).await?;
+        Ok(())
+    }
+
+    async fn add_uri(
+        &self,
+        url: &str,
+        path: &Path,
+        referer: Option<&str>,
+        headers: &[(String, String)],
+    ) -> Result<String, DownloaderError> {
+        let dir = path.parent().ok_or_else(|| DownloaderError::DownloadFailed("Invalid output path".to_string()))?;
+        let out = path.file_name().ok_or_else(|| DownloaderError::DownloadFailed("Invalid output path".to_string()))?;
+
+        let mut header_lines: Vec<String> = headers.iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+        if let Some(referer) = referer {
+            header_lines.push(format!("Referer: {}", referer));
+        }
+
+        let connections = self.config.connections.clamp(1, 16).to_string();
+        let options = json!({
+            "dir": dir.to_string_lossy(),
+            "out": out.to_string_lossy(),
+            "user-agent": USER_AGENT,
+            "header": header_lines,
+            "split": connections,
+            "max-connection-per-server": connections,
+            "allow-overwrite": "true",
+            "auto-file-renaming": "false",
+        });
+
+        let result = self.call("aria2.addUri", vec![json!([url]), options]).await?;
+        result
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| DownloaderError::DownloadFailed("aria2 returned no GID".to_string()))
+    }
+
+    async fn tell_status(&self, gid: &str) -> Result<Aria2Status, DownloaderError> {
+        let fields = json!(["status", "totalLength", "completedLength", "errorMessage"]);
+        let result = self.call("aria2.tellStatus", vec![json!(gid), fields]).await?;
+
+        let text = |key: &str| result.get(key).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+        Ok(Aria2Status {
+            status: text("status"),
+            total_length: text("totalLength").parse().unwrap_or(0),
+            completed_length: text("completedLength").parse().unwrap_or(0),
+            error_message: text("errorMessage"),
+        })
+    }
+
+    /// Poll until aria2 finishes the transfer, reporting (done, total) bytes
+    async fn wait(&self, gid: &str, on_progress: impl Fn(u64, u64)) -> Result<(), DownloaderError> {
+        loop {
+            let status = self.tell_status(gid).await?;
+            on_progress(status.completed_length, status.total_length);
+
+            match status.status.as_str() {
+                "complete" => return Ok(()),
+                "error" | "removed" => {
+                    return Err(DownloaderError::DownloadFailed(format!("aria2: {}", status.error_message)));
+                }
+                _ => tokio::time::sleep(POLL_INTERVAL).await,
+            }
+        }
+    }
+
+    /// Download one URL to `path` through aria2
+    pub async fn download_file(
+        &self,
+        url: &str,
+        path: &Path,
+        referer: Option<&str>,
+        headers: &[(String, String)],
+        progress_callback: impl Fn(f32, String) + Send + 'static,
+    ) -> Result<PathBuf, DownloaderError> {
+        self.prepare(&[url.to_string()]).await?;
+        let gid = self.add_uri(url, path, referer, headers).await?;
+        let guard = RemoveOnDrop::new(self.clone(), vec![gid.clone()]);
+
+        self.wait(&gid, |done, total| {
+            if total > 0 {
+                let progress = (done as f32 / total as f32) * 100.0;
+                progress_callback(progress, format!("aria2: {} / {} bytes", done, total));
+            }
+        })
+        .await?;
+
+        guard.disarm();
+        Ok(path.to_path_buf())
+    }
+
+    /// Fetch segments into `dir` as numbered files, `batch` at a time, and
+    /// return their paths in playlist order
+    pub async fn download_segments(
+        &self,
+        urls: &[String],
+        dir: &Path,
+        batch: usize,
+        referer: Option<&str>,
+        headers: &[(String, String)],
+        progress_callback: &impl Fn(f32, String),
+    ) -> Result<Vec<PathBuf>, DownloaderError> {
+        self.prepare(urls).await?;
+        let total = urls.len();
+        let mut paths = Vec::with_capacity(total);
+
+        for (chunk_index, chunk) in urls.chunks(batch.max(1)).enumerate() {
+            let mut gids = Vec::with_capacity(chunk.len());
+            let mut guard = RemoveOnDrop::new(self.clone(), Vec::new());
+
+            for (offset, url) in chunk.iter().enumerate() {
+                let path = dir.join(format!("{:06}.ts", chunk_index * batch.max(1) + offset));
+                let gid = self.add_uri(url, &path, referer, headers).await?;
+                guard.gids.push(gid.clone());
+                gids.push(gid);
+                paths.push(path);
+            }
+
+            for gid in &gids {
+                self.wait(gid, |_, _| {}).await?;
+            }
+            guard.disarm();
+
+            let completed = paths.len();
+            let progress = (completed as f32 / total as f32) * 100.0;
+            progress_callback(progress, format!("Downloading segment {}/{}", completed, total));
+        }
+
+        Ok(paths)
+    }
+}
+
+/// Stops transfers left in aria2 when a download is paused or cancelled
+struct RemoveOnDrop {
+    client: Aria2Client,
+    gids: Vec<String>,
+}
+
+impl RemoveOnDrop {
+    fn new(client: Aria2Client, gids: Vec<String>) -> Self {
+        Self { client, gids }
+    }
+
+    fn disarm(mut self) {
+        self.gids.clear();
+    }
+}
+
+impl Drop for RemoveOnDrop {
+    fn drop(&mut self) {
+        if self.gids.is_empty() {
+            return;
+        }
+        let client = self.client.clone();
+        let gids = std::mem::take(&mut self.gids);
+        tokio::spawn(async move {
+            for gid in gids {
+                client.call("aria2.forceRemove", vec![json!(gid)]).await.ok();
+            }
+        });
+    }
+}
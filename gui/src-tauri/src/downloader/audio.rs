@@ -0,0 +1,110 @@
+use m3u8_rs::{AlternativeMedia, AlternativeMediaType, MasterPlaylist};
+use serde::{Deserialize, Serialize};
+
+// Audio track selections; anything else is a language code ("th", "ja")
+pub const AUDIO_DEFAULT: &str = "default";
+pub const AUDIO_ALL: &str = "all";
+
+const UNDETERMINED_LANGUAGE: &str = "und";
+
+/// An alternative audio rendition offered by an HLS master playlist
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AudioTrack {
+    /// Language code from the playlist, "und" when it has none
+    pub language: String,
+    pub name: String,
+    /// Marked DEFAULT=YES, what a player would pick
+    pub default: bool,
+}
+
+impl AudioTrack {
+    pub fn from_media(media: &AlternativeMedia) -> Self {
+        Self {
+            language: media.language.as_deref().map(normalize_language).unwrap_or_else(|| UNDETERMINED_LANGUAGE.to_string()),
+            name: media.name.clone(),
+            default: media.default,
+        }
+    }
+}
+
+/// "th-TH" -> "th"; ffmpeg maps two-letter codes to the container's own
+pub fn normalize_language(language: &str) -> String {
+    language.split(['-', '_']).next().unwrap_or_default().trim().to_lowercase()
+}
+
+/// Audio renditions of a master playlist, without duplicates across groups
+pub fn list_tracks(master: &MasterPlaylist) -> Vec<AudioTrack> {
+    let mut tracks: Vec<AudioTrack> = Vec::new();
+    for media in master.alternatives.iter().filter(|m| m.media_type == AlternativeMediaType::Audio) {
+        let track = AudioTrack::from_media(media);
+        if !tracks.iter().any(|t| t.language == track.language && t.name == track.name) {
+            tracks.push(track);
+        }
+    }
+    tracks
+}
+
+/// Renditions of `group` to download separately for `selection`. Empty
+/// when the variant carries its own audio (no group, or no rendition with a
+/// URI), so the video playlist is used as is.
+pub fn select_renditions<'a>(master: &'a MasterPlaylist, group: Option<&str>, selection: &str) -> Vec<&'a AlternativeMedia> {
+    let Some(group) = group else {
+        return Vec::new();
+    };
+    let renditions: Vec<&AlternativeMedia> = master
+        .alternatives
+        .iter()
+        .filter(|m| m.media_type == AlternativeMediaType::Audio && m.group_id == group && m.uri.is_some())
+        .collect();
+
+    if selection == AUDIO_ALL {
+        return renditions;
+    }
+
+    if selection != AUDIO_DEFAULT {
+        let wanted = normalize_language(selection);
+        let matching: Vec<&AlternativeMedia> = renditions
+            .iter()
+            .copied()
+            .filter(|m| m.language.as_deref().map(normalize_language).as_deref() == Some(wanted.as_str()))
+            .take(1)
+            .collect();
+        if !matching.is_empty() {
+            return matching;
+        }
+    }
+
+    // The playlist's default, else the first rendition
+    renditions
+        .iter()
+        .find(|m| m.default)
+        .or(renditions.first())
+        .map(|m| vec![*m])
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn audio_renditions_are_selected_by_language() {
+        let master = b"#EXTM3U\n\
+            #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",LANGUAGE=\"th-TH\",NAME=\"Thai\",DEFAULT=YES,URI=\"th.m3u8\"\n\
+            #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",LANGUAGE=\"ja\",NAME=\"Japanese\",URI=\"ja.m3u8\"\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=2800000,AUDIO=\"aud\"\nvideo.m3u8\n";
+        let m3u8_rs::Playlist::MasterPlaylist(master) = m3u8_rs::parse_playlist_res(master).unwrap() else {
+            panic!("not a master playlist");
+        };
+        let names = |selection: &str| -> Vec<String> {
+            select_renditions(&master, Some("aud"), selection).iter().map(|m| m.name.clone()).collect()
+        };
+
+        assert_eq!(list_tracks(&master).iter().map(|t| t.language.as_str()).collect::<Vec<_>>(), ["th", "ja"]);
+        assert_eq!(names(AUDIO_DEFAULT), ["Thai"]);
+        assert_eq!(names("JA"), ["Japanese"]);
+        assert_eq!(names("ko"), ["Thai"]);
+        assert_eq!(names(AUDIO_ALL), ["Thai", "Japanese"]);
+        assert!(select_renditions(&master, None, AUDIO_ALL).is_empty());
+    }
+}
@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::time::{Duration, Instant};
+
+/// Splits a total speed limit across the downloads running at once, so one
+/// HLS job with many segment workers can't starve the rest. The aria2
+/// backend gets the same total as aria2's overall limit instead.
+pub struct BandwidthScheduler {
+    /// Bytes per second for all downloads together; 0 is unlimited
+    limit: AtomicU64,
+    /// Temporary lower limit (e.g. on battery); 0 is none
+    cap: AtomicU64,
+    /// Give the download highest in the queue half of the limit
+    prioritize_top: AtomicBool,
+    shares: Mutex<Vec<ShareState>>,
+    next_id: AtomicU64,
+}
+
+struct ShareState {
+    id: u64,
+    /// Lower runs first (queue position)
+    priority: usize,
+    /// When this share may read again
+    next: Instant,
+}
+
+impl BandwidthScheduler {
+    pub fn new() -> Self {
+        Self {
+            limit: AtomicU64::new(0),
+            cap: AtomicU64::new(0),
+            prioritize_top: AtomicBool::new(false),
+            shares: Mutex::new(Vec::new()),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    pub fn configure(&self, limit_kbps: u64, prioritize_top: bool) {
+        self.limit.store(limit_kbps * 1024, Ordering::Relaxed);
+        self.prioritize_top.store(prioritize_top, Ordering::Relaxed);
+    }
+
+    pub fn set_cap(&self, cap_kbps: u64) {
+        self.cap.store(cap_kbps * 1024, Ordering::Relaxed);
+    }
+
+    /// The tighter of the limit and the cap, in bytes per second; 0 is
+    /// unlimited
+    pub fn total(&self) -> u64 {
+        match (self.limit.load(Ordering::Relaxed), self.cap.load(Ordering::Relaxed)) {
+            (0, cap) => cap,
+            (limit, 0) => limit,
+            (limit, cap) => limit.min(cap),
+        }
+    }
+
+    /// Join the pool; the share leaves it when the last clone is dropped
+    pub fn register(self: &Arc<Self>, priority: usize) -> BandwidthShare {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.shares.lock().unwrap().push(ShareState {
+            id,
+            priority,
+            next: Instant::now(),
+        });
+        BandwidthShare(Arc::new(ShareHandle {
+            scheduler: self.clone(),
+            id,
+            received: AtomicU64::new(0),
+        }))
+    }
+
+    /// Bytes per second for share `id` right now
+    fn rate_for(&self, shares: &[ShareState], id: u64) -> u64 {
+        let limit = self.total();
+        let count = shares.len().max(1) as u64;
+        if count == 1 || !self.prioritize_top.load(Ordering::Relaxed) {
+            return limit / count;
+        }
+
+        let top = shares.iter().min_by_key(|s| (s.priority, s.id)).map(|s| s.id);
+        if top == Some(id) {
+            limit / 2
+        } else {
+            limit / 2 / (count - 1)
+        }
+    }
+
+    async fn consume(&self, id: u64, bytes: usize) {
+        if self.total() == 0 {
+            return;
+        }
+
+        let wake_at = {
+            let mut shares = self.shares.lock().unwrap();
+            let rate = self.rate_for(&shares, id).max(1);
+            let Some(share) = shares.iter_mut().find(|s| s.id == id) else {
+                return;
+            };
+            let now = Instant::now();
+            // Don't bank unused time from idle periods
+            let start = share.next.max(now);
+            share.next = start + Duration::from_secs_f64(bytes as f64 / rate as f64);
+            share.next
+        };
+
+        tokio::time::sleep_until(wake_at).await;
+    }
+
+    fn unregister(&self, id: u64) {
+        self.shares.lock().unwrap().retain(|s| s.id != id);
+    }
+}
+
+impl Default for BandwidthScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A total speed limit for part of the day. `start` and `end` are local
+/// "HH:MM"; a rule that ends before it starts runs past midnight.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpeedRule {
+    pub start: String,
+    pub end: String,
+    /// KB/s; 0 is full speed
+    pub limit_kbps: u64,
+}
+
+impl SpeedRule {
+    fn contains(&self, minute: u32) -> bool {
+        let (Some(start), Some(end)) = (parse_time(&self.start), parse_time(&self.end)) else {
+            return false;
+        };
+        if start <= end {
+            (start..end).contains(&minute)
+        } else {
+            minute >= start || minute < end
+        }
+    }
+}
+
+/// Minutes since midnight for "HH:MM"
+fn parse_time(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let (hours, minutes) = (hours.parse::<u32>().ok()?, minutes.parse::<u32>().ok()?);
+    // "24:00" closes a rule at midnight
+    (hours < 24 && minutes < 60 || hours == 24 && minutes == 0).then_some(hours * 60 + minutes)
+}
+
+/// Limit of the first rule covering `minute` of the day, if any
+pub fn scheduled_limit(rules: &[SpeedRule], minute: u32) -> Option<u64> {
+    rules.iter().find(|rule| rule.contains(minute)).map(|rule| rule.limit_kbps)
+}
+
+pub fn validate_schedule(rules: &[SpeedRule]) -> Result<(), String> {
+    for rule in rules {
+        for time in [&rule.start, &rule.end] {
+            if parse_time(time).is_none() {
+                return Err(format!("Invalid schedule time: {}", time));
+            }
+        }
+    }
+    Ok(())
+}
+
+struct ShareHandle {
+    scheduler: Arc<BandwidthScheduler>,
+    id: u64,
+    /// Bytes accounted so far, limited or not
+    received: AtomicU64,
+}
+
+impl Drop for ShareHandle {
+    fn drop(&mut self) {
+        self.scheduler.unregister(self.id);
+    }
+}
+
+/// One download's slice of the total speed limit
+#[derive(Clone)]
+pub struct BandwidthShare(Arc<ShareHandle>);
+
+impl BandwidthShare {
+    /// Account for `bytes` just received, waiting as long as the share's
+    /// rate requires
+    pub async fn consume(&self, bytes: usize) {
+        self.0.received.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.0.scheduler.consume(self.0.id, bytes).await;
+    }
+
+    /// Bytes received through this share so far
+    pub fn received(&self) -> u64 {
+        self.0.received.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,131 @@
+use futures::StreamExt;
+use m3u8_rs::Playlist;
+use reqwest::Client;
+use std::time::{Duration, Instant};
+use url::Url;
+
+use super::hls::build_request;
+use super::dns;
+use super::{DownloaderError, VideoSource};
+
+// Bytes read from a direct file per mirror
+const SAMPLE_BYTES: u64 = 1024 * 1024;
+// HLS mirrors are timed on their first segments
+const SAMPLE_SEGMENTS: usize = 2;
+const MIRROR_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time a small download from each mirror concurrently and return the index
+/// of the fastest one. Mirrors that fail or time out are skipped.
+pub async fn fastest_mirror(
+    sources: &[&VideoSource],
+    referer: Option<&str>,
+    headers: &[(String, String)],
+) -> Option<usize> {
+    let client = dns::client_builder().build().ok()?;
+
+    let speeds = futures::future::join_all(sources.iter().map(|source| {
+        let client = client.clone();
+        async move {
+            tokio::time::timeout(MIRROR_TIMEOUT, measure(&client, source, referer, headers))
+                .await
+                .ok()
+                .flatten()
+        }
+    }))
+    .await;
+
+    speeds
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, speed)| speed.map(|s| (index, s)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(index, _)| index)
+}
+
+/// Connection speed in bits per second, timed on the first segments of an
+/// HLS playlist. None when the test fails or times out.
+pub async fn playlist_bits_per_second(
+    playlist_url: &str,
+    referer: Option<&str>,
+    headers: &[(String, String)],
+) -> Option<f64> {
+    let client = dns::client_builder().build().ok()?;
+    let source = VideoSource {
+        url: playlist_url.to_string(),
+        source_type: "hls".to_string(),
+        ..Default::default()
+    };
+    tokio::time::timeout(MIRROR_TIMEOUT, measure(&client, &source, referer, headers))
+        .await
+        .ok()
+        .flatten()
+        .map(|bytes_per_second| bytes_per_second * 8.0)
+}
+
+/// Throughput in bytes per second
+async fn measure(
+    client: &Client,
+    source: &VideoSource,
+    referer: Option<&str>,
+    headers: &[(String, String)],
+) -> Option<f64> {
+    let request = |url: &str| build_request(client, url, referer, headers);
+
+    let started = Instant::now();
+    let mut bytes = 0u64;
+
+    if source.source_type == "hls" || source.url.contains(".m3u8") {
+        for segment_url in first_segments(&source.url, &request).await? {
+            bytes += request(&segment_url).ok()?.send().await.ok()?.error_for_status().ok()?.bytes().await.ok()?.len() as u64;
+        }
+    } else {
+        let response = request(&source.url)
+            .ok()?
+            .header("Range", format!("bytes=0-{}", SAMPLE_BYTES - 1))
+            .send()
+            .await
+            .ok()?
+            .error_for_status()
+            .ok()?;
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            bytes += chunk.ok()?.len() as u64;
+            // Servers that ignore Range would send the whole file
+            if bytes >= SAMPLE_BYTES {
+                break;
+            }
+        }
+    }
+
+    let elapsed = started.elapsed().as_secs_f64();
+    (bytes > 0 && elapsed > 0.0).then(|| bytes as f64 / elapsed)
+}
+
+/// Resolve a playlist (following the best master variant) to its first segment URLs
+async fn first_segments(
+    playlist_url: &str,
+    request: &impl Fn(&str) -> Result<reqwest::RequestBuilder, DownloaderError>,
+) -> Option<Vec<String>> {
+    let mut url = Url::parse(playlist_url).ok()?;
+
+    // At most one master -> media hop
+    for _ in 0..2 {
+        let content = request(url.as_str()).ok()?.send().await.ok()?.bytes().await.ok()?;
+        match m3u8_rs::parse_playlist_res(&content).ok()? {
+            Playlist::MasterPlaylist(master) => {
+                let best = master.variants.iter().max_by_key(|v| v.bandwidth)?;
+                url = url.join(&best.uri).ok()?;
+            }
+            Playlist::MediaPlaylist(media) => {
+                return media
+                    .segments
+                    .iter()
+                    .take(SAMPLE_SEGMENTS)
+                    .map(|s| url.join(&s.uri).ok().map(|u| u.to_string()))
+                    .collect();
+            }
+        }
+    }
+
+    None
+}
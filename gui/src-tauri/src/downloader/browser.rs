@@ -1,55 +1,301 @@
 use chromiumoxide::browser::{Browser, BrowserConfig};
+use chromiumoxide::detection::{self, DetectionOptions};
+use chromiumoxide::Page;
 use chromiumoxide::cdp::browser_protocol::network::EventResponseReceived;
 use futures::StreamExt;
-use regex::Regex;
 use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Semaphore};
+use tokio::task::JoinHandle;
+
+use super::chromium;
+use super::cookies::{cookie_matches_domain, parse_netscape, to_netscape};
+use super::diagnostics::ExtractionDiagnostics;
+use super::drm;
+use super::dns;
+use super::rules;
+use super::watchdog;
+use super::hooks::{sources_from_value, SiteHook};
+use super::{build_video_info, extract_quality_from_url, find_sources_in_content, is_ad_url, validate_url, VideoInfo, VideoSource, DownloaderError};
+
+/// Browser binary and extra command-line flags chosen by the user
+struct LaunchOptions {
+    executable: Option<PathBuf>,
+    args: Vec<String>,
+}
+
+// Read at every launch, so a change applies to the next browser started
+static LAUNCH_OPTIONS: std::sync::RwLock<LaunchOptions> =
+    std::sync::RwLock::new(LaunchOptions { executable: None, args: Vec::new() });
 
-use super::{extract_quality_from_url, is_ad_url, validate_url, VideoInfo, VideoSource, DownloaderError};
+/// An empty executable means auto-detect; flags must look like `--flag`
+/// or `--flag=value`
+pub fn validate_launch_options(executable: &str, args: &[String]) -> Result<(), String> {
+    if !executable.trim().is_empty() && !Path::new(executable.trim()).is_file() {
+        return Err(format!("Browser executable not found: {}", executable));
+    }
+    if let Some(bad) = args.iter().find(|a| !a.trim().starts_with("--")) {
+        return Err(format!("Invalid browser flag: {}", bad));
+    }
+    Ok(())
+}
+
+pub fn set_launch_options(executable: &str, args: &[String]) {
+    if let Ok(mut current) = LAUNCH_OPTIONS.write() {
+        let executable = executable.trim();
+        current.executable = (!executable.is_empty()).then(|| PathBuf::from(executable));
+        current.args = args.iter().map(|a| a.trim().to_string()).filter(|a| !a.is_empty()).collect();
+    }
+}
+
+/// Site login used to sign in before extracting from member-only pages
+#[derive(Clone, Debug)]
+pub struct LoginCredential {
+    pub domain: String,
+    pub username: String,
+    pub password: String,
+    pub login_url: String,
+}
+
+impl LoginCredential {
+    fn matches(&self, url: &str) -> bool {
+        let host = url::Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_lowercase()))
+            .unwrap_or_default();
+        let domain = self.domain.to_lowercase();
+        !domain.is_empty() && (host == domain || host.ends_with(&format!(".{}", domain)))
+    }
+}
 
 pub struct BrowserAutomation {
     headless: bool,
+    /// Persistent Chrome profile, so cookies and logins survive restarts
+    profile_dir: std::sync::RwLock<Option<PathBuf>>,
+    credentials: std::sync::RwLock<Vec<LoginCredential>>,
+    hooks: std::sync::RwLock<Vec<SiteHook>>,
+    // Domains signed in during the current browser session
+    logged_in: Mutex<HashSet<String>>,
 }
 
 impl BrowserAutomation {
     pub fn new(headless: bool) -> Self {
-        Self { headless }
+        Self {
+            headless,
+            profile_dir: std::sync::RwLock::new(None),
+            credentials: std::sync::RwLock::new(Vec::new()),
+            hooks: std::sync::RwLock::new(Vec::new()),
+            logged_in: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn set_credentials(&self, credentials: Vec<LoginCredential>) {
+        if let Ok(mut current) = self.credentials.write() {
+            *current = credentials;
+        }
+    }
+
+    pub fn set_profile_dir(&self, dir: PathBuf) {
+        if let Ok(mut current) = self.profile_dir.write() {
+            *current = Some(dir);
+        }
+    }
+
+    pub fn set_hooks(&self, hooks: Vec<SiteHook>) {
+        if let Ok(mut current) = self.hooks.write() {
+            *current = hooks;
+        }
+    }
+
+    pub fn hook_for(&self, url: &str) -> Option<SiteHook> {
+        self.hooks.read().ok()?.iter().find(|h| h.matches(url)).cloned()
+    }
+
+    /// Run the site's page script and collect any URLs it returns
+    async fn run_page_hook(page: &Page, hook: &SiteHook, video_urls: &Mutex<Vec<VideoSource>>) {
+        let Some(expression) = hook.page_expression() else {
+            return;
+        };
+
+        let value = page
+            .evaluate(expression)
+            .await
+            .ok()
+            .and_then(|v| v.into_value::<serde_json::Value>().ok());
+
+        if let Some(value) = value {
+            let mut urls = video_urls.lock().await;
+            for source in sources_from_value(value) {
+                if !urls.iter().any(|s| s.url == source.url) {
+                    urls.push(source);
+                }
+            }
+        }
+    }
+
+    fn credential_for(&self, url: &str) -> Option<LoginCredential> {
+        self.credentials
+            .read()
+            .ok()?
+            .iter()
+            .find(|c| c.matches(url))
+            .cloned()
+    }
+
+    /// Sign in with the saved credential for the page's domain, once per
+    /// browser session. Fills the first password field and the text/email
+    /// field before it, then submits the form.
+    async fn login_if_needed(&self, browser: &Browser, url: &str) -> Result<(), DownloaderError> {
+        let Some(credential) = self.credential_for(url) else {
+            return Ok(());
+        };
+
+        let mut logged_in = self.logged_in.lock().await;
+        if logged_in.contains(&credential.domain) {
+            return Ok(());
+        }
+
+        let login_url = if credential.login_url.is_empty() {
+            url.to_string()
+        } else {
+            validate_url(&credential.login_url)?
+        };
+
+        let page = browser
+            .new_page(login_url.as_str())
+            .await
+            .map_err(|e| DownloaderError::Browser(e.to_string()))?;
+
+        tokio::time::sleep(watchdog::extraction_waits().page_load).await;
+
+        let script = format!(
+            r#"
+            (function(username, password) {{
+                var pass = document.querySelector('input[type="password"]');
+                if (!pass) return false;
+                var scope = pass.form || document;
+                var fields = Array.from(scope.querySelectorAll('input[type="text"], input[type="email"], input[type="tel"], input:not([type])'));
+                var user = fields.filter(function(f) {{
+                    return f.compareDocumentPosition(pass) & Node.DOCUMENT_POSITION_FOLLOWING;
+                }}).pop() || fields[0];
+                var setter = Object.getOwnPropertyDescriptor(HTMLInputElement.prototype, 'value').set;
+                function fill(input, value) {{
+                    setter.call(input, value);
+                    input.dispatchEvent(new Event('input', {{ bubbles: true }}));
+                    input.dispatchEvent(new Event('change', {{ bubbles: true }}));
+                }}
+                if (user) fill(user, username);
+                fill(pass, password);
+                var submit = scope.querySelector('button[type="submit"], input[type="submit"], button:not([type])');
+                if (submit) submit.click();
+                else if (pass.form) pass.form.requestSubmit ? pass.form.requestSubmit() : pass.form.submit();
+                return true;
+            }})({}, {})
+            "#,
+            serde_json::to_string(&credential.username).unwrap_or_default(),
+            serde_json::to_string(&credential.password).unwrap_or_default(),
+        );
+
+        let submitted = page
+            .evaluate(script)
+            .await
+            .ok()
+            .and_then(|v| v.into_value::<bool>().ok())
+            .unwrap_or(false);
+
+        if submitted {
+            // Give the site time to set its session cookies
+            tokio::time::sleep(tokio::time::Duration::from_secs(4)).await;
+            logged_in.insert(credential.domain.clone());
+        }
+
+        page.close().await.ok();
+
+        if submitted {
+            Ok(())
+        } else {
+            Err(DownloaderError::Browser(format!("No login form found for {}", credential.domain)))
+        }
     }
 
     pub async fn get_video_info(&self, url: &str) -> Result<VideoInfo, DownloaderError> {
         // Validate URL to prevent SSRF attacks
         let validated = validate_url(url)?;
 
+        let (mut browser, handler_task) = self.launch().await?;
+
+        let result = self.extract_info(&browser, &validated).await;
+
+        browser.close().await.ok();
+        handler_task.abort();
+
+        result
+    }
+
+    async fn launch(&self) -> Result<(Browser, JoinHandle<()>), DownloaderError> {
+        self.launch_with(self.headless).await
+    }
+
+    async fn launch_with(&self, headless: bool) -> Result<(Browser, JoinHandle<()>), DownloaderError> {
         let mut builder = BrowserConfig::builder();
 
-        if !self.headless {
+        if !headless {
             builder = builder.with_head();
         }
 
+        let profile_dir = self.profile_dir.read().ok().and_then(|d| d.clone());
+        if let Some(dir) = profile_dir {
+            std::fs::create_dir_all(&dir)?;
+            builder = builder.user_data_dir(dir);
+        }
+
+        builder = builder.args(dns::browser_args());
+        let (executable, args) = LAUNCH_OPTIONS
+            .read()
+            .map(|o| (o.executable.clone(), o.args.clone()))
+            .unwrap_or_default();
+        // Nothing installed: fall back to a downloaded Chromium
+        let executable = match executable {
+            Some(path) => Some(path),
+            None if detection::default_executable(DetectionOptions::default()).is_ok() => None,
+            None => Some(chromium::ensure_installed().await?),
+        };
+        if let Some(executable) = executable {
+            builder = builder.chrome_executable(executable);
+        }
+        builder = builder.args(args);
+
         let config = builder
             .build()
             .map_err(|e| DownloaderError::Browser(e.to_string()))?;
 
-        let (mut browser, mut handler) = Browser::launch(config)
+        let (browser, mut handler) = Browser::launch(config)
             .await
             .map_err(|e| DownloaderError::Browser(e.to_string()))?;
 
         let handler_task = tokio::spawn(async move {
-            while let Some(_) = handler.next().await {}
+            while handler.next().await.is_some() {}
         });
 
-        let result = self.extract_info(&browser, &validated).await;
-
-        browser.close().await.ok();
-        handler_task.abort();
+        // Sessions don't survive a fresh browser
+        self.logged_in.lock().await.clear();
 
-        result
+        Ok((browser, handler_task))
     }
 
     async fn extract_info(&self, browser: &Browser, url: &str) -> Result<VideoInfo, DownloaderError> {
+        // A failed login still lets free content through
+        self.login_if_needed(browser, url).await.ok();
+
+        let hook = self.hook_for(url);
+        let waits = watchdog::extraction_waits();
+
         // Collect video URLs
         let video_urls: Arc<Mutex<Vec<VideoSource>>> = Arc::new(Mutex::new(Vec::new()));
+        // Set when the player talks to a license server or uses EME
+        let drm_detected = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let diagnostics = Arc::new(Mutex::new(ExtractionDiagnostics::new(url)));
 
         // Open main page first
         let page = browser
@@ -58,7 +304,11 @@ impl BrowserAutomation {
             .map_err(|e| DownloaderError::Browser(e.to_string()))?;
 
         // Wait for page to load
-        tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
+        tokio::time::sleep(waits.page_load).await;
+
+        if let Some(hook) = &hook {
+            Self::run_page_hook(&page, hook, &video_urls).await;
+        }
 
         // Get page title
         let title = page
@@ -94,6 +344,14 @@ impl BrowserAutomation {
             .and_then(|v| v.into_value().ok())
             .unwrap_or_default();
 
+        {
+            let mut diagnostics = diagnostics.lock().await;
+            diagnostics.page_title = title.clone();
+            diagnostics.iframe_count = iframes.len();
+            diagnostics.iframes_skipped_as_ads = iframes.iter().filter(|u| is_ad_url(u)).count();
+            diagnostics.hook_used = hook.is_some();
+        }
+
         // Process each iframe - open it with network listener
         for iframe_url in iframes {
             if is_ad_url(&iframe_url) {
@@ -108,12 +366,19 @@ impl BrowserAutomation {
                 // Set up network listener BEFORE waiting
                 if let Ok(mut events) = iframe_page.event_listener::<EventResponseReceived>().await {
                     let urls_for_listener = urls_clone.clone();
+                    let drm_for_listener = drm_detected.clone();
+                    let diagnostics_for_listener = diagnostics.clone();
+                    let embed_for_listener = iframe_url.clone();
 
                     let listener_task = tokio::spawn(async move {
                         while let Some(event) = events.next().await {
                             let resp_url = event.response.url.as_str();
                             let mime: String = event.response.mime_type.clone();
 
+                            if drm::is_license_url(resp_url) {
+                                drm_for_listener.store(true, std::sync::atomic::Ordering::Relaxed);
+                            }
+
                             // Check for video-related responses
                             let is_video = resp_url.contains(".m3u8")
                                 || resp_url.contains(".mp4")
@@ -121,6 +386,16 @@ impl BrowserAutomation {
                                 || mime.contains("mpegurl")
                                 || mime.contains("video/mp4");
 
+                            if is_video {
+                                let mut diagnostics = diagnostics_for_listener.lock().await;
+                                diagnostics.media_responses_seen += 1;
+                                if is_ad_url(resp_url) {
+                                    diagnostics.record_ad_url(resp_url);
+                                }
+                            } else if resp_url.contains(".ts") || mime.contains("mp2t") {
+                                diagnostics_for_listener.lock().await.segment_responses += 1;
+                            }
+
                             if is_video && !is_ad_url(resp_url) {
                                 let quality = extract_quality_from_url(resp_url);
                                 let source_type = if resp_url.contains(".m3u8") || mime.contains("mpegurl") {
@@ -135,6 +410,8 @@ impl BrowserAutomation {
                                         url: resp_url.to_string(),
                                         quality,
                                         source_type: source_type.to_string(),
+                                        embed_url: Some(embed_for_listener.clone()),
+                                        ..Default::default()
                                     });
                                 }
                             }
@@ -142,7 +419,11 @@ impl BrowserAutomation {
                     });
 
                     // Wait for iframe to load
-                    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                    tokio::time::sleep(waits.iframe_load).await;
+
+                    if let Some(hook) = &hook {
+                        Self::run_page_hook(&iframe_page, hook, &urls_clone).await;
+                    }
 
                     // Try to click play button
                     let _ = iframe_page.evaluate(r#"
@@ -163,7 +444,18 @@ impl BrowserAutomation {
                     "#).await.ok();
 
                     // Wait for video to start loading
-                    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                    tokio::time::sleep(waits.playback).await;
+
+                    // Encrypted Media Extensions attach MediaKeys for DRM playback
+                    let uses_eme = iframe_page
+                        .evaluate("Array.from(document.querySelectorAll('video')).some(v => !!v.mediaKeys)")
+                        .await
+                        .ok()
+                        .and_then(|v| v.into_value::<bool>().ok())
+                        .unwrap_or(false);
+                    if uses_eme {
+                        drm_detected.store(true, std::sync::atomic::Ordering::Relaxed);
+                    }
 
                     // Also try to get video URL from jwplayer directly
                     if let Some(jwplayer_sources) = iframe_page
@@ -205,6 +497,8 @@ impl BrowserAutomation {
                                     url: src,
                                     quality,
                                     source_type: source_type.to_string(),
+                                    embed_url: Some(iframe_url.clone()),
+                                    ..Default::default()
                                 });
                             }
                         }
@@ -218,82 +512,208 @@ impl BrowserAutomation {
         }
 
         // Also check main page for video sources (for sites without iframes)
-        let m3u8_regex = Regex::new(r#"(https?://[^\s"'<>\\)]+\.m3u8[^\s"'<>\\)]*)"#).unwrap();
-        let mp4_regex = Regex::new(r#"(https?://[^\s"'<>\\)]+\.mp4[^\s"'<>\\)]*)"#).unwrap();
-
-        if let Some(content) = page
+        let page_html = page
             .evaluate("document.documentElement.outerHTML")
             .await
             .ok()
-            .and_then(|v| v.into_value::<String>().ok())
-        {
+            .and_then(|v| v.into_value::<String>().ok());
+        if let Some(content) = &page_html {
             let mut urls = video_urls.lock().await;
 
-            for cap in m3u8_regex.captures_iter(&content) {
-                let url = &cap[1];
-                if !is_ad_url(url) && !urls.iter().any(|s| s.url == url) {
-                    let quality = extract_quality_from_url(url);
-                    urls.push(VideoSource {
-                        url: url.to_string(),
-                        quality,
-                        source_type: "hls".to_string(),
-                    });
+            for source in find_sources_in_content(content) {
+                if !urls.iter().any(|s| s.url == source.url) {
+                    urls.push(source);
                 }
             }
+        }
 
-            for cap in mp4_regex.captures_iter(&content) {
-                let url = &cap[1];
-                if !is_ad_url(url) && !urls.iter().any(|s| s.url == url) {
-                    let quality = extract_quality_from_url(url);
-                    urls.push(VideoSource {
-                        url: url.to_string(),
-                        quality,
-                        source_type: "direct".to_string(),
-                    });
+        // Let the site's transform script rewrite or filter what was found
+        let transformed = match hook.as_ref() {
+            Some(hook) => {
+                let urls = video_urls.lock().await;
+                match hook.transform_expression(&urls) {
+                    Some(expression) => page
+                        .evaluate(expression)
+                        .await
+                        .ok()
+                        .and_then(|v| v.into_value::<serde_json::Value>().ok())
+                        .map(sources_from_value),
+                    None => None,
                 }
             }
+            None => None,
+        };
+        if let Some(sources) = transformed {
+            *video_urls.lock().await = sources;
         }
 
         page.close().await.ok();
 
         // Deduplicate and filter sources
         let urls = video_urls.lock().await;
-        let mut seen = HashSet::new();
-        let mut unique_sources: Vec<VideoSource> = Vec::new();
-        let mut qualities = HashSet::new();
+        let info = build_video_info(url, title, thumbnail, &urls);
 
-        for source in urls.iter() {
-            // Skip .ts segment files and ads
-            if source.url.contains(".ts") && !source.url.contains(".m3u8") {
-                continue;
+        if info.sources.is_empty() {
+            // A license request only matters when nothing plays without it
+            if drm_detected.load(std::sync::atomic::Ordering::Relaxed) {
+                return Err(DownloaderError::DrmProtected("license request observed".to_string()));
             }
-            if is_ad_url(&source.url) {
-                continue;
-            }
-            if seen.insert(source.url.clone()) {
-                qualities.insert(source.quality.clone());
-                unique_sources.push(source.clone());
+            let mut diagnostics = diagnostics.lock().await.clone();
+            diagnostics.check_anti_bot(page_html.as_deref().unwrap_or_default());
+            diagnostics.rule_used = rules::rule_for(url).map(|r| r.name);
+            return Err(DownloaderError::ExtractionFailed(Box::new(diagnostics)));
+        }
+
+        Ok(info)
+    }
+}
+
+struct PooledBrowser {
+    browser: Arc<Browser>,
+    handler_task: JoinHandle<()>,
+}
+
+/// A single long-lived browser shared by info fetches. Each extraction gets
+/// its own tabs, and the number of extractions running at once is capped so
+/// batch fetches don't open dozens of pages simultaneously.
+pub struct BrowserPool {
+    automation: BrowserAutomation,
+    browser: Mutex<Option<PooledBrowser>>,
+    permits: Semaphore,
+    max_tabs: usize,
+}
+
+impl BrowserPool {
+    pub fn new(headless: bool, max_tabs: usize) -> Self {
+        let max_tabs = max_tabs.max(1);
+        Self {
+            automation: BrowserAutomation::new(headless),
+            browser: Mutex::new(None),
+            permits: Semaphore::new(max_tabs),
+            max_tabs,
+        }
+    }
+
+    pub fn max_tabs(&self) -> usize {
+        self.max_tabs
+    }
+
+    pub fn set_credentials(&self, credentials: Vec<LoginCredential>) {
+        self.automation.set_credentials(credentials);
+    }
+
+    pub fn set_hooks(&self, hooks: Vec<SiteHook>) {
+        self.automation.set_hooks(hooks);
+    }
+
+    pub fn has_hook(&self, url: &str) -> bool {
+        self.hook_for(url).is_some()
+    }
+
+    pub fn hook_for(&self, url: &str) -> Option<SiteHook> {
+        self.automation.hook_for(url)
+    }
+
+    /// Use a persistent profile directory. Takes effect on the next launch.
+    pub fn set_profile_dir(&self, dir: PathBuf) {
+        self.automation.set_profile_dir(dir);
+    }
+
+    /// Export the profile's cookies for `domain` (and its subdomains) as a
+    /// Netscape cookies.txt. Returns the file content and cookie count.
+    pub async fn export_cookies(&self, domain: &str) -> Result<(String, usize), DownloaderError> {
+        let browser = self.browser().await?;
+        let cookies: Vec<_> = browser
+            .get_cookies()
+            .await
+            .map_err(|e| DownloaderError::Browser(e.to_string()))?
+            .into_iter()
+            .filter(|c| cookie_matches_domain(&c.domain, domain))
+            .collect();
+
+        Ok((to_netscape(&cookies), cookies.len()))
+    }
+
+    /// Load cookies from a Netscape cookies.txt into the profile
+    pub async fn import_cookies(&self, content: &str) -> Result<usize, DownloaderError> {
+        let cookies = parse_netscape(content);
+        if cookies.is_empty() {
+            return Err(DownloaderError::Parse("No cookies found in file".to_string()));
+        }
+
+        let count = cookies.len();
+        let browser = self.browser().await?;
+        browser
+            .set_cookies(cookies)
+            .await
+            .map_err(|e| DownloaderError::Browser(e.to_string()))?;
+
+        Ok(count)
+    }
+
+    pub async fn get_video_info(&self, url: &str) -> Result<VideoInfo, DownloaderError> {
+        // Validate URL to prevent SSRF attacks
+        let validated = validate_url(url)?;
+
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .map_err(|e| DownloaderError::Browser(e.to_string()))?;
+
+        let browser = self.browser().await?;
+        self.automation.extract_info(&browser, &validated).await
+    }
+
+    /// Extract in a visible window, for CAPTCHA walls and sites that refuse
+    /// headless Chrome. The window uses the same profile, so the shared
+    /// browser is closed once running extractions finish and relaunched by
+    /// the next fetch.
+    pub async fn get_video_info_visible(&self, url: &str) -> Result<VideoInfo, DownloaderError> {
+        let validated = validate_url(url)?;
+
+        let _permits = self
+            .permits
+            .acquire_many(self.max_tabs as u32)
+            .await
+            .map_err(|e| DownloaderError::Browser(e.to_string()))?;
+        self.shutdown().await;
+
+        let (mut browser, handler_task) = self.automation.launch_with(false).await?;
+        let result = self.automation.extract_info(&browser, &validated).await;
+        browser.close().await.ok();
+        handler_task.abort();
+        result
+    }
+
+    /// Returns the shared browser, (re)launching it if it isn't running
+    async fn browser(&self) -> Result<Arc<Browser>, DownloaderError> {
+        let mut slot = self.browser.lock().await;
+
+        if let Some(pooled) = slot.as_ref() {
+            if !pooled.handler_task.is_finished() {
+                return Ok(pooled.browser.clone());
             }
         }
 
-        let mut quality_list: Vec<String> = qualities.into_iter().collect();
-        quality_list.sort_by(|a, b| {
-            let a_num: i32 = a.replace("p", "").replace("auto", "0").parse().unwrap_or(0);
-            let b_num: i32 = b.replace("p", "").replace("auto", "0").parse().unwrap_or(0);
-            b_num.cmp(&a_num)
+        let (browser, handler_task) = self.automation.launch().await?;
+        let browser = Arc::new(browser);
+        *slot = Some(PooledBrowser {
+            browser: browser.clone(),
+            handler_task,
         });
 
-        if quality_list.is_empty() {
-            quality_list.push("auto".to_string());
-        }
+        Ok(browser)
+    }
 
-        Ok(VideoInfo {
-            url: url.to_string(),
-            title,
-            thumbnail,
-            duration: String::new(),
-            qualities: quality_list,
-            sources: unique_sources,
-        })
+    /// Close the shared browser; the next extraction launches a fresh one
+    pub async fn shutdown(&self) {
+        let pooled = self.browser.lock().await.take();
+        if let Some(pooled) = pooled {
+            if let Ok(mut browser) = Arc::try_unwrap(pooled.browser) {
+                browser.close().await.ok();
+            }
+            pooled.handler_task.abort();
+        }
     }
 }
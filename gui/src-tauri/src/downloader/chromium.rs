@@ -0,0 +1,276 @@
+use futures::StreamExt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use super::dns;
+use super::DownloaderError;
+
+/// Chrome for Testing build downloaded when no browser is installed
+pub const CHROMIUM_VERSION: &str = "131.0.6778.85";
+const DOWNLOAD_BASE: &str = "https://storage.googleapis.com/chrome-for-testing-public";
+// SHA-256 of each platform's archive of CHROMIUM_VERSION. Chrome for Testing
+// doesn't publish digests, so they're taken with `sha256sum` whenever the
+// version is bumped. A platform without one gets no download: an archive
+// that can't be verified is never unpacked or run.
+const CHROMIUM_SHA256: &[(&str, &str)] = &[];
+
+type ProgressFn = Arc<dyn Fn(u64, u64) + Send + Sync>;
+
+// Where builds are installed and who hears about download progress; set
+// once the app knows its data folder
+static INSTALL_DIR: RwLock<Option<(PathBuf, ProgressFn)>> = RwLock::new(None);
+// One download at a time, however many extractions are waiting
+static INSTALL_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+pub fn set_install_dir(dir: PathBuf, on_progress: impl Fn(u64, u64) + Send + Sync + 'static) {
+    if let Ok(mut current) = INSTALL_DIR.write() {
+        *current = Some((dir, Arc::new(on_progress)));
+    }
+}
+
+/// Chrome for Testing platform name for this build
+fn platform() -> Option<&'static str> {
+    if cfg!(all(target_os = "windows", target_arch = "x86_64")) {
+        Some("win64")
+    } else if cfg!(all(target_os = "windows", target_arch = "x86")) {
+        Some("win32")
+    } else if cfg!(all(target_os = "macos", target_arch = "aarch64")) {
+        Some("mac-arm64")
+    } else if cfg!(all(target_os = "macos", target_arch = "x86_64")) {
+        Some("mac-x64")
+    } else if cfg!(all(target_os = "linux", target_arch = "x86_64")) {
+        Some("linux64")
+    } else {
+        None
+    }
+}
+
+fn executable_in(version_dir: &Path, platform: &str) -> PathBuf {
+    let root = version_dir.join(format!("chrome-{}", platform));
+    if platform.starts_with("win") {
+        root.join("chrome.exe")
+    } else if platform.starts_with("mac") {
+        root.join("Google Chrome for Testing.app/Contents/MacOS/Google Chrome for Testing")
+    } else {
+        root.join("chrome")
+    }
+}
+
+/// The downloaded browser, if the pinned version is installed
+pub fn installed(dir: &Path) -> Option<PathBuf> {
+    let executable = executable_in(&dir.join(CHROMIUM_VERSION), platform()?);
+    executable.is_file().then_some(executable)
+}
+
+/// The downloaded browser, fetching and unpacking it first if needed
+pub async fn ensure_installed() -> Result<PathBuf, DownloaderError> {
+    let Some((dir, on_progress)) = INSTALL_DIR.read().ok().and_then(|d| d.clone()) else {
+        return Err(DownloaderError::Browser("No Chrome, Chromium or Edge installation found".to_string()));
+    };
+
+    let _guard = INSTALL_LOCK.lock().await;
+    if let Some(executable) = installed(&dir) {
+        return Ok(executable);
+    }
+    let platform = platform().ok_or_else(|| {
+        DownloaderError::Browser("No Chrome, Chromium or Edge found, and no download exists for this platform".to_string())
+    })?;
+
+    let expected = CHROMIUM_SHA256
+        .iter()
+        .find(|(name, _)| *name == platform)
+        .map(|(_, digest)| *digest)
+        .ok_or_else(|| {
+            DownloaderError::Browser("No Chrome, Chromium or Edge found, and the browser download isn't verified for this platform".to_string())
+        })?;
+
+    std::fs::create_dir_all(&dir)?;
+    let url = format!("{}/{}/{}/chrome-{}.zip", DOWNLOAD_BASE, CHROMIUM_VERSION, platform, platform);
+    let archive = dir.join(format!("{}.zip", CHROMIUM_VERSION));
+    let digest = download(&url, &archive, on_progress.as_ref()).await?;
+    if !digest.eq_ignore_ascii_case(expected) {
+        std::fs::remove_file(&archive).ok();
+        return Err(DownloaderError::Browser(format!(
+            "Downloaded Chromium failed its checksum (expected {}, got {})",
+            expected, digest
+        )));
+    }
+
+    // Unpack beside the final folder and rename, so a half-extracted
+    // build is never picked up
+    let unpacking = dir.join(format!("{}.unpacking", CHROMIUM_VERSION));
+    let version_dir = dir.join(CHROMIUM_VERSION);
+    let (from, to) = (archive.clone(), unpacking.clone());
+    tokio::task::spawn_blocking(move || {
+        std::fs::remove_dir_all(&to).ok();
+        unzip(&from, &to)
+    })
+    .await
+    .map_err(|e| DownloaderError::Browser(e.to_string()))??;
+    std::fs::remove_dir_all(&version_dir).ok();
+    std::fs::rename(&unpacking, &version_dir)?;
+    std::fs::remove_file(&archive).ok();
+
+    // Older pinned builds are no longer used
+    if let Ok(entries) = std::fs::read_dir(&dir) {
+        for entry in entries.flatten().filter(|e| e.file_name() != CHROMIUM_VERSION) {
+            std::fs::remove_dir_all(entry.path()).or_else(|_| std::fs::remove_file(entry.path())).ok();
+        }
+    }
+
+    installed(&dir).ok_or_else(|| DownloaderError::Browser("Downloaded Chromium has no browser executable".to_string()))
+}
+
+/// Fetch `url` to `path`; returns the file's SHA-256, hex encoded
+async fn download(url: &str, path: &Path, on_progress: &(dyn Fn(u64, u64) + Send + Sync)) -> Result<String, DownloaderError> {
+    let response = dns::client_builder().build()?.get(url).send().await?.error_for_status()?;
+    let total = response.content_length().unwrap_or(0);
+
+    let partial = path.with_extension("zip.part");
+    let mut file = File::create(&partial)?;
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    let mut received = 0u64;
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        file.write_all(&chunk)?;
+        context.update(&chunk);
+        received += chunk.len() as u64;
+        on_progress(received, total);
+    }
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&partial, path)?;
+    Ok(hex::encode(context.finish().as_ref()))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Extract a ZIP archive, keeping Unix modes and symlinks. Entries that
+/// would land outside `dest` are refused, and so are symlinks that don't
+/// point further down (relative, no ".."), so nothing written through one
+/// can leave `dest` either. CRCs are checked as entries are read.
+pub fn unzip(archive: &Path, dest: &Path) -> io::Result<()> {
+    let mut zip = zip::ZipArchive::new(File::open(archive)?).map_err(io::Error::other)?;
+    std::fs::create_dir_all(dest)?;
+
+    for index in 0..zip.len() {
+        let mut entry = zip.by_index(index).map_err(io::Error::other)?;
+        let relative = entry.enclosed_name().ok_or_else(|| invalid("ZIP entry points outside the folder"))?;
+        let target = dest.join(relative);
+        if entry.is_dir() {
+            std::fs::create_dir_all(&target)?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        if entry.is_symlink() {
+            let mut link = String::new();
+            entry.read_to_string(&mut link)?;
+            if !points_down(Path::new(&link)) {
+                return Err(invalid("ZIP symlink points outside the folder"));
+            }
+            // Only macOS builds have symlinks, inside the app bundle
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&link, &target)?;
+            continue;
+        }
+
+        let mut out = File::create(&target)?;
+        io::copy(&mut entry, &mut out)?;
+
+        #[cfg(unix)]
+        if let Some(mode) = entry.unix_mode().map(|m| m & 0o777).filter(|m| *m != 0) {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&target, std::fs::Permissions::from_mode(mode))?;
+        }
+    }
+    Ok(())
+}
+
+/// A symlink target that stays below the link's own folder
+fn points_down(link: &Path) -> bool {
+    !link.as_os_str().is_empty() && link.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zip::write::SimpleFileOptions;
+
+    fn write_archive(path: &Path, build: impl FnOnce(&mut zip::ZipWriter<File>, SimpleFileOptions)) {
+        let mut writer = zip::ZipWriter::new(File::create(path).unwrap());
+        build(&mut writer, SimpleFileOptions::default().unix_permissions(0o755));
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn archive_is_unpacked_with_its_modes() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary: Vec<u8> = (0..5000).map(|i| (i * 7) as u8).collect();
+        let archive = dir.path().join("chrome.zip");
+        write_archive(&archive, |zip, options| {
+            zip.add_directory("chrome-linux64/", options).unwrap();
+            zip.start_file("chrome-linux64/chrome", options).unwrap();
+            zip.write_all(&binary).unwrap();
+            zip.start_file("chrome-linux64/LICENSE", options.unix_permissions(0o644)).unwrap();
+            zip.write_all(b"license").unwrap();
+            zip.add_symlink("chrome-linux64/current", "LICENSE", options).unwrap();
+        });
+
+        let dest = dir.path().join("unpacked");
+        unzip(&archive, &dest).unwrap();
+        assert_eq!(std::fs::read(dest.join("chrome-linux64/chrome")).unwrap(), binary);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dest.join("chrome-linux64/chrome")).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o755);
+            assert_eq!(std::fs::read(dest.join("chrome-linux64/current")).unwrap(), b"license");
+        }
+    }
+
+    #[test]
+    fn entries_and_symlinks_leaving_the_folder_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("chrome.zip");
+
+        write_archive(&archive, |zip, options| {
+            zip.start_file("../escaped", options).unwrap();
+            zip.write_all(b"x").unwrap();
+        });
+        assert!(unzip(&archive, &dir.path().join("a")).is_err());
+        assert!(!dir.path().join("escaped").exists());
+
+        for link in ["../..", "/etc", "sub/../../.."] {
+            write_archive(&archive, |zip, options| {
+                zip.add_symlink("out", link, options).unwrap();
+                zip.start_file("out/escaped", options).unwrap();
+                zip.write_all(b"x").unwrap();
+            });
+            assert!(unzip(&archive, &dir.path().join("b")).is_err(), "{} should be refused", link);
+        }
+    }
+
+    #[test]
+    fn corrupted_entries_fail_their_crc() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("chrome.zip");
+        write_archive(&archive, |zip, options| {
+            zip.start_file("chrome", options.compression_method(zip::CompressionMethod::Stored)).unwrap();
+            zip.write_all(b"recognisable payload").unwrap();
+        });
+
+        let mut bytes = std::fs::read(&archive).unwrap();
+        let at = bytes.windows(7).position(|w| w == b"payload").unwrap();
+        bytes[at] ^= 1;
+        std::fs::write(&archive, bytes).unwrap();
+        assert!(unzip(&archive, &dir.path().join("c")).is_err());
+    }
+}
@@ -0,0 +1,60 @@
+// Used when neither the bytes, the Content-Type nor the URL say otherwise
+pub const DEFAULT_EXTENSION: &str = "mp4";
+
+/// Container from the file's first bytes
+pub fn sniff_extension(head: &[u8]) -> Option<&'static str> {
+    if head.len() >= 12 && &head[4..8] == b"ftyp" {
+        return Some(match &head[8..12] {
+            b"qt  " => "mov",
+            b"M4V " | b"M4VH" | b"M4VP" => "m4v",
+            _ => "mp4",
+        });
+    }
+    if head.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        // Matroska and WebM share EBML; the DocType tells them apart
+        let is_webm = head.windows(4).take(64).any(|w| w == b"webm");
+        return Some(if is_webm { "webm" } else { "mkv" });
+    }
+    // MPEG-TS packets are 188 bytes, each starting with the 0x47 sync byte
+    if head.len() > 188 && head[0] == 0x47 && head[188] == 0x47 {
+        return Some("ts");
+    }
+    if head.starts_with(b"FLV") {
+        return Some("flv");
+    }
+    if head.len() >= 12 && head.starts_with(b"RIFF") && &head[8..12] == b"AVI " {
+        return Some("avi");
+    }
+    None
+}
+
+pub fn extension_from_content_type(content_type: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+    match mime.as_str() {
+        "video/mp4" => Some("mp4"),
+        "video/webm" => Some("webm"),
+        "video/x-matroska" => Some("mkv"),
+        "video/mp2t" => Some("ts"),
+        "video/quicktime" => Some("mov"),
+        "video/x-flv" => Some("flv"),
+        "video/x-msvideo" => Some("avi"),
+        _ => None,
+    }
+}
+
+pub fn extension_from_url(url: &str) -> Option<&'static str> {
+    let path = url::Url::parse(url).ok()?.path().to_lowercase();
+    let ext = path.rsplit_once('.')?.1;
+    ["mp4", "m4v", "webm", "mkv", "ts", "mov", "flv", "avi"]
+        .into_iter()
+        .find(|known| *known == ext)
+}
+
+/// Bytes first since servers often send application/octet-stream, then
+/// the Content-Type, then the URL
+pub fn detect_extension(head: &[u8], content_type: Option<&str>, url: &str) -> &'static str {
+    sniff_extension(head)
+        .or_else(|| content_type.and_then(extension_from_content_type))
+        .or_else(|| extension_from_url(url))
+        .unwrap_or(DEFAULT_EXTENSION)
+}
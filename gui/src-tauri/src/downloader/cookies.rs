@@ -0,0 +1,73 @@
+use chromiumoxide::cdp::browser_protocol::network::{Cookie, CookieParam, TimeSinceEpoch};
+
+const HEADER: &str = "# Netscape HTTP Cookie File\n# Exported by Thai Video Downloader\n\n";
+// curl and yt-dlp mark HttpOnly cookies with this prefix on the domain column
+const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+
+/// Whether a cookie domain (".example.com" or "example.com") applies to `domain`
+pub fn cookie_matches_domain(cookie_domain: &str, domain: &str) -> bool {
+    let cookie_domain = cookie_domain.trim_start_matches('.').to_lowercase();
+    let domain = domain.trim_start_matches('.').to_lowercase();
+    domain.is_empty()
+        || cookie_domain == domain
+        || cookie_domain.ends_with(&format!(".{}", domain))
+}
+
+/// Serialize cookies to the Netscape cookies.txt format
+pub fn to_netscape(cookies: &[Cookie]) -> String {
+    let mut out = String::from(HEADER);
+
+    for cookie in cookies {
+        let prefix = if cookie.http_only { HTTP_ONLY_PREFIX } else { "" };
+        let include_subdomains = if cookie.domain.starts_with('.') { "TRUE" } else { "FALSE" };
+        let expires = if cookie.session { 0 } else { cookie.expires.max(0.0) as i64 };
+
+        out.push_str(&format!(
+            "{}{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            prefix,
+            cookie.domain,
+            include_subdomains,
+            cookie.path,
+            if cookie.secure { "TRUE" } else { "FALSE" },
+            expires,
+            cookie.name,
+            cookie.value,
+        ));
+    }
+
+    out
+}
+
+/// Parse a Netscape cookies.txt. Malformed lines are skipped.
+pub fn parse_netscape(content: &str) -> Vec<CookieParam> {
+    let mut cookies = Vec::new();
+
+    for line in content.lines() {
+        let (line, http_only) = match line.strip_prefix(HTTP_ONLY_PREFIX) {
+            Some(rest) => (rest, true),
+            None => (line, false),
+        };
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 7 {
+            continue;
+        }
+
+        let mut cookie = CookieParam::new(fields[5], fields[6].trim_end_matches('\r'));
+        cookie.domain = Some(fields[0].to_string());
+        cookie.path = Some(fields[2].to_string());
+        cookie.secure = Some(fields[3].eq_ignore_ascii_case("TRUE"));
+        cookie.http_only = Some(http_only);
+        if let Ok(expires) = fields[4].parse::<i64>() {
+            if expires > 0 {
+                cookie.expires = Some(TimeSinceEpoch::new(expires as f64));
+            }
+        }
+        cookies.push(cookie);
+    }
+
+    cookies
+}
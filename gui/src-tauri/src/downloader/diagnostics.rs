@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+
+// Markers of challenge / block pages served instead of the real site
+const ANTI_BOT_MARKERS: &[(&str, &str)] = &[
+    ("just a moment", "Cloudflare challenge"),
+    ("cf-chl-", "Cloudflare challenge"),
+    ("attention required", "Cloudflare block page"),
+    ("ddos-guard", "DDoS-Guard"),
+    ("captcha", "CAPTCHA"),
+    ("access denied", "Access denied page"),
+    ("verify you are human", "Human verification"),
+];
+
+// Ad URLs kept in a report; enough to spot a new ad network
+const MAX_AD_URLS: usize = 10;
+
+/// What an extraction saw when it found no sources, so a failure says why
+/// a site stopped working instead of just "no sources"
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ExtractionDiagnostics {
+    pub url: String,
+    pub page_title: String,
+    /// Name of the challenge page when one was served
+    pub anti_bot: Option<String>,
+    pub iframe_count: usize,
+    pub iframes_skipped_as_ads: usize,
+    /// Video responses seen on the network, including ads
+    pub media_responses_seen: usize,
+    pub ad_responses: usize,
+    pub ad_urls_seen: Vec<String>,
+    /// Bare .ts segment requests; with no sources the playlist was missed
+    pub segment_responses: usize,
+    pub hook_used: bool,
+    pub rule_used: Option<String>,
+}
+
+impl ExtractionDiagnostics {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Look for a challenge page in the title or HTML
+    pub fn check_anti_bot(&mut self, content: &str) {
+        let title = self.page_title.to_lowercase();
+        let content = content.to_lowercase();
+        self.anti_bot = ANTI_BOT_MARKERS
+            .iter()
+            .find(|(marker, _)| title.contains(marker) || content.contains(marker))
+            .map(|(_, name)| name.to_string());
+    }
+
+    pub fn record_ad_url(&mut self, url: &str) {
+        self.ad_responses += 1;
+        if self.ad_urls_seen.len() < MAX_AD_URLS && !self.ad_urls_seen.iter().any(|u| u == url) {
+            self.ad_urls_seen.push(url.to_string());
+        }
+    }
+
+    /// Most likely causes, most specific first
+    pub fn reasons(&self) -> Vec<String> {
+        let mut reasons = Vec::new();
+
+        if let Some(anti_bot) = &self.anti_bot {
+            reasons.push(format!("page blocked by anti-bot protection ({})", anti_bot));
+        }
+        if self.iframe_count == 0 && self.media_responses_seen == 0 {
+            reasons.push("no player iframes and no video requests on the page".to_string());
+        } else if self.iframe_count > 0 && self.iframe_count == self.iframes_skipped_as_ads {
+            reasons.push(format!("all {} iframes were ads", self.iframe_count));
+        }
+        if self.ad_responses > 0 && self.media_responses_seen == self.ad_responses {
+            reasons.push("only ad videos were seen".to_string());
+        }
+        if self.segment_responses > 0 {
+            reasons.push(format!("{} .ts segments were loaded but the playlist URL was not captured", self.segment_responses));
+        }
+        if let Some(rule) = &self.rule_used {
+            reasons.push(format!("extractor rule '{}' matched nothing", rule));
+        }
+        if self.hook_used {
+            reasons.push("site hook returned no sources".to_string());
+        }
+        if reasons.is_empty() {
+            reasons.push("the player may need interaction or a login".to_string());
+        }
+
+        reasons
+    }
+
+    pub fn summary(&self) -> String {
+        self.reasons().join("; ")
+    }
+}
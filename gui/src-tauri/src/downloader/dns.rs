@@ -0,0 +1,309 @@
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{Client, ClientBuilder};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use super::lan;
+use super::USER_AGENT;
+
+// Address family preference
+pub const IP_ANY: &str = "any";
+pub const IP_V4: &str = "ipv4";
+pub const IP_V6: &str = "ipv6";
+
+// chromiumoxide already passes --enable-features; Chrome keeps only the last one
+const CHROME_DEFAULT_FEATURES: &str = "NetworkService,NetworkServiceInProcess";
+
+const DOH_TIMEOUT: Duration = Duration::from_secs(5);
+// Same limit as reqwest's default policy
+const MAX_REDIRECTS: usize = 10;
+// DNS record types in DoH JSON answers
+const RECORD_A: u16 = 1;
+const RECORD_AAAA: u16 = 28;
+
+/// DNS and address family settings shared by every HTTP client and the
+/// browser, so hosts blocked by the ISP's resolver still work
+#[derive(Clone, Debug, Default)]
+pub struct NetworkConfig {
+    /// DoH resolver speaking the JSON API, e.g.
+    /// https://cloudflare-dns.com/dns-query or https://dns.google/resolve;
+    /// empty uses the system resolver
+    pub doh_url: String,
+    /// Host -> IP pinned by the user, checked before any lookup
+    pub host_overrides: BTreeMap<String, IpAddr>,
+    /// IP_ANY, IP_V4 or IP_V6: which addresses to try first
+    pub ip_preference: String,
+}
+
+impl NetworkConfig {
+    /// Build from settings; mappings with an unparsable IP are dropped
+    pub fn new(doh_url: &str, host_overrides: &BTreeMap<String, String>, ip_preference: &str) -> Self {
+        Self {
+            doh_url: doh_url.trim().to_string(),
+            host_overrides: host_overrides
+                .iter()
+                .filter_map(|(host, ip)| Some((host.trim().to_lowercase(), ip.trim().parse().ok()?)))
+                .collect(),
+            ip_preference: ip_preference.to_string(),
+        }
+    }
+
+    fn is_default(&self) -> bool {
+        self.doh_url.is_empty() && self.host_overrides.is_empty() && !self.prefers_family()
+    }
+
+    fn prefers_family(&self) -> bool {
+        self.ip_preference == IP_V4 || self.ip_preference == IP_V6
+    }
+
+    fn override_for(&self, host: &str) -> Option<IpAddr> {
+        self.host_overrides.get(&host.to_lowercase()).copied()
+    }
+
+    /// Put the preferred family first, keeping the other as a fallback
+    fn order(&self, mut addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        match self.ip_preference.as_str() {
+            IP_V4 => addrs.sort_by_key(|a| !a.is_ipv4()),
+            IP_V6 => addrs.sort_by_key(|a| !a.is_ipv6()),
+            _ => {}
+        }
+        addrs
+    }
+}
+
+/// Reject a DoH URL that isn't https and mappings that aren't IP addresses
+pub fn validate_settings(doh_url: &str, host_overrides: &BTreeMap<String, String>) -> Result<(), String> {
+    let doh_url = doh_url.trim();
+    if !doh_url.is_empty() && !url::Url::parse(doh_url).map(|u| u.scheme() == "https").unwrap_or(false) {
+        return Err(format!("Invalid DNS-over-HTTPS URL: {}", doh_url));
+    }
+    for (host, ip) in host_overrides {
+        if host.trim().is_empty() || ip.trim().parse::<IpAddr>().is_err() {
+            return Err(format!("Invalid DNS override: {} -> {}", host, ip));
+        }
+    }
+    Ok(())
+}
+
+static NETWORK: RwLock<Option<Arc<NetworkConfig>>> = RwLock::new(None);
+
+pub fn set_network_config(config: NetworkConfig) {
+    if let Ok(mut current) = NETWORK.write() {
+        *current = Some(Arc::new(config));
+    }
+}
+
+fn current() -> Option<Arc<NetworkConfig>> {
+    NETWORK.read().ok()?.clone().filter(|c| !c.is_default())
+}
+
+/// Client builder with the app's user agent and DNS settings; every
+/// download and probe client starts here. Its resolver also refuses
+/// names that resolve to private addresses, so a host passing
+/// validate_url can't rebind to the LAN afterwards. The resolver never
+/// sees IP literals, so each redirect hop goes through validate_url too.
+pub fn client_builder() -> ClientBuilder {
+    Client::builder()
+        .user_agent(USER_AGENT)
+        .dns_resolver(Arc::new(Resolver { config: current() }))
+        .redirect(reqwest::redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error("too many redirects");
+            }
+            match super::validate_url(attempt.url().as_str()) {
+                Ok(_) => attempt.follow(),
+                Err(e) => attempt.error(e.to_string()),
+            }
+        }))
+}
+
+/// Chrome flags for the same DNS settings. The address family preference
+/// has no Chrome switch and only applies to the HTTP clients.
+pub fn browser_args() -> Vec<String> {
+    let Some(config) = current() else {
+        return Vec::new();
+    };
+    let mut args = Vec::new();
+
+    if !config.host_overrides.is_empty() {
+        let rules: Vec<String> = config
+            .host_overrides
+            .iter()
+            .map(|(host, ip)| match ip {
+                IpAddr::V6(ip) => format!("MAP {} [{}]", host, ip),
+                IpAddr::V4(ip) => format!("MAP {} {}", host, ip),
+            })
+            .collect();
+        args.push(format!("--host-resolver-rules={}", rules.join(", ")));
+    }
+
+    if !config.doh_url.is_empty() {
+        // Chrome speaks RFC 8484 only, which Google serves on /dns-query
+        // instead of /resolve
+        let wire_url = match config.doh_url.strip_suffix("/resolve") {
+            Some(base) => format!("{}/dns-query", base),
+            None => config.doh_url.clone(),
+        };
+        let template: String = url::form_urlencoded::byte_serialize(wire_url.as_bytes()).collect();
+        args.push(format!(
+            "--enable-features={},DnsOverHttps:Fallback/false/Templates/{}",
+            CHROME_DEFAULT_FEATURES, template
+        ));
+    }
+
+    args
+}
+
+struct Resolver {
+    config: Option<Arc<NetworkConfig>>,
+}
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let config = self.config.clone().unwrap_or_default();
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Addrs = Box::new(lookup(&config, &host).await?.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Addresses of `host` in preference order, with the same DNS settings and
+/// private address filtering as the HTTP clients
+async fn lookup(config: &NetworkConfig, host: &str) -> Result<Vec<SocketAddr>, Box<dyn std::error::Error + Send + Sync>> {
+    // The user's own mappings are trusted as given
+    if let Some(ip) = config.override_for(host) {
+        return Ok(vec![SocketAddr::new(ip, 0)]);
+    }
+
+    let mut addrs: Vec<SocketAddr> = if config.doh_url.is_empty() {
+        tokio::net::lookup_host((host, 0)).await?.collect()
+    } else {
+        doh_lookup(&config.doh_url, host).await?
+    };
+    if !lan::is_trusted_host(host) {
+        addrs.retain(|a| !lan::is_blocked_ip(a.ip()));
+        if addrs.is_empty() {
+            return Err(format!("{} resolves to a private network address", host).into());
+        }
+    }
+    Ok(config.order(addrs))
+}
+
+/// Check a URL for a tool that connects on its own, like aria2: it must
+/// pass validate_url, and its host must resolve to a public address (or be
+/// trusted) just like for the HTTP clients
+pub async fn check_external_url(url: &str) -> Result<(), super::DownloaderError> {
+    super::validate_url(url)?;
+    let parsed = url::Url::parse(url).map_err(|e| super::DownloaderError::Parse(e.to_string()))?;
+    if let Some(url::Host::Domain(host)) = parsed.host() {
+        let config = current().unwrap_or_default();
+        lookup(&config, host)
+            .await
+            .map_err(|e| super::DownloaderError::DownloadFailed(e.to_string()))?;
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct DohResponse {
+    #[serde(rename = "Status")]
+    status: u32,
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DohAnswer>,
+}
+
+#[derive(Deserialize)]
+struct DohAnswer {
+    #[serde(rename = "type")]
+    record_type: u16,
+    data: String,
+}
+
+/// A and AAAA lookup over the DoH JSON API. The resolver's own host goes
+/// through the system DNS.
+async fn doh_lookup(doh_url: &str, host: &str) -> Result<Vec<SocketAddr>, Box<dyn std::error::Error + Send + Sync>> {
+    let client = Client::builder().user_agent(USER_AGENT).timeout(DOH_TIMEOUT).build()?;
+    let query = |record: &'static str| {
+        client
+            .get(doh_url)
+            .query(&[("name", host), ("type", record)])
+            .header(reqwest::header::ACCEPT, "application/dns-json")
+            .send()
+    };
+
+    let mut addrs = Vec::new();
+    for response in futures::future::join_all([query("A"), query("AAAA")]).await {
+        let Ok(response) = response else {
+            continue;
+        };
+        let Ok(body) = response.json::<DohResponse>().await else {
+            continue;
+        };
+        if body.status != 0 {
+            continue;
+        }
+        addrs.extend(
+            body.answer
+                .iter()
+                .filter(|a| a.record_type == RECORD_A || a.record_type == RECORD_AAAA)
+                .filter_map(|a| a.data.parse::<IpAddr>().ok())
+                .map(|ip| SocketAddr::new(ip, 0)),
+        );
+    }
+
+    if addrs.is_empty() {
+        return Err(format!("DoH lookup of {} via {} returned no addresses", host, doh_url).into());
+    }
+    Ok(addrs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Loopback server answering /video with a body and anything else with
+    /// a redirect to /video by IP literal; returns its port
+    async fn serve() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0u8; 1024];
+                let n = socket.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let response = if request.starts_with("GET /video ") {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok".to_string()
+                } else {
+                    format!("HTTP/1.1 302 Found\r\nLocation: http://127.0.0.1:{}/video\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", port)
+                };
+                socket.write_all(response.as_bytes()).await.ok();
+            }
+        });
+        port
+    }
+
+    // One test, since the network config is global
+    #[tokio::test]
+    async fn overrides_are_trusted_but_private_names_and_redirects_are_not() {
+        let port = serve().await;
+        let overrides = BTreeMap::from([("blocked.example".to_string(), "127.0.0.1".to_string())]);
+        validate_settings("", &overrides).unwrap();
+        assert!(validate_settings("http://dns.example/resolve", &overrides).is_err());
+        set_network_config(NetworkConfig::new("", &overrides, IP_V4));
+        let client = client_builder().build().unwrap();
+
+        let body = client.get(format!("http://blocked.example:{}/video", port)).send().await.unwrap().text().await.unwrap();
+        assert_eq!(body, "ok");
+
+        // localhost resolves to loopback, and the redirect leads to an IP literal
+        assert!(client.get(format!("http://localhost:{}/video", port)).send().await.is_err());
+        assert!(client.get(format!("http://blocked.example:{}/elsewhere", port)).send().await.is_err());
+    }
+}
@@ -0,0 +1,88 @@
+// DRM system IDs / key formats as they appear in HLS playlists
+const WIDEVINE_UUID: &str = "edef8ba9-79d6-4ace-a3c8-27dcd51d21ed";
+const PLAYREADY_UUID: &str = "9a04f079-9840-4286-ab92-e65be0885f95";
+const FAIRPLAY_KEYFORMAT: &str = "com.apple.streamingkeydelivery";
+
+// License servers of the DRM systems and the big DRM vendors, matched
+// against the host and path of requests seen while the player loads.
+// Generic words like "license" also hit terms and licensing pages.
+const LICENSE_URL_PATTERNS: &[&str] = &[
+    "widevine",
+    "playready",
+    "fairplay",
+    "rightsmanager.asmx",
+    "drmtoday.com",
+    "ezdrm.com",
+    "axprod.net",
+    "license.pallycon.com",
+    "license.vdocipher.com",
+];
+
+pub fn is_license_url(url: &str) -> bool {
+    let Ok(url) = url::Url::parse(url) else {
+        return false;
+    };
+    let target = format!("{}{}", url.host_str().unwrap_or_default(), url.path()).to_lowercase();
+    LICENSE_URL_PATTERNS.iter().any(|pattern| target.contains(pattern))
+}
+
+/// Name of the DRM scheme declared by an HLS playlist's EXT-X-KEY /
+/// EXT-X-SESSION-KEY tags. Plain AES-128 is not DRM and returns None.
+pub fn playlist_drm(content: &str) -> Option<&'static str> {
+    content
+        .lines()
+        .filter(|line| line.starts_with("#EXT-X-KEY:") || line.starts_with("#EXT-X-SESSION-KEY:"))
+        .find_map(|line| {
+            let line_lower = line.to_lowercase();
+            if line_lower.contains(WIDEVINE_UUID) {
+                Some("Widevine")
+            } else if line_lower.contains(PLAYREADY_UUID) || line_lower.contains("com.microsoft.playready") {
+                Some("PlayReady")
+            } else if line_lower.contains(FAIRPLAY_KEYFORMAT) || line_lower.contains("uri=\"skd://") {
+                Some("FairPlay")
+            } else if line_lower.contains("method=sample-aes-ctr") {
+                Some("SAMPLE-AES-CTR")
+            } else {
+                None
+            }
+        })
+}
+
+/// Whether the start of an MP4 carries protection boxes (`pssh` for the
+/// license system, `encv`/`enca` for encrypted tracks)
+pub fn mp4_drm(head: &[u8]) -> Option<&'static str> {
+    let has_box = |name: &[u8]| head.windows(4).any(|w| w == name);
+    if has_box(b"pssh") {
+        Some("Common Encryption (pssh)")
+    } else if has_box(b"encv") || has_box(b"enca") {
+        Some("Common Encryption")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_drm_license_servers_count() {
+        for url in [
+            "https://lic.drmtoday.com/license-proxy-widevine/cenc/",
+            "https://example.com/widevine/getlicense",
+            "https://pr.example.net/playready/rightsmanager.asmx",
+            "https://license.pallycon.com/ri/licenseManager.do",
+        ] {
+            assert!(is_license_url(url), "{} is a license server", url);
+        }
+        for url in [
+            "https://example.com/license",
+            "https://example.com/terms/license.html",
+            "https://license.example.com/about",
+            "https://example.com/drm/faq",
+            "https://example.com/video.m3u8?note=widevine",
+        ] {
+            assert!(!is_license_url(url), "{} isn't a license server", url);
+        }
+    }
+}
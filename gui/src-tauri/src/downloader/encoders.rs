@@ -0,0 +1,89 @@
+use tokio::sync::OnceCell;
+
+// Encoder choice (AppSettings::video_encoder)
+pub const ENCODER_AUTO: &str = "auto";
+pub const ENCODER_SOFTWARE: &str = "software";
+pub const ENCODER_NVENC: &str = "nvenc";
+pub const ENCODER_QSV: &str = "qsv";
+pub const ENCODER_VIDEOTOOLBOX: &str = "videotoolbox";
+pub const ENCODER_AMF: &str = "amf";
+
+// Output codecs
+pub const CODEC_H264: &str = "h264";
+pub const CODEC_HEVC: &str = "hevc";
+
+// Hardware families, in the order auto tries them
+const HW_FAMILIES: [&str; 4] = [ENCODER_NVENC, ENCODER_QSV, ENCODER_VIDEOTOOLBOX, ENCODER_AMF];
+
+static AVAILABLE: OnceCell<Vec<String>> = OnceCell::const_new();
+
+/// Hardware encoder families this ffmpeg build offers. Listed once per run;
+/// a listed encoder can still fail at runtime without the GPU or driver.
+pub async fn available_hw() -> &'static [String] {
+    AVAILABLE
+        .get_or_init(|| async {
+            let Ok(output) = tokio::process::Command::new("ffmpeg")
+                .args(["-hide_banner", "-encoders"])
+                .output()
+                .await
+            else {
+                return Vec::new();
+            };
+            let listing = String::from_utf8_lossy(&output.stdout);
+            let names: Vec<&str> = listing
+                .lines()
+                .filter_map(|line| line.split_whitespace().nth(1))
+                .collect();
+            HW_FAMILIES
+                .iter()
+                .filter(|family| names.iter().any(|n| n.ends_with(&format!("_{}", family))))
+                .map(|family| family.to_string())
+                .collect()
+        })
+        .await
+}
+
+/// ffmpeg encoder name for a family and codec
+pub fn encoder_name(family: &str, codec: &str) -> String {
+    match (family, codec) {
+        (ENCODER_SOFTWARE, CODEC_HEVC) => "libx265".to_string(),
+        (ENCODER_SOFTWARE, _) => "libx264".to_string(),
+        (family, codec) => format!("{}_{}", codec, family),
+    }
+}
+
+/// Encoders to try for `preference`, best first. Software always comes last
+/// so a failing GPU encoder falls back to it.
+pub async fn candidates(preference: &str) -> Vec<String> {
+    let available = available_hw().await;
+    let mut families: Vec<String> = match preference {
+        ENCODER_SOFTWARE => Vec::new(),
+        ENCODER_AUTO | "" => available.to_vec(),
+        family => available.iter().filter(|f| f.as_str() == family).cloned().collect(),
+    };
+    families.push(ENCODER_SOFTWARE.to_string());
+    families
+}
+
+/// Video encoding arguments for a family at a CRF-like quality (lower is
+/// better). Each hardware family spells constant quality differently.
+pub fn video_args(family: &str, codec: &str, quality: u8) -> Vec<String> {
+    // VideoToolbox takes 1-100 where higher is better
+    let vt_quality = 100u8.saturating_sub(quality.saturating_mul(2)).max(1).to_string();
+    let quality = quality.to_string();
+    let rest: Vec<&str> = match family {
+        ENCODER_NVENC => vec!["-preset", "p5", "-rc", "vbr", "-cq", &quality, "-b:v", "0"],
+        ENCODER_QSV => vec!["-preset", "medium", "-global_quality", &quality],
+        ENCODER_AMF => vec!["-quality", "balanced", "-rc", "cqp", "-qp_i", &quality, "-qp_p", &quality],
+        ENCODER_VIDEOTOOLBOX => vec!["-q:v", &vt_quality],
+        _ => vec!["-preset", "medium", "-crf", &quality],
+    };
+
+    let mut args = vec!["-c:v".to_string(), encoder_name(family, codec)];
+    args.extend(rest.into_iter().map(String::from));
+    if codec == CODEC_HEVC {
+        // Lets QuickTime and iOS play H.265 in MP4
+        args.extend(["-tag:v".to_string(), "hvc1".to_string()]);
+    }
+    args
+}
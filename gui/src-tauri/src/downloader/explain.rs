@@ -0,0 +1,219 @@
+use serde::{Deserialize, Serialize};
+
+use super::DownloaderError;
+
+// Error codes the UI can key icons or actions on
+pub const ERROR_FFMPEG_MISSING: &str = "ffmpeg_missing";
+pub const ERROR_FFMPEG_FAILED: &str = "ffmpeg_failed";
+pub const ERROR_DRM: &str = "drm";
+pub const ERROR_LINK_EXPIRED: &str = "link_expired";
+pub const ERROR_NOT_FOUND: &str = "not_found";
+pub const ERROR_TIMEOUT: &str = "timeout";
+pub const ERROR_NETWORK: &str = "network";
+pub const ERROR_NO_SOURCES: &str = "no_sources";
+pub const ERROR_DISK_FULL: &str = "disk_full";
+pub const ERROR_PERMISSION: &str = "permission";
+pub const ERROR_TOO_LARGE: &str = "too_large";
+pub const ERROR_BROWSER: &str = "browser";
+pub const ERROR_UNKNOWN: &str = "unknown";
+
+/// Thai explanation of a failure and what the user can do about it, sent
+/// next to the raw error
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ErrorHelp {
+    pub code: String,
+    pub message: String,
+    pub hint: String,
+}
+
+fn help(code: &str, message: &str, hint: &str) -> ErrorHelp {
+    ErrorHelp {
+        code: code.to_string(),
+        message: message.to_string(),
+        hint: hint.to_string(),
+    }
+}
+
+fn status_help(status: u16) -> Option<ErrorHelp> {
+    match status {
+        401 | 403 | 410 => Some(link_expired_help()),
+        404 => Some(help(
+            ERROR_NOT_FOUND,
+            "ไม่พบวิดีโอบนเซิร์ฟเวอร์",
+            "ตรวจสอบลิงก์อีกครั้ง วิดีโออาจถูกลบไปแล้ว",
+        )),
+        429 => Some(help(
+            ERROR_NETWORK,
+            "เว็บไซต์จำกัดจำนวนคำขอ",
+            "รอสักครู่แล้วลองใหม่ หรือลดจำนวนการดาวน์โหลดพร้อมกัน",
+        )),
+        500..=599 => Some(help(
+            ERROR_NETWORK,
+            "เซิร์ฟเวอร์ของเว็บไซต์ขัดข้อง",
+            "ลองใหม่อีกครั้งภายหลัง",
+        )),
+        _ => None,
+    }
+}
+
+impl DownloaderError {
+    /// Explanation for this error, using its details where the variant
+    /// carries them
+    pub fn help(&self) -> ErrorHelp {
+        match self {
+            DownloaderError::Network(e) => {
+                if let Some(help) = e.status().and_then(|s| status_help(s.as_u16())) {
+                    help
+                } else if e.is_timeout() {
+                    timeout_help()
+                } else {
+                    network_help()
+                }
+            }
+            DownloaderError::Io(e) => io_help(e.kind()).unwrap_or_else(|| explain(&self.to_string())),
+            DownloaderError::NoSources | DownloaderError::ExtractionFailed(_) => no_sources_help(),
+            DownloaderError::DrmProtected(_) => drm_help(),
+            DownloaderError::SegmentExpired(_) => link_expired_help(),
+            DownloaderError::Timeout(_) => timeout_help(),
+            DownloaderError::TooLarge(_) => too_large_help(),
+            _ => explain(&self.to_string()),
+        }
+    }
+}
+
+/// Explanation for an error that only survives as text, such as the
+/// `Err(String)` of a command
+pub fn explain(error: &str) -> ErrorHelp {
+    let lower = error.to_lowercase();
+
+    if lower.contains("ffmpeg not found") || lower.contains("ffprobe not found") {
+        return help(
+            ERROR_FFMPEG_MISSING,
+            "ไม่พบโปรแกรม ffmpeg",
+            "ติดตั้ง ffmpeg แล้วเพิ่มลงใน PATH จากนั้นเปิดแอปใหม่",
+        );
+    }
+    if lower.contains("drm") {
+        return drm_help();
+    }
+    if lower.contains("no video sources") {
+        return no_sources_help();
+    }
+    if lower.contains("link expired") {
+        return link_expired_help();
+    }
+    if let Some(help) = find_status(&lower).and_then(status_help) {
+        return help;
+    }
+    if lower.contains("size limit") {
+        return too_large_help();
+    }
+    if lower.contains("timed out") || lower.contains("timeout") {
+        return timeout_help();
+    }
+    if lower.contains("no space left") || lower.contains("disk full") || lower.contains("not enough space") {
+        return io_help(std::io::ErrorKind::StorageFull).unwrap_or_else(unknown_help);
+    }
+    if lower.contains("permission denied") || lower.contains("access is denied") {
+        return io_help(std::io::ErrorKind::PermissionDenied).unwrap_or_else(unknown_help);
+    }
+    if lower.contains("network error") || lower.contains("dns") || lower.contains("connection") {
+        return network_help();
+    }
+    if lower.contains("browser") || lower.contains("chrome") || lower.contains("chromium") {
+        return help(
+            ERROR_BROWSER,
+            "เปิดเบราว์เซอร์เพื่อดึงข้อมูลวิดีโอไม่สำเร็จ",
+            "ติดตั้ง Google Chrome หรือ Chromium แล้วลองใหม่",
+        );
+    }
+    if lower.contains("ffmpeg") {
+        return help(
+            ERROR_FFMPEG_FAILED,
+            "แปลงไฟล์วิดีโอไม่สำเร็จ",
+            "ไฟล์อาจดาวน์โหลดมาไม่ครบ ลองดาวน์โหลดใหม่ หรือดูรายละเอียดในบันทึกการดาวน์โหลด",
+        );
+    }
+    unknown_help()
+}
+
+/// HTTP status mentioned in an error text ("HTTP status client error (403 Forbidden)")
+fn find_status(lower: &str) -> Option<u16> {
+    let start = lower.find("status")?;
+    lower[start..]
+        .split(|c: char| !c.is_ascii_digit())
+        .find(|part| part.len() == 3)
+        .and_then(|part| part.parse().ok())
+}
+
+fn io_help(kind: std::io::ErrorKind) -> Option<ErrorHelp> {
+    match kind {
+        std::io::ErrorKind::StorageFull => Some(help(
+            ERROR_DISK_FULL,
+            "พื้นที่ดิสก์เต็ม",
+            "ลบไฟล์ที่ไม่ใช้ หรือเปลี่ยนโฟลเดอร์ดาวน์โหลดไปยังไดรฟ์อื่น",
+        )),
+        std::io::ErrorKind::PermissionDenied => Some(help(
+            ERROR_PERMISSION,
+            "ไม่มีสิทธิ์เขียนไฟล์ในโฟลเดอร์นี้",
+            "เลือกโฟลเดอร์ดาวน์โหลดอื่น หรือปิดโปรแกรมที่เปิดไฟล์นี้อยู่",
+        )),
+        _ => None,
+    }
+}
+
+fn link_expired_help() -> ErrorHelp {
+    help(
+        ERROR_LINK_EXPIRED,
+        "ลิงก์วิดีโอหมดอายุหรือถูกปฏิเสธการเข้าถึง",
+        "ลิงก์หมดอายุ ลองดึงข้อมูลใหม่ หรือเข้าสู่ระบบเว็บไซต์ก่อน",
+    )
+}
+
+fn drm_help() -> ErrorHelp {
+    help(
+        ERROR_DRM,
+        "เว็บไซต์นี้ใช้ DRM",
+        "วิดีโอที่มีการป้องกันลิขสิทธิ์ไม่สามารถดาวน์โหลดได้",
+    )
+}
+
+fn no_sources_help() -> ErrorHelp {
+    help(
+        ERROR_NO_SOURCES,
+        "ไม่พบวิดีโอในหน้านี้",
+        "เปิดหน้าที่มีตัวเล่นวิดีโอโดยตรง หรือเข้าสู่ระบบเว็บไซต์ก่อนแล้วลองใหม่",
+    )
+}
+
+fn timeout_help() -> ErrorHelp {
+    help(
+        ERROR_TIMEOUT,
+        "การเชื่อมต่อใช้เวลานานเกินไป",
+        "ตรวจสอบอินเทอร์เน็ตแล้วลองใหม่ หรือลดจำนวนการดาวน์โหลดพร้อมกัน",
+    )
+}
+
+fn too_large_help() -> ErrorHelp {
+    help(
+        ERROR_TOO_LARGE,
+        "ไฟล์วิดีโอใหญ่เกินขนาดสูงสุดที่ตั้งไว้",
+        "เลือกความละเอียดที่ต่ำลง หรือเพิ่มขนาดไฟล์สูงสุดในการตั้งค่า",
+    )
+}
+
+fn network_help() -> ErrorHelp {
+    help(
+        ERROR_NETWORK,
+        "เชื่อมต่อเว็บไซต์ไม่ได้",
+        "ตรวจสอบการเชื่อมต่ออินเทอร์เน็ต แล้วลองใหม่อีกครั้ง",
+    )
+}
+
+fn unknown_help() -> ErrorHelp {
+    help(
+        ERROR_UNKNOWN,
+        "เกิดข้อผิดพลาดที่ไม่รู้จัก",
+        "ลองใหม่อีกครั้ง หากยังไม่ได้ให้ดูรายละเอียดในบันทึกการดาวน์โหลด",
+    )
+}
@@ -0,0 +1,436 @@
+use std::path::{Path, PathBuf};
+
+use super::audio::AudioTrack;
+use super::{long_path, DownloaderError};
+
+// Progress messages starting with this are the ffmpeg phase, not the download
+pub const CONVERTING_MESSAGE: &str = "Converting to MP4";
+
+pub fn is_converting_message(message: &str) -> bool {
+    message.starts_with(CONVERTING_MESSAGE)
+}
+
+/// Run ffmpeg with `args` plus `-progress pipe:1` and report the
+/// percentage of `total_seconds` converted so far. The process is killed if
+/// the future is dropped (pause / cancel).
+pub async fn run_with_progress(
+    args: &[std::ffi::OsString],
+    total_seconds: f64,
+    on_progress: &impl Fn(f32, String),
+) -> Result<(), DownloaderError> {
+    use std::process::Stdio;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+    // -progress is a global option, so it has to precede the output file
+    let mut child = tokio::process::Command::new("ffmpeg")
+        .args(["-progress", "pipe:1", "-nostats"])
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| DownloaderError::DownloadFailed(format!("ffmpeg not found: {}", e)))?;
+
+    // Drain stderr concurrently so a chatty ffmpeg can't block on a full pipe
+    let mut stderr = child.stderr.take();
+    let stderr_task = tokio::spawn(async move {
+        let mut text = String::new();
+        if let Some(stderr) = stderr.as_mut() {
+            stderr.read_to_string(&mut text).await.ok();
+        }
+        text
+    });
+
+    if let Some(stdout) = child.stdout.take() {
+        let mut lines = BufReader::new(stdout).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            // out_time_us and (despite the name) out_time_ms are microseconds
+            let Some(value) = line.strip_prefix("out_time_us=").or_else(|| line.strip_prefix("out_time_ms=")) else {
+                continue;
+            };
+            if let (Ok(micros), true) = (value.trim().parse::<f64>(), total_seconds > 0.0) {
+                let percent = (micros / 1_000_000.0 / total_seconds * 100.0).clamp(0.0, 99.9) as f32;
+                on_progress(percent, format!("{} {:.0}%", CONVERTING_MESSAGE, percent));
+            }
+        }
+    }
+
+    let status = child.wait().await?;
+    let stderr = stderr_task.await.unwrap_or_default();
+
+    if !status.success() {
+        return Err(DownloaderError::DownloadFailed(format!("ffmpeg failed: {}", stderr)));
+    }
+
+    on_progress(100.0, format!("{} 100%", CONVERTING_MESSAGE));
+    Ok(())
+}
+
+/// EBU R128 measurements from a loudnorm analysis pass
+#[derive(Clone, Debug)]
+pub struct Loudness {
+    /// Integrated loudness, LUFS
+    pub integrated: f64,
+    /// True peak, dBTP
+    pub true_peak: f64,
+    /// Loudness range, LU
+    pub range: f64,
+    pub threshold: f64,
+}
+
+/// Measure the loudness of the first audio stream (decodes the whole file)
+pub async fn measure_loudness(path: &Path) -> Result<Loudness, DownloaderError> {
+    let output = tokio::process::Command::new("ffmpeg")
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(long_path(path))
+        .args(["-map", "0:a:0", "-af", "loudnorm=print_format=json", "-f", "null", "-"])
+        .output()
+        .await
+        .map_err(|e| DownloaderError::DownloadFailed(format!("ffmpeg not found: {}", e)))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(DownloaderError::DownloadFailed(format!("ffmpeg loudness analysis failed: {}", stderr)));
+    }
+
+    // The JSON summary is the last {...} block of the log
+    let json = stderr
+        .rfind('{')
+        .and_then(|start| stderr[start..].find('}').map(|end| &stderr[start..=start + end]))
+        .ok_or_else(|| DownloaderError::Parse("No loudnorm summary in ffmpeg output".to_string()))?;
+    let value: serde_json::Value = serde_json::from_str(json)
+        .map_err(|e| DownloaderError::Parse(format!("Invalid loudnorm summary: {}", e)))?;
+    // loudnorm prints the numbers as strings
+    let field = |name: &str| {
+        value[name]
+            .as_str()
+            .and_then(|s| s.trim().parse::<f64>().ok())
+            .filter(|v| v.is_finite())
+            .ok_or_else(|| DownloaderError::Parse(format!("Missing {} in loudnorm summary", name)))
+    };
+
+    Ok(Loudness {
+        integrated: field("input_i")?,
+        true_peak: field("input_tp")?,
+        range: field("input_lra")?,
+        threshold: field("input_thresh")?,
+    })
+}
+
+/// Read the container duration (seconds) of a media file with ffprobe
+pub async fn probe_duration(path: &Path) -> Result<f64, DownloaderError> {
+    let output = tokio::process::Command::new("ffprobe")
+        .args([
+            "-v", "error",
+            "-show_entries", "format=duration",
+            "-of", "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(path)
+        .output()
+        .await
+        .map_err(|e| DownloaderError::DownloadFailed(format!("ffprobe not found: {}", e)))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(DownloaderError::Parse(format!("ffprobe failed: {}", stderr)));
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<f64>()
+        .map_err(|e| DownloaderError::Parse(format!("Invalid duration: {}", e)))
+}
+
+/// First video stream of a file or URL, as ffprobe reports it
+#[derive(Clone, Debug)]
+pub struct VideoStream {
+    pub height: u32,
+    /// ffprobe codec name ("h264", "hevc", ...)
+    pub codec: String,
+}
+
+/// Height and codec of the first video stream of a local file or URL. For
+/// URLs the referer / headers the CDN expects are passed to ffprobe.
+pub async fn probe_video_stream(
+    input: &str,
+    user_agent: &str,
+    referer: Option<&str>,
+    headers: &[(String, String)],
+) -> Result<VideoStream, DownloaderError> {
+    let mut command = tokio::process::Command::new("ffprobe");
+    command.args([
+        "-v", "error",
+        "-select_streams", "v:0",
+        "-show_entries", "stream=codec_name,height",
+        "-of", "default=noprint_wrappers=1",
+    ]);
+
+    http_input_args(&mut command, input, user_agent, referer, headers);
+
+    let output = command
+        .arg(input)
+        .output()
+        .await
+        .map_err(|e| DownloaderError::DownloadFailed(format!("ffprobe not found: {}", e)))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(DownloaderError::Parse(format!("ffprobe failed: {}", stderr)));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let field = |name: &str| {
+        stdout
+            .lines()
+            .find_map(|line| line.trim().strip_prefix(name)?.strip_prefix('='))
+            .map(str::to_string)
+    };
+    let height = field("height")
+        .unwrap_or_default()
+        .parse::<u32>()
+        .map_err(|e| DownloaderError::Parse(format!("Invalid height: {}", e)))?;
+
+    Ok(VideoStream {
+        height,
+        codec: field("codec_name").unwrap_or_default(),
+    })
+}
+
+/// User agent, referer and headers the CDN expects, for URL inputs of
+/// ffmpeg and ffprobe
+fn http_input_args(
+    command: &mut tokio::process::Command,
+    input: &str,
+    user_agent: &str,
+    referer: Option<&str>,
+    headers: &[(String, String)],
+) {
+    if !input.starts_with("http") {
+        return;
+    }
+    let mut header_lines: String = headers.iter().map(|(k, v)| format!("{}: {}\r\n", k, v)).collect();
+    if let Some(referer) = referer {
+        header_lines.push_str(&format!("Referer: {}\r\n", referer));
+    }
+    command.args(["-user_agent", user_agent]);
+    if !header_lines.is_empty() {
+        command.args(["-headers", &header_lines]);
+    }
+}
+
+/// One frame of a file or URL (direct or HLS) `seek_seconds` in, as a JPEG
+/// `width` pixels wide. Only the start of the stream is fetched.
+pub async fn capture_frame(
+    input: &str,
+    seek_seconds: f64,
+    width: u32,
+    user_agent: &str,
+    referer: Option<&str>,
+    headers: &[(String, String)],
+) -> Result<Vec<u8>, DownloaderError> {
+    let mut command = tokio::process::Command::new("ffmpeg");
+    command.args(["-v", "error"]);
+    http_input_args(&mut command, input, user_agent, referer, headers);
+    command
+        .args(["-ss", &format!("{:.1}", seek_seconds), "-i", input])
+        .args(["-frames:v", "1", "-vf", &format!("scale={}:-2", width)])
+        .args(["-c:v", "mjpeg", "-f", "image2", "pipe:1"])
+        .kill_on_drop(true);
+
+    let output = command
+        .output()
+        .await
+        .map_err(|e| DownloaderError::DownloadFailed(format!("ffmpeg not found: {}", e)))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(DownloaderError::DownloadFailed(format!("ffmpeg frame capture failed: {}", stderr)));
+    }
+    if output.stdout.is_empty() {
+        return Err(DownloaderError::Parse("No video frame at that position".to_string()));
+    }
+    Ok(output.stdout)
+}
+
+/// Copy the streams of `input` into an MP4 container without re-encoding.
+/// With `faststart` the index (moov) goes before the media data.
+pub async fn remux_to_mp4(input: &Path, output: &Path, faststart: bool) -> Result<(), DownloaderError> {
+    let mut command = tokio::process::Command::new("ffmpeg");
+    command.args(["-y", "-v", "error", "-i"]).arg(input).args(["-map", "0", "-c", "copy"]);
+    if faststart {
+        command.args(["-movflags", "+faststart"]);
+    }
+    let output = command
+        .arg(output)
+        .output()
+        .await
+        .map_err(|e| DownloaderError::DownloadFailed(format!("ffmpeg not found: {}", e)))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(DownloaderError::DownloadFailed(format!("ffmpeg remux failed: {}", stderr)));
+    }
+
+    Ok(())
+}
+
+/// Mux the video of `video` with the first audio stream of each file in
+/// `audio`, tagged with the track's language and name, into an MPEG-TS
+pub async fn mux_audio_tracks(video: &Path, audio: &[(PathBuf, AudioTrack)], output: &Path) -> Result<(), DownloaderError> {
+    let mut command = tokio::process::Command::new("ffmpeg");
+    command.args(["-y", "-v", "error", "-i"]).arg(video);
+    for (path, _) in audio {
+        command.arg("-i").arg(path);
+    }
+    command.args(["-map", "0:v"]);
+    for (index, (_, track)) in audio.iter().enumerate() {
+        command
+            .arg("-map")
+            .arg(format!("{}:a:0", index + 1))
+            .arg(format!("-metadata:s:a:{}", index))
+            .arg(format!("language={}", track.language))
+            .arg(format!("-metadata:s:a:{}", index))
+            .arg(format!("title={}", track.name));
+        if track.default {
+            command.arg(format!("-disposition:a:{}", index)).arg("default");
+        }
+    }
+    let output = command
+        .args(["-c", "copy", "-f", "mpegts"])
+        .arg(output)
+        .output()
+        .await
+        .map_err(|e| DownloaderError::DownloadFailed(format!("ffmpeg not found: {}", e)))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(DownloaderError::DownloadFailed(format!("ffmpeg audio mux failed: {}", stderr)));
+    }
+
+    Ok(())
+}
+
+/// Join files with identical codecs end to end (concat demuxer, no
+/// re-encoding). With a title per input, each input becomes a chapter.
+pub async fn concat_files(inputs: &[PathBuf], chapter_titles: &[String], output: &Path) -> Result<(), DownloaderError> {
+    // Paths are quoted for the list file; a quote is closed, escaped, reopened
+    let list: String = inputs
+        .iter()
+        .map(|path| format!("file '{}'\n", path.to_string_lossy().replace('\'', "'\\''")))
+        .collect();
+    let temp_id = uuid::Uuid::new_v4();
+    let list_path = std::env::temp_dir().join(format!("concat_{}.txt", temp_id));
+    tokio::fs::write(&list_path, list).await?;
+
+    let mut command = tokio::process::Command::new("ffmpeg");
+    command.args(["-y", "-v", "error", "-f", "concat", "-safe", "0", "-i"]).arg(&list_path);
+
+    // Chapters are a nicety: without ffprobe the files are still joined
+    let metadata_path = std::env::temp_dir().join(format!("chapters_{}.txt", temp_id));
+    let chapters = if chapter_titles.len() == inputs.len() {
+        chapter_metadata(inputs, chapter_titles).await
+    } else {
+        None
+    };
+    if let Some(metadata) = chapters {
+        tokio::fs::write(&metadata_path, metadata).await?;
+        command.arg("-i").arg(&metadata_path).args(["-map_chapters", "1"]);
+    }
+
+    let result = command.args(["-map", "0", "-c", "copy"]).arg(output).output().await;
+    tokio::fs::remove_file(&list_path).await.ok();
+    tokio::fs::remove_file(&metadata_path).await.ok();
+    let output = result.map_err(|e| DownloaderError::DownloadFailed(format!("ffmpeg not found: {}", e)))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(DownloaderError::DownloadFailed(format!("ffmpeg concat failed: {}", stderr)));
+    }
+
+    Ok(())
+}
+
+/// FFMETADATA with one chapter per input, in milliseconds
+async fn chapter_metadata(inputs: &[PathBuf], titles: &[String]) -> Option<String> {
+    let mut metadata = String::from(";FFMETADATA1\n");
+    let mut start = 0u64;
+    for (input, title) in inputs.iter().zip(titles) {
+        let end = start + (probe_duration(input).await.ok()? * 1000.0) as u64;
+        metadata.push_str(&format!(
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            start,
+            end,
+            escape_metadata(title)
+        ));
+        start = end;
+    }
+    Some(metadata)
+}
+
+/// FFMETADATA values escape '=', ';', '#', '\' and newlines with a backslash
+fn escape_metadata(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Whether an MP4's moov box comes after mdat, so players have to read the
+/// end of the file before they can start
+pub fn needs_faststart(path: &Path) -> std::io::Result<bool> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let mut file = std::fs::File::open(long_path(path))?;
+    let len = file.metadata()?.len();
+    let mut offset = 0u64;
+
+    while offset + 8 <= len {
+        let mut header = [0u8; 16];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut header[..8])?;
+
+        let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        match &header[4..8] {
+            b"moov" => return Ok(false),
+            b"mdat" => return Ok(true),
+            _ => {}
+        }
+        if size == 1 {
+            // 64-bit size follows the type
+            file.read_exact(&mut header[8..16])?;
+            size = u64::from_be_bytes(header[8..16].try_into().unwrap_or_default());
+        }
+        if size < 8 {
+            break;
+        }
+        offset += size;
+    }
+
+    Ok(false)
+}
+
+/// Rewrite an MP4 in place with its index at the front
+pub async fn apply_faststart(path: &Path) -> Result<(), DownloaderError> {
+    let temp = path.with_extension("faststart.mp4");
+    if let Err(e) = remux_to_mp4(path, &temp, true).await {
+        tokio::fs::remove_file(long_path(&temp)).await.ok();
+        return Err(e);
+    }
+    tokio::fs::rename(long_path(&temp), long_path(path)).await?;
+    Ok(())
+}
+
+/// Format seconds as H:MM:SS (or M:SS for short clips)
+pub fn format_duration(seconds: f64) -> String {
+    let total = seconds.max(0.0).round() as u64;
+    let (h, m, s) = (total / 3600, (total % 3600) / 60, total % 60);
+    if h > 0 {
+        format!("{}:{:02}:{:02}", h, m, s)
+    } else {
+        format!("{}:{:02}", m, s)
+    }
+}
@@ -1,26 +1,197 @@
+use cbc::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
 use futures::StreamExt;
-use m3u8_rs::{MediaPlaylist, MasterPlaylist, Playlist};
-use reqwest::Client;
+use m3u8_rs::{KeyMethod, MasterPlaylist, MediaPlaylist, Playlist, VariantStream};
+use reqwest::{Client, RequestBuilder};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::{mpsc, Notify, OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
 use url::Url;
 
-use super::DownloaderError;
+use super::aria2::{Aria2Client, Aria2Config};
+use super::audio::{self, AudioTrack};
+use super::bandwidth::BandwidthShare;
+use super::benchmark;
+use super::container;
+use super::dns;
+use super::drm;
+use super::ffmpeg;
+use super::log::DownloadLog;
+use super::redirect::{self, CookieJar};
+use super::segment_cache::SegmentCache;
+use super::watchdog::{self, Timeouts};
+use super::{long_path, output_file_path, scratch_dir, validate_url, DownloaderError};
+
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+
+pub const DEFAULT_SEGMENT_WORKERS: usize = 4;
+pub const MAX_SEGMENT_WORKERS: usize = 16;
+// Fetched segments waiting for the writer are capped at this many MB
+pub const DEFAULT_SEGMENT_BUFFER_MB: usize = 64;
+// Network chunks are small (~16 KB); batch them into large writes
+const WRITE_BUFFER_SIZE: usize = 1024 * 1024;
+// Tries per segment before the download fails, waiting longer each time
+const SEGMENT_ATTEMPTS: u32 = 3;
+const SEGMENT_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+// Reconnects after a stalled transfer before the download fails
+const STALL_RECONNECTS: u32 = 3;
+const RECONNECTING_MESSAGE: &str = "กำลังเชื่อมต่อใหม่...";
+// Starts of error pages some CDNs send with 200 OK instead of media
+// Token gateways rarely chain more than a few hops
+const MAX_REDIRECTS: usize = 10;
+
+// A variant must download this much faster than it plays to be picked by
+// speed, leaving room for speed swings and the audio tracks
+const REALTIME_HEADROOM: f64 = 1.25;
+
+const ERROR_PAGE_PREFIXES: [&str; 5] = ["<!doctype", "<html", "<head", "<body", "<?xml"];
 
 pub struct HlsDownloader {
     client: Client,
     referer: Option<String>,
+    headers: Vec<(String, String)>,
+    workers: usize,
+    buffer_mb: usize,
+    fsync: bool,
+    aria2: Option<Aria2Client>,
+    faststart: bool,
+    defer_conversion: bool,
+    bandwidth: Option<BandwidthShare>,
+    log: DownloadLog,
+    segment_cache: Option<Arc<SegmentCache>>,
+    audio_tracks: String,
+    passthrough: bool,
+    timeouts: Timeouts,
+    max_bytes: Option<u64>,
+    match_speed: bool,
 }
 
 impl HlsDownloader {
     pub fn new(referer: Option<String>) -> Self {
-        let client = Client::builder()
-            .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
+        let client = dns::client_builder()
             .build()
             .unwrap();
 
-        Self { client, referer }
+        Self {
+            client,
+            referer,
+            headers: Vec::new(),
+            workers: DEFAULT_SEGMENT_WORKERS,
+            buffer_mb: DEFAULT_SEGMENT_BUFFER_MB,
+            fsync: false,
+            aria2: None,
+            faststart: false,
+            defer_conversion: false,
+            bandwidth: None,
+            log: DownloadLog::default(),
+            segment_cache: None,
+            audio_tracks: audio::AUDIO_DEFAULT.to_string(),
+            passthrough: false,
+            timeouts: Timeouts::default(),
+            max_bytes: None,
+            match_speed: false,
+        }
+    }
+
+    /// Fail a segment that takes too long or a transfer that stops sending
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// fsync the finished file so it survives a crash or power loss
+    pub fn with_fsync(mut self, fsync: bool) -> Self {
+        self.fsync = fsync;
+        self
+    }
+
+    /// Put the MP4 index first so playback can start while streaming
+    pub fn with_faststart(mut self, faststart: bool) -> Self {
+        self.faststart = faststart;
+        self
+    }
+
+    /// Pace segment fetches to this download's share of the speed limit
+    pub fn with_bandwidth(mut self, share: Option<BandwidthShare>) -> Self {
+        self.bandwidth = share;
+        self
+    }
+
+    /// Record playlists, segment failures and ffmpeg errors
+    pub fn with_log(mut self, log: DownloadLog) -> Self {
+        self.log = log;
+        self
+    }
+
+    /// Reuse segments fetched by an earlier attempt at the same video
+    pub fn with_segment_cache(mut self, cache: Option<Arc<SegmentCache>>) -> Self {
+        self.segment_cache = cache;
+        self
+    }
+
+    /// Alternative audio renditions to download and mux: audio::AUDIO_DEFAULT,
+    /// AUDIO_ALL or a language code
+    pub fn with_audio_tracks(mut self, selection: &str) -> Self {
+        self.audio_tracks = selection.to_string();
+        self
+    }
+
+    /// Save the joined segments as they are (.ts or fragmented .mp4) with
+    /// no ffmpeg step; separate audio tracks are saved next to the video
+    pub fn with_passthrough(mut self, passthrough: bool) -> Self {
+        self.passthrough = passthrough;
+        self
+    }
+
+    /// Leave the joined .ts next to the output instead of converting it, so
+    /// the conversion can run outside the download slot
+    pub fn with_deferred_conversion(mut self, defer: bool) -> Self {
+        self.defer_conversion = defer;
+        self
+    }
+
+    /// Hand segment transfers to aria2 instead of the built-in fetcher
+    pub fn with_aria2(mut self, config: Option<Aria2Config>) -> Self {
+        self.aria2 = config.map(Aria2Client::new);
+        self
+    }
+
+    /// Memory budget for fetched segments not yet written (clamped to 8..=1024 MB)
+    pub fn with_buffer_limit(mut self, megabytes: usize) -> Self {
+        self.buffer_mb = megabytes.clamp(8, 1024);
+        self
+    }
+
+    /// Extra headers sent with every playlist and segment request
+    pub fn with_headers(mut self, headers: Vec<(String, String)>) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Pick the highest variant the connection downloads faster than real
+    /// time instead of the highest one, timed on its first segments
+    pub fn with_speed_matched_variant(mut self, enabled: bool) -> Self {
+        self.match_speed = enabled;
+        self
+    }
+
+    /// Abort once the joined segments pass this many bytes
+    pub fn with_max_bytes(mut self, max_bytes: Option<u64>) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    fn request(&self, url: &str) -> Result<RequestBuilder, DownloaderError> {
+        build_request(&self.client, url, self.referer.as_deref(), &self.headers)
+    }
+
+    /// Number of segments fetched in parallel (clamped to 1..=16)
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.clamp(1, MAX_SEGMENT_WORKERS);
+        self
     }
 
     pub async fn download(
@@ -33,13 +204,9 @@ impl HlsDownloader {
             .map_err(|e| DownloaderError::Parse(e.to_string()))?;
 
         // Fetch the m3u8 playlist
-        let mut request = self.client.get(m3u8_url);
-        if let Some(ref referer) = self.referer {
-            request = request.header("Referer", referer);
-        }
-
-        let response = request.send().await?;
-        let content = response.text().await?;
+        self.log.info(format!("Playlist: {}", m3u8_url));
+        let content = self.fetch_text(m3u8_url).await?;
+        check_playlist_drm(&content)?;
 
         // Parse the playlist
         let playlist = m3u8_rs::parse_playlist_res(content.as_bytes())
@@ -48,8 +215,28 @@ impl HlsDownloader {
         match playlist {
             Playlist::MasterPlaylist(master) => {
                 // Find the best quality stream
-                let stream_url = self.get_best_stream(&master, &base_url)?;
-                self.download_media_playlist(&stream_url, output_path, progress_callback).await
+                let best = if self.match_speed {
+                    progress_callback(0.0, "กำลังทดสอบความเร็วอินเทอร์เน็ต...".to_string());
+                    self.speed_matched_variant(&master, &base_url).await?
+                } else {
+                    best_variant(&master)?
+                };
+                let stream_url = resolve_url(&base_url, &best.uri)?;
+                self.log.info(format!("{} variant(s); using {}", master.variants.len(), stream_url));
+
+                let renditions = audio::select_renditions(&master, best.audio.as_deref(), &self.audio_tracks)
+                    .into_iter()
+                    .map(|media| {
+                        let uri = media.uri.as_deref().unwrap_or_default();
+                        Ok((resolve_url(&base_url, uri)?, AudioTrack::from_media(media)))
+                    })
+                    .collect::<Result<Vec<_>, DownloaderError>>()?;
+
+                if renditions.is_empty() {
+                    self.download_media_playlist(&stream_url, output_path, progress_callback).await
+                } else {
+                    self.download_with_audio(&stream_url, renditions, output_path, progress_callback).await
+                }
             }
             Playlist::MediaPlaylist(media) => {
                 self.download_segments(&media, &base_url, output_path, progress_callback).await
@@ -57,22 +244,47 @@ impl HlsDownloader {
         }
     }
 
-    fn get_best_stream(&self, master: &MasterPlaylist, base_url: &Url) -> Result<String, DownloaderError> {
-        let best = master
-            .variants
-            .iter()
-            .max_by_key(|v| v.bandwidth)
-            .ok_or(DownloaderError::NoSources)?;
-
-        let stream_url = if best.uri.starts_with("http") {
-            best.uri.clone()
-        } else {
-            base_url.join(&best.uri)
-                .map_err(|e| DownloaderError::Parse(e.to_string()))?
-                .to_string()
+    /// Highest variant that downloads faster than real time here; the best
+    /// one when the connection can't be measured
+    async fn speed_matched_variant<'a>(
+        &self,
+        master: &'a MasterPlaylist,
+        base_url: &Url,
+    ) -> Result<&'a VariantStream, DownloaderError> {
+        let best = best_variant(master)?;
+        let url = resolve_url(base_url, &best.uri)?;
+        let Some(bits_per_second) = benchmark::playlist_bits_per_second(&url, self.referer.as_deref(), &self.headers).await else {
+            self.log.warn("Speed test failed; using the highest variant");
+            return Ok(best);
         };
 
-        Ok(stream_url)
+        let chosen = variant_for_speed(master, bits_per_second).unwrap_or(best);
+        self.log.info(format!(
+            "Connection ~{:.1} Mbit/s; picked the {:.1} Mbit/s variant",
+            bits_per_second / 1_000_000.0,
+            chosen.bandwidth as f64 / 1_000_000.0
+        ));
+        Ok(chosen)
+    }
+
+    async fn fetch_media_playlist(&self, url: &str) -> Result<(MediaPlaylist, Url), DownloaderError> {
+        let base_url = Url::parse(url)
+            .map_err(|e| DownloaderError::Parse(e.to_string()))?;
+
+        let content = self.fetch_text(url).await?;
+        check_playlist_drm(&content)?;
+
+        let playlist = m3u8_rs::parse_media_playlist_res(content.as_bytes())
+            .map_err(|e| DownloaderError::Parse(format!("Failed to parse media playlist: {:?}", e)))?;
+
+        Ok((playlist, base_url))
+    }
+
+    async fn fetch_text(&self, url: &str) -> Result<String, DownloaderError> {
+        watchdog::within(self.timeouts.stall, "Playlist request", async {
+            Ok(self.request(url)?.send().await?.text().await?)
+        })
+        .await
     }
 
     async fn download_media_playlist(
@@ -81,21 +293,66 @@ impl HlsDownloader {
         output_path: &Path,
         progress_callback: impl Fn(f32, String) + Send + 'static,
     ) -> Result<PathBuf, DownloaderError> {
-        let base_url = Url::parse(url)
-            .map_err(|e| DownloaderError::Parse(e.to_string()))?;
+        let (playlist, base_url) = self.fetch_media_playlist(url).await?;
+        self.download_segments(&playlist, &base_url, output_path, progress_callback).await
+    }
 
-        let mut request = self.client.get(url);
-        if let Some(ref referer) = self.referer {
-            request = request.header("Referer", referer);
+    /// Video and separate audio renditions, each joined on its own and then
+    /// muxed into one .ts with language tags
+    async fn download_with_audio(
+        &self,
+        video_url: &str,
+        renditions: Vec<(String, AudioTrack)>,
+        output_path: &Path,
+        progress_callback: impl Fn(f32, String) + Send + 'static,
+    ) -> Result<PathBuf, DownloaderError> {
+        let (playlist, base_url) = self.fetch_media_playlist(video_url).await?;
+        let total_seconds: f64 = playlist.segments.iter().map(|s| s.duration as f64).sum();
+        let video_ts = self.join_segments(&playlist, &base_url, &progress_callback).await?;
+
+        let mut audio_files: Vec<(PathBuf, AudioTrack)> = Vec::new();
+        let mut failure = None;
+        for (url, track) in renditions {
+            self.log.info(format!("Audio track {} ({}): {}", track.name, track.language, url));
+            let audio_progress = |progress: f32, message: String| {
+                progress_callback(progress, format!("{} [{}]", message, track.name));
+            };
+            let joined = match self.fetch_media_playlist(&url).await {
+                Ok((playlist, base_url)) => self.join_segments(&playlist, &base_url, &audio_progress).await,
+                Err(e) => Err(e),
+            };
+            match joined {
+                Ok(path) => audio_files.push((path, track)),
+                Err(e) => {
+                    failure = Some(e);
+                    break;
+                }
+            }
         }
 
-        let response = request.send().await?;
-        let content = response.text().await?;
+        if self.passthrough && failure.is_none() {
+            for (path, track) in &audio_files {
+                self.save_raw(path, output_path, Some(&track.language)).await?;
+            }
+            return self.save_raw(&video_ts, output_path, None).await;
+        }
 
-        let playlist = m3u8_rs::parse_media_playlist_res(content.as_bytes())
-            .map_err(|e| DownloaderError::Parse(format!("Failed to parse media playlist: {:?}", e)))?;
+        let muxed_ts = temp_ts_path();
+        let result = match failure {
+            Some(e) => Err(e),
+            None => ffmpeg::mux_audio_tracks(&video_ts, &audio_files, &muxed_ts).await,
+        };
+        tokio::fs::remove_file(&video_ts).await.ok();
+        for (path, _) in &audio_files {
+            tokio::fs::remove_file(path).await.ok();
+        }
+        if let Err(e) = result {
+            self.log.error(format!("Muxing audio tracks failed: {}", e));
+            tokio::fs::remove_file(&muxed_ts).await.ok();
+            return Err(e);
+        }
 
-        self.download_segments(&playlist, &base_url, output_path, progress_callback).await
+        self.finish_ts(muxed_ts, total_seconds, output_path, &progress_callback).await
     }
 
     async fn download_segments(
@@ -105,129 +362,1019 @@ impl HlsDownloader {
         output_path: &Path,
         progress_callback: impl Fn(f32, String) + Send + 'static,
     ) -> Result<PathBuf, DownloaderError> {
-        let total_segments = playlist.segments.len();
-
-        // Use a temp file with safe ASCII name for ffmpeg compatibility
-        let temp_dir = std::env::temp_dir();
-        let temp_id = uuid::Uuid::new_v4().to_string();
-        let temp_ts_path = temp_dir.join(format!("video_{}.ts", temp_id));
-
-        let mut output_file = File::create(&temp_ts_path).await?;
-
-        for (i, segment) in playlist.segments.iter().enumerate() {
-            let segment_url = if segment.uri.starts_with("http") {
-                segment.uri.clone()
-            } else {
-                base_url.join(&segment.uri)
-                    .map_err(|e| DownloaderError::Parse(e.to_string()))?
-                    .to_string()
-            };
+        let temp_ts_path = self.join_segments(playlist, base_url, &progress_callback).await?;
+        let total_seconds: f64 = playlist.segments.iter().map(|s| s.duration as f64).sum();
+        self.finish_ts(temp_ts_path, total_seconds, output_path, &progress_callback).await
+    }
+
+    /// Fetch every segment of `playlist` into a new temp .ts
+    async fn join_segments(
+        &self,
+        playlist: &MediaPlaylist,
+        base_url: &Url,
+        progress_callback: &impl Fn(f32, String),
+    ) -> Result<PathBuf, DownloaderError> {
+        let temp_ts_path = temp_ts_path();
 
-            let progress = ((i + 1) as f32 / total_segments as f32) * 100.0;
-            progress_callback(progress, format!("Downloading segment {}/{}", i + 1, total_segments));
+        let segments = self.plan_segments(playlist, base_url).await?;
+        // aria2 only fetches whole, plain files
+        let aria2 = self
+            .aria2
+            .as_ref()
+            .filter(|_| segments.iter().all(|s| s.range.is_none() && s.key.is_none()));
+        self.log.info(format!(
+            "{} segment(s) via {}",
+            segments.len(),
+            if aria2.is_some() { "aria2" } else { "built-in fetcher" }
+        ));
 
-            let mut request = self.client.get(&segment_url);
-            if let Some(ref referer) = self.referer {
-                request = request.header("Referer", referer);
+        match aria2 {
+            Some(aria2) => {
+                let segment_urls: Vec<String> = segments.into_iter().map(|s| s.url).collect();
+                let segments_dir = temp_ts_path.with_file_name(format!(
+                    "{}_segments",
+                    temp_ts_path.file_stem().unwrap_or_default().to_string_lossy()
+                ));
+                let result = self
+                    .fetch_with_aria2(aria2, &segment_urls, &segments_dir, &temp_ts_path, progress_callback)
+                    .await;
+                tokio::fs::remove_dir_all(&segments_dir).await.ok();
+                result?;
             }
+            None => {
+                let result = self.fetch_segments(segments, &temp_ts_path, progress_callback).await;
+                if let Some(cache) = &self.segment_cache {
+                    cache.trim().await;
+                }
+                result?;
+            }
+        }
 
-            let response = request.send().await?;
-            let bytes = response.bytes().await?;
+        Ok(temp_ts_path)
+    }
 
-            output_file.write_all(&bytes).await?;
+    /// Move the joined .ts to the output, or convert it to MP4 there
+    async fn finish_ts(
+        &self,
+        temp_ts_path: PathBuf,
+        total_seconds: f64,
+        output_path: &Path,
+        progress_callback: &impl Fn(f32, String),
+    ) -> Result<PathBuf, DownloaderError> {
+        if self.passthrough {
+            return self.save_raw(&temp_ts_path, output_path, None).await;
         }
 
-        output_file.flush().await?;
+        if self.defer_conversion {
+            let ts_path = output_file_path(output_path, "ts");
+            move_file(&temp_ts_path, &ts_path).await?;
+            return Ok(ts_path);
+        }
 
         // Convert TS to MP4 using ffmpeg with temp files
-        let temp_mp4_path = temp_dir.join(format!("video_{}.mp4", temp_id));
-        self.convert_to_mp4(&temp_ts_path, &temp_mp4_path).await?;
+        let temp_mp4_path = temp_ts_path.with_extension("mp4");
+        let converted = self.convert_to_mp4(&temp_ts_path, &temp_mp4_path, total_seconds, progress_callback).await;
+        if let Err(e) = &converted {
+            self.log.error(format!("Conversion to MP4 failed: {}", e));
+            tokio::fs::remove_file(&temp_ts_path).await.ok();
+        }
+        converted?;
 
         // Clean up temp TS file
         tokio::fs::remove_file(&temp_ts_path).await.ok();
 
         // Move final MP4 to target location with original name
-        let mp4_path = output_path.with_extension("mp4");
-        if let Err(_) = tokio::fs::rename(&temp_mp4_path, &mp4_path).await {
-            // If rename fails (cross-device), copy and delete
-            tokio::fs::copy(&temp_mp4_path, &mp4_path).await?;
-            tokio::fs::remove_file(&temp_mp4_path).await.ok();
+        let mp4_path = output_file_path(output_path, "mp4");
+        move_file(&temp_mp4_path, &mp4_path).await?;
+
+        if self.fsync {
+            sync_file(&mp4_path).await?;
         }
 
         Ok(mp4_path)
     }
 
-    async fn convert_to_mp4(&self, ts_path: &Path, mp4_path: &Path) -> Result<(), DownloaderError> {
-        let output = tokio::process::Command::new("ffmpeg")
-            .args([
-                "-y",
-                "-i", ts_path.to_str().unwrap(),
-                "-c", "copy",
-                "-bsf:a", "aac_adtstoasc",
-                mp4_path.to_str().unwrap(),
-            ])
-            .output()
-            .await
-            .map_err(|e| DownloaderError::DownloadFailed(format!("ffmpeg not found: {}", e)))?;
+    /// Move joined segments to the output under the extension their bytes
+    /// call for; `suffix` (a language) goes before the extension
+    async fn save_raw(&self, temp_path: &Path, output_path: &Path, suffix: Option<&str>) -> Result<PathBuf, DownloaderError> {
+        let mut head = vec![0u8; 512];
+        let read = {
+            use tokio::io::AsyncReadExt;
+            File::open(temp_path).await?.read(&mut head).await?
+        };
+        head.truncate(read);
+
+        let extension = container::sniff_extension(&head).unwrap_or("ts");
+        let extension = match suffix {
+            Some(suffix) => format!("{}.{}", suffix, extension),
+            None => extension.to_string(),
+        };
+        let path = output_file_path(output_path, &extension);
+        move_file(temp_path, &path).await?;
+        self.log.info(format!("Saved as downloaded: {}", path.display()));
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(DownloaderError::DownloadFailed(format!("ffmpeg failed: {}", stderr)));
+        if self.fsync {
+            sync_file(&path).await?;
         }
+        Ok(path)
+    }
+
+    /// Resolve segment URLs, byte ranges and AES-128 keys. Keys and ranges
+    /// carry over from earlier segments the way the HLS spec describes.
+    async fn plan_segments(&self, playlist: &MediaPlaylist, base_url: &Url) -> Result<Vec<SegmentRequest>, DownloaderError> {
+        let mut keys: HashMap<String, [u8; 16]> = HashMap::new();
+        let mut current_key = None;
+        // End of the previous range per URL, for ranges without an offset
+        let mut range_ends: HashMap<String, u64> = HashMap::new();
+        // fMP4 init section (EXT-X-MAP) in effect
+        let mut current_map: Option<(String, Option<(u64, u64)>)> = None;
+        let mut segments = Vec::with_capacity(playlist.segments.len());
+
+        for (index, segment) in playlist.segments.iter().enumerate() {
+            let url = resolve_url(base_url, &segment.uri)?;
 
+            // m3u8-rs rejects METHOD=NONE without an IV and keeps it as an
+            // unknown tag
+            let clears_key = segment
+                .unknown_tags
+                .iter()
+                .any(|t| t.tag == "X-KEY" && t.rest.as_deref().is_some_and(|r| r.contains("METHOD=NONE")));
+            if clears_key {
+                current_key = None;
+            }
+
+            if let Some(key) = &segment.key {
+                current_key = match &key.method {
+                    KeyMethod::None => None,
+                    KeyMethod::AES128 => {
+                        let key_uri = key.uri.as_deref().ok_or_else(|| DownloaderError::Parse("AES-128 key without URI".to_string()))?;
+                        let key_url = resolve_url(base_url, key_uri)?;
+                        if !keys.contains_key(&key_url) {
+                            self.log.info(format!("Key: {}", key_url));
+                            let bytes = self.request(&key_url)?.send().await?.error_for_status()?.bytes().await?;
+                            let key_bytes: [u8; 16] = bytes
+                                .as_ref()
+                                .try_into()
+                                .map_err(|_| DownloaderError::DownloadFailed(format!("Invalid AES-128 key ({} bytes)", bytes.len())))?;
+                            keys.insert(key_url.clone(), key_bytes);
+                        }
+                        let iv = key.iv.as_deref().map(parse_iv).transpose()?;
+                        Some((keys[&key_url], iv))
+                    }
+                    method => {
+                        return Err(DownloaderError::DownloadFailed(format!("Unsupported HLS encryption: {}", method)));
+                    }
+                };
+            }
+
+            let range = segment.byte_range.as_ref().map(|byte_range| {
+                let start = byte_range.offset.unwrap_or_else(|| range_ends.get(&url).copied().unwrap_or(0));
+                range_ends.insert(url.clone(), start + byte_range.length);
+                (start, start + byte_range.length.saturating_sub(1))
+            });
+
+            // Without an IV attribute the media sequence number is the IV
+            let key = current_key.map(|(key, iv): ([u8; 16], Option<[u8; 16]>)| {
+                let sequence = playlist.media_sequence + index as u64;
+                (key, iv.unwrap_or_else(|| (sequence as u128).to_be_bytes()))
+            });
+
+            // The init section goes before the first segment it applies to
+            if let Some(map) = &segment.map {
+                let map_url = resolve_url(base_url, &map.uri)?;
+                let map_range = map.byte_range.as_ref().map(|byte_range| {
+                    let start = byte_range.offset.unwrap_or(0);
+                    (start, start + byte_range.length.saturating_sub(1))
+                });
+                let map = Some((map_url.clone(), map_range));
+                if current_map != map {
+                    segments.push(SegmentRequest { url: map_url, range: map_range, key });
+                    current_map = map;
+                }
+            }
+
+            segments.push(SegmentRequest { url, range, key });
+        }
+
+        Ok(segments)
+    }
+
+    /// Built-in pipeline: fetch segments concurrently and append them to `ts_path`
+    async fn fetch_segments(
+        &self,
+        segments: Vec<SegmentRequest>,
+        ts_path: &Path,
+        progress_callback: &impl Fn(f32, String),
+    ) -> Result<(), DownloaderError> {
+        let total_segments = segments.len();
+        let mut output_file = BufWriter::with_capacity(WRITE_BUFFER_SIZE, File::create(ts_path).await?);
+
+        // Producer: fetch up to `workers` segments concurrently, in playlist
+        // order, and hand them to the writer through a bounded channel. Each
+        // segment holds permits from a KB-denominated semaphore until it is
+        // written, so a fast link can't outrun the disk by more than the
+        // buffer limit.
+        let budget_kb = (self.buffer_mb * 1024) as u32;
+        let buffer = Arc::new(Semaphore::new(budget_kb as usize));
+        let (tx, mut rx) = mpsc::channel::<(bytes::Bytes, OwnedSemaphorePermit)>(self.workers * 2);
+        let fetcher = SegmentFetcher {
+            client: self.client.clone(),
+            referer: self.referer.clone(),
+            headers: self.headers.clone(),
+            bandwidth: self.bandwidth.clone(),
+            log: self.log.clone(),
+            cache: self.segment_cache.clone(),
+            timeouts: self.timeouts,
+            reconnecting: Arc::new(Notify::new()),
+        };
+        let reconnecting = fetcher.reconnecting.clone();
+        let workers = self.workers;
+
+        let producer = AbortOnDrop(tokio::spawn(async move {
+            let mut segments = futures::stream::iter(segments)
+                .map(|segment| fetcher.fetch(segment))
+                .buffered(workers);
+
+            while let Some(bytes) = segments.next().await {
+                let bytes = bytes?;
+                let kb = ((bytes.len() / 1024) as u32 + 1).min(budget_kb);
+                let permit = buffer.clone().acquire_many_owned(kb).await
+                    .map_err(|e| DownloaderError::DownloadFailed(e.to_string()))?;
+                if tx.send((bytes, permit)).await.is_err() {
+                    // Writer stopped; it reports its own error
+                    break;
+                }
+            }
+            Ok::<(), DownloaderError>(())
+        }));
+
+        // Consumer: a single writer appends segments while the next ones download
+        let mut completed = 0;
+        let mut written: u64 = 0;
+        loop {
+            let (bytes, permit) = tokio::select! {
+                received = rx.recv() => match received {
+                    Some(received) => received,
+                    None => break,
+                },
+                _ = reconnecting.notified() => {
+                    let progress = (completed as f32 / total_segments as f32) * 100.0;
+                    progress_callback(progress, RECONNECTING_MESSAGE.to_string());
+                    continue;
+                }
+            };
+            written += bytes.len() as u64;
+            if let Err(e) = check_size(written, self.max_bytes) {
+                self.log.error(format!("Stopped after {} of {} segments: {}", completed, total_segments, e));
+                drop(output_file);
+                tokio::fs::remove_file(ts_path).await.ok();
+                return Err(e);
+            }
+            output_file.write_all(&bytes).await?;
+            drop(permit);
+
+            completed += 1;
+            let progress = (completed as f32 / total_segments as f32) * 100.0;
+            progress_callback(progress, format!("Downloading segment {}/{}", completed, total_segments));
+        }
+
+        producer.join().await?;
+
+        output_file.flush().await?;
+        Ok(())
+    }
+
+    /// aria2 pipeline: let aria2 fetch the segments into `segments_dir`, then
+    /// join them into `ts_path` in playlist order
+    async fn fetch_with_aria2(
+        &self,
+        aria2: &Aria2Client,
+        segment_urls: &[String],
+        segments_dir: &Path,
+        ts_path: &Path,
+        progress_callback: &impl Fn(f32, String),
+    ) -> Result<(), DownloaderError> {
+        tokio::fs::create_dir_all(segments_dir).await?;
+
+        let batch = self.workers * 4;
+        let segment_paths = aria2
+            .download_segments(segment_urls, segments_dir, batch, self.referer.as_deref(), &self.headers, progress_callback)
+            .await?;
+        let total: u64 = segment_paths.iter().filter_map(|p| p.metadata().ok()).map(|m| m.len()).sum();
+        check_size(total, self.max_bytes)?;
+
+        let mut output_file = BufWriter::with_capacity(WRITE_BUFFER_SIZE, File::create(ts_path).await?);
+        for path in segment_paths {
+            let mut segment = File::open(&path).await?;
+            tokio::io::copy(&mut segment, &mut output_file).await?;
+        }
+        output_file.flush().await?;
         Ok(())
     }
+
+    async fn convert_to_mp4(
+        &self,
+        ts_path: &Path,
+        mp4_path: &Path,
+        total_seconds: f64,
+        progress_callback: &impl Fn(f32, String),
+    ) -> Result<(), DownloaderError> {
+        let mut args: Vec<std::ffi::OsString> = ["-y", "-i"].iter().map(Into::into).collect();
+        args.push(ts_path.into());
+        // Every audio track, not just the one ffmpeg would pick
+        args.extend(["-map", "0:v?", "-map", "0:a?", "-c", "copy", "-bsf:a", "aac_adtstoasc"].iter().map(Into::into));
+        if self.faststart {
+            args.extend(["-movflags", "+faststart"].iter().map(Into::into));
+        }
+        args.push(mp4_path.into());
+
+        ffmpeg::run_with_progress(&args, total_seconds, progress_callback).await
+    }
+}
+
+/// Owned copy of the request settings so segment fetches can run in a
+/// spawned producer task
+struct SegmentFetcher {
+    client: Client,
+    referer: Option<String>,
+    headers: Vec<(String, String)>,
+    bandwidth: Option<BandwidthShare>,
+    log: DownloadLog,
+    cache: Option<Arc<SegmentCache>>,
+    timeouts: Timeouts,
+    /// Tells the writer to show that a stalled segment is being re-requested
+    reconnecting: Arc<Notify>,
+}
+
+impl SegmentFetcher {
+    async fn fetch(&self, segment: SegmentRequest) -> Result<bytes::Bytes, DownloaderError> {
+        if let Some(cache) = &self.cache {
+            if let Some(data) = cache.get(&segment.url, segment.range).await {
+                return Ok(data);
+            }
+        }
+
+        let mut attempt = 1;
+        let result = loop {
+            match watchdog::within(self.timeouts.segment, "Segment", self.fetch_once(&segment)).await {
+                Err(e @ (DownloaderError::Network(_) | DownloaderError::SegmentExpired(_) | DownloaderError::Timeout(_)))
+                    if attempt < SEGMENT_ATTEMPTS =>
+                {
+                    self.log.warn(format!("Segment attempt {} failed: {}: {}", attempt, segment.url, e));
+                    if matches!(e, DownloaderError::Timeout(_)) {
+                        self.reconnecting.notify_one();
+                    }
+                    tokio::time::sleep(SEGMENT_RETRY_DELAY * attempt).await;
+                    attempt += 1;
+                }
+                result => break result,
+            }
+        };
+        match &result {
+            Ok(data) => {
+                if let Some(cache) = &self.cache {
+                    cache.put(&segment.url, segment.range, data).await;
+                }
+            }
+            Err(e) => self.log.error(format!("Segment failed: {}: {}", segment.url, e)),
+        }
+        result
+    }
+
+    async fn fetch_once(&self, segment: &SegmentRequest) -> Result<bytes::Bytes, DownloaderError> {
+        let mut request = build_request(&self.client, &segment.url, self.referer.as_deref(), &self.headers)?;
+        if let Some((start, end)) = segment.range {
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-{}", start, end));
+        }
+
+        let response = watchdog::within(self.timeouts.stall, "Segment request", async { Ok(request.send().await?) }).await?;
+        let status = response.status();
+        if matches!(status.as_u16(), 401 | 403 | 410) {
+            return Err(DownloaderError::SegmentExpired(format!("HTTP {}", status)));
+        }
+        let response = response.error_for_status()?;
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_lowercase();
+        if content_type.starts_with("text/html") {
+            return Err(DownloaderError::SegmentExpired(format!("server sent {}", content_type)));
+        }
+        // A server that ignores Range sends the whole file
+        let whole_file = segment.range.is_some() && response.status() != reqwest::StatusCode::PARTIAL_CONTENT;
+
+        // Read chunk by chunk so the speed limit and stall check apply
+        // while the segment arrives
+        let mut body = bytes::BytesMut::new();
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = watchdog::next_chunk(&mut stream, self.timeouts.stall).await {
+            let chunk = chunk?;
+            if let Some(bandwidth) = &self.bandwidth {
+                bandwidth.consume(chunk.len()).await;
+            }
+            body.extend_from_slice(&chunk);
+        }
+        let body = body.freeze();
+
+        let body = match segment.range {
+            Some((start, end)) if whole_file => {
+                let end = (end as usize + 1).min(body.len());
+                body.slice((start as usize).min(end)..end)
+            }
+            _ => body,
+        };
+
+        if is_error_page(&body) {
+            return Err(DownloaderError::SegmentExpired("server sent an HTML page".to_string()));
+        }
+
+        match &segment.key {
+            // A padding error almost always means a wrong key
+            Some((key, iv)) => Aes128CbcDec::new(key.into(), iv.into())
+                .decrypt_padded_vec_mut::<Pkcs7>(&body)
+                .map(bytes::Bytes::from)
+                .map_err(|_| DownloaderError::DownloadFailed("Failed to decrypt segment".to_string())),
+            None => Ok(body),
+        }
+    }
+}
+
+/// One media segment to fetch
+struct SegmentRequest {
+    url: String,
+    /// Inclusive byte range within `url`
+    range: Option<(u64, u64)>,
+    /// AES-128 key and IV
+    key: Option<([u8; 16], [u8; 16])>,
+}
+
+/// Body looks like an HTML/XML error page rather than media
+fn is_error_page(body: &[u8]) -> bool {
+    let start = body.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(body.len());
+    let head = String::from_utf8_lossy(&body[start..body.len().min(start + 16)]).to_lowercase();
+    ERROR_PAGE_PREFIXES.iter().any(|prefix| head.starts_with(prefix))
+}
+
+/// Temp file with a safe ASCII name for ffmpeg compatibility
+fn temp_ts_path() -> PathBuf {
+    let dir = scratch_dir();
+    std::fs::create_dir_all(&dir).ok();
+    dir.join(format!("video_{}.ts", uuid::Uuid::new_v4()))
+}
+
+/// Highest-bandwidth variant of a master playlist
+pub fn best_variant(master: &MasterPlaylist) -> Result<&VariantStream, DownloaderError> {
+    master
+        .variants
+        .iter()
+        .filter(|v| !v.is_i_frame)
+        .max_by_key(|v| v.bandwidth)
+        .ok_or(DownloaderError::NoSources)
+}
+
+/// Highest-bandwidth variant that `bits_per_second` fetches faster than real
+/// time, else the lowest one
+pub fn variant_for_speed(master: &MasterPlaylist, bits_per_second: f64) -> Option<&VariantStream> {
+    let variants = || master.variants.iter().filter(|v| !v.is_i_frame);
+    variants()
+        .filter(|v| v.bandwidth as f64 * REALTIME_HEADROOM <= bits_per_second)
+        .max_by_key(|v| v.bandwidth)
+        .or_else(|| variants().min_by_key(|v| v.bandwidth))
+}
+
+fn resolve_url(base_url: &Url, uri: &str) -> Result<String, DownloaderError> {
+    if uri.starts_with("http") {
+        Ok(uri.to_string())
+    } else {
+        base_url.join(uri)
+            .map(|u| u.to_string())
+            .map_err(|e| DownloaderError::Parse(e.to_string()))
+    }
+}
+
+/// IV attribute: "0x" and 32 hex digits
+fn parse_iv(iv: &str) -> Result<[u8; 16], DownloaderError> {
+    let digits = iv.trim_start_matches("0x").trim_start_matches("0X");
+    hex::decode(format!("{:0>32}", digits))
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| DownloaderError::Parse(format!("Invalid IV: {}", iv)))
+}
+
+/// Cancels the producer if the download is dropped (paused/cancelled)
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl AbortOnDrop<Result<(), DownloaderError>> {
+    async fn join(mut self) -> Result<(), DownloaderError> {
+        (&mut self.0).await.map_err(|e| DownloaderError::DownloadFailed(e.to_string()))?
+    }
+}
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
 }
 
 pub struct DirectDownloader {
     client: Client,
     referer: Option<String>,
+    fallback_referer: Option<String>,
+    headers: Vec<(String, String)>,
+    fsync: bool,
+    aria2: Option<Aria2Client>,
+    remux_mp4: bool,
+    faststart: bool,
+    bandwidth: Option<BandwidthShare>,
+    log: DownloadLog,
+    timeouts: Timeouts,
+    max_bytes: Option<u64>,
+}
+
+/// A response at the end of a redirect chain, with what it took to get there
+struct Opened {
+    response: reqwest::Response,
+    url: String,
+    referer: Option<String>,
+    cookies: Option<String>,
 }
 
 impl DirectDownloader {
     pub fn new(referer: Option<String>) -> Self {
-        let client = Client::builder()
-            .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
+        // Redirects are followed by hand so headers and cookies survive each hop
+        let client = dns::client_builder()
+            .redirect(reqwest::redirect::Policy::none())
             .build()
             .unwrap();
 
-        Self { client, referer }
+        Self {
+            client,
+            referer,
+            fallback_referer: None,
+            headers: Vec::new(),
+            fsync: false,
+            aria2: None,
+            remux_mp4: false,
+            faststart: false,
+            bandwidth: None,
+            log: DownloadLog::default(),
+            timeouts: Timeouts::default(),
+            max_bytes: None,
+        }
     }
 
+    pub fn with_headers(mut self, headers: Vec<(String, String)>) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Fail the transfer when the server stops sending
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    pub fn with_fsync(mut self, fsync: bool) -> Self {
+        self.fsync = fsync;
+        self
+    }
+
+    /// Referer to retry with when the host answers 403, usually the player
+    /// iframe the source came from
+    pub fn with_fallback_referer(mut self, referer: Option<String>) -> Self {
+        self.fallback_referer = referer;
+        self
+    }
+
+    /// Let aria2 fetch the file with several connections
+    pub fn with_aria2(mut self, config: Option<Aria2Config>) -> Self {
+        self.aria2 = config.map(Aria2Client::new);
+        self
+    }
+
+    /// Remux non-MP4 containers (webm, mkv, ts, ...) to MP4 after download.
+    /// The original is kept when the codecs don't fit in MP4.
+    pub fn with_remux_mp4(mut self, remux: bool) -> Self {
+        self.remux_mp4 = remux;
+        self
+    }
+
+    /// Move the index of MP4 downloads to the front when it's at the end
+    pub fn with_faststart(mut self, faststart: bool) -> Self {
+        self.faststart = faststart;
+        self
+    }
+
+    /// Pace the transfer to this download's share of the speed limit
+    pub fn with_bandwidth(mut self, share: Option<BandwidthShare>) -> Self {
+        self.bandwidth = share;
+        self
+    }
+
+    /// Record the response, container and remux results
+    pub fn with_log(mut self, log: DownloadLog) -> Self {
+        self.log = log;
+        self
+    }
+
+    /// Refuse files whose Content-Length is over this many bytes, and abort
+    /// ones without it once they grow past it
+    pub fn with_max_bytes(mut self, max_bytes: Option<u64>) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Open the file, retrying a 403 with the fallback referers in turn
+    async fn open(&self, url: &str) -> Result<Opened, DownloaderError> {
+        let mut opened = self.follow(url, self.referer.as_deref()).await?;
+        if opened.response.status() == reqwest::StatusCode::FORBIDDEN {
+            for referer in self.fallback_referers(url) {
+                self.log.warn(format!("HTTP 403, retrying with referer {}", referer));
+                opened = self.follow(url, Some(&referer)).await?;
+                if opened.response.status() != reqwest::StatusCode::FORBIDDEN {
+                    break;
+                }
+            }
+        }
+        opened.response = opened.response.error_for_status()?;
+        Ok(opened)
+    }
+
+    /// Request the rest of the file from `offset` at the URL, referer and
+    /// cookies that worked the first time
+    async fn resume(
+        &self,
+        url: &str,
+        referer: Option<&str>,
+        cookies: Option<&str>,
+        offset: u64,
+    ) -> Result<reqwest::Response, DownloaderError> {
+        let headers: Vec<(String, String)> =
+            self.headers.iter().filter(|(n, _)| !n.eq_ignore_ascii_case("cookie")).cloned().collect();
+        let mut request = build_request(&self.client, url, referer, &headers)?
+            .header(reqwest::header::RANGE, format!("bytes={}-", offset));
+        if let Some(origin) = referer.filter(|_| header_value(&headers, "origin").is_none()).and_then(redirect::origin_of) {
+            request = request.header(reqwest::header::ORIGIN, origin);
+        }
+        if let Some(cookies) = cookies {
+            request = request.header(reqwest::header::COOKIE, cookies);
+        }
+
+        let response = request.send().await?.error_for_status()?;
+        if response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+            return Err(DownloaderError::DownloadFailed("Server can't resume the transfer".to_string()));
+        }
+        Ok(response)
+    }
+
+    /// The embed URL, then the file's own origin, skipping the referer
+    /// already tried
+    fn fallback_referers(&self, url: &str) -> Vec<String> {
+        let own_origin = redirect::origin_of(url).map(|origin| format!("{}/", origin));
+        let mut referers: Vec<String> = Vec::new();
+        for referer in [self.fallback_referer.clone(), own_origin].into_iter().flatten() {
+            if self.referer.as_deref() != Some(referer.as_str()) && !referers.contains(&referer) {
+                referers.push(referer);
+            }
+        }
+        referers
+    }
+
+    /// Follow a redirect chain sending Referer, a matching Origin, the
+    /// custom headers and the cookies set so far on every hop
+    async fn follow(&self, url: &str, referer: Option<&str>) -> Result<Opened, DownloaderError> {
+        let user_cookies = header_value(&self.headers, "cookie");
+        let headers: Vec<(String, String)> =
+            self.headers.iter().filter(|(n, _)| !n.eq_ignore_ascii_case("cookie")).cloned().collect();
+        let origin = referer
+            .filter(|_| header_value(&headers, "origin").is_none())
+            .and_then(redirect::origin_of);
+
+        // The user's cookies and credentials are for the host they asked
+        // for; other hosts along the chain only get what they set themselves
+        let anonymous: Vec<(String, String)> = headers
+            .iter()
+            .filter(|(n, _)| !n.eq_ignore_ascii_case("authorization") && !n.eq_ignore_ascii_case("proxy-authorization"))
+            .cloned()
+            .collect();
+
+        let mut jar = CookieJar::default();
+        let mut current = Url::parse(url).map_err(|e| DownloaderError::Parse(e.to_string()))?;
+        let first_host = current.host_str().map(str::to_string);
+        for _ in 0..=MAX_REDIRECTS {
+            let same_host = current.host_str().map(str::to_string) == first_host;
+            let user_cookies = user_cookies.clone().filter(|_| same_host);
+            let cookies = match (user_cookies, jar.header_for(&current)) {
+                (Some(user), Some(set)) => Some(format!("{}; {}", user, set)),
+                (user, set) => user.or(set),
+            };
+
+            let headers = if same_host { &headers } else { &anonymous };
+            let mut request = build_request(&self.client, current.as_str(), referer, headers)?;
+            if let Some(origin) = &origin {
+                request = request.header(reqwest::header::ORIGIN, origin.as_str());
+            }
+            if let Some(cookies) = &cookies {
+                request = request.header(reqwest::header::COOKIE, cookies.as_str());
+            }
+            let response = request.send().await?;
+
+            for value in response.headers().get_all(reqwest::header::SET_COOKIE) {
+                if let Ok(value) = value.to_str() {
+                    jar.store(&current, value);
+                }
+            }
+
+            let location = response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .filter(|_| response.status().is_redirection());
+            let Some(location) = location else {
+                return Ok(Opened {
+                    response,
+                    url: current.to_string(),
+                    referer: referer.map(str::to_string),
+                    cookies,
+                });
+            };
+
+            // build_request validates the next hop like a URL the user typed
+            let next = current.join(location).map_err(|e| DownloaderError::Parse(e.to_string()))?;
+            self.log.info(format!("HTTP {} redirect to {}", response.status(), next));
+            current = next;
+        }
+
+        Err(DownloaderError::DownloadFailed(format!("Too many redirects from {}", url)))
+    }
+
+    /// Output path named after the real container instead of always .mp4
     pub async fn download(
         &self,
         url: &str,
         output_path: &Path,
         progress_callback: impl Fn(f32, String) + Send + 'static,
     ) -> Result<PathBuf, DownloaderError> {
-        let mut request = self.client.get(url);
-        if let Some(ref referer) = self.referer {
-            request = request.header("Referer", referer);
+        self.log.info(format!(
+            "File: {} via {}",
+            url,
+            if self.aria2.is_some() { "aria2" } else { "built-in fetcher" }
+        ));
+        let path = match &self.aria2 {
+            Some(aria2) => self.download_with_aria2(aria2, url, output_path, progress_callback).await?,
+            None => self.download_builtin(url, output_path, progress_callback).await?,
+        };
+
+        let is_mp4 = |p: &Path| p.extension().map(|e| e.eq_ignore_ascii_case("mp4")).unwrap_or(false);
+        let path = if self.remux_mp4 && !is_mp4(&path) {
+            self.remux(path).await
+        } else {
+            if self.faststart && is_mp4(&path) && ffmpeg::needs_faststart(&path).unwrap_or(false) {
+                // Best effort: a file that can't be rewritten still plays
+                if let Err(e) = ffmpeg::apply_faststart(&path).await {
+                    self.log.warn(format!("Faststart failed: {}", e));
+                }
+            }
+            path
+        };
+
+        if self.fsync {
+            sync_file(&path).await?;
+        }
+
+        Ok(path)
+    }
+
+    /// aria2 picks no extension, so download under a temporary name and
+    /// rename once the first bytes can be inspected
+    async fn download_with_aria2(
+        &self,
+        aria2: &Aria2Client,
+        url: &str,
+        output_path: &Path,
+        progress_callback: impl Fn(f32, String) + Send + 'static,
+    ) -> Result<PathBuf, DownloaderError> {
+        // Resolve gateways and referer checks first; aria2 gets the final
+        // URL with the referer and cookies that worked
+        let opened = self.open(url).await?;
+        check_size(opened.response.content_length().unwrap_or(0), self.max_bytes)?;
+        drop(opened.response);
+        let mut headers: Vec<(String, String)> =
+            self.headers.iter().filter(|(n, _)| !n.eq_ignore_ascii_case("cookie")).cloned().collect();
+        if let Some(cookies) = opened.cookies {
+            headers.push(("Cookie".to_string(), cookies));
+        }
+
+        let part_path = output_file_path(output_path, "part");
+        aria2
+            .download_file(&opened.url, &part_path, opened.referer.as_deref(), &headers, progress_callback)
+            .await?;
+
+        let mut head = vec![0u8; 512];
+        let read = {
+            use tokio::io::AsyncReadExt;
+            File::open(long_path(&part_path)).await?.read(&mut head).await?
+        };
+        head.truncate(read);
+
+        let size = tokio::fs::metadata(long_path(&part_path)).await.map(|m| m.len()).unwrap_or(0);
+        if let Err(e) = check_size(size, self.max_bytes) {
+            tokio::fs::remove_file(long_path(&part_path)).await.ok();
+            return Err(e);
         }
 
-        let response = request.send().await?;
+        if let Some(scheme) = drm::mp4_drm(&head) {
+            tokio::fs::remove_file(long_path(&part_path)).await.ok();
+            return Err(DownloaderError::DrmProtected(scheme.to_string()));
+        }
+
+        let path = output_file_path(output_path, container::detect_extension(&head, None, url));
+        tokio::fs::rename(long_path(&part_path), long_path(&path)).await?;
+        Ok(path)
+    }
+
+    async fn download_builtin(
+        &self,
+        url: &str,
+        output_path: &Path,
+        progress_callback: impl Fn(f32, String) + Send + 'static,
+    ) -> Result<PathBuf, DownloaderError> {
+        let opened = watchdog::within(self.timeouts.stall, "Connecting", self.open(url)).await?;
+        let response = opened.response;
         let total_size = response.content_length().unwrap_or(0);
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        self.log.info(format!(
+            "HTTP {}, {} bytes, {}",
+            response.status(),
+            total_size,
+            content_type.as_deref().unwrap_or("no content type")
+        ));
+        // Stop before writing anything when the server says it's too big
+        check_size(total_size, self.max_bytes)?;
+
+        let mut stream = response.bytes_stream();
 
-        let mp4_path = output_path.with_extension("mp4");
-        let mut output_file = File::create(&mp4_path).await?;
+        // The first chunk decides the container before the file is created
+        let first = match watchdog::next_chunk(&mut stream, self.timeouts.stall).await {
+            Some(chunk) => chunk?,
+            None => return Err(DownloaderError::DownloadFailed("Empty response".to_string())),
+        };
+        // Protected MP4s declare it up front; stop before writing junk
+        if let Some(scheme) = drm::mp4_drm(&first) {
+            return Err(DownloaderError::DrmProtected(scheme.to_string()));
+        }
+
+        let extension = container::detect_extension(&first, content_type.as_deref(), url);
+        let path = output_file_path(output_path, extension);
+        let mut output_file = BufWriter::with_capacity(WRITE_BUFFER_SIZE, File::create(long_path(&path)).await?);
 
         let mut downloaded: u64 = 0;
-        let mut stream = response.bytes_stream();
+        let mut stream = futures::stream::iter([Ok(first)]).chain(stream).boxed();
+        let progress = |downloaded: u64| if total_size > 0 { (downloaded as f32 / total_size as f32) * 100.0 } else { 0.0 };
+        let mut reconnects = 0;
 
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk?;
+        while let Some(chunk) = watchdog::next_chunk(&mut stream, self.timeouts.stall).await {
+            let chunk = match chunk {
+                Err(DownloaderError::Timeout(reason)) if reconnects < STALL_RECONNECTS => {
+                    // Drop the dead connection and ask for the rest
+                    reconnects += 1;
+                    self.log.warn(format!("Transfer stalled ({}), reconnecting at byte {}", reason, downloaded));
+                    progress_callback(progress(downloaded), RECONNECTING_MESSAGE.to_string());
+                    let resumed = self.resume(&opened.url, opened.referer.as_deref(), opened.cookies.as_deref(), downloaded);
+                    match watchdog::within(self.timeouts.stall, "Reconnecting", resumed).await {
+                        Ok(response) => stream = response.bytes_stream().boxed(),
+                        Err(e) => self.log.warn(format!("Reconnect failed: {}", e)),
+                    }
+                    continue;
+                }
+                chunk => chunk?,
+            };
+            // Data is flowing again; later stalls get a fresh set of reconnects
+            reconnects = 0;
+            if let Err(e) = check_size(downloaded + chunk.len() as u64, self.max_bytes) {
+                self.log.error(format!("Stopped at byte {}: {}", downloaded, e));
+                drop(output_file);
+                tokio::fs::remove_file(long_path(&path)).await.ok();
+                return Err(e);
+            }
             output_file.write_all(&chunk).await?;
+            if let Some(bandwidth) = &self.bandwidth {
+                bandwidth.consume(chunk.len()).await;
+            }
 
             downloaded += chunk.len() as u64;
 
             if total_size > 0 {
-                let progress = (downloaded as f32 / total_size as f32) * 100.0;
-                progress_callback(progress, format!("Downloaded {} / {} bytes", downloaded, total_size));
+                progress_callback(progress(downloaded), format!("Downloaded {} / {} bytes", downloaded, total_size));
             }
         }
 
         output_file.flush().await?;
 
-        Ok(mp4_path)
+        Ok(path)
+    }
+
+    /// Stream-copy into an MP4 next to the download; on failure the
+    /// original container is kept
+    async fn remux(&self, path: PathBuf) -> PathBuf {
+        let mp4_path = output_file_path(&path, "mp4");
+        if mp4_path.exists() {
+            return path;
+        }
+
+        match ffmpeg::remux_to_mp4(&path, &mp4_path, self.faststart).await {
+            Ok(()) => {
+                tokio::fs::remove_file(long_path(&path)).await.ok();
+                mp4_path
+            }
+            Err(e) => {
+                self.log.warn(format!("Remux to MP4 failed, keeping original: {}", e));
+                tokio::fs::remove_file(long_path(&mp4_path)).await.ok();
+                path
+            }
+        }
+    }
+}
+
+/// Fail before fetching any segment when the playlist uses a DRM key system
+/// Error once `bytes` passes the size cap, if there is one
+fn check_size(bytes: u64, max_bytes: Option<u64>) -> Result<(), DownloaderError> {
+    match max_bytes {
+        Some(limit) if bytes > limit => Err(DownloaderError::TooLarge(limit)),
+        _ => Ok(()),
+    }
+}
+
+fn check_playlist_drm(content: &str) -> Result<(), DownloaderError> {
+    match drm::playlist_drm(content) {
+        Some(scheme) => Err(DownloaderError::DrmProtected(scheme.to_string())),
+        None => Ok(()),
+    }
+}
+
+/// Move a temp file into place, copying when it's on another device
+async fn move_file(from: &Path, to: &Path) -> Result<(), DownloaderError> {
+    if tokio::fs::rename(from, long_path(to)).await.is_err() {
+        tokio::fs::copy(from, long_path(to)).await?;
+        tokio::fs::remove_file(from).await.ok();
+    }
+    Ok(())
+}
+
+async fn sync_file(path: &Path) -> Result<(), DownloaderError> {
+    File::open(long_path(path)).await?.sync_all().await?;
+    Ok(())
+}
+
+/// First value of a header in a custom header list, ignoring case
+fn header_value(headers: &[(String, String)], name: &str) -> Option<String> {
+    headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.clone())
+}
+
+/// GET with the referer and custom headers. URLs come from playlists and
+/// redirects as often as from the user, so each is validated first: the
+/// resolver only sees host names, never IP literals.
+pub(super) fn build_request(
+    client: &Client,
+    url: &str,
+    referer: Option<&str>,
+    headers: &[(String, String)],
+) -> Result<RequestBuilder, DownloaderError> {
+    validate_url(url)?;
+    let mut request = client.get(url);
+    if let Some(referer) = referer {
+        request = request.header("Referer", referer);
+    }
+    for (name, value) in headers {
+        request = request.header(name.as_str(), value.as_str());
+    }
+    Ok(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_variant_is_the_highest_that_downloads_faster_than_real_time() {
+        let master = b"#EXTM3U\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=800000\nlow.m3u8\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=2800000\nmid.m3u8\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=6000000\nhigh.m3u8\n";
+        let m3u8_rs::Playlist::MasterPlaylist(master) = m3u8_rs::parse_playlist_res(master).unwrap() else {
+            panic!("not a master playlist");
+        };
+        let picked = |bits_per_second: f64| variant_for_speed(&master, bits_per_second).map(|v| v.uri.clone());
+
+        assert_eq!(picked(50_000_000.0).as_deref(), Some("high.m3u8"));
+        assert_eq!(picked(5_000_000.0).as_deref(), Some("mid.m3u8"));
+        // Too slow for anything: the smallest variant still beats failing
+        assert_eq!(picked(300_000.0).as_deref(), Some("low.m3u8"));
     }
 }
@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::{extract_quality_from_url, is_ad_url, VideoSource};
+
+// Hook files in the scripts folder are named after the site's domain:
+//   example.com.page.js       runs in the page and each iframe after load;
+//                             may `return [...]` extra video URLs
+//   example.com.transform.js  receives `sources` ([{url, quality, source_type}])
+//                             and returns the sources to keep
+const PAGE_SUFFIX: &str = ".page.js";
+const TRANSFORM_SUFFIX: &str = ".transform.js";
+
+/// User scripts for one domain (and its subdomains)
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SiteHook {
+    pub domain: String,
+    pub page_script: Option<String>,
+    pub transform_script: Option<String>,
+}
+
+impl SiteHook {
+    pub fn matches(&self, url: &str) -> bool {
+        let host = url::Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_lowercase()))
+            .unwrap_or_default();
+        host == self.domain || host.ends_with(&format!(".{}", self.domain))
+    }
+
+    /// Expression running the page script; evaluates to its return value
+    pub fn page_expression(&self) -> Option<String> {
+        self.page_script
+            .as_ref()
+            .map(|script| format!("(function() {{\n{}\n}})()", script))
+    }
+
+    /// Expression passing `sources` to the transform script
+    pub fn transform_expression(&self, sources: &[VideoSource]) -> Option<String> {
+        let script = self.transform_script.as_ref()?;
+        let json = serde_json::to_string(sources).ok()?;
+        Some(format!("(function(sources) {{\n{}\n}})({})", script, json))
+    }
+}
+
+/// Read every `*.page.js` / `*.transform.js` in `dir`, grouped by domain
+pub fn load_hooks(dir: &Path) -> Vec<SiteHook> {
+    let mut hooks: Vec<SiteHook> = Vec::new();
+
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return hooks;
+    };
+
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().to_lowercase();
+        let (domain, is_page) = if let Some(domain) = name.strip_suffix(PAGE_SUFFIX) {
+            (domain.to_string(), true)
+        } else if let Some(domain) = name.strip_suffix(TRANSFORM_SUFFIX) {
+            (domain.to_string(), false)
+        } else {
+            continue;
+        };
+
+        let Ok(script) = std::fs::read_to_string(entry.path()) else {
+            continue;
+        };
+
+        let index = match hooks.iter().position(|h| h.domain == domain) {
+            Some(index) => index,
+            None => {
+                hooks.push(SiteHook {
+                    domain,
+                    ..Default::default()
+                });
+                hooks.len() - 1
+            }
+        };
+
+        if is_page {
+            hooks[index].page_script = Some(script);
+        } else {
+            hooks[index].transform_script = Some(script);
+        }
+    }
+
+    hooks.sort_by(|a, b| a.domain.cmp(&b.domain));
+    hooks
+}
+
+/// Turn a page script's return value (URLs or source objects) into sources
+pub fn sources_from_value(value: serde_json::Value) -> Vec<VideoSource> {
+    let serde_json::Value::Array(items) = value else {
+        return Vec::new();
+    };
+
+    items
+        .into_iter()
+        .filter_map(|item| match item {
+            serde_json::Value::String(url) => Some(VideoSource {
+                quality: extract_quality_from_url(&url),
+                source_type: guess_source_type(&url),
+                url,
+                ..Default::default()
+            }),
+            other => serde_json::from_value::<VideoSource>(other).ok(),
+        })
+        .filter(|s| s.url.starts_with("http") && !is_ad_url(&s.url))
+        .collect()
+}
+
+fn guess_source_type(url: &str) -> String {
+    if url.contains(".m3u8") { "hls" } else { "direct" }.to_string()
+}
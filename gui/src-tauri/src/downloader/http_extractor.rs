@@ -0,0 +1,264 @@
+use regex::Regex;
+use reqwest::Client;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::dns;
+use super::rules::{self, ExtractorRule};
+use super::{build_video_info, extract_quality_from_url, find_sources_in_content, is_ad_url, validate_url, VideoInfo, VideoSource, DownloaderError};
+
+// Limit how many embeds we fetch so a page full of ad iframes stays fast
+const MAX_IFRAMES: usize = 8;
+
+/// Browser-less extraction: fetch the page and its iframes over plain HTTP
+/// and scan the HTML for player sources. Works for sites that embed the
+/// stream URL directly in markup or inline scripts.
+pub struct HttpExtractor {
+    client: Client,
+}
+
+/// Everything a custom rule matched on a page
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RuleMatch {
+    pub title: String,
+    pub thumbnail: String,
+    /// Iframes selected by the rule's iframe selector
+    pub iframes: Vec<String>,
+    /// Raw regex captures from the rule's source patterns
+    pub captures: Vec<String>,
+    pub sources: Vec<VideoSource>,
+}
+
+struct ParsedPage {
+    title: String,
+    thumbnail: String,
+    iframes: Vec<String>,
+    sources: Vec<VideoSource>,
+}
+
+impl HttpExtractor {
+    pub fn new() -> Self {
+        let client = dns::client_builder()
+            .timeout(std::time::Duration::from_secs(20))
+            .build()
+            .unwrap();
+
+        Self { client }
+    }
+
+    pub async fn get_video_info(&self, url: &str) -> Result<VideoInfo, DownloaderError> {
+        // Validate URL to prevent SSRF attacks
+        let validated = validate_url(url)?;
+
+        // User-provided site rules take precedence over the generic scan
+        if let Some(rule) = rules::rule_for(&validated) {
+            let matched = self.extract_with_rule(&validated, &rule).await?;
+            let info = build_video_info(&validated, matched.title, matched.thumbnail, &matched.sources);
+            if info.sources.is_empty() {
+                return Err(DownloaderError::NoSources);
+            }
+            return Ok(info);
+        }
+
+        let base_url = Url::parse(&validated)
+            .map_err(|e| DownloaderError::Parse(e.to_string()))?;
+
+        let html = self.fetch(&validated, None, &[]).await?;
+        let page = parse_page(&html, &base_url, "iframe");
+
+        let mut sources = page.sources;
+
+        // Player embeds usually live in iframes, fetch them with the page as referer
+        for iframe_url in page.iframes.iter().take(MAX_IFRAMES) {
+            if validate_url(iframe_url).is_err() {
+                continue;
+            }
+
+            let Ok(iframe_html) = self.fetch(iframe_url, Some(&validated), &[]).await else {
+                continue;
+            };
+
+            let Ok(iframe_base) = Url::parse(iframe_url) else {
+                continue;
+            };
+
+            for mut source in parse_page(&iframe_html, &iframe_base, "iframe").sources {
+                if !sources.iter().any(|s| s.url == source.url) {
+                    source.embed_url = Some(iframe_url.clone());
+                    sources.push(source);
+                }
+            }
+        }
+
+        let info = build_video_info(&validated, page.title, page.thumbnail, &sources);
+
+        if info.sources.is_empty() {
+            return Err(DownloaderError::NoSources);
+        }
+
+        Ok(info)
+    }
+
+    /// Run a site rule against a page: fetch it with the rule's headers,
+    /// follow the iframes its selector picks and apply its source regexes
+    /// (plus the generic scan) to every document
+    pub async fn extract_with_rule(&self, url: &str, rule: &ExtractorRule) -> Result<RuleMatch, DownloaderError> {
+        let validated = validate_url(url)?;
+        let base_url = Url::parse(&validated)
+            .map_err(|e| DownloaderError::Parse(e.to_string()))?;
+
+        rule.validate().map_err(DownloaderError::Parse)?;
+        let patterns: Vec<Regex> = rule.source_patterns.iter()
+            .filter_map(|p| Regex::new(p).ok())
+            .collect();
+        let selector = if rule.iframe_selector.is_empty() { "iframe" } else { rule.iframe_selector.as_str() };
+        let headers = rule.header_list();
+
+        let html = self.fetch(&validated, None, &headers).await?;
+        let page = parse_page(&html, &base_url, selector);
+
+        let mut matched = RuleMatch {
+            title: page.title,
+            thumbnail: page.thumbnail,
+            iframes: page.iframes.clone(),
+            captures: Vec::new(),
+            sources: page.sources,
+        };
+        apply_patterns(&patterns, &html, &base_url, None, &mut matched);
+
+        for iframe_url in page.iframes.iter().take(MAX_IFRAMES) {
+            if validate_url(iframe_url).is_err() {
+                continue;
+            }
+            let Ok(iframe_html) = self.fetch(iframe_url, Some(&validated), &headers).await else {
+                continue;
+            };
+            let Ok(iframe_base) = Url::parse(iframe_url) else {
+                continue;
+            };
+
+            for mut source in parse_page(&iframe_html, &iframe_base, selector).sources {
+                if !matched.sources.iter().any(|s| s.url == source.url) {
+                    source.embed_url = Some(iframe_url.clone());
+                    matched.sources.push(source);
+                }
+            }
+            apply_patterns(&patterns, &iframe_html, &iframe_base, Some(iframe_url), &mut matched);
+        }
+
+        Ok(matched)
+    }
+
+    async fn fetch(&self, url: &str, referer: Option<&str>, headers: &[(String, String)]) -> Result<String, DownloaderError> {
+        let mut request = self.client.get(url);
+        if let Some(referer) = referer {
+            request = request.header("Referer", referer);
+        }
+        for (name, value) in headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+
+        let response = request.send().await?.error_for_status()?;
+        Ok(response.text().await?)
+    }
+}
+
+impl Default for HttpExtractor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Add the capture of each source regex match, resolved against the page URL
+fn apply_patterns(patterns: &[Regex], html: &str, base_url: &Url, embed_url: Option<&str>, matched: &mut RuleMatch) {
+    for pattern in patterns {
+        for cap in pattern.captures_iter(html) {
+            let Some(raw) = cap.get(1).or_else(|| cap.get(0)) else {
+                continue;
+            };
+            // Inline scripts often escape slashes in JSON strings
+            let raw = raw.as_str().replace("\\/", "/");
+            matched.captures.push(raw.clone());
+
+            let Ok(resolved) = base_url.join(&raw) else {
+                continue;
+            };
+            let url = resolved.to_string();
+            if is_ad_url(&url) || matched.sources.iter().any(|s| s.url == url) {
+                continue;
+            }
+            matched.sources.push(VideoSource {
+                quality: extract_quality_from_url(&url),
+                source_type: if url.contains(".m3u8") { "hls" } else { "direct" }.to_string(),
+                url,
+                embed_url: embed_url.map(str::to_string),
+                ..Default::default()
+            });
+        }
+    }
+}
+
+fn parse_page(html: &str, base_url: &Url, iframe_selector: &str) -> ParsedPage {
+    let document = Html::parse_document(html);
+
+    let select_attr = |selector: &str, attrs: &[&str]| -> Vec<String> {
+        let selector = Selector::parse(selector).unwrap();
+        document
+            .select(&selector)
+            .filter_map(|el| attrs.iter().find_map(|attr| el.value().attr(attr)))
+            .filter(|v| !v.trim().is_empty())
+            .filter_map(|v| base_url.join(v.trim()).ok())
+            .map(|u| u.to_string())
+            .collect()
+    };
+
+    let title = select_attr_text(&document, "meta[property=\"og:title\"]", "content")
+        .or_else(|| {
+            let selector = Selector::parse("title").unwrap();
+            document
+                .select(&selector)
+                .next()
+                .map(|el| el.text().collect::<String>().trim().to_string())
+        })
+        .unwrap_or_default();
+
+    let thumbnail = select_attr("meta[property=\"og:image\"]", &["content"])
+        .into_iter()
+        .chain(select_attr("video[poster]", &["poster"]))
+        .next()
+        .unwrap_or_default();
+
+    let iframes: Vec<String> = select_attr(iframe_selector, &["src", "data-lazy-src", "data-src"])
+        .into_iter()
+        .filter(|src| src.starts_with("http") && !is_ad_url(src))
+        .collect();
+
+    let mut sources = find_sources_in_content(html);
+
+    // <video src> / <source src> may use relative paths the regexes can't see
+    for src in select_attr("video[src], video source[src], source[src]", &["src"]) {
+        if is_ad_url(&src) || sources.iter().any(|s| s.url == src) {
+            continue;
+        }
+        for source in find_sources_in_content(&src) {
+            sources.push(source);
+        }
+    }
+
+    ParsedPage {
+        title,
+        thumbnail,
+        iframes,
+        sources,
+    }
+}
+
+fn select_attr_text(document: &Html, selector: &str, attr: &str) -> Option<String> {
+    let selector = Selector::parse(selector).unwrap();
+    document
+        .select(&selector)
+        .find_map(|el| el.value().attr(attr))
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
@@ -0,0 +1,204 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::RwLock;
+
+// Ranges that reach this machine or the local network rather than the internet
+const PRIVATE_V4: [(Ipv4Addr, u8); 9] = [
+    (Ipv4Addr::new(0, 0, 0, 0), 8),       // "this network"
+    (Ipv4Addr::new(10, 0, 0, 0), 8),      // RFC 1918
+    (Ipv4Addr::new(100, 64, 0, 0), 10),   // carrier-grade NAT
+    (Ipv4Addr::new(127, 0, 0, 0), 8),     // loopback
+    (Ipv4Addr::new(169, 254, 0, 0), 16),  // link-local
+    (Ipv4Addr::new(172, 16, 0, 0), 12),   // RFC 1918
+    (Ipv4Addr::new(192, 168, 0, 0), 16),  // RFC 1918
+    (Ipv4Addr::new(198, 18, 0, 0), 15),   // benchmarking
+    (Ipv4Addr::new(240, 0, 0, 0), 4),     // reserved, and broadcast
+];
+const PRIVATE_V6: [(Ipv6Addr, u8); 6] = [
+    (Ipv6Addr::UNSPECIFIED, 128),
+    (Ipv6Addr::LOCALHOST, 128),
+    (Ipv6Addr::new(0x64, 0xff9b, 1, 0, 0, 0, 0, 0), 48),  // local-use NAT64
+    (Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0), 7),   // unique local
+    (Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0), 10),  // link-local
+    (Ipv6Addr::new(0xfec0, 0, 0, 0, 0, 0, 0, 0), 10),  // old site-local
+];
+
+/// A LAN source the user trusts: a hostname ("nas.lan"), an IP address or
+/// a CIDR block ("192.168.1.0/24")
+#[derive(Clone, Debug, PartialEq)]
+enum Trusted {
+    Host(String),
+    Network(IpAddr, u8),
+}
+
+impl Trusted {
+    fn parse(entry: &str) -> Option<Self> {
+        let entry = entry.trim().trim_start_matches('[').trim_end_matches(']');
+        if entry.is_empty() {
+            return None;
+        }
+
+        if let Some((ip, prefix)) = entry.split_once('/') {
+            let ip: IpAddr = ip.parse().ok()?;
+            let prefix: u8 = prefix.parse().ok()?;
+            let max = if ip.is_ipv4() { 32 } else { 128 };
+            return (prefix <= max).then_some(Trusted::Network(ip, prefix));
+        }
+
+        match entry.parse::<IpAddr>() {
+            Ok(ip) => Some(Trusted::Network(ip, if ip.is_ipv4() { 32 } else { 128 })),
+            Err(_) if entry.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-') => {
+                Some(Trusted::Host(entry.to_lowercase()))
+            }
+            Err(_) => None,
+        }
+    }
+}
+
+/// Whether `ip` falls inside `network`/`prefix`
+fn in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+/// IPv4 address carried inside an IPv6 one: IPv4-mapped (::ffff:a.b.c.d),
+/// IPv4-compatible (::a.b.c.d), NAT64 (64:ff9b::a.b.c.d) and 6to4
+/// (2002:aabb:ccdd::). Traffic to these ends up at the IPv4 address.
+fn embedded_v4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let segments = ip.segments();
+    let low = Ipv4Addr::from((u128::from(ip) & 0xffff_ffff) as u32);
+    match segments {
+        [0, 0, 0, 0, 0, 0xffff, _, _] | [0, 0, 0, 0, 0, 0, _, _] => Some(low),
+        [0x64, 0xff9b, 0, 0, 0, 0, _, _] => Some(low),
+        [0x2002, hi, lo, ..] => Some(Ipv4Addr::from(((hi as u32) << 16) | lo as u32)),
+        _ => None,
+    }
+}
+
+/// Loopback, RFC 1918, CGNAT, link-local, reserved and unique local
+/// addresses. IPv4 addresses wrapped in IPv6 count as the IPv4 one.
+pub fn is_private_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => PRIVATE_V4.iter().any(|(net, prefix)| in_network(ip, IpAddr::V4(*net), *prefix)) || v4.is_multicast(),
+        IpAddr::V6(v6) => {
+            PRIVATE_V6.iter().any(|(net, prefix)| in_network(ip, IpAddr::V6(*net), *prefix))
+                || v6.is_multicast()
+                || embedded_v4(v6).is_some_and(|v4| is_private_ip(IpAddr::V4(v4)))
+        }
+    }
+}
+
+/// A private address the user didn't put on the allowlist
+pub fn is_blocked_ip(ip: IpAddr) -> bool {
+    is_private_ip(ip) && !is_trusted_ip(ip)
+}
+
+/// Names that always mean this machine or the local network
+pub fn is_local_hostname(host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_lowercase();
+    host == "localhost" || host.ends_with(".localhost") || host.ends_with(".local")
+}
+
+static ALLOWLIST: RwLock<Vec<Trusted>> = RwLock::new(Vec::new());
+
+/// Reject entries that are neither a hostname, an IP nor a CIDR block
+pub fn validate_allowlist(entries: &[String]) -> Result<(), String> {
+    for entry in entries {
+        if Trusted::parse(entry).is_none() {
+            return Err(format!("Invalid LAN allowlist entry: {}", entry));
+        }
+    }
+    Ok(())
+}
+
+pub fn set_lan_allowlist(entries: &[String]) {
+    if let Ok(mut current) = ALLOWLIST.write() {
+        *current = entries.iter().filter_map(|e| Trusted::parse(e)).collect();
+    }
+}
+
+/// URL host (name or IP literal) the user allowed despite the private
+/// network block
+pub fn is_trusted_host(host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']').to_lowercase();
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return is_trusted_ip(ip);
+    }
+    ALLOWLIST
+        .read()
+        .map(|list| list.iter().any(|t| t == &Trusted::Host(host.clone())))
+        .unwrap_or(false)
+}
+
+pub fn is_trusted_ip(ip: IpAddr) -> bool {
+    ALLOWLIST
+        .read()
+        .map(|list| {
+            list.iter().any(|t| match t {
+                Trusted::Network(network, prefix) => in_network(ip, *network, *prefix),
+                Trusted::Host(_) => false,
+            })
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::validate_url;
+    use super::*;
+
+    #[test]
+    fn allowlist_only_opens_trusted_addresses() {
+        let entries = vec!["192.168.1.0/24".to_string(), "nas.local".to_string()];
+        validate_allowlist(&entries).unwrap();
+        assert!(validate_allowlist(&["192.168.1.0/33".to_string()]).is_err());
+        set_lan_allowlist(&entries);
+
+        assert!(validate_url("http://192.168.1.20:8096/video.mp4").is_ok());
+        assert!(validate_url("http://nas.local/share/ep1.mkv").is_ok());
+        assert!(validate_url("http://192.168.2.20/video.mp4").is_err());
+        assert!(validate_url("http://other.local/video.mp4").is_err());
+        assert!(validate_url("http://127.0.0.1/video.mp4").is_err());
+    }
+
+    #[test]
+    fn private_ranges_are_matched_by_address() {
+        for blocked in [
+            "http://172.20.0.5/v.mp4",
+            "http://172.31.255.255/v.mp4",
+            "http://100.64.1.1/v.mp4",
+            "http://198.19.0.1/v.mp4",
+            "http://250.1.2.3/v.mp4",
+            "http://0x7f.1/v.mp4",
+            "http://[::ffff:10.0.0.1]/v.mp4",
+            "http://[::127.0.0.1]/v.mp4",
+            "http://[64:ff9b::a9fe:a9fe]/v.mp4",
+            "http://[2002:c0a8:0101::1]/v.mp4",
+            "http://[fd12::1]/v.mp4",
+            "http://[fe80::1]/v.mp4",
+            "http://media.localhost/v.mp4",
+        ] {
+            assert!(validate_url(blocked).is_err(), "{} should be blocked", blocked);
+        }
+        for allowed in [
+            "https://172.32.0.5/v.mp4",
+            "https://198.20.0.1/v.mp4",
+            "https://[::ffff:8.8.8.8]/v.mp4",
+            "https://[64:ff9b::808:808]/v.mp4",
+            "https://[2002:808:808::1]/v.mp4",
+            "https://[2606:4700::1111]/v.mp4",
+            "https://localnews.example/v.mp4",
+            "https://cdn.localhosting.example/v.mp4",
+        ] {
+            assert!(validate_url(allowed).is_ok(), "{} should be allowed", allowed);
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use std::future::Future;
+use std::sync::OnceLock;
+
+use tokio::runtime::{Builder, Runtime};
+
+use super::DownloaderError;
+
+// Enough for a few previews at once; the browser tabs do the heavy lifting
+const WORKER_THREADS: usize = 2;
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+/// Info fetches drive a browser and parse whole pages in bursts. They run
+/// on their own worker threads so previewing a URL can't hold up the
+/// tasks that keep active downloads moving.
+fn runtime() -> &'static Runtime {
+    RUNTIME.get_or_init(|| {
+        Builder::new_multi_thread()
+            .worker_threads(WORKER_THREADS)
+            .thread_name("extraction")
+            .enable_all()
+            .build()
+            .expect("failed to start the extraction runtime")
+    })
+}
+
+/// Run an extraction on the extraction lane and wait for its result
+pub async fn run<F, T>(future: F) -> Result<T, DownloaderError>
+where
+    F: Future<Output = Result<T, DownloaderError>> + Send + 'static,
+    T: Send + 'static,
+{
+    runtime()
+        .spawn(future)
+        .await
+        .map_err(|e| DownloaderError::Browser(format!("Extraction task failed: {}", e)))?
+}
@@ -0,0 +1,54 @@
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+
+// Entries kept per download; the oldest go first
+const MAX_ENTRIES: usize = 1000;
+
+// Entry levels
+pub const LEVEL_INFO: &str = "info";
+pub const LEVEL_WARN: &str = "warn";
+pub const LEVEL_ERROR: &str = "error";
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LogEntry {
+    /// RFC 3339 local time
+    pub time: String,
+    pub level: String,
+    pub message: String,
+}
+
+/// Event trail of one download: URLs tried, choices made and failures with
+/// their ffmpeg output. Clones share the same trail; a default log is
+/// simply never read.
+#[derive(Clone, Default)]
+pub struct DownloadLog(Arc<Mutex<Vec<LogEntry>>>);
+
+impl DownloadLog {
+    pub fn info(&self, message: impl Into<String>) {
+        self.push(LEVEL_INFO, message.into());
+    }
+
+    pub fn warn(&self, message: impl Into<String>) {
+        self.push(LEVEL_WARN, message.into());
+    }
+
+    pub fn error(&self, message: impl Into<String>) {
+        self.push(LEVEL_ERROR, message.into());
+    }
+
+    fn push(&self, level: &str, message: String) {
+        let mut entries = self.0.lock().unwrap();
+        if entries.len() >= MAX_ENTRIES {
+            entries.remove(0);
+        }
+        entries.push(LogEntry {
+            time: chrono::Local::now().to_rfc3339(),
+            level: level.to_string(),
+            message,
+        });
+    }
+
+    pub fn entries(&self) -> Vec<LogEntry> {
+        self.0.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,113 @@
+//! Stand-in for extraction and transfers behind the `mock-downloader`
+//! feature. `VideoDownloader` keeps its own builder, entry points and source
+//! selection; only `extract` and `download_source` come from here. Every URL
+//! resolves to fixture info and downloads write a dummy file with simulated
+//! progress, so the queue, scheduler and UI can be worked on without touching
+//! real sites or launching Chromium.
+//!
+//! A URL containing one of these words fails on purpose:
+//! `nosources`, `drm`, `fail` (halfway through the download).
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::audio::AudioTrack;
+use super::video::VideoDownloader;
+use super::{output_file_path, DownloaderError, VideoInfo, VideoSource};
+
+// Simulated download: this many chunks of CHUNK_SIZE, one per STEP
+const CHUNKS: usize = 50;
+const CHUNK_SIZE: usize = 64 * 1024;
+const STEP: Duration = Duration::from_millis(100);
+// Simulated extraction time
+const EXTRACT_DELAY: Duration = Duration::from_millis(300);
+
+const QUALITIES: [&str; 3] = ["1080p", "720p", "480p"];
+
+// The builder's other options don't affect the mock
+impl VideoDownloader {
+    pub(super) async fn extract(&self, url: &str) -> Result<VideoInfo, DownloaderError> {
+        let parsed = url::Url::parse(url).map_err(|_| DownloaderError::DownloadFailed("Invalid URL format".to_string()))?;
+        tokio::time::sleep(EXTRACT_DELAY).await;
+        self.log.info(format!("Mock extraction: {}", url));
+
+        if url.contains("nosources") {
+            return Err(DownloaderError::NoSources);
+        }
+
+        let title = parsed
+            .path_segments()
+            .and_then(|mut segments| segments.rfind(|s| !s.is_empty()))
+            .map(|s| s.replace(['-', '_'], " "))
+            .unwrap_or_else(|| "Mock video".to_string());
+
+        Ok(VideoInfo {
+            url: url.to_string(),
+            title,
+            thumbnail: String::new(),
+            duration: "00:24:00".to_string(),
+            qualities: QUALITIES.iter().map(|q| q.to_string()).collect(),
+            sources: QUALITIES
+                .iter()
+                .map(|quality| VideoSource {
+                    url: format!("mock://{}/{}.m3u8", parsed.host_str().unwrap_or("mock"), quality),
+                    quality: quality.to_string(),
+                    source_type: "hls".to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            audio_tracks: vec![
+                AudioTrack {
+                    language: "th".to_string(),
+                    name: "พากย์ไทย".to_string(),
+                    default: true,
+                },
+                AudioTrack {
+                    language: "ja".to_string(),
+                    name: "Original".to_string(),
+                    default: false,
+                },
+            ],
+        })
+    }
+
+    /// Mock output never needs converting; the speed limit still applies
+    #[allow(clippy::too_many_arguments)]
+    pub(super) async fn download_source(
+        &self,
+        url: &str,
+        _source: &VideoSource,
+        _headers: &[(String, String)],
+        output_path: &Path,
+        _defer_conversion: bool,
+        _speed_matched: bool,
+        progress_callback: impl Fn(f32, String) + Send + Clone + 'static,
+    ) -> Result<(PathBuf, bool), DownloaderError> {
+        if url.contains("drm") {
+            return Err(DownloaderError::DrmProtected("Widevine".to_string()));
+        }
+
+        let path = output_file_path(output_path, "mp4");
+        let mut data = Vec::with_capacity(CHUNKS * CHUNK_SIZE);
+
+        for chunk in 1..=CHUNKS {
+            if url.contains("fail") && chunk > CHUNKS / 2 {
+                self.log.error("Mock failure halfway through");
+                return Err(DownloaderError::DownloadFailed("Simulated failure".to_string()));
+            }
+
+            tokio::time::sleep(STEP).await;
+            if let Some(bandwidth) = &self.bandwidth {
+                bandwidth.consume(CHUNK_SIZE).await;
+            }
+            data.resize(chunk * CHUNK_SIZE, 0);
+
+            let progress = chunk as f32 / CHUNKS as f32 * 100.0;
+            progress_callback(progress, format!("Downloading segment {}/{}", chunk, CHUNKS));
+        }
+
+        tokio::fs::write(&path, data).await?;
+        self.log.info(format!("Mock file written: {}", path.display()));
+        Ok((path, false))
+    }
+}
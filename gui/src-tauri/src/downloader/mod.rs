@@ -1,10 +1,53 @@
+pub mod ads;
+pub mod aria2;
+pub mod audio;
+pub mod bandwidth;
+pub mod benchmark;
 pub mod browser;
+pub mod chromium;
+pub mod container;
+pub mod cookies;
+pub mod diagnostics;
+pub mod dns;
+pub mod drm;
+pub mod explain;
+pub mod encoders;
+pub mod ffmpeg;
 pub mod hls;
+pub mod hooks;
+pub mod http_extractor;
+pub mod lan;
+pub mod lane;
+pub mod log;
+#[cfg(feature = "mock-downloader")]
+pub mod mock;
+pub mod naming;
+pub mod playlist;
+pub mod probe;
+pub mod redirect;
+pub mod rule_updates;
+pub mod rules;
+pub mod scoring;
+pub mod segment_cache;
+pub mod series;
+pub mod thumbnails;
+pub mod size;
+pub mod transliterate;
 pub mod video;
+pub mod watchdog;
+pub mod webdriver;
 
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use thiserror::Error;
 
+use diagnostics::ExtractionDiagnostics;
+
+pub const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+
 #[derive(Error, Debug)]
 pub enum DownloaderError {
     #[error("Browser error: {0}")]
@@ -19,13 +62,110 @@ pub enum DownloaderError {
     NoSources,
     #[error("Download failed: {0}")]
     DownloadFailed(String),
+    #[error("No video sources found: {}", .0.summary())]
+    ExtractionFailed(Box<ExtractionDiagnostics>),
+    #[error("Video is DRM protected ({0}) and can't be downloaded")]
+    DrmProtected(String),
+    /// A segment URL stopped working (auth error or an HTML error page);
+    /// fresh links from the page usually fix it
+    #[error("Segment link expired: {0}")]
+    SegmentExpired(String),
+    /// A page, segment or transfer hung past its configured limit
+    #[error("Timed out: {0}")]
+    Timeout(String),
+    /// The file is bigger than the max file size setting allows (in bytes)
+    #[error("File is larger than the {} MB size limit", .0 / (1024 * 1024))]
+    TooLarge(u64),
+    /// Stopped by the user while post-processing
+    #[error("Cancelled")]
+    Cancelled,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct VideoSource {
     pub url: String,
     pub quality: String,
     pub source_type: String,
+    /// Player iframe the source was found in; hotlink-protected hosts
+    /// often only accept it as referer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embed_url: Option<String>,
+    /// Page the extraction started from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_url: Option<String>,
+    /// RFC 3339 time the source was extracted; stream tokens age from here
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extracted_at: Option<String>,
+    /// Video codec found by probing: "h264", "h265", "av1", "vp9"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub codec: Option<String>,
+    /// "ts" or "fmp4" for HLS segments, the file type for direct sources
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    /// Bits per second from the HLS variant
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth: Option<u64>,
+    /// Bytes, from Content-Range or the HLS playlist, when sources are checked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Seconds, from a finished HLS playlist, when sources are checked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    /// How long the server took to answer the source check
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+}
+
+impl VideoSource {
+    pub fn host(&self) -> Option<String> {
+        url::Url::parse(&self.url).ok()?.host_str().map(str::to_lowercase)
+    }
+
+    pub fn origin(&self) -> SourceOrigin {
+        SourceOrigin {
+            page_url: self.page_url.clone(),
+            embed_url: self.embed_url.clone(),
+            embed_host: self.embed_url.as_deref().and_then(|u| url::Url::parse(u).ok()?.host_str().map(str::to_lowercase)),
+            extracted_at: self.extracted_at.clone(),
+        }
+    }
+}
+
+/// Where a stream came from, kept on queue items for referer choice,
+/// link refreshes and diagnostics
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SourceOrigin {
+    pub page_url: Option<String>,
+    pub embed_url: Option<String>,
+    pub embed_host: Option<String>,
+    pub extracted_at: Option<String>,
+}
+
+impl SourceOrigin {
+    /// Time since extraction; None when unknown
+    pub fn age(&self) -> Option<std::time::Duration> {
+        let extracted = chrono::DateTime::parse_from_rfc3339(self.extracted_at.as_deref()?).ok()?;
+        (chrono::Utc::now() - extracted.with_timezone(&chrono::Utc)).to_std().ok()
+    }
+
+    /// One-line description for the download log
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(page) = &self.page_url {
+            parts.push(format!("page {}", page));
+        }
+        if let Some(embed) = &self.embed_url {
+            parts.push(format!("embed {}", embed));
+        }
+        if let Some(at) = &self.extracted_at {
+            parts.push(format!("extracted {}", at));
+        }
+        if parts.is_empty() {
+            "unknown origin".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -36,6 +176,9 @@ pub struct VideoInfo {
     pub duration: String,
     pub qualities: Vec<String>,
     pub sources: Vec<VideoSource>,
+    /// Separate audio renditions (e.g. Thai dub and original)
+    #[serde(default)]
+    pub audio_tracks: Vec<audio::AudioTrack>,
 }
 
 impl Default for VideoInfo {
@@ -47,11 +190,12 @@ impl Default for VideoInfo {
             duration: String::new(),
             qualities: vec!["auto".to_string()],
             sources: Vec::new(),
+            audio_tracks: Vec::new(),
         }
     }
 }
 
-// Ad patterns to filter
+// Built-in ad patterns; the user's ad_patterns.txt starts from these
 pub const AD_PATTERNS: &[&str] = &[
     "adSrc",
     "/ad/",
@@ -62,8 +206,7 @@ pub const AD_PATTERNS: &[&str] = &[
 ];
 
 pub fn is_ad_url(url: &str) -> bool {
-    let url_lower = url.to_lowercase();
-    AD_PATTERNS.iter().any(|pattern| url_lower.contains(&pattern.to_lowercase()))
+    ads::is_ad(url)
 }
 
 pub fn extract_quality_from_url(url: &str) -> String {
@@ -80,11 +223,182 @@ pub fn extract_quality_from_url(url: &str) -> String {
     }
 }
 
+/// Scan raw page content (HTML, inline scripts) for m3u8/mp4 URLs
+pub fn find_sources_in_content(content: &str) -> Vec<VideoSource> {
+    let m3u8_regex = Regex::new(r#"(https?://[^\s"'<>\\)]+\.m3u8[^\s"'<>\\)]*)"#).unwrap();
+    let mp4_regex = Regex::new(r#"(https?://[^\s"'<>\\)]+\.mp4[^\s"'<>\\)]*)"#).unwrap();
+
+    let mut sources: Vec<VideoSource> = Vec::new();
+
+    for cap in m3u8_regex.captures_iter(content) {
+        let url = &cap[1];
+        if !is_ad_url(url) && !sources.iter().any(|s| s.url == url) {
+            sources.push(VideoSource {
+                url: url.to_string(),
+                quality: extract_quality_from_url(url),
+                source_type: "hls".to_string(),
+                ..Default::default()
+            });
+        }
+    }
+
+    for cap in mp4_regex.captures_iter(content) {
+        let url = &cap[1];
+        if !is_ad_url(url) && !sources.iter().any(|s| s.url == url) {
+            sources.push(VideoSource {
+                url: url.to_string(),
+                quality: extract_quality_from_url(url),
+                source_type: "direct".to_string(),
+                ..Default::default()
+            });
+        }
+    }
+
+    sources
+}
+
+/// Deduplicate collected sources, drop segments and ads, and build the
+/// sorted quality list shared by every extraction strategy. Sources are
+/// stamped with the page URL and extraction time.
+pub fn build_video_info(
+    url: &str,
+    title: String,
+    thumbnail: String,
+    sources: &[VideoSource],
+) -> VideoInfo {
+    let mut seen = HashSet::new();
+    let mut unique_sources: Vec<VideoSource> = Vec::new();
+    let extracted_at = chrono::Utc::now().to_rfc3339();
+
+    for source in sources {
+        // Skip .ts segment files and ads
+        if source.url.contains(".ts") && !source.url.contains(".m3u8") {
+            continue;
+        }
+        if is_ad_url(&source.url) {
+            continue;
+        }
+        if seen.insert(source.url.clone()) {
+            let mut source = source.clone();
+            source.page_url.get_or_insert_with(|| url.to_string());
+            source.extracted_at.get_or_insert_with(|| extracted_at.clone());
+            unique_sources.push(source);
+        }
+    }
+
+    VideoInfo {
+        url: url.to_string(),
+        title,
+        thumbnail,
+        duration: String::new(),
+        qualities: quality_list(&unique_sources),
+        sources: unique_sources,
+        audio_tracks: Vec::new(),
+    }
+}
+
+/// Distinct qualities, highest first ("auto" last)
+pub fn quality_list(sources: &[VideoSource]) -> Vec<String> {
+    let qualities: HashSet<String> = sources.iter().map(|s| s.quality.clone()).collect();
+
+    let mut quality_list: Vec<String> = qualities.into_iter().collect();
+    quality_list.sort_by(|a, b| {
+        let a_num: i32 = a.replace("p", "").replace("auto", "0").parse().unwrap_or(0);
+        let b_num: i32 = b.replace("p", "").replace("auto", "0").parse().unwrap_or(0);
+        b_num.cmp(&a_num)
+    });
+
+    if quality_list.is_empty() {
+        quality_list.push("auto".to_string());
+    }
+
+    quality_list
+}
+
+// Max filename length in bytes (without extension), configurable in settings
+pub const DEFAULT_MAX_FILENAME_LENGTH: usize = 180;
+static MAX_FILENAME_LENGTH: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_FILENAME_LENGTH);
+
+// Windows device names that can't be used as filenames, with or without extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+pub fn set_max_filename_length(max: usize) {
+    // Leave room for the extension within the 255-byte component limit
+    MAX_FILENAME_LENGTH.store(max.clamp(16, 240), Ordering::Relaxed);
+}
+
+/// Truncate to at most `max_bytes` bytes without splitting a UTF-8 character
+pub fn truncate_on_char_boundary(s: &str, max_bytes: usize) -> &str {
+    if s.len() <= max_bytes {
+        return s;
+    }
+    let mut end = max_bytes;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+fn is_reserved_name(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+    RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem))
+}
+
+// Extensions treated as an existing container suffix on output names
+pub const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mkv", "webm", "ts", "m4v", "mov", "avi", "flv"];
+
+/// `path` with extension `ext`. A known video extension is replaced; any
+/// other dot belongs to the title ("EP.5 ...") and is kept, unlike
+/// `Path::with_extension`.
+pub fn output_file_path(path: &Path, ext: &str) -> PathBuf {
+    let has_video_extension = path
+        .extension()
+        .map(|e| VIDEO_EXTENSIONS.contains(&e.to_string_lossy().to_lowercase().as_str()) || e == "part")
+        .unwrap_or(false);
+
+    if has_video_extension {
+        path.with_extension(ext)
+    } else {
+        let mut name = path.as_os_str().to_os_string();
+        name.push(".");
+        name.push(ext);
+        PathBuf::from(name)
+    }
+}
+
+/// The app's own folder in the temp dir for download scratch files.
+/// Startup recovery and the periodic sweep only ever look in here.
+pub fn scratch_dir() -> PathBuf {
+    std::env::temp_dir().join("thai-video-downloader")
+}
+
+/// Prefix absolute paths with `\\?\` on Windows so file operations aren't
+/// limited to MAX_PATH (260 chars). No-op on other platforms.
+pub fn long_path(path: &Path) -> PathBuf {
+    #[cfg(target_os = "windows")]
+    {
+        let s = path.to_string_lossy();
+        if path.is_absolute() && !s.starts_with(r"\\?\") {
+            if let Some(unc) = s.strip_prefix(r"\\") {
+                return PathBuf::from(format!(r"\\?\UNC\{}", unc));
+            }
+            return PathBuf::from(format!(r"\\?\{}", s.replace('/', "\\")));
+        }
+    }
+    path.to_path_buf()
+}
+
 /// Sanitize filename to prevent path traversal and other attacks
 /// - Removes path separators (/, \)
 /// - Removes directory traversal components (..)
 /// - Removes null bytes
 /// - Replaces other invalid characters with underscores
+/// - Transliterates Thai text when enabled in settings
+/// - Trims to the configured max length and avoids Windows reserved names
 pub fn sanitize_filename(filename: &str) -> String {
     let mut result = String::with_capacity(filename.len());
 
@@ -100,10 +414,19 @@ pub fn sanitize_filename(filename: &str) -> String {
     }
 
     // Remove any remaining .. sequences to prevent traversal
-    let cleaned = result.replace("..", "");
+    let cleaned = transliterate::apply(&result.replace("..", ""));
+
+    let max_len = MAX_FILENAME_LENGTH.load(Ordering::Relaxed);
+    let truncated = truncate_on_char_boundary(&cleaned, max_len);
 
     // Remove leading/trailing dots and spaces
-    cleaned.trim_matches(|c| c == '.' || c == ' ').to_string()
+    let trimmed = truncated.trim_matches(|c| c == '.' || c == ' ');
+
+    if is_reserved_name(trimmed) {
+        format!("_{}", trimmed)
+    } else {
+        trimmed.to_string()
+    }
 }
 
 /// Validate and sanitize output directory path
@@ -127,7 +450,7 @@ pub fn validate_output_dir(dir: &str) -> Result<String, DownloaderError> {
 
 /// Validate URL to prevent SSRF (Server-Side Request Forgery) attacks
 /// - Only allows http/https schemes
-/// - Blocks private/local network addresses
+/// - Blocks private/local network addresses, except those in the LAN allowlist
 /// - Blocks file:// and other dangerous schemes
 pub fn validate_url(url: &str) -> Result<String, DownloaderError> {
     use url::Url;
@@ -153,34 +476,54 @@ pub fn validate_url(url: &str) -> Result<String, DownloaderError> {
     }
 
     // Block private and local network addresses to prevent SSRF
-    if let Some(host) = parsed.host_str() {
-        let host_lower = host.to_lowercase();
-
-        // Block localhost variants
-        if host_lower == "localhost" || host == "127.0.0.1" || host == "::1" || host == "0.0.0.0" {
-            return Err(DownloaderError::DownloadFailed(
-                "Localhost addresses are not allowed".to_string()
-            ));
-        }
-
-        // Block private IP ranges
-        if host_lower.starts_with("127.") || host_lower.starts_with("10.") ||
-           host_lower.starts_with("192.168.") || host_lower.starts_with("172.16.") ||
-           host_lower.starts_with("169.254.") || host_lower.starts_with("fc00:") ||
-           host_lower.starts_with("fe80:") || host_lower.starts_with("::1") ||
-           host_lower.starts_with("::ffff:") {
-            return Err(DownloaderError::DownloadFailed(
-                "Private network addresses are not allowed".to_string()
-            ));
+    // (url already turns numeric forms like 0x7f.1 into plain IPs). Names
+    // are checked again against what they resolve to when connecting.
+    let ip = match parsed.host() {
+        Some(url::Host::Ipv4(ip)) => Some(std::net::IpAddr::V4(ip)),
+        Some(url::Host::Ipv6(ip)) => Some(std::net::IpAddr::V6(ip)),
+        Some(url::Host::Domain(domain)) => {
+            if lan::is_local_hostname(domain) && !lan::is_trusted_host(domain) {
+                return Err(DownloaderError::DownloadFailed(
+                    "Local hostnames are not allowed".to_string()
+                ));
+            }
+            None
         }
+        None => None,
+    };
 
-        // Block local hostname variants
-        if host_lower.contains("localhost") || host_lower.contains(".local") {
-            return Err(DownloaderError::DownloadFailed(
-                "Local hostnames are not allowed".to_string()
-            ));
-        }
+    if let Some(ip) = ip.filter(|ip| lan::is_blocked_ip(*ip)) {
+        return Err(DownloaderError::DownloadFailed(
+            if ip.is_loopback() || ip.is_unspecified() {
+                "Localhost addresses are not allowed"
+            } else {
+                "Private network addresses are not allowed"
+            }
+            .to_string()
+        ));
     }
 
     Ok(url.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracted_sources_record_their_page_embed_and_time() {
+        let embedded = VideoSource {
+            url: "https://cdn.example/720/index.m3u8".to_string(),
+            quality: "720p".to_string(),
+            source_type: "hls".to_string(),
+            embed_url: Some("https://Player.example/embed/1".to_string()),
+            ..Default::default()
+        };
+        let info = build_video_info("https://site.example/ep-1", String::new(), String::new(), &[embedded]);
+
+        let origin = info.sources[0].origin();
+        assert_eq!(origin.page_url.as_deref(), Some("https://site.example/ep-1"));
+        assert_eq!(origin.embed_host.as_deref(), Some("player.example"));
+        assert!(origin.age().unwrap() < std::time::Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,222 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use super::{long_path, sanitize_filename, DownloaderError};
+
+// Output layouts (AppSettings::output_layout)
+pub const LAYOUT_FLAT: &str = "flat";
+pub const LAYOUT_MEDIA_SERVER: &str = "media_server";
+
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "{title}";
+
+/// Series/season/episode metadata for an item
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct EpisodeInfo {
+    pub series: String,
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+}
+
+/// Extract series/season/episode from a page title. Understands Thai and
+/// English markers: "ตอนที่ 12", "ตอน 12", "EP.12", "Episode 12", "ภาค 2",
+/// "ซีซั่น 2", "Season 2", "SS2" and "S02E12". Thai digits (๑๒) are accepted.
+/// Returns None when the title has neither a season nor an episode marker.
+pub fn parse_episode_title(title: &str) -> Option<EpisodeInfo> {
+    let title = normalize_thai_digits(title);
+
+    let combined_re = Regex::new(r"(?i)\bS(\d{1,2})\s*E(\d{1,4})\b").unwrap();
+    let episode_re = Regex::new(r"(?i)(?:ตอนที่|ตอน|\bEpisode|\bEP)\s*\.?\s*(\d{1,4})").unwrap();
+    let season_re = Regex::new(r"(?i)(?:ภาคที่|ภาค|ซีซั่น|ซีซัน|\bSeason|\bSS)\s*\.?\s*(\d{1,2})").unwrap();
+
+    let mut season = None;
+    let mut episode = None;
+    let mut markers: Vec<(usize, usize)> = Vec::new();
+
+    if let Some(cap) = combined_re.captures(&title) {
+        season = cap[1].parse().ok();
+        episode = cap[2].parse().ok();
+        let m = cap.get(0).unwrap();
+        markers.push((m.start(), m.end()));
+    }
+
+    if episode.is_none() {
+        if let Some(cap) = episode_re.captures(&title) {
+            episode = cap[1].parse().ok();
+            let m = cap.get(0).unwrap();
+            markers.push((m.start(), m.end()));
+        }
+    }
+
+    if season.is_none() {
+        if let Some(cap) = season_re.captures(&title) {
+            season = cap[1].parse().ok();
+            let m = cap.get(0).unwrap();
+            markers.push((m.start(), m.end()));
+        }
+    }
+
+    if season.is_none() && episode.is_none() {
+        return None;
+    }
+
+    // The series name is whatever precedes the first marker; titles that
+    // start with the marker ("EP.3 ชื่อเรื่อง") use the text after it
+    markers.sort();
+    let first_start = markers[0].0;
+    let mut series = trim_separators(&title[..first_start]);
+    if series.is_empty() {
+        let mut rest = title.clone();
+        for (start, end) in markers.iter().rev() {
+            rest.replace_range(*start..*end, " ");
+        }
+        series = trim_separators(rest.split(['|', '–', '—']).next().unwrap_or(""));
+    }
+
+    Some(EpisodeInfo {
+        series,
+        season,
+        episode,
+    })
+}
+
+/// Expand `{title}`, `{series}`, `{season}` and `{episode}` placeholders.
+/// Season/episode are zero-padded to two digits and left empty when unknown.
+pub fn apply_filename_template(template: &str, title: &str, episode: Option<&EpisodeInfo>) -> String {
+    let series = episode
+        .map(|e| e.series.as_str())
+        .filter(|s| !s.is_empty())
+        .unwrap_or(title);
+    let season = episode
+        .and_then(|e| e.season)
+        .map(|n| format!("{:02}", n))
+        .unwrap_or_default();
+    let episode_num = episode
+        .and_then(|e| e.episode)
+        .map(|n| format!("{:02}", n))
+        .unwrap_or_default();
+
+    let result = template
+        .replace("{title}", title)
+        .replace("{series}", series)
+        .replace("{season}", &season)
+        .replace("{episode}", &episode_num);
+
+    trim_separators(&result)
+}
+
+fn normalize_thai_digits(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '\u{0E50}'..='\u{0E59}' => char::from(b'0' + (c as u32 - 0x0E50) as u8),
+            _ => c,
+        })
+        .collect()
+}
+
+fn trim_separators(s: &str) -> String {
+    s.trim_matches(|c: char| c.is_whitespace() || matches!(c, '-' | '|' | '–' | '—' | ':' | '.' | ',' | '(' | '['))
+        .to_string()
+}
+
+/// Metadata written into .nfo files
+pub struct NfoMetadata<'a> {
+    pub title: &'a str,
+    pub source_url: &'a str,
+    pub thumbnail: &'a str,
+}
+
+/// Resolve the directory and base filename for a download.
+///
+/// With the media server layout, episodes go to
+/// `<dir>/<Series>/Season 01/<Series> - S01E05` and movies to
+/// `<dir>/<Title>/<Title>`, matching what Jellyfin/Plex scanners expect.
+/// The flat layout returns the inputs unchanged.
+pub fn resolve_output(
+    layout: &str,
+    output_dir: &str,
+    filename: &str,
+    episode: Option<&EpisodeInfo>,
+) -> Result<(String, String), DownloaderError> {
+    if layout != LAYOUT_MEDIA_SERVER {
+        return Ok((output_dir.to_string(), filename.to_string()));
+    }
+
+    let (dir, name) = match episode {
+        Some(info) if info.episode.is_some() && !sanitize_filename(&info.series).is_empty() => {
+            let series = sanitize_filename(&info.series);
+            let season = info.season.unwrap_or(1);
+            let episode = info.episode.unwrap_or(1);
+            (
+                PathBuf::from(output_dir).join(&series).join(format!("Season {:02}", season)),
+                format!("{} - S{:02}E{:02}", series, season, episode),
+            )
+        }
+        _ => {
+            let title = sanitize_filename(filename);
+            let title = if title.is_empty() { "video".to_string() } else { title };
+            (PathBuf::from(output_dir).join(&title), title)
+        }
+    };
+
+    std::fs::create_dir_all(long_path(&dir))?;
+
+    Ok((dir.to_string_lossy().to_string(), name))
+}
+
+/// Write Kodi-style .nfo sidecars next to a finished download: an
+/// `episodedetails` file per episode plus a `tvshow.nfo` in the series
+/// folder, or a `movie` file when there is no episode number.
+pub fn write_nfo(
+    video_path: &Path,
+    episode: Option<&EpisodeInfo>,
+    meta: &NfoMetadata,
+) -> Result<PathBuf, DownloaderError> {
+    let nfo_path = video_path.with_extension("nfo");
+    let date_added = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+    let body = match episode {
+        Some(info) if info.episode.is_some() => {
+            // tvshow.nfo lives in the series folder, one level above "Season NN"
+            if let Some(series_dir) = video_path.parent().and_then(|p| p.parent()) {
+                let tvshow_path = series_dir.join("tvshow.nfo");
+                if !tvshow_path.exists() {
+                    let tvshow = format!(
+                        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<tvshow>\n  <title>{}</title>\n</tvshow>\n",
+                        xml_escape(&info.series)
+                    );
+                    std::fs::write(long_path(&tvshow_path), tvshow)?;
+                }
+            }
+
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<episodedetails>\n  <title>{}</title>\n  <showtitle>{}</showtitle>\n  <season>{}</season>\n  <episode>{}</episode>\n  <thumb>{}</thumb>\n  <dateadded>{}</dateadded>\n  <!-- source: {} -->\n</episodedetails>\n",
+                xml_escape(meta.title),
+                xml_escape(&info.series),
+                info.season.unwrap_or(1),
+                info.episode.unwrap_or(1),
+                xml_escape(meta.thumbnail),
+                date_added,
+                xml_escape(meta.source_url).replace("--", "%2D%2D"),
+            )
+        }
+        _ => format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<movie>\n  <title>{}</title>\n  <thumb>{}</thumb>\n  <dateadded>{}</dateadded>\n  <!-- source: {} -->\n</movie>\n",
+            xml_escape(meta.title),
+            xml_escape(meta.thumbnail),
+            date_added,
+            xml_escape(meta.source_url).replace("--", "%2D%2D"),
+        ),
+    };
+
+    std::fs::write(long_path(&nfo_path), body)?;
+    Ok(nfo_path)
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+use super::rules;
+use super::USER_AGENT;
+
+// Export formats (export_playlist `format`)
+pub const FORMAT_M3U: &str = "m3u";
+pub const FORMAT_STRM: &str = "strm";
+
+/// One playable link: the sniffed stream plus the page it came from, which
+/// CDNs usually require as the referer
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlaylistEntry {
+    pub title: String,
+    pub page_url: String,
+    pub source_url: String,
+}
+
+impl PlaylistEntry {
+    /// Referer, user agent and any site rule headers for this entry
+    fn headers(&self) -> Vec<(String, String)> {
+        let mut headers = vec![("User-Agent".to_string(), USER_AGENT.to_string())];
+        if !self.page_url.is_empty() {
+            headers.push(("Referer".to_string(), self.page_url.clone()));
+        }
+        if let Some(rule) = rules::rule_for(&self.page_url) {
+            headers.extend(rule.header_list());
+        }
+        headers
+    }
+}
+
+/// Extended M3U with VLC options carrying the headers, one entry per link
+pub fn to_m3u(entries: &[PlaylistEntry]) -> String {
+    let mut out = String::from("#EXTM3U\n");
+
+    for entry in entries {
+        out.push_str(&format!("#EXTINF:-1,{}\n", entry.title.replace(['\r', '\n'], " ")));
+        for (name, value) in entry.headers() {
+            match name.as_str() {
+                "User-Agent" => out.push_str(&format!("#EXTVLCOPT:http-user-agent={}\n", value)),
+                "Referer" => out.push_str(&format!("#EXTVLCOPT:http-referrer={}\n", value)),
+                _ => {}
+            }
+        }
+        out.push_str(&entry.source_url);
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Kodi .strm content: the URL with headers appended after `|` as
+/// URL-encoded `Name=value` pairs
+pub fn to_strm(entry: &PlaylistEntry) -> String {
+    let headers: Vec<String> = entry
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            let value: String = url::form_urlencoded::byte_serialize(value.as_bytes()).collect();
+            format!("{}={}", name, value)
+        })
+        .collect();
+
+    format!("{}|{}\n", entry.source_url, headers.join("&"))
+}
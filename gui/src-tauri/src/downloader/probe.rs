@@ -0,0 +1,421 @@
+use m3u8_rs::{MediaPlaylist, Playlist};
+use reqwest::Client;
+use std::cmp::Reverse;
+use std::time::{Duration, Instant};
+use url::Url;
+
+use super::ads;
+use super::audio;
+use super::container;
+use super::ffmpeg::{self, probe_video_stream};
+use super::hls::{best_variant, build_request};
+use super::dns;
+use super::size::{content_length, total_length};
+use super::watchdog;
+use super::{quality_list, DownloaderError, VideoInfo, VideoSource, USER_AGENT};
+
+// Sources are probed concurrently; a slow CDN just keeps its URL-based label
+const PROBE_TIMEOUT: Duration = Duration::from_secs(8);
+// Preview frames are taken past fade-ins and intro logos, small enough to
+// send to the UI inline
+const PREVIEW_SEEK_SECONDS: f64 = 5.0;
+const PREVIEW_WIDTH: u32 = 480;
+const PREVIEW_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Replace URL-guessed qualities with the real resolution from the stream
+/// and fill in codec, container and bitrate: the attributes of the variant
+/// HLS would download, else ffprobe on the media itself
+pub async fn label_sources(info: &mut VideoInfo, referer: Option<&str>, headers: &[(String, String)]) {
+    let Ok(client) = dns::client_builder().build() else {
+        return;
+    };
+
+    let details = futures::future::join_all(info.sources.iter().map(|source| {
+        let client = client.clone();
+        async move {
+            tokio::time::timeout(PROBE_TIMEOUT, probe_source(&client, source, referer, headers))
+                .await
+                .unwrap_or_default()
+        }
+    }))
+    .await;
+
+    let mut changed = false;
+    for (source, details) in info.sources.iter_mut().zip(details) {
+        if let Some(height) = details.height {
+            source.quality = format!("{}p", height);
+            changed = true;
+        }
+        source.codec = details.codec.or(source.codec.take());
+        source.container = details.container.or(source.container.take());
+        source.bandwidth = details.bandwidth.or(source.bandwidth);
+    }
+
+    if changed {
+        info.qualities = quality_list(&info.sources);
+    }
+}
+
+/// One JPEG frame of a source, so the user can tell the real video from an
+/// ad mirror before downloading. Clips shorter than the seek point are shot
+/// at their first frame instead.
+pub async fn preview_frame(
+    source_url: &str,
+    referer: Option<&str>,
+    headers: &[(String, String)],
+) -> Result<Vec<u8>, DownloaderError> {
+    let capture = |seek: f64| ffmpeg::capture_frame(source_url, seek, PREVIEW_WIDTH, USER_AGENT, referer, headers);
+    watchdog::within(Some(PREVIEW_TIMEOUT), "Preview frame", async {
+        match capture(PREVIEW_SEEK_SECONDS).await {
+            Err(DownloaderError::Parse(_)) => capture(0.0).await,
+            result => result,
+        }
+    })
+    .await
+}
+
+/// Drop sources that look like ads rather than the video: HLS playlists
+/// under a minute and direct files of a few MB. Nothing is dropped when
+/// every source looks like that; the video may just be short.
+pub async fn drop_ad_streams(info: &mut VideoInfo, referer: Option<&str>, headers: &[(String, String)]) {
+    if info.sources.len() < 2 {
+        return;
+    }
+    let Ok(client) = dns::client_builder().build() else {
+        return;
+    };
+
+    let is_ad = futures::future::join_all(info.sources.iter().map(|source| {
+        let client = client.clone();
+        async move {
+            tokio::time::timeout(PROBE_TIMEOUT, looks_like_ad(&client, source, referer, headers))
+                .await
+                .unwrap_or(false)
+        }
+    }))
+    .await;
+
+    if is_ad.iter().all(|ad| *ad) || !is_ad.iter().any(|ad| *ad) {
+        return;
+    }
+    let mut is_ad = is_ad.into_iter();
+    info.sources.retain(|_| !is_ad.next().unwrap_or(false));
+    info.qualities = quality_list(&info.sources);
+}
+
+async fn looks_like_ad(client: &Client, source: &VideoSource, referer: Option<&str>, headers: &[(String, String)]) -> bool {
+    if source.source_type == "hls" || source.url.contains(".m3u8") {
+        // Only a finished playlist has a known length
+        match media_playlist(client, &source.url, referer, headers).await {
+            Some(media) if media.end_list => {
+                let seconds: f64 = media.segments.iter().map(|s| s.duration as f64).sum();
+                seconds < ads::MIN_CONTENT_SECONDS
+            }
+            _ => false,
+        }
+    } else {
+        content_length(client, &source.url, referer, headers)
+            .await
+            .map(|bytes| bytes < ads::MIN_CONTENT_BYTES)
+            .unwrap_or(false)
+    }
+}
+
+/// Request every source concurrently, drop the ones the server refuses and
+/// fill in size, duration and response time. The rest are sorted best
+/// first: highest resolution, then quickest to answer. Nothing is dropped
+/// when every source is refused.
+pub async fn check_sources(info: &mut VideoInfo, referer: Option<&str>, headers: &[(String, String)]) {
+    let Ok(client) = dns::client_builder().build() else {
+        return;
+    };
+
+    let checks = futures::future::join_all(info.sources.iter().map(|source| {
+        let client = client.clone();
+        async move {
+            tokio::time::timeout(PROBE_TIMEOUT, check_source(&client, source, referer, headers))
+                .await
+                .unwrap_or_default()
+        }
+    }))
+    .await;
+
+    if checks.iter().all(|c| c.refused) {
+        return;
+    }
+    let mut checks = checks.into_iter();
+    info.sources.retain_mut(|source| {
+        let check = checks.next().unwrap_or_default();
+        source.size = check.size.or(source.size);
+        source.duration = check.duration.or(source.duration);
+        source.latency_ms = check.latency_ms;
+        !check.refused
+    });
+
+    info.sources.sort_by_key(|s| {
+        let height: u32 = s.quality.trim_end_matches('p').parse().unwrap_or(0);
+        (Reverse(height), s.latency_ms.unwrap_or(u64::MAX))
+    });
+    info.qualities = quality_list(&info.sources);
+}
+
+/// What a quick request to one source showed
+#[derive(Default)]
+struct SourceCheck {
+    /// The server answered with an error or something that isn't media;
+    /// no answer at all leaves this false
+    refused: bool,
+    size: Option<u64>,
+    duration: Option<f64>,
+    latency_ms: Option<u64>,
+}
+
+/// How a server answered one probe request
+enum Reply {
+    Answered(reqwest::Response),
+    /// HTTP error status: the link is dead or blocked
+    Refused,
+    /// No response at all, which says nothing about the link
+    Silent,
+}
+
+async fn send(client: &Client, url: &str, referer: Option<&str>, headers: &[(String, String)], range: bool) -> Reply {
+    // A URL validate_url refuses will never be downloaded either
+    let Ok(mut request) = build_request(client, url, referer, headers) else {
+        return Reply::Refused;
+    };
+    if range {
+        request = request.header("Range", "bytes=0-0");
+    }
+    match request.send().await {
+        Ok(response) if response.status().is_client_error() || response.status().is_server_error() => Reply::Refused,
+        Ok(response) => Reply::Answered(response),
+        Err(_) => Reply::Silent,
+    }
+}
+
+/// One-byte request for direct files; for HLS the playlist HlsDownloader
+/// would use and its first segment
+async fn check_source(
+    client: &Client,
+    source: &VideoSource,
+    referer: Option<&str>,
+    headers: &[(String, String)],
+) -> SourceCheck {
+    let started = Instant::now();
+    let refused = SourceCheck { refused: true, ..Default::default() };
+    let mut check = SourceCheck::default();
+
+    if !(source.source_type == "hls" || source.url.contains(".m3u8")) {
+        return match send(client, &source.url, referer, headers, true).await {
+            Reply::Answered(response) => SourceCheck {
+                size: total_length(&response),
+                latency_ms: Some(started.elapsed().as_millis() as u64),
+                ..check
+            },
+            Reply::Refused => refused,
+            Reply::Silent => check,
+        };
+    }
+
+    let Ok(mut url) = Url::parse(&source.url) else {
+        return refused;
+    };
+    let mut bandwidth = None;
+
+    // At most one master -> media hop
+    for _ in 0..2 {
+        let content = match send(client, url.as_str(), referer, headers, false).await {
+            Reply::Answered(response) => match response.bytes().await {
+                Ok(content) => content,
+                Err(_) => return check,
+            },
+            Reply::Refused => return refused,
+            Reply::Silent => return check,
+        };
+        check.latency_ms = check.latency_ms.or(Some(started.elapsed().as_millis() as u64));
+
+        // A 200 with an HTML error page instead of a playlist is as dead as a 404
+        let Ok(playlist) = m3u8_rs::parse_playlist_res(&content) else {
+            return refused;
+        };
+        match playlist {
+            Playlist::MasterPlaylist(master) => {
+                let Ok(best) = best_variant(&master) else {
+                    return refused;
+                };
+                bandwidth = Some(best.bandwidth);
+                let Ok(next) = url.join(&best.uri) else {
+                    return refused;
+                };
+                url = next;
+            }
+            Playlist::MediaPlaylist(media) => {
+                let Some(first) = media.segments.first() else {
+                    return refused;
+                };
+                let seconds: f64 = media.segments.iter().map(|s| s.duration as f64).sum();
+                check.duration = media.end_list.then_some(seconds);
+
+                let Ok(first) = url.join(&first.uri) else {
+                    return refused;
+                };
+                let segment_size = match send(client, first.as_str(), referer, headers, true).await {
+                    Reply::Answered(response) => total_length(&response),
+                    Reply::Refused => return refused,
+                    Reply::Silent => None,
+                };
+                // A live playlist keeps growing, so it has no size yet
+                check.size = match bandwidth {
+                    _ if !media.end_list => None,
+                    Some(bandwidth) => Some((bandwidth as f64 / 8.0 * seconds) as u64),
+                    None => segment_size.map(|size| size * media.segments.len() as u64),
+                };
+                return check;
+            }
+        }
+    }
+
+    check
+}
+
+/// The media playlist HlsDownloader would use, following one master hop
+async fn media_playlist(
+    client: &Client,
+    playlist_url: &str,
+    referer: Option<&str>,
+    headers: &[(String, String)],
+) -> Option<MediaPlaylist> {
+    let mut url = Url::parse(playlist_url).ok()?;
+    for _ in 0..2 {
+        let content = build_request(client, url.as_str(), referer, headers)
+            .ok()?
+            .send().await.ok()?
+            .bytes().await.ok()?;
+        match m3u8_rs::parse_playlist_res(&content).ok()? {
+            Playlist::MasterPlaylist(master) => url = url.join(&best_variant(&master).ok()?.uri).ok()?,
+            Playlist::MediaPlaylist(media) => return Some(media),
+        }
+    }
+    None
+}
+
+/// Fill `audio_tracks` from the first HLS source's master playlist
+pub async fn list_audio_tracks(info: &mut VideoInfo, referer: Option<&str>, headers: &[(String, String)]) {
+    let Some(source) = info.sources.iter().find(|s| s.source_type == "hls" || s.url.contains(".m3u8")) else {
+        return;
+    };
+    let Ok(client) = dns::client_builder().build() else {
+        return;
+    };
+
+    let Ok(request) = build_request(&client, &source.url, referer, headers) else {
+        return;
+    };
+    let request = request.send();
+    let Ok(Ok(response)) = tokio::time::timeout(PROBE_TIMEOUT, request).await else {
+        return;
+    };
+    if let Ok(content) = response.bytes().await {
+        if let Ok(Playlist::MasterPlaylist(master)) = m3u8_rs::parse_playlist_res(&content) {
+            info.audio_tracks = audio::list_tracks(&master);
+        }
+    }
+}
+
+/// What probing learned about one source
+#[derive(Default)]
+struct SourceDetails {
+    height: Option<u32>,
+    codec: Option<String>,
+    container: Option<String>,
+    bandwidth: Option<u64>,
+}
+
+async fn probe_source(
+    client: &Client,
+    source: &VideoSource,
+    referer: Option<&str>,
+    headers: &[(String, String)],
+) -> SourceDetails {
+    let (mut details, media_url) = if source.source_type == "hls" || source.url.contains(".m3u8") {
+        match hls_details(client, &source.url, referer, headers).await {
+            Some(found) => found,
+            None => return SourceDetails::default(),
+        }
+    } else {
+        let container = container::extension_from_url(&source.url).map(str::to_string);
+        (SourceDetails { container, ..Default::default() }, Some(source.url.clone()))
+    };
+
+    // ffprobe only for what the playlist didn't say
+    if details.height.is_none() || details.codec.is_none() {
+        if let Some(media_url) = media_url {
+            if let Ok(stream) = probe_video_stream(&media_url, USER_AGENT, referer, headers).await {
+                details.height = details.height.or(Some(stream.height));
+                details.codec = details.codec.or(normalize_codec(&stream.codec));
+            }
+        }
+    }
+    details
+}
+
+/// Details from the variant HlsDownloader would pick, plus the first
+/// segment's URL for ffprobe
+async fn hls_details(
+    client: &Client,
+    playlist_url: &str,
+    referer: Option<&str>,
+    headers: &[(String, String)],
+) -> Option<(SourceDetails, Option<String>)> {
+    let mut url = Url::parse(playlist_url).ok()?;
+    let mut details = SourceDetails::default();
+
+    // At most one master -> media hop
+    for _ in 0..2 {
+        let content = build_request(client, url.as_str(), referer, headers)
+            .ok()?
+            .send().await.ok()?
+            .bytes().await.ok()?;
+        match m3u8_rs::parse_playlist_res(&content).ok()? {
+            Playlist::MasterPlaylist(master) => {
+                // Same choice as HlsDownloader
+                let best = best_variant(&master).ok()?;
+                details.height = best.resolution.as_ref().map(|r| r.height as u32);
+                details.codec = best.codecs.as_deref().and_then(normalize_codec);
+                details.bandwidth = Some(best.bandwidth);
+                url = url.join(&best.uri).ok()?;
+            }
+            Playlist::MediaPlaylist(media) => {
+                let Some(first) = media.segments.first() else {
+                    return Some((details, None));
+                };
+                let is_fmp4 = first.map.is_some() || [".m4s", ".mp4"].iter().any(|e| first.uri.contains(e));
+                details.container = Some(if is_fmp4 { "fmp4" } else { "ts" }.to_string());
+                let first = url.join(&first.uri).ok().map(|u| u.to_string());
+                return Some((details, first));
+            }
+        }
+    }
+
+    None
+}
+
+/// Short codec name from an HLS CODECS attribute ("avc1.64001f,mp4a.40.2")
+/// or an ffprobe codec name; None for audio-only lists
+pub fn normalize_codec(codecs: &str) -> Option<String> {
+    codecs.split(',').find_map(|codec| {
+        let codec = codec.trim().to_lowercase();
+        let family = codec.split('.').next().unwrap_or_default();
+        let name = match family {
+            "avc1" | "avc3" | "h264" => "h264",
+            "hvc1" | "hev1" | "hevc" | "h265" => "h265",
+            "av01" | "av1" => "av1",
+            "vp09" | "vp9" => "vp9",
+            "vp8" => "vp8",
+            "mpeg2video" => "mpeg2",
+            _ => return None,
+        };
+        Some(name.to_string())
+    })
+}
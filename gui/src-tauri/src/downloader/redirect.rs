@@ -0,0 +1,84 @@
+use url::Url;
+
+/// Cookies set by the hosts of a redirect chain, so token gateways that
+/// hand out a session cookie before the final 302 still work
+#[derive(Default)]
+pub struct CookieJar {
+    cookies: Vec<StoredCookie>,
+}
+
+struct StoredCookie {
+    domain: String,
+    /// No Domain attribute: only the host that set it gets it back
+    host_only: bool,
+    name: String,
+    value: String,
+}
+
+impl StoredCookie {
+    fn applies_to(&self, host: &str) -> bool {
+        host == self.domain || (!self.host_only && host.ends_with(&format!(".{}", self.domain)))
+    }
+}
+
+impl CookieJar {
+    /// Remember a Set-Cookie header received from `url`. Cookies for
+    /// another site and deletions (Max-Age=0) are handled like a browser.
+    pub fn store(&mut self, url: &Url, set_cookie: &str) {
+        let Some(host) = url.host_str().map(str::to_lowercase) else {
+            return;
+        };
+        let mut parts = set_cookie.split(';');
+        let Some((name, value)) = parts.next().and_then(|p| p.split_once('=')) else {
+            return;
+        };
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            return;
+        }
+
+        let mut domain = None;
+        let mut expired = false;
+        for attribute in parts {
+            let (key, val) = attribute.split_once('=').unwrap_or((attribute, ""));
+            match key.trim().to_lowercase().as_str() {
+                "domain" => domain = Some(val.trim().trim_start_matches('.').to_lowercase()),
+                "max-age" => expired = val.trim().parse::<i64>().map(|age| age <= 0).unwrap_or(false),
+                _ => {}
+            }
+        }
+
+        let (domain, host_only) = match domain {
+            Some(d) if !d.is_empty() => {
+                if host != d && !host.ends_with(&format!(".{}", d)) {
+                    return;
+                }
+                (d, false)
+            }
+            _ => (host, true),
+        };
+
+        self.cookies.retain(|c| !(c.domain == domain && c.name == name));
+        if !expired {
+            self.cookies.push(StoredCookie { domain, host_only, name, value: value.trim().to_string() });
+        }
+    }
+
+    /// Cookie header value for a request to `url`, if any cookie applies
+    pub fn header_for(&self, url: &Url) -> Option<String> {
+        let host = url.host_str()?.to_lowercase();
+        let pairs: Vec<String> = self
+            .cookies
+            .iter()
+            .filter(|c| c.applies_to(&host))
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect();
+        (!pairs.is_empty()).then(|| pairs.join("; "))
+    }
+}
+
+/// "https://host[:port]" of a URL, the Origin a browser pairs with it as referer
+pub fn origin_of(url: &str) -> Option<String> {
+    let origin = Url::parse(url).ok()?.origin();
+    origin.is_tuple().then(|| origin.ascii_serialization())
+}
@@ -0,0 +1,128 @@
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+use super::ads::AdPattern;
+use super::dns;
+use super::rules::ExtractorRule;
+
+/// Rule set published with the project; the signature sits next to it
+/// as `<url>.sig`
+pub const DEFAULT_MANIFEST_URL: &str =
+    "https://raw.githubusercontent.com/TheerasakPing/thai-video-downloader/main/rules/manifest.json";
+const SIGNATURE_SUFFIX: &str = ".sig";
+const FETCH_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Ad patterns and extractor rules shipped between releases. Applied after
+/// the user's own rules and patterns, which always win.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteRuleSet {
+    /// Increases with every publish; older manifests are never applied
+    pub version: u64,
+    pub ad_patterns: Vec<String>,
+    pub rules: Vec<ExtractorRule>,
+}
+
+impl RemoteRuleSet {
+    /// Drop entries this build can't use instead of rejecting the whole set
+    fn without_invalid(mut self) -> Self {
+        self.ad_patterns.retain(|p| AdPattern::parse(p).is_ok());
+        self.rules.retain(|r| r.validate().is_ok());
+        self
+    }
+}
+
+/// Check the Ed25519 signature (hex) of the manifest bytes against the
+/// publisher's public key (hex) and parse it
+pub fn verify(manifest: &[u8], signature_hex: &str, public_key_hex: &str) -> Result<RemoteRuleSet, String> {
+    let public_key = hex::decode(public_key_hex.trim()).map_err(|_| "Invalid rule update public key".to_string())?;
+    let signature = hex::decode(signature_hex.trim()).map_err(|_| "Invalid rule update signature".to_string())?;
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(manifest, &signature)
+        .map_err(|_| "Rule update signature doesn't match".to_string())?;
+
+    let rule_set: RemoteRuleSet =
+        serde_json::from_slice(manifest).map_err(|e| format!("Failed to parse rule update: {}", e))?;
+    Ok(rule_set.without_invalid())
+}
+
+/// Download and verify the manifest and its signature
+pub async fn fetch(manifest_url: &str, public_key_hex: &str) -> Result<RemoteRuleSet, String> {
+    if public_key_hex.trim().is_empty() {
+        return Err("Rule updates need the publisher's public key".to_string());
+    }
+    super::validate_url(manifest_url).map_err(|e| e.to_string())?;
+
+    let client = dns::client_builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to fetch rule update: {}", e))?;
+    let get = |url: String| {
+        let client = client.clone();
+        async move {
+            client
+                .get(url)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| format!("Failed to fetch rule update: {}", e))?
+                .bytes()
+                .await
+                .map_err(|e| format!("Failed to fetch rule update: {}", e))
+        }
+    };
+
+    let manifest = get(manifest_url.to_string()).await?;
+    let signature = get(format!("{}{}", manifest_url, SIGNATURE_SUFFIX)).await?;
+    verify(&manifest, &String::from_utf8_lossy(&signature), public_key_hex)
+}
+
+/// The last verified rule set, if one was saved
+pub fn load_saved(path: &Path) -> Option<RemoteRuleSet> {
+    let content = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str::<RemoteRuleSet>(&content).ok().map(RemoteRuleSet::without_invalid)
+}
+
+pub fn save(path: &Path, rule_set: &RemoteRuleSet) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(rule_set)
+        .map_err(|e| format!("Failed to serialize rule update: {}", e))?;
+    std::fs::write(path, content).map_err(|e| format!("Failed to save rule update: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rule_update_needs_a_valid_signature() {
+        use ring::signature::{Ed25519KeyPair, KeyPair};
+
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let public_key = hex::encode(key_pair.public_key().as_ref());
+
+        let manifest = br#"{
+            "version": 7,
+            "ad_patterns": ["*.preroll.example/*", "re:("],
+            "rules": [{ "name": "Example", "url_pattern": "^https://example\\.com/" }, { "name": "Broken", "url_pattern": "(" }]
+        }"#;
+        let signature = hex::encode(key_pair.sign(manifest).as_ref());
+
+        let rule_set = verify(manifest, &signature, &public_key).unwrap();
+        assert_eq!(rule_set.version, 7);
+        assert_eq!(rule_set.ad_patterns, vec!["*.preroll.example/*"]);
+        assert_eq!(rule_set.rules.len(), 1);
+
+        let mut tampered = manifest.to_vec();
+        tampered[20] ^= 1;
+        assert!(verify(&tampered, &signature, &public_key).is_err());
+
+        let other = Ed25519KeyPair::from_pkcs8(
+            Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new()).unwrap().as_ref(),
+        )
+        .unwrap();
+        assert!(verify(manifest, &signature, &hex::encode(other.public_key().as_ref())).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::RwLock;
+
+/// A site description written by users in `rules/*.json`. Each file holds
+/// one rule or an array of rules, e.g.
+///
+/// ```json
+/// {
+///   "name": "Example",
+///   "url_pattern": "^https://(www\\.)?example\\.com/watch/",
+///   "iframe_selector": "#player iframe",
+///   "source_patterns": ["file:\\s*\"([^\"]+\\.m3u8[^\"]*)\""],
+///   "headers": { "Origin": "https://example.com" }
+/// }
+/// ```
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtractorRule {
+    pub name: String,
+    /// Regex matched against the page URL
+    pub url_pattern: String,
+    /// CSS selector for player iframes; all iframes when empty
+    pub iframe_selector: String,
+    /// Regexes run over the page and iframe HTML. The first capture group
+    /// (or the whole match) is the video URL.
+    pub source_patterns: Vec<String>,
+    /// Sent when fetching the page, its iframes and the media itself
+    pub headers: BTreeMap<String, String>,
+}
+
+impl ExtractorRule {
+    pub fn matches(&self, url: &str) -> bool {
+        !self.url_pattern.is_empty()
+            && Regex::new(&self.url_pattern)
+                .map(|re| re.is_match(url))
+                .unwrap_or(false)
+    }
+
+    /// Check that every regex and the selector compile
+    pub fn validate(&self) -> Result<(), String> {
+        Regex::new(&self.url_pattern)
+            .map_err(|e| format!("Invalid url_pattern in rule '{}': {}", self.name, e))?;
+        for pattern in &self.source_patterns {
+            Regex::new(pattern)
+                .map_err(|e| format!("Invalid source pattern in rule '{}': {}", self.name, e))?;
+        }
+        if !self.iframe_selector.is_empty() {
+            scraper::Selector::parse(&self.iframe_selector)
+                .map_err(|e| format!("Invalid iframe_selector in rule '{}': {:?}", self.name, e))?;
+        }
+        Ok(())
+    }
+
+    pub fn header_list(&self) -> Vec<(String, String)> {
+        self.headers.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    }
+}
+
+// Loaded once at startup (and on reload) so every extractor sees the same set
+static RULES: RwLock<Vec<ExtractorRule>> = RwLock::new(Vec::new());
+
+pub fn set_rules(rules: Vec<ExtractorRule>) {
+    if let Ok(mut current) = RULES.write() {
+        *current = rules;
+    }
+}
+
+/// First loaded rule whose pattern matches the URL
+pub fn rule_for(url: &str) -> Option<ExtractorRule> {
+    RULES.read().ok()?.iter().find(|r| r.matches(url)).cloned()
+}
+
+/// Read every `*.json` in `dir`. Invalid files and rules are skipped and
+/// reported in the returned error list instead of failing the whole load.
+pub fn load_rules(dir: &Path) -> (Vec<ExtractorRule>, Vec<String>) {
+    let mut rules = Vec::new();
+    let mut errors = Vec::new();
+
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return (rules, errors);
+    };
+
+    let mut paths: Vec<_> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().map(|e| e.eq_ignore_ascii_case("json")).unwrap_or(false))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let file_name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let parsed = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| parse_rules(&content));
+
+        match parsed {
+            Ok(file_rules) => {
+                for mut rule in file_rules {
+                    if rule.name.is_empty() {
+                        rule.name = file_name.clone();
+                    }
+                    match rule.validate() {
+                        Ok(()) => rules.push(rule),
+                        Err(e) => errors.push(format!("{}: {}", file_name, e)),
+                    }
+                }
+            }
+            Err(e) => errors.push(format!("{}: {}", file_name, e)),
+        }
+    }
+
+    (rules, errors)
+}
+
+fn parse_rules(content: &str) -> Result<Vec<ExtractorRule>, String> {
+    let value: serde_json::Value = serde_json::from_str(content).map_err(|e| e.to_string())?;
+    match value {
+        serde_json::Value::Array(_) => serde_json::from_value(value).map_err(|e| e.to_string()),
+        _ => serde_json::from_value(value).map(|rule| vec![rule]).map_err(|e| e.to_string()),
+    }
+}
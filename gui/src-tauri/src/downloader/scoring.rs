@@ -0,0 +1,77 @@
+use super::VideoSource;
+
+// Source type preference (AppSettings::source_type_preference)
+pub const PREFER_ANY: &str = "any";
+pub const PREFER_DIRECT: &str = "direct";
+pub const PREFER_HLS: &str = "hls";
+
+// H.264 plays on every TV and phone; other codecs only win on other grounds
+const COMPATIBLE_CODEC: &str = "h264";
+
+/// User preferences used to rank sources of the same quality
+#[derive(Clone, Debug)]
+pub struct SourcePreferences {
+    pub source_type: String,
+    pub preferred_hosts: Vec<String>,
+    pub avoided_hosts: Vec<String>,
+}
+
+impl Default for SourcePreferences {
+    fn default() -> Self {
+        Self {
+            source_type: PREFER_ANY.to_string(),
+            preferred_hosts: Vec::new(),
+            avoided_hosts: Vec::new(),
+        }
+    }
+}
+
+impl SourcePreferences {
+    /// Higher is better. Avoided hosts only win when nothing else is left.
+    pub fn score(&self, source: &VideoSource) -> i32 {
+        let mut score = 0;
+
+        let is_hls = source.source_type == "hls" || source.url.contains(".m3u8");
+        match self.source_type.as_str() {
+            PREFER_DIRECT if !is_hls => score += 10,
+            PREFER_HLS if is_hls => score += 10,
+            _ => {}
+        }
+
+        if source.codec.as_deref() == Some(COMPATIBLE_CODEC) {
+            score += 5;
+        }
+
+        if let Some(host) = source.host() {
+            if let Some(rank) = self.preferred_hosts.iter().position(|h| host_matches(&host, h)) {
+                // Earlier entries in the list rank higher
+                score += 100 - rank.min(50) as i32;
+            }
+            if self.avoided_hosts.iter().any(|h| host_matches(&host, h)) {
+                score -= 1000;
+            }
+        }
+
+        score
+    }
+
+    /// Best-scoring candidate, the higher bitrate on equal scores; full
+    /// ties keep the extractor's order so the choice is deterministic
+    pub fn best<'a>(&self, candidates: impl IntoIterator<Item = &'a VideoSource>) -> Option<&'a VideoSource> {
+        candidates
+            .into_iter()
+            .fold(None, |best: Option<(&VideoSource, (i32, u64))>, source| {
+                let score = (self.score(source), source.bandwidth.unwrap_or(0));
+                match best {
+                    Some((_, best_score)) if best_score >= score => best,
+                    _ => Some((source, score)),
+                }
+            })
+            .map(|(source, _)| source)
+    }
+}
+
+fn host_matches(host: &str, pattern: &str) -> bool {
+    let pattern = pattern.trim().trim_start_matches("www.").to_lowercase();
+    !pattern.is_empty() && (host == pattern || host.ends_with(&format!(".{}", pattern)))
+}
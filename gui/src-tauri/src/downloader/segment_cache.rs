@@ -0,0 +1,188 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub const DEFAULT_SEGMENT_CACHE_MB: u64 = 2048;
+
+// Query parameters that only carry expiring auth, so a re-extracted
+// playlist still maps to the same cache entries
+const VOLATILE_PARAMS: &[&str] = &[
+    "token", "expires", "expire", "exp", "e", "st", "sig", "signature", "hash", "hdnts", "hdntl", "policy",
+    "key-pair-id", "auth",
+];
+
+const BLOB_EXT: &str = "seg";
+const REF_EXT: &str = "ref";
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct CacheStats {
+    pub bytes: u64,
+    pub segments: usize,
+}
+
+/// Fetched HLS segments kept in the temp dir so a re-download of the same
+/// video and quality (e.g. after a failed mux) skips the network. Data is
+/// stored once per content hash; a small ref file maps each segment
+/// request to its blob.
+pub struct SegmentCache {
+    dir: PathBuf,
+    /// Bytes kept after trimming; 0 disables the cache
+    max_bytes: AtomicU64,
+}
+
+impl SegmentCache {
+    pub fn new(dir: PathBuf, max_mb: u64) -> Self {
+        Self {
+            dir,
+            max_bytes: AtomicU64::new(max_mb * 1024 * 1024),
+        }
+    }
+
+    /// Cache under the system temp dir
+    pub fn in_temp_dir(max_mb: u64) -> Self {
+        Self::new(std::env::temp_dir().join("thai-video-downloader-segments"), max_mb)
+    }
+
+    pub fn set_max_mb(&self, max_mb: u64) {
+        self.max_bytes.store(max_mb * 1024 * 1024, Ordering::Relaxed);
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.max_bytes.load(Ordering::Relaxed) > 0
+    }
+
+    fn ref_path(&self, url: &str, range: Option<(u64, u64)>) -> PathBuf {
+        let key = match range {
+            Some((start, end)) => format!("{}#{}-{}", request_key(url), start, end),
+            None => request_key(url),
+        };
+        self.dir.join(format!("{}.{}", sha256_hex(key.as_bytes()), REF_EXT))
+    }
+
+    /// Cached data for a segment request, if any
+    pub async fn get(&self, url: &str, range: Option<(u64, u64)>) -> Option<bytes::Bytes> {
+        if !self.enabled() {
+            return None;
+        }
+        let ref_path = self.ref_path(url, range);
+        let hash = tokio::fs::read_to_string(&ref_path).await.ok()?;
+        let blob = self.dir.join(format!("{}.{}", hash.trim(), BLOB_EXT));
+        match tokio::fs::read(&blob).await {
+            // Check the content still matches its address
+            Ok(data) if sha256_hex(&data) == hash.trim() => {
+                touch(&blob);
+                Some(bytes::Bytes::from(data))
+            }
+            _ => {
+                tokio::fs::remove_file(&ref_path).await.ok();
+                None
+            }
+        }
+    }
+
+    /// Remember a fetched (decrypted) segment. Best effort: a cache that
+    /// can't be written only costs a refetch later.
+    pub async fn put(&self, url: &str, range: Option<(u64, u64)>, data: &[u8]) {
+        if !self.enabled() || tokio::fs::create_dir_all(&self.dir).await.is_err() {
+            return;
+        }
+        let hash = sha256_hex(data);
+        let blob = self.dir.join(format!("{}.{}", hash, BLOB_EXT));
+        if !blob.exists() {
+            // Write then rename so a reader never sees half a blob
+            let temp = blob.with_extension("tmp");
+            if tokio::fs::write(&temp, data).await.is_err() || tokio::fs::rename(&temp, &blob).await.is_err() {
+                tokio::fs::remove_file(&temp).await.ok();
+                return;
+            }
+        }
+        tokio::fs::write(self.ref_path(url, range), &hash).await.ok();
+    }
+
+    /// Drop least recently used blobs until the cache fits its limit
+    pub async fn trim(&self) {
+        let max_bytes = self.max_bytes.load(Ordering::Relaxed);
+        let dir = self.dir.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut blobs = list(&dir, BLOB_EXT);
+            let mut total: u64 = blobs.iter().map(|(_, size, _)| size).sum();
+            if total <= max_bytes {
+                return;
+            }
+            blobs.sort_by_key(|(_, _, used)| *used);
+            for (path, size, _) in blobs {
+                if total <= max_bytes {
+                    break;
+                }
+                if std::fs::remove_file(&path).is_ok() {
+                    total -= size;
+                }
+            }
+            // Refs to removed blobs are dropped lazily by get()
+        })
+        .await
+        .ok();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let blobs = list(&self.dir, BLOB_EXT);
+        CacheStats {
+            bytes: blobs.iter().map(|(_, size, _)| size).sum(),
+            segments: blobs.len(),
+        }
+    }
+
+    /// Delete everything; returns the bytes freed
+    pub fn purge(&self) -> Result<u64, String> {
+        let freed = self.stats().bytes;
+        if self.dir.exists() {
+            std::fs::remove_dir_all(&self.dir).map_err(|e| format!("Failed to purge segment cache: {}", e))?;
+        }
+        Ok(freed)
+    }
+}
+
+/// URL without auth parameters that change on every extraction
+fn request_key(url: &str) -> String {
+    let Ok(mut parsed) = url::Url::parse(url) else {
+        return url.to_string();
+    };
+    let kept: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter(|(name, _)| !VOLATILE_PARAMS.contains(&name.to_lowercase().as_str()))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    parsed.set_fragment(None);
+    if kept.is_empty() {
+        parsed.set_query(None);
+    } else {
+        parsed.query_pairs_mut().clear().extend_pairs(kept);
+    }
+    parsed.to_string()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(ring::digest::digest(&ring::digest::SHA256, data))
+}
+
+/// Mark a blob as recently used for trimming
+fn touch(path: &Path) {
+    if let Ok(file) = std::fs::File::options().append(true).open(path) {
+        file.set_modified(std::time::SystemTime::now()).ok();
+    }
+}
+
+/// Files with `ext`: path, size and last use
+fn list(dir: &Path, ext: &str) -> Vec<(PathBuf, u64, std::time::SystemTime)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|x| x == ext))
+        .filter_map(|e| {
+            let meta = e.metadata().ok()?;
+            Some((e.path(), meta.len(), meta.modified().ok()?))
+        })
+        .collect()
+}
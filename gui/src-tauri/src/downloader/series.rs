@@ -0,0 +1,157 @@
+use std::collections::{HashSet, VecDeque};
+
+use regex::Regex;
+use reqwest::Client;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::dns;
+use super::{is_ad_url, validate_url, DownloaderError};
+
+// Index pages followed per series unless settings say otherwise
+pub const DEFAULT_MAX_PAGES: usize = 5;
+// Upper bound for the setting, so a runaway pager can't crawl a whole site
+pub const MAX_PAGES_LIMIT: usize = 50;
+
+/// One episode link found on a series index page
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SeriesEpisode {
+    pub url: String,
+    pub title: String,
+    /// Index page (1-based, in crawl order) the link was found on
+    pub page: usize,
+}
+
+/// Collects episode links from a series index page, following its
+/// pagination ("หน้า 2", "ถัดไป", /page/2, ?page=2) up to `max_pages` pages
+pub struct SeriesExtractor {
+    client: Client,
+    max_pages: usize,
+}
+
+impl SeriesExtractor {
+    pub fn new(max_pages: usize) -> Self {
+        let client = dns::client_builder()
+            .timeout(std::time::Duration::from_secs(20))
+            .build()
+            .unwrap();
+
+        Self { client, max_pages: max_pages.clamp(1, MAX_PAGES_LIMIT) }
+    }
+
+    pub async fn episodes(&self, url: &str) -> Result<Vec<SeriesEpisode>, DownloaderError> {
+        let validated = validate_url(url)?;
+
+        let mut pending = VecDeque::from([normalize(&validated)]);
+        let mut visited: HashSet<String> = HashSet::new();
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut episodes: Vec<SeriesEpisode> = Vec::new();
+
+        while let Some(page_url) = pending.pop_front() {
+            if visited.len() >= self.max_pages || !visited.insert(page_url.clone()) {
+                continue;
+            }
+
+            // Later pages failing shouldn't lose what earlier ones found
+            let html = match self.fetch(&page_url).await {
+                Ok(html) => html,
+                Err(e) if visited.len() == 1 => return Err(e),
+                Err(_) => continue,
+            };
+            let Ok(base) = Url::parse(&page_url) else {
+                continue;
+            };
+
+            let index = parse_index(&html, &base);
+            for episode in index.episodes {
+                if seen.insert(episode.url.clone()) {
+                    episodes.push(SeriesEpisode { page: visited.len(), ..episode });
+                }
+            }
+            for next in index.pages {
+                if !visited.contains(&next) && !pending.contains(&next) {
+                    pending.push_back(next);
+                }
+            }
+        }
+
+        if episodes.is_empty() {
+            return Err(DownloaderError::Parse("No episode links found on the page".to_string()));
+        }
+        Ok(episodes)
+    }
+
+    async fn fetch(&self, url: &str) -> Result<String, DownloaderError> {
+        // Pagination links come from the page, check them like user input
+        validate_url(url)?;
+        let response = self.client.get(url).send().await?.error_for_status()?;
+        Ok(response.text().await?)
+    }
+}
+
+struct ParsedIndex {
+    episodes: Vec<SeriesEpisode>,
+    pages: Vec<String>,
+}
+
+/// Episode links and pagination links on one index page, both limited to
+/// the page's own site
+fn parse_index(html: &str, base: &Url) -> ParsedIndex {
+    let document = Html::parse_document(html);
+    let selector = Selector::parse("a[href]").unwrap();
+    let site = base.host_str().map(site_host);
+    let episode_pattern = Regex::new(r"(?i)(ตอนที่|ตอน\s*\d|\bep\.?\s*\d|episode|\bep-?\d)").unwrap();
+    let pager_href = Regex::new(r"(?i)(/page/\d+|[?&](page|paged|p)=\d+)").unwrap();
+    let pager_text = Regex::new(r"(?i)^((หน้า|page)\s*)?\d+$|^(ถัดไป|หน้าถัดไป|next)(\s*[»›>]+)?$|^[»›>]+$").unwrap();
+
+    let mut parsed = ParsedIndex { episodes: Vec::new(), pages: Vec::new() };
+
+    for link in document.select(&selector) {
+        let Some(href) = link.value().attr("href") else {
+            continue;
+        };
+        let Ok(resolved) = base.join(href) else {
+            continue;
+        };
+        if !matches!(resolved.scheme(), "http" | "https") || resolved.host_str().map(site_host) != site {
+            continue;
+        }
+        let url = normalize(resolved.as_str());
+        if url == normalize(base.as_str()) || is_ad_url(&url) {
+            continue;
+        }
+
+        let text = link.text().collect::<Vec<_>>().join(" ").split_whitespace().collect::<Vec<_>>().join(" ");
+        let title = link.value().attr("title").map(str::trim).filter(|t| !t.is_empty()).unwrap_or(&text);
+        let is_next = link.value().attr("rel").is_some_and(|rel| rel.split_whitespace().any(|r| r == "next"));
+
+        // Pager hrefs are the surest sign; bare numbers are only pages when
+        // the link doesn't look like an episode itself
+        let is_episode = episode_pattern.is_match(title) || episode_pattern.is_match(resolved.path());
+        let is_page = is_next || pager_href.is_match(resolved.as_str()) || (!is_episode && pager_text.is_match(&text));
+
+        if is_page && !parsed.pages.contains(&url) {
+            parsed.pages.push(url);
+        } else if !is_page && is_episode && !parsed.episodes.iter().any(|e| e.url == url) {
+            parsed.episodes.push(SeriesEpisode { url, title: title.to_string(), page: 0 });
+        }
+    }
+
+    parsed
+}
+
+fn site_host(host: &str) -> String {
+    host.trim_start_matches("www.").to_lowercase()
+}
+
+/// URL without its fragment, so "#comments" links don't count twice
+fn normalize(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(mut parsed) => {
+            parsed.set_fragment(None);
+            parsed.to_string()
+        }
+        Err(_) => url.to_string(),
+    }
+}
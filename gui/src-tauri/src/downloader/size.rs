@@ -0,0 +1,117 @@
+use m3u8_rs::Playlist;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::hls::{best_variant, build_request};
+use super::dns;
+use super::VideoSource;
+
+// Quality value for the size budget mode, e.g. "fit:2.5" (GB)
+pub const FIT_PREFIX: &str = "fit:";
+const BYTES_PER_GB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SizeEstimate {
+    pub url: String,
+    pub quality: String,
+    /// None when the server gave nothing to estimate from
+    pub bytes: Option<u64>,
+}
+
+/// Budget in bytes for a "fit:<GB>" quality value
+pub fn size_budget(quality: &str) -> Option<u64> {
+    let gb: f64 = quality.strip_prefix(FIT_PREFIX)?.trim().parse().ok()?;
+    (gb > 0.0).then_some((gb * BYTES_PER_GB) as u64)
+}
+
+/// Estimate every source concurrently
+pub async fn estimate_sizes(
+    sources: &[VideoSource],
+    referer: Option<&str>,
+    headers: &[(String, String)],
+) -> Vec<SizeEstimate> {
+    let Ok(client) = dns::client_builder().build() else {
+        return Vec::new();
+    };
+
+    futures::future::join_all(sources.iter().map(|source| {
+        let client = client.clone();
+        async move {
+            SizeEstimate {
+                url: source.url.clone(),
+                quality: source.quality.clone(),
+                bytes: estimate_size(&client, source, referer, headers).await,
+            }
+        }
+    }))
+    .await
+}
+
+/// Expected download size: Content-Length for direct files; for HLS the
+/// variant the downloader would pick, from its BANDWIDTH and total duration
+/// (or the first segment's size times the segment count)
+async fn estimate_size(
+    client: &Client,
+    source: &VideoSource,
+    referer: Option<&str>,
+    headers: &[(String, String)],
+) -> Option<u64> {
+    if !(source.source_type == "hls" || source.url.contains(".m3u8")) {
+        return content_length(client, &source.url, referer, headers).await;
+    }
+
+    let mut url = Url::parse(&source.url).ok()?;
+    let mut bandwidth = None;
+
+    // At most one master -> media hop
+    for _ in 0..2 {
+        let content = build_request(client, url.as_str(), referer, headers)
+            .ok()?
+            .send().await.ok()?
+            .bytes().await.ok()?;
+        match m3u8_rs::parse_playlist_res(&content).ok()? {
+            Playlist::MasterPlaylist(master) => {
+                // Same choice as HlsDownloader
+                let best = best_variant(&master).ok()?;
+                bandwidth = Some(best.bandwidth);
+                url = url.join(&best.uri).ok()?;
+            }
+            Playlist::MediaPlaylist(media) => {
+                let seconds: f64 = media.segments.iter().map(|s| s.duration as f64).sum();
+                if let Some(bandwidth) = bandwidth {
+                    return Some((bandwidth as f64 / 8.0 * seconds) as u64);
+                }
+                let first = url.join(&media.segments.first()?.uri).ok()?;
+                let segment = content_length(client, first.as_str(), referer, headers).await?;
+                return Some(segment * media.segments.len() as u64);
+            }
+        }
+    }
+
+    None
+}
+
+pub(super) async fn content_length(
+    client: &Client,
+    url: &str,
+    referer: Option<&str>,
+    headers: &[(String, String)],
+) -> Option<u64> {
+    let mut request = build_request(client, url, referer, headers).ok()?;
+    // HEAD isn't always allowed; a one-byte range reports the full size too
+    request = request.header("Range", "bytes=0-0");
+    let response = request.send().await.ok()?.error_for_status().ok()?;
+    total_length(&response)
+}
+
+/// Full size of the file behind a `Range: bytes=0-0` response
+pub(super) fn total_length(response: &reqwest::Response) -> Option<u64> {
+    response
+        .headers()
+        .get(reqwest::header::CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit('/').next())
+        .and_then(|total| total.parse().ok())
+        .or_else(|| response.content_length().filter(|len| *len > 1))
+}
@@ -0,0 +1,89 @@
+use reqwest::header::{CONTENT_TYPE, REFERER};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::dns;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+// Posters are small; anything bigger isn't a thumbnail
+const MAX_THUMBNAIL_BYTES: usize = 5 * 1024 * 1024;
+
+/// Cache file for a thumbnail URL, named by its SHA-256 so the same poster
+/// shared by queue and history items is stored once
+pub fn cache_path(dir: &Path, thumbnail_url: &str) -> PathBuf {
+    let digest = ring::digest::digest(&ring::digest::SHA256, thumbnail_url.as_bytes());
+    dir.join(format!("{}.img", hex::encode(digest)))
+}
+
+pub fn cached(dir: &Path, thumbnail_url: &str) -> Option<Vec<u8>> {
+    std::fs::read(cache_path(dir, thumbnail_url)).ok()
+}
+
+/// The cached image, downloaded first when it isn't on disk yet. `referer`
+/// is the page the video came from; many CDNs refuse posters without it.
+pub async fn fetch(dir: &Path, thumbnail_url: &str, referer: Option<&str>) -> Result<Vec<u8>, String> {
+    if let Some(bytes) = cached(dir, thumbnail_url) {
+        return Ok(bytes);
+    }
+    super::validate_url(thumbnail_url).map_err(|e| e.to_string())?;
+
+    let client = dns::client_builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to fetch thumbnail: {}", e))?;
+    let mut request = client.get(thumbnail_url);
+    if let Some(referer) = referer {
+        request = request.header(REFERER, referer);
+    }
+    let response = request
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("Failed to fetch thumbnail: {}", e))?;
+
+    let is_image = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with("image/") || v.starts_with("application/octet-stream"))
+        .unwrap_or(true);
+    if !is_image {
+        return Err("Thumbnail URL didn't return an image".to_string());
+    }
+    if response.content_length().unwrap_or(0) as usize > MAX_THUMBNAIL_BYTES {
+        return Err("Thumbnail is too large".to_string());
+    }
+    let bytes = response.bytes().await.map_err(|e| format!("Failed to fetch thumbnail: {}", e))?;
+    if bytes.len() > MAX_THUMBNAIL_BYTES {
+        return Err("Thumbnail is too large".to_string());
+    }
+
+    // Write then rename so a half-written file is never served
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to cache thumbnail: {}", e))?;
+    let path = cache_path(dir, thumbnail_url);
+    let partial = path.with_extension("part");
+    std::fs::write(&partial, &bytes)
+        .and_then(|_| std::fs::rename(&partial, &path))
+        .map_err(|e| format!("Failed to cache thumbnail: {}", e))?;
+    Ok(bytes.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn cached_thumbnails_are_served_without_the_network() {
+        let dir = tempfile::tempdir().unwrap();
+        let poster = "https://thumbs.invalid/poster.jpg";
+        assert_eq!(cache_path(dir.path(), poster), cache_path(dir.path(), poster));
+        assert!(fetch(dir.path(), poster, None).await.is_err());
+
+        std::fs::write(cache_path(dir.path(), poster), b"jpeg").unwrap();
+        assert_eq!(fetch(dir.path(), poster, None).await.unwrap(), b"jpeg");
+
+        // Private addresses stay off limits for thumbnails too
+        assert!(fetch(dir.path(), "http://127.0.0.1/poster.jpg", None).await.is_err());
+        assert!(cached(dir.path(), "http://127.0.0.1/poster.jpg").is_none());
+    }
+}
@@ -0,0 +1,207 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+// Filename transliteration modes (AppSettings::filename_transliteration)
+pub const MODE_OFF: &str = "off";
+pub const MODE_ROMANIZE: &str = "romanize";
+pub const MODE_SLUG: &str = "slug";
+
+// Process-wide so sanitize_filename picks it up wherever it's called
+static MODE: AtomicU8 = AtomicU8::new(0);
+
+pub fn set_mode(mode: &str) {
+    let value = match mode {
+        MODE_ROMANIZE => 1,
+        MODE_SLUG => 2,
+        _ => 0,
+    };
+    MODE.store(value, Ordering::Relaxed);
+}
+
+/// Apply the configured transliteration to a filename containing Thai text
+pub fn apply(name: &str) -> String {
+    if !contains_thai(name) {
+        return name.to_string();
+    }
+
+    match MODE.load(Ordering::Relaxed) {
+        1 => romanize(name),
+        2 => slug_with_id(name),
+        _ => name.to_string(),
+    }
+}
+
+pub fn contains_thai(s: &str) -> bool {
+    s.chars().any(is_thai)
+}
+
+fn is_thai(c: char) -> bool {
+    ('\u{0E00}'..='\u{0E7F}').contains(&c)
+}
+
+/// Simplified RTGS romanization. Handles consonants, vowels, leading vowels
+/// (เ แ โ ใ ไ are written before the consonant but pronounced after it) and
+/// drops tone marks. It is not syllable-aware, so the result is readable
+/// rather than linguistically exact.
+pub fn romanize(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut pending_vowel: Option<&str> = None;
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if let Some(vowel) = leading_vowel(c) {
+            pending_vowel = Some(vowel);
+            continue;
+        }
+
+        if let Some(consonant) = consonant(c) {
+            // อ at the start of a syllable is a silent vowel carrier
+            let sound = if c == 'อ' && (pending_vowel.is_some() || next_is_vowel(chars.peek())) {
+                ""
+            } else if c == 'อ' {
+                "o"
+            } else {
+                consonant
+            };
+            out.push_str(sound);
+            if let Some(vowel) = pending_vowel.take() {
+                // Clusters like "เพล" put the vowel after the second consonant
+                if let Some(&next) = chars.peek() {
+                    if matches!(next, 'ร' | 'ล' | 'ว') && !next_is_vowel(chars.clone().nth(1).as_ref()) {
+                        out.push_str(consonant_or_empty(next));
+                        chars.next();
+                    }
+                }
+                out.push_str(vowel);
+            }
+            continue;
+        }
+
+        if let Some(vowel) = following_vowel(c) {
+            out.push_str(vowel);
+            continue;
+        }
+
+        if ('\u{0E50}'..='\u{0E59}').contains(&c) {
+            out.push(char::from(b'0' + (c as u32 - 0x0E50) as u8));
+            continue;
+        }
+
+        if is_thai(c) {
+            // Tone marks, karan, mai yamok and other signs have no Latin form
+            continue;
+        }
+
+        if let Some(vowel) = pending_vowel.take() {
+            out.push_str(vowel);
+        }
+        out.push(c);
+    }
+
+    if let Some(vowel) = pending_vowel {
+        out.push_str(vowel);
+    }
+
+    capitalize_words(&out)
+}
+
+/// ASCII-only slug plus a short stable id derived from the original name,
+/// so different Thai titles never collapse to the same filename
+pub fn slug_with_id(s: &str) -> String {
+    let mut slug = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_matches('-');
+
+    let id = format!("{:08x}", fnv1a(s.as_bytes()) as u32);
+    if slug.is_empty() {
+        format!("video-{}", id)
+    } else {
+        format!("{}-{}", slug, id)
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn capitalize_words(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut at_word_start = true;
+    for c in s.chars() {
+        if at_word_start && c.is_ascii_lowercase() {
+            out.push(c.to_ascii_uppercase());
+        } else {
+            out.push(c);
+        }
+        at_word_start = c.is_whitespace() || c == '-' || c == '_';
+    }
+    out
+}
+
+fn next_is_vowel(c: Option<&char>) -> bool {
+    c.map(|c| following_vowel(*c).is_some()).unwrap_or(false)
+}
+
+fn consonant_or_empty(c: char) -> &'static str {
+    consonant(c).unwrap_or("")
+}
+
+fn leading_vowel(c: char) -> Option<&'static str> {
+    Some(match c {
+        'เ' => "e",
+        'แ' => "ae",
+        'โ' => "o",
+        'ใ' | 'ไ' => "ai",
+        _ => return None,
+    })
+}
+
+fn following_vowel(c: char) -> Option<&'static str> {
+    Some(match c {
+        'ะ' | 'ั' | 'า' => "a",
+        'ำ' => "am",
+        'ิ' | 'ี' => "i",
+        'ึ' | 'ื' => "ue",
+        'ุ' | 'ู' => "u",
+        'ๅ' => "a",
+        _ => return None,
+    })
+}
+
+fn consonant(c: char) -> Option<&'static str> {
+    Some(match c {
+        'ก' => "k",
+        'ข' | 'ฃ' | 'ค' | 'ฅ' | 'ฆ' => "kh",
+        'ง' => "ng",
+        'จ' | 'ฉ' | 'ช' | 'ฌ' => "ch",
+        'ซ' | 'ศ' | 'ษ' | 'ส' => "s",
+        'ญ' | 'ย' => "y",
+        'ฎ' | 'ด' => "d",
+        'ฏ' | 'ต' => "t",
+        'ฐ' | 'ฑ' | 'ฒ' | 'ถ' | 'ท' | 'ธ' => "th",
+        'ณ' | 'น' => "n",
+        'บ' => "b",
+        'ป' => "p",
+        'ผ' | 'พ' | 'ภ' => "ph",
+        'ฝ' | 'ฟ' => "f",
+        'ม' => "m",
+        'ร' => "r",
+        'ฤ' => "rue",
+        'ล' | 'ฬ' => "l",
+        'ฦ' => "lue",
+        'ว' => "w",
+        'ห' | 'ฮ' => "h",
+        'อ' => "",
+        _ => return None,
+    })
+}
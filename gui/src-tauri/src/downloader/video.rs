@@ -1,23 +1,335 @@
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 
-use super::{VideoInfo, VideoSource, DownloaderError, sanitize_filename, validate_output_dir, validate_url};
+use serde::Serialize;
+
+use super::{SourceOrigin, VideoInfo, VideoSource, DownloaderError, sanitize_filename, validate_output_dir};
+use super::browser::BrowserPool;
+use super::aria2::Aria2Config;
+use super::audio;
+use super::bandwidth::BandwidthShare;
+use super::benchmark;
+use super::log::DownloadLog;
+use super::probe;
+use super::rules;
+use super::scoring::SourcePreferences;
+use super::segment_cache::SegmentCache;
+use super::watchdog::{self, Timeouts};
+use super::size;
+use super::hls::{DEFAULT_SEGMENT_BUFFER_MB, DEFAULT_SEGMENT_WORKERS};
+
+// Only the real extraction and transfers use these
+#[cfg(not(feature = "mock-downloader"))]
+use std::path::Path;
+#[cfg(not(feature = "mock-downloader"))]
+use super::validate_url;
+#[cfg(not(feature = "mock-downloader"))]
 use super::browser::BrowserAutomation;
+#[cfg(not(feature = "mock-downloader"))]
+use super::http_extractor::HttpExtractor;
+#[cfg(not(feature = "mock-downloader"))]
+use super::webdriver::{self, WebDriverExtractor};
+#[cfg(not(feature = "mock-downloader"))]
 use super::hls::{HlsDownloader, DirectDownloader};
 
+// The mock ignores the extraction and transfer options
+#[cfg_attr(feature = "mock-downloader", allow(dead_code))]
 pub struct VideoDownloader {
     headless: bool,
+    browser_pool: Option<Arc<BrowserPool>>,
+    segment_workers: usize,
+    segment_buffer_mb: usize,
+    fsync: bool,
+    site_qualities: BTreeMap<String, String>,
+    aria2: Option<Aria2Config>,
+    smart_source_selection: bool,
+    speed_matched_auto: bool,
+    check_sources: bool,
+    source_preferences: SourcePreferences,
+    remux_mp4: bool,
+    faststart: bool,
+    pub(super) bandwidth: Option<BandwidthShare>,
+    allow_browser: bool,
+    pub(super) log: DownloadLog,
+    segment_cache: Option<Arc<SegmentCache>>,
+    audio_tracks: String,
+    passthrough: bool,
+    max_file_size: Option<u64>,
+    timeouts: Timeouts,
+    info: Option<VideoInfo>,
+    selected_origin: SelectedOrigin,
+    quality_fallback: bool,
+    fallback_listener: Option<Arc<dyn Fn(QualityFallback) + Send + Sync>>,
+}
+
+/// A download gave up on one quality and moved on to the next lower one
+#[derive(Clone, Debug, Serialize)]
+pub struct QualityFallback {
+    pub from: String,
+    pub to: String,
+    pub reason: String,
+}
+
+/// Where the source a download settled on came from. Clones share the
+/// same slot, so the caller can read it after the download ends.
+#[derive(Clone, Default)]
+pub struct SelectedOrigin(Arc<Mutex<Option<SourceOrigin>>>);
+
+impl SelectedOrigin {
+    fn set(&self, source: &VideoSource) {
+        *self.0.lock().unwrap() = Some(source.origin());
+    }
+
+    pub fn get(&self) -> Option<SourceOrigin> {
+        self.0.lock().unwrap().clone()
+    }
 }
 
 impl VideoDownloader {
     pub fn new(headless: bool) -> Self {
-        Self { headless }
+        Self {
+            headless,
+            browser_pool: None,
+            segment_workers: DEFAULT_SEGMENT_WORKERS,
+            segment_buffer_mb: DEFAULT_SEGMENT_BUFFER_MB,
+            fsync: false,
+            site_qualities: BTreeMap::new(),
+            aria2: None,
+            smart_source_selection: false,
+            speed_matched_auto: false,
+            check_sources: false,
+            source_preferences: SourcePreferences::default(),
+            remux_mp4: false,
+            faststart: false,
+            bandwidth: None,
+            allow_browser: true,
+            log: DownloadLog::default(),
+            segment_cache: None,
+            audio_tracks: audio::AUDIO_DEFAULT.to_string(),
+            passthrough: false,
+            max_file_size: None,
+            timeouts: Timeouts::default(),
+            info: None,
+            selected_origin: SelectedOrigin::default(),
+            quality_fallback: false,
+            fallback_listener: None,
+        }
+    }
+
+    /// Download from sources extracted just before instead of extracting again
+    pub fn with_info(mut self, info: VideoInfo) -> Self {
+        self.info = Some(info);
+        self
+    }
+
+    /// Limits on extraction, segments and stalled transfers, so a hung
+    /// page or connection fails instead of holding its queue slot
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Parallel segment fetches for HLS downloads
+    pub fn with_segment_workers(mut self, workers: usize) -> Self {
+        self.segment_workers = workers;
+        self
+    }
+
+    /// Memory cap for downloaded HLS segments waiting to be written
+    pub fn with_segment_buffer_mb(mut self, megabytes: usize) -> Self {
+        self.segment_buffer_mb = megabytes;
+        self
+    }
+
+    /// fsync output files once they are complete
+    pub fn with_fsync(mut self, fsync: bool) -> Self {
+        self.fsync = fsync;
+        self
+    }
+
+    /// Preferred quality per domain (e.g. "example.com" -> "480p"), used
+    /// when a download asks for "auto"
+    pub fn with_site_qualities(mut self, site_qualities: BTreeMap<String, String>) -> Self {
+        self.site_qualities = site_qualities;
+        self
+    }
+
+    /// Delegate transfers to a local aria2c over JSON-RPC
+    pub fn with_aria2(mut self, config: Option<Aria2Config>) -> Self {
+        self.aria2 = config;
+        self
+    }
+
+    /// Benchmark mirrors of the chosen quality and download from the fastest
+    pub fn with_smart_source_selection(mut self, enabled: bool) -> Self {
+        self.smart_source_selection = enabled;
+        self
+    }
+
+    /// For "auto" quality, download the HLS variant the connection can keep
+    /// up with instead of always the highest one
+    pub fn with_speed_matched_auto(mut self, enabled: bool) -> Self {
+        self.speed_matched_auto = enabled;
+        self
+    }
+
+    /// Request every source after extraction: drop dead links, add sizes,
+    /// durations and response times, and sort the sources best first
+    pub fn with_source_check(mut self, enabled: bool) -> Self {
+        self.check_sources = enabled;
+        self
+    }
+
+    /// Type and host preferences used to rank sources of the same quality
+    pub fn with_source_preferences(mut self, preferences: SourcePreferences) -> Self {
+        self.source_preferences = preferences;
+        self
+    }
+
+    /// Remux direct downloads in other containers to MP4
+    pub fn with_remux_mp4(mut self, remux: bool) -> Self {
+        self.remux_mp4 = remux;
+        self
+    }
+
+    /// Write MP4s with the index first (web-optimized)
+    pub fn with_faststart(mut self, faststart: bool) -> Self {
+        self.faststart = faststart;
+        self
+    }
+
+    /// This download's share of the total speed limit
+    pub fn with_bandwidth(mut self, share: Option<BandwidthShare>) -> Self {
+        self.bandwidth = share;
+        self
+    }
+
+    /// Whether extraction may fall back to launching Chromium
+    pub fn with_browser_allowed(mut self, allowed: bool) -> Self {
+        self.allow_browser = allowed;
+        self
+    }
+
+    /// Keep fetched HLS segments so a retry doesn't download them again
+    pub fn with_segment_cache(mut self, cache: Option<Arc<SegmentCache>>) -> Self {
+        self.segment_cache = cache;
+        self
+    }
+
+    /// Which separate audio tracks to keep: audio::AUDIO_DEFAULT, AUDIO_ALL
+    /// or a language code
+    pub fn with_audio_tracks(mut self, selection: String) -> Self {
+        self.audio_tracks = selection;
+        self
+    }
+
+    /// Keep streams exactly as downloaded: no conversion, remux or faststart
+    pub fn with_passthrough(mut self, passthrough: bool) -> Self {
+        self.passthrough = passthrough;
+        self
+    }
+
+    /// Abort the download once it is known to be bigger than this many bytes
+    pub fn with_max_file_size(mut self, max_bytes: Option<u64>) -> Self {
+        self.max_file_size = max_bytes;
+        self
+    }
+
+    /// When a quality keeps returning 404 or stalling after its retries,
+    /// download the next lower quality instead of failing
+    pub fn with_quality_fallback(mut self, enabled: bool) -> Self {
+        self.quality_fallback = enabled;
+        self
+    }
+
+    /// Called each time the download falls back to a lower quality
+    pub fn with_fallback_listener(mut self, listener: impl Fn(QualityFallback) + Send + Sync + 'static) -> Self {
+        self.fallback_listener = Some(Arc::new(listener));
+        self
+    }
+
+    /// Report the page, embed and extraction time of the chosen source
+    pub fn with_selected_origin(mut self, origin: SelectedOrigin) -> Self {
+        self.selected_origin = origin;
+        self
+    }
+
+    /// Record what the download tries and why it fails
+    pub fn with_log(mut self, log: DownloadLog) -> Self {
+        self.log = log;
+        self
+    }
+
+    /// Extract through a shared browser instead of launching one per call
+    pub fn with_browser_pool(mut self, pool: Arc<BrowserPool>) -> Self {
+        self.browser_pool = Some(pool);
+        self
     }
 
     pub async fn get_info(&self, url: &str) -> Result<VideoInfo, DownloaderError> {
+        let mut info = watchdog::within(self.timeouts.extraction, "Extraction", self.extract(url)).await?;
+
+        // URL substrings are only a guess; label sources with their real resolution
+        let headers = rules::rule_for(url).map(|r| r.header_list()).unwrap_or_default();
+        probe::drop_ad_streams(&mut info, Some(url), &headers).await;
+        probe::label_sources(&mut info, Some(url), &headers).await;
+        if self.check_sources {
+            probe::check_sources(&mut info, Some(url), &headers).await;
+            self.log.info(format!("{} source(s) answered the source check", info.sources.len()));
+        }
+        probe::list_audio_tracks(&mut info, Some(url), &headers).await;
+
+        Ok(info)
+    }
+
+    // mock.rs provides extract and download_source behind the mock-downloader feature
+    #[cfg(not(feature = "mock-downloader"))]
+    async fn extract(&self, url: &str) -> Result<VideoInfo, DownloaderError> {
         // Validate URL to prevent SSRF attacks
         let validated = validate_url(url)?;
-        let browser = BrowserAutomation::new(self.headless);
-        browser.get_video_info(&validated).await
+
+        // Sites with user scripts need the browser to run them
+        let has_hook = self.browser_pool.as_ref().map(|p| p.has_hook(&validated)).unwrap_or(false);
+
+        // Try the lightweight HTTP path first, only launch Chrome when it finds nothing
+        if !has_hook {
+            match HttpExtractor::new().get_video_info(&validated).await {
+                Ok(info) => {
+                    self.log.info(format!("HTTP extraction found {} source(s)", info.sources.len()));
+                    return Ok(info);
+                }
+                Err(e) => self.log.info(format!("HTTP extraction failed: {}", e)),
+            }
+        }
+
+        if !self.allow_browser {
+            self.log.warn("Browser extraction skipped (disabled right now)");
+            return Err(DownloaderError::Browser("Browser extraction is disabled right now".to_string()));
+        }
+
+        if webdriver::firefox_selected() {
+            self.log.info("Extracting with Firefox");
+            let hook = self.browser_pool.as_ref().and_then(|p| p.hook_for(&validated));
+            let result = WebDriverExtractor::new(self.headless).with_hook(hook).get_video_info(&validated).await;
+            match &result {
+                Ok(info) => self.log.info(format!("Firefox extraction found {} source(s)", info.sources.len())),
+                Err(e) => self.log.error(format!("Firefox extraction failed: {}", e)),
+            }
+            return result;
+        }
+
+        self.log.info("Extracting with the browser");
+        let result = match &self.browser_pool {
+            Some(pool) if self.headless => pool.get_video_info(&validated).await,
+            Some(pool) => pool.get_video_info_visible(&validated).await,
+            None => BrowserAutomation::new(self.headless).get_video_info(&validated).await,
+        };
+        match &result {
+            Ok(info) => self.log.info(format!("Browser extraction found {} source(s)", info.sources.len())),
+            Err(e) => self.log.error(format!("Browser extraction failed: {}", e)),
+        }
+        result
     }
 
     pub async fn download(
@@ -28,18 +340,76 @@ impl VideoDownloader {
         quality: Option<&str>,
         progress_callback: impl Fn(f32, String) + Send + Clone + 'static,
     ) -> Result<PathBuf, DownloaderError> {
+        self.download_with(url, output_dir, filename, quality, false, progress_callback)
+            .await
+            .map(|(path, _)| path)
+    }
+
+    /// Like `download`, but HLS output is left as the raw .ts for the
+    /// post-processing queue. The flag tells whether it still needs converting.
+    pub async fn download_deferred(
+        &self,
+        url: &str,
+        output_dir: &str,
+        filename: Option<&str>,
+        quality: Option<&str>,
+        progress_callback: impl Fn(f32, String) + Send + Clone + 'static,
+    ) -> Result<(PathBuf, bool), DownloaderError> {
+        self.download_with(url, output_dir, filename, quality, true, progress_callback).await
+    }
+
+    async fn download_with(
+        &self,
+        url: &str,
+        output_dir: &str,
+        filename: Option<&str>,
+        quality: Option<&str>,
+        defer_conversion: bool,
+        progress_callback: impl Fn(f32, String) + Send + Clone + 'static,
+    ) -> Result<(PathBuf, bool), DownloaderError> {
         // Validate and sanitize output directory
         let validated_dir = validate_output_dir(output_dir)?;
 
         // Get video info first
-        let info = self.get_info(url).await?;
+        self.log.info(format!("Page: {}", url));
+        let info = match &self.info {
+            Some(info) => info.clone(),
+            None => self.get_info(url).await?,
+        };
 
         if info.sources.is_empty() {
+            self.log.error("No video sources found");
             return Err(DownloaderError::NoSources);
         }
+        for source in &info.sources {
+            self.log.info(format!("Source {} ({}): {}", source.quality, source.source_type, source.url));
+        }
+
+        // Site rules may require extra headers on media requests too
+        let headers = rules::rule_for(url).map(|r| r.header_list()).unwrap_or_default();
 
         // Select source based on quality
-        let source = self.select_source(&info.sources, quality);
+        let mut source = match quality.and_then(size::size_budget) {
+            Some(budget) => {
+                progress_callback(0.0, "กำลังประเมินขนาดไฟล์...".to_string());
+                self.select_by_size(url, &info.sources, budget, &headers).await
+            }
+            None => self.select_source(url, &info.sources, quality),
+        };
+
+        if self.smart_source_selection {
+            let mirrors: Vec<&VideoSource> = info.sources.iter().filter(|s| s.quality == source.quality).collect();
+            if mirrors.len() > 1 {
+                progress_callback(0.0, "กำลังทดสอบความเร็วของแหล่งวิดีโอ...".to_string());
+                if let Some(index) = benchmark::fastest_mirror(&mirrors, Some(url), &headers).await {
+                    source = mirrors[index];
+                }
+            }
+        }
+
+        self.log.info(format!("Selected {} source: {}", source.quality, source.url));
+        self.log.info(format!("Source origin: {}", source.origin().describe()));
+        self.selected_origin.set(source);
 
         // Sanitize filename to prevent path traversal
         let sanitized_filename = filename
@@ -55,26 +425,289 @@ impl VideoDownloader {
 
         let output_path = PathBuf::from(&validated_dir).join(&output_filename);
 
-        // Download based on source type
+        // A site's preferred quality stands; otherwise "auto" follows the connection
+        let speed_matched = self.speed_matched_auto
+            && matches!(quality, None | Some("auto"))
+            && self.site_quality(url).is_none();
+
+        // A quality that keeps 404ing or stalling gives way to the next
+        // lower one when fallback is on
+        let mut attempt = source;
+        loop {
+            let error = match self
+                .download_source(url, attempt, &headers, &output_path, defer_conversion, speed_matched, progress_callback.clone())
+                .await
+            {
+                Err(e) if self.quality_fallback && should_fall_back(&e) => e,
+                result => return result,
+            };
+            let Some(lower) = self.lower_quality(&info.sources, &attempt.quality) else {
+                return Err(error);
+            };
+
+            self.log.warn(format!("{} failed ({}), falling back to {}", attempt.quality, error, lower.quality));
+            progress_callback(0.0, format!("ดาวน์โหลด {} ไม่สำเร็จ กำลังเปลี่ยนเป็น {}...", attempt.quality, lower.quality));
+            if let Some(listener) = &self.fallback_listener {
+                listener(QualityFallback {
+                    from: attempt.quality.clone(),
+                    to: lower.quality.clone(),
+                    reason: error.to_string(),
+                });
+            }
+            self.selected_origin.set(lower);
+            attempt = lower;
+        }
+    }
+
+    /// Download one source to `output_path`, extracting fresh links once if
+    /// HLS segment tokens expire on the way
+    #[cfg(not(feature = "mock-downloader"))]
+    #[allow(clippy::too_many_arguments)]
+    async fn download_source(
+        &self,
+        url: &str,
+        source: &VideoSource,
+        headers: &[(String, String)],
+        output_path: &Path,
+        defer_conversion: bool,
+        speed_matched: bool,
+        progress_callback: impl Fn(f32, String) + Send + Clone + 'static,
+    ) -> Result<(PathBuf, bool), DownloaderError> {
         if source.source_type == "hls" || source.url.contains(".m3u8") {
-            let downloader = HlsDownloader::new(Some(url.to_string()));
-            downloader.download(&source.url, &output_path, progress_callback).await
+            let path = match self
+                .hls_downloader(url, headers, defer_conversion, speed_matched)
+                .download(&source.url, output_path, progress_callback.clone())
+                .await
+            {
+                Err(DownloaderError::SegmentExpired(reason)) => {
+                    // Segment tokens ran out; extract fresh links once and
+                    // continue, reusing segments already in the cache
+                    let age = source.origin().age().map(|a| format!(" {} min after extraction", a.as_secs() / 60)).unwrap_or_default();
+                    self.log.warn(format!("Segment links expired ({}){}, extracting again", reason, age));
+                    progress_callback(0.0, "ลิงก์หมดอายุ กำลังดึงลิงก์ใหม่...".to_string());
+                    let fresh = self.get_info(url).await?;
+                    if fresh.sources.is_empty() {
+                        return Err(DownloaderError::NoSources);
+                    }
+                    let source = self.select_source(url, &fresh.sources, Some(&source.quality));
+                    self.log.info(format!("Refreshed {} source: {}", source.quality, source.url));
+                    self.selected_origin.set(source);
+                    self.hls_downloader(url, headers, defer_conversion, speed_matched)
+                        .download(&source.url, output_path, progress_callback)
+                        .await?
+                }
+                result => result?,
+            };
+            Ok((path, defer_conversion && !self.passthrough))
         } else {
-            let downloader = DirectDownloader::new(Some(url.to_string()));
-            downloader.download(&source.url, &output_path, progress_callback).await
+            // The page the source was found on; the player embed if that's refused
+            let referer = source.page_url.clone().unwrap_or_else(|| url.to_string());
+            let downloader = DirectDownloader::new(Some(referer))
+                .with_fallback_referer(source.embed_url.clone())
+                .with_headers(headers.to_vec())
+                .with_fsync(self.fsync)
+                .with_aria2(self.aria2.clone())
+                .with_remux_mp4(self.remux_mp4 && !self.passthrough)
+                .with_faststart(self.faststart && !self.passthrough)
+                .with_bandwidth(self.bandwidth.clone())
+                .with_timeouts(self.timeouts)
+                .with_max_bytes(self.max_file_size)
+                .with_log(self.log.clone());
+            let path = downloader.download(&source.url, output_path, progress_callback).await?;
+            Ok((path, false))
         }
     }
 
-    fn select_source<'a>(&self, sources: &'a [VideoSource], quality: Option<&str>) -> &'a VideoSource {
+    #[cfg(not(feature = "mock-downloader"))]
+    fn hls_downloader(
+        &self,
+        url: &str,
+        headers: &[(String, String)],
+        defer_conversion: bool,
+        speed_matched: bool,
+    ) -> HlsDownloader {
+        HlsDownloader::new(Some(url.to_string()))
+            .with_headers(headers.to_vec())
+            .with_workers(self.segment_workers)
+            .with_buffer_limit(self.segment_buffer_mb)
+            .with_fsync(self.fsync)
+            .with_aria2(self.aria2.clone())
+            .with_faststart(self.faststart)
+            .with_bandwidth(self.bandwidth.clone())
+            .with_deferred_conversion(defer_conversion)
+            .with_log(self.log.clone())
+            .with_segment_cache(self.segment_cache.clone())
+            .with_audio_tracks(&self.audio_tracks)
+            .with_passthrough(self.passthrough)
+            .with_timeouts(self.timeouts)
+            .with_max_bytes(self.max_file_size)
+            .with_speed_matched_variant(speed_matched)
+    }
+
+    fn select_source<'a>(&self, url: &str, sources: &'a [VideoSource], quality: Option<&str>) -> &'a VideoSource {
+        let preferences = &self.source_preferences;
+        let best_of_quality = |q: &str| preferences.best(sources.iter().filter(|s| s.quality == q));
+
         if let Some(q) = quality {
             if q != "auto" && q != "best" {
-                if let Some(source) = sources.iter().find(|s| s.quality == q) {
+                if let Some(source) = best_of_quality(q) {
                     return source;
                 }
             }
         }
 
-        // Return first source (usually best quality)
-        &sources[0]
+        if quality.is_none() || quality == Some("auto") {
+            if let Some(preferred) = self.site_quality(url) {
+                if let Some(source) = closest_quality(sources, &preferred) {
+                    return best_of_quality(&source.quality).unwrap_or(source);
+                }
+            }
+        }
+
+        // Best-scoring source; without preferences that's the first one
+        // (usually best quality)
+        preferences.best(sources).unwrap_or(&sources[0])
+    }
+
+    /// Highest quality whose estimated size fits the budget, else the
+    /// smallest estimate; falls back to the normal choice when nothing
+    /// could be estimated
+    async fn select_by_size<'a>(
+        &self,
+        url: &str,
+        sources: &'a [VideoSource],
+        budget: u64,
+        headers: &[(String, String)],
+    ) -> &'a VideoSource {
+        let estimates = size::estimate_sizes(sources, Some(url), headers).await;
+        let sized: Vec<(&VideoSource, u64)> = sources
+            .iter()
+            .zip(&estimates)
+            .filter_map(|(source, estimate)| estimate.bytes.map(|bytes| (source, bytes)))
+            .collect();
+
+        sized
+            .iter()
+            .filter(|(_, bytes)| *bytes <= budget)
+            .max_by_key(|(source, bytes)| (quality_height(&source.quality).unwrap_or(0), *bytes))
+            .or_else(|| sized.iter().min_by_key(|(_, bytes)| *bytes))
+            .map(|(source, _)| *source)
+            .unwrap_or_else(|| self.select_source(url, sources, None))
+    }
+
+    /// Best source of the next quality below `quality`; None at the bottom
+    /// or when qualities aren't heights
+    fn lower_quality<'a>(&self, sources: &'a [VideoSource], quality: &str) -> Option<&'a VideoSource> {
+        let current = quality_height(quality)?;
+        let next = sources
+            .iter()
+            .filter_map(|s| quality_height(&s.quality))
+            .filter(|h| *h < current)
+            .max()?;
+        self.source_preferences
+            .best(sources.iter().filter(|s| quality_height(&s.quality) == Some(next)))
+    }
+
+    fn site_quality(&self, url: &str) -> Option<String> {
+        site_quality(&self.site_qualities, url)
+    }
+}
+
+/// Quality set for the site of `url`; the most specific domain wins, so a
+/// rule for video.example.com beats one for example.com
+fn site_quality(site_qualities: &BTreeMap<String, String>, url: &str) -> Option<String> {
+    let host = url::Url::parse(url).ok()?.host_str()?.to_lowercase();
+    site_qualities
+        .iter()
+        .filter_map(|(domain, quality)| {
+            let domain = domain.trim().trim_start_matches("www.").to_lowercase();
+            (host == domain || host.ends_with(&format!(".{}", domain))).then_some((domain.len(), quality))
+        })
+        .max_by_key(|(length, _)| *length)
+        .map(|(_, quality)| quality.clone())
+}
+
+/// Failures a lower quality may get around: the file is gone (404/410),
+/// the server errors, or the transfer keeps stalling
+fn should_fall_back(error: &DownloaderError) -> bool {
+    match error {
+        DownloaderError::Timeout(_) | DownloaderError::SegmentExpired(_) => true,
+        DownloaderError::Network(e) => {
+            e.is_timeout() || e.status().is_some_and(|s| s.as_u16() == 404 || s.as_u16() == 410 || s.is_server_error())
+        }
+        _ => false,
+    }
+}
+
+fn quality_height(quality: &str) -> Option<u32> {
+    quality.trim_end_matches('p').parse().ok()
+}
+
+/// Closest quality still offered when `quality` no longer is; None when it
+/// is, or when it isn't a height (auto, best, size budgets)
+pub fn remap_quality(sources: &[VideoSource], quality: &str) -> Option<String> {
+    if sources.iter().any(|s| s.quality == quality) {
+        return None;
+    }
+    closest_quality(sources, quality).map(|s| s.quality.clone())
+}
+
+/// Exact match, else the best quality not above the preference (to save
+/// space), else the lowest one available
+fn closest_quality<'a>(sources: &'a [VideoSource], preferred: &str) -> Option<&'a VideoSource> {
+    if let Some(source) = sources.iter().find(|s| s.quality == preferred) {
+        return Some(source);
+    }
+
+    let target = quality_height(preferred)?;
+    let sized = || sources.iter().filter_map(|s| quality_height(&s.quality).map(|h| (h, s)));
+
+    sized()
+        .filter(|(h, _)| *h <= target)
+        .max_by_key(|(h, _)| *h)
+        .or_else(|| sized().min_by_key(|(h, _)| *h))
+        .map(|(_, s)| s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sources(qualities: &[&str]) -> Vec<VideoSource> {
+        qualities
+            .iter()
+            .map(|q| VideoSource { quality: q.to_string(), ..Default::default() })
+            .collect()
+    }
+
+    #[test]
+    fn the_most_specific_site_rule_wins() {
+        let rules: BTreeMap<String, String> = [
+            ("example.com", "480p"),
+            ("video.example.com", "1080p"),
+            ("www.other.org", "720p"),
+        ]
+        .iter()
+        .map(|(d, q)| (d.to_string(), q.to_string()))
+        .collect();
+
+        assert_eq!(site_quality(&rules, "https://video.example.com/watch/1").as_deref(), Some("1080p"));
+        assert_eq!(site_quality(&rules, "https://cdn.video.example.com/a.m3u8").as_deref(), Some("1080p"));
+        assert_eq!(site_quality(&rules, "https://example.com/watch/1").as_deref(), Some("480p"));
+        assert_eq!(site_quality(&rules, "https://other.org/").as_deref(), Some("720p"));
+        assert_eq!(site_quality(&rules, "https://notexample.com/").as_deref(), None);
+    }
+
+    #[test]
+    fn closest_quality_prefers_the_best_one_not_above_the_preference() {
+        let offered = sources(&["360p", "720p", "1080p"]);
+        let pick = |preferred| closest_quality(&offered, preferred).map(|s| s.quality.as_str());
+
+        assert_eq!(pick("720p"), Some("720p"));
+        assert_eq!(pick("900p"), Some("720p"));
+        assert_eq!(pick("240p"), Some("360p"));
+        assert_eq!(pick("auto"), None);
+        assert_eq!(remap_quality(&offered, "720p"), None);
+        assert_eq!(remap_quality(&offered, "480p").as_deref(), Some("360p"));
     }
 }
@@ -0,0 +1,118 @@
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use std::future::Future;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use super::DownloaderError;
+
+pub const DEFAULT_EXTRACTION_TIMEOUT_SECS: u64 = 180;
+pub const DEFAULT_SEGMENT_TIMEOUT_SECS: u64 = 120;
+pub const DEFAULT_STALL_TIMEOUT_SECS: u64 = 60;
+pub const DEFAULT_PAGE_LOAD_WAIT_MS: u64 = 3000;
+pub const DEFAULT_IFRAME_LOAD_WAIT_MS: u64 = 5000;
+pub const DEFAULT_PLAYBACK_WAIT_MS: u64 = 5000;
+// Longest pause the settings accept, so a typo can't stall every extraction
+const MAX_WAIT_MS: u64 = 60_000;
+
+// Read at every browser extraction, so a change applies to the next one
+static EXTRACTION_WAITS: RwLock<ExtractionWaits> = RwLock::new(ExtractionWaits::DEFAULT);
+
+/// Limits that keep a hung page or connection from holding a download
+/// slot forever; None turns a limit off
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timeouts {
+    /// Whole extraction, HTTP and browser
+    pub extraction: Option<Duration>,
+    /// One HLS segment, all of its bytes
+    pub segment: Option<Duration>,
+    /// No bytes arriving on a transfer
+    pub stall: Option<Duration>,
+}
+
+impl Timeouts {
+    /// From settings in seconds, where 0 turns a limit off
+    pub fn from_secs(extraction: u64, segment: u64, stall: u64) -> Self {
+        let limit = |secs: u64| (secs > 0).then(|| Duration::from_secs(secs));
+        Self {
+            extraction: limit(extraction),
+            segment: limit(segment),
+            stall: limit(stall),
+        }
+    }
+}
+
+/// Pauses a browser extraction makes for the page to do its work. Slow
+/// connections need longer ones; fast ones can shrink them.
+#[derive(Clone, Copy, Debug)]
+pub struct ExtractionWaits {
+    /// After opening the page, before reading its title and iframes
+    pub page_load: Duration,
+    /// After opening each player iframe, before clicking play
+    pub iframe_load: Duration,
+    /// After clicking play, for the player to request its stream
+    pub playback: Duration,
+}
+
+impl ExtractionWaits {
+    const DEFAULT: Self = Self {
+        page_load: Duration::from_millis(DEFAULT_PAGE_LOAD_WAIT_MS),
+        iframe_load: Duration::from_millis(DEFAULT_IFRAME_LOAD_WAIT_MS),
+        playback: Duration::from_millis(DEFAULT_PLAYBACK_WAIT_MS),
+    };
+
+    /// From settings in milliseconds, capped at a minute each
+    pub fn from_millis(page_load: u64, iframe_load: u64, playback: u64) -> Self {
+        let wait = |ms: u64| Duration::from_millis(ms.min(MAX_WAIT_MS));
+        Self {
+            page_load: wait(page_load),
+            iframe_load: wait(iframe_load),
+            playback: wait(playback),
+        }
+    }
+}
+
+impl Default for ExtractionWaits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+pub fn set_extraction_waits(waits: ExtractionWaits) {
+    if let Ok(mut current) = EXTRACTION_WAITS.write() {
+        *current = waits;
+    }
+}
+
+pub fn extraction_waits() -> ExtractionWaits {
+    EXTRACTION_WAITS.read().map(|w| *w).unwrap_or_default()
+}
+
+/// Run `future`, failing with a Timeout error once `limit` passes
+pub async fn within<T>(
+    limit: Option<Duration>,
+    what: &str,
+    future: impl Future<Output = Result<T, DownloaderError>>,
+) -> Result<T, DownloaderError> {
+    let Some(limit) = limit else {
+        return future.await;
+    };
+    tokio::time::timeout(limit, future)
+        .await
+        .unwrap_or_else(|_| Err(DownloaderError::Timeout(format!("{} took longer than {}s", what, limit.as_secs()))))
+}
+
+/// Next chunk of a response body, or a Timeout error when nothing arrives
+/// for `stall`
+pub async fn next_chunk<S>(stream: &mut S, stall: Option<Duration>) -> Option<Result<Bytes, DownloaderError>>
+where
+    S: Stream<Item = reqwest::Result<Bytes>> + Unpin,
+{
+    let Some(limit) = stall else {
+        return stream.next().await.map(|chunk| chunk.map_err(Into::into));
+    };
+    match tokio::time::timeout(limit, stream.next()).await {
+        Ok(chunk) => chunk.map(|chunk| chunk.map_err(Into::into)),
+        Err(_) => Some(Err(DownloaderError::Timeout(format!("no data received for {}s", limit.as_secs())))),
+    }
+}
@@ -0,0 +1,337 @@
+use reqwest::{Client, Method};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::RwLock;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+
+use super::diagnostics::ExtractionDiagnostics;
+use super::hooks::{sources_from_value, SiteHook};
+use super::rules;
+use super::watchdog;
+use super::{build_video_info, drm, extract_quality_from_url, find_sources_in_content, is_ad_url, validate_url, DownloaderError, VideoInfo, VideoSource};
+
+pub const BACKEND_CHROMIUM: &str = "chromium";
+pub const BACKEND_FIREFOX: &str = "firefox";
+
+const DEFAULT_GECKODRIVER: &str = "geckodriver";
+const DRIVER_START_TIMEOUT: Duration = Duration::from_secs(15);
+
+// Whether Firefox is used, and the geckodriver binary (None looks it up
+// on PATH); read at every extraction
+static FIREFOX: RwLock<Option<Option<PathBuf>>> = RwLock::new(None);
+
+pub fn validate_backend(backend: &str, geckodriver: &str) -> Result<(), String> {
+    match backend {
+        BACKEND_CHROMIUM => Ok(()),
+        BACKEND_FIREFOX if !geckodriver.trim().is_empty() && !Path::new(geckodriver.trim()).is_file() => {
+            Err(format!("geckodriver not found: {}", geckodriver))
+        }
+        BACKEND_FIREFOX => Ok(()),
+        other => Err(format!("Unknown browser backend: {}", other)),
+    }
+}
+
+pub fn set_backend(backend: &str, geckodriver: &str) {
+    if let Ok(mut current) = FIREFOX.write() {
+        let geckodriver = geckodriver.trim();
+        *current = (backend == BACKEND_FIREFOX).then(|| (!geckodriver.is_empty()).then(|| PathBuf::from(geckodriver)));
+    }
+}
+
+/// Extractions go through Firefox instead of Chromium
+pub fn firefox_selected() -> bool {
+    FIREFOX.read().map(|f| f.is_some()).unwrap_or(false)
+}
+
+// Page scripts; WebDriver runs them as function bodies and awaits promises
+const THUMBNAIL_SCRIPT: &str = r#"
+    var meta = document.querySelector('meta[property="og:image"]');
+    if (meta) return meta.getAttribute('content');
+    var video = document.querySelector('video');
+    if (video && video.poster) return video.poster;
+    return '';
+"#;
+const IFRAMES_SCRIPT: &str = r#"
+    return Array.from(document.querySelectorAll('iframe')).map(f => f.src || f.getAttribute('data-lazy-src') || f.getAttribute('data-src') || '').filter(s => s.length > 0 && s.startsWith('http'));
+"#;
+const PLAY_SCRIPT: &str = r#"
+    var playSelectors = [
+        '.play-button', '.vjs-big-play-button', '.plyr__control--overlaid',
+        '[class*="play"]', '.jwplayer', '#player', '.jw-icon-display', 'video'
+    ];
+    for (var selector of playSelectors) {
+        var elem = document.querySelector(selector);
+        if (elem) { elem.click(); break; }
+    }
+    document.querySelectorAll('video').forEach(function(v) {
+        try { v.play(); } catch(e) {}
+    });
+"#;
+// No network events over WebDriver; the Resource Timing list has every
+// URL the page fetched, and the player and <video> elements the rest
+const MEDIA_URLS_SCRIPT: &str = r#"
+    var urls = performance.getEntriesByType('resource').map(e => e.name);
+    if (typeof jwplayer !== 'undefined') {
+        try {
+            var item = jwplayer().getPlaylistItem();
+            if (item && item.file) urls.push(item.file);
+            if (item && item.sources) item.sources.forEach(s => { if (s.file) urls.push(s.file); });
+        } catch(e) {}
+    }
+    document.querySelectorAll('video').forEach(function(v) {
+        if (v.currentSrc) urls.push(v.currentSrc);
+        if (v.src) urls.push(v.src);
+    });
+    return urls;
+"#;
+const EME_SCRIPT: &str = "return Array.from(document.querySelectorAll('video')).some(v => !!v.mediaKeys);";
+
+/// Extraction through Firefox and geckodriver, for sites that fingerprint
+/// and block headless Chromium. Finds sources the same way as
+/// BrowserAutomation, except that requests are read from the page's
+/// Resource Timing entries.
+pub struct WebDriverExtractor {
+    headless: bool,
+    hook: Option<SiteHook>,
+}
+
+impl WebDriverExtractor {
+    pub fn new(headless: bool) -> Self {
+        Self { headless, hook: None }
+    }
+
+    /// The site's hook scripts, run like in the Chromium backend
+    pub fn with_hook(mut self, hook: Option<SiteHook>) -> Self {
+        self.hook = hook;
+        self
+    }
+
+    pub async fn get_video_info(&self, url: &str) -> Result<VideoInfo, DownloaderError> {
+        let validated = validate_url(url)?;
+        let session = Session::start(self.headless).await?;
+        let result = self.extract_info(&session, &validated).await;
+        session.close().await;
+        result
+    }
+
+    async fn extract_info(&self, session: &Session, url: &str) -> Result<VideoInfo, DownloaderError> {
+        let mut diagnostics = ExtractionDiagnostics::new(url);
+        let mut sources: Vec<VideoSource> = Vec::new();
+        let mut drm_detected = false;
+        let waits = watchdog::extraction_waits();
+
+        session.navigate(url).await?;
+        tokio::time::sleep(waits.page_load).await;
+        self.run_page_hook(session, &mut sources).await;
+
+        let title: String = session.execute("return document.title;").await.unwrap_or_default();
+        let thumbnail: String = session.execute(THUMBNAIL_SCRIPT).await.unwrap_or_default();
+        let iframes: Vec<String> = session.execute(IFRAMES_SCRIPT).await.unwrap_or_default();
+        let page_html: Option<String> = session.execute("return document.documentElement.outerHTML;").await;
+        // Sites without iframes load the player on the page itself
+        let page_urls: Vec<String> = session.execute(MEDIA_URLS_SCRIPT).await.unwrap_or_default();
+
+        diagnostics.page_title = title.clone();
+        diagnostics.iframe_count = iframes.len();
+        diagnostics.iframes_skipped_as_ads = iframes.iter().filter(|u| is_ad_url(u)).count();
+        diagnostics.hook_used = self.hook.is_some();
+        collect_media_urls(&page_urls, None, &mut sources, &mut diagnostics, &mut drm_detected);
+
+        for iframe_url in iframes.iter().filter(|u| !is_ad_url(u)) {
+            if session.navigate(iframe_url).await.is_err() {
+                continue;
+            }
+            tokio::time::sleep(waits.iframe_load).await;
+            self.run_page_hook(session, &mut sources).await;
+
+            session.execute::<Value>(PLAY_SCRIPT).await;
+            tokio::time::sleep(waits.playback).await;
+
+            if session.execute::<bool>(EME_SCRIPT).await.unwrap_or(false) {
+                drm_detected = true;
+            }
+            let urls: Vec<String> = session.execute(MEDIA_URLS_SCRIPT).await.unwrap_or_default();
+            collect_media_urls(&urls, Some(iframe_url), &mut sources, &mut diagnostics, &mut drm_detected);
+        }
+
+        if let Some(content) = &page_html {
+            for source in find_sources_in_content(content) {
+                if !sources.iter().any(|s| s.url == source.url) {
+                    sources.push(source);
+                }
+            }
+        }
+
+        // Let the site's transform script rewrite or filter what was found
+        if let Some(expression) = self.hook.as_ref().and_then(|h| h.transform_expression(&sources)) {
+            if let Some(value) = session.execute::<Value>(&format!("return ({});", expression)).await {
+                sources = sources_from_value(value);
+            }
+        }
+
+        let info = build_video_info(url, title, thumbnail, &sources);
+        if info.sources.is_empty() {
+            // A license request only matters when nothing plays without it
+            if drm_detected {
+                return Err(DownloaderError::DrmProtected("license request observed".to_string()));
+            }
+            diagnostics.check_anti_bot(page_html.as_deref().unwrap_or_default());
+            diagnostics.rule_used = rules::rule_for(url).map(|r| r.name);
+            return Err(DownloaderError::ExtractionFailed(Box::new(diagnostics)));
+        }
+        Ok(info)
+    }
+
+    async fn run_page_hook(&self, session: &Session, sources: &mut Vec<VideoSource>) {
+        let Some(expression) = self.hook.as_ref().and_then(|h| h.page_expression()) else {
+            return;
+        };
+        if let Some(value) = session.execute::<Value>(&format!("return ({});", expression)).await {
+            for source in sources_from_value(value) {
+                if !sources.iter().any(|s| s.url == source.url) {
+                    sources.push(source);
+                }
+            }
+        }
+    }
+}
+
+/// Keep the video URLs among what a page fetched
+fn collect_media_urls(
+    urls: &[String],
+    embed_url: Option<&str>,
+    sources: &mut Vec<VideoSource>,
+    diagnostics: &mut ExtractionDiagnostics,
+    drm_detected: &mut bool,
+) {
+    for url in urls {
+        if drm::is_license_url(url) {
+            *drm_detected = true;
+        }
+
+        let is_video = url.contains(".m3u8") || url.contains(".mp4") || url.contains(".webm");
+        if !is_video {
+            if url.contains(".ts") {
+                diagnostics.segment_responses += 1;
+            }
+            continue;
+        }
+
+        diagnostics.media_responses_seen += 1;
+        if is_ad_url(url) {
+            diagnostics.record_ad_url(url);
+            continue;
+        }
+        if !url.starts_with("http") || sources.iter().any(|s| &s.url == url) {
+            continue;
+        }
+        let source_type = if url.contains(".m3u8") { "hls" } else { "direct" };
+        sources.push(VideoSource {
+            url: url.clone(),
+            quality: extract_quality_from_url(url),
+            source_type: source_type.to_string(),
+            embed_url: embed_url.map(str::to_string),
+            ..Default::default()
+        });
+    }
+}
+
+/// A geckodriver process and the browser session it controls
+struct Session {
+    client: Client,
+    base: String,
+    // Killed on drop
+    _driver: Child,
+}
+
+impl Session {
+    async fn start(headless: bool) -> Result<Self, DownloaderError> {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|l| l.local_addr())
+            .map_err(|e| DownloaderError::Browser(format!("No free port for geckodriver: {}", e)))?
+            .port();
+        let program = FIREFOX
+            .read()
+            .ok()
+            .and_then(|f| f.clone().flatten())
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_GECKODRIVER));
+        let driver = Command::new(&program)
+            .args(["--port", &port.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| DownloaderError::Browser(format!("Failed to start geckodriver (is it installed?): {}", e)))?;
+
+        // The driver only talks to this process, so no DNS or LAN rules
+        let client = Client::builder()
+            .build()
+            .map_err(|e| DownloaderError::Browser(e.to_string()))?;
+        let mut session = Self { client, base: format!("http://127.0.0.1:{}", port), _driver: driver };
+        session.wait_ready().await?;
+
+        let args: Vec<&str> = if headless { vec!["-headless"] } else { Vec::new() };
+        let capabilities = json!({
+            "capabilities": {
+                "alwaysMatch": {
+                    "browserName": "firefox",
+                    "moz:firefoxOptions": { "args": args }
+                }
+            }
+        });
+        let created = session.request(Method::POST, "/session", Some(capabilities)).await?;
+        let id = created
+            .get("sessionId")
+            .and_then(Value::as_str)
+            .ok_or_else(|| DownloaderError::Browser("geckodriver didn't create a session".to_string()))?;
+        session.base = format!("{}/session/{}", session.base, id);
+        Ok(session)
+    }
+
+    async fn wait_ready(&self) -> Result<(), DownloaderError> {
+        let deadline = tokio::time::Instant::now() + DRIVER_START_TIMEOUT;
+        while tokio::time::Instant::now() < deadline {
+            if let Ok(status) = self.request(Method::GET, "/status", None).await {
+                if status.get("ready").and_then(Value::as_bool).unwrap_or(false) {
+                    return Ok(());
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+        Err(DownloaderError::Browser("geckodriver didn't start".to_string()))
+    }
+
+    /// One WebDriver command; returns the response's `value`
+    async fn request(&self, method: Method, path: &str, body: Option<Value>) -> Result<Value, DownloaderError> {
+        let mut request = self.client.request(method, format!("{}{}", self.base, path));
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let response: Value = request.send().await?.json().await?;
+        let value = response.get("value").cloned().unwrap_or(Value::Null);
+        if let Some(error) = value.get("error").and_then(Value::as_str) {
+            let message = value.get("message").and_then(Value::as_str).unwrap_or_default();
+            return Err(DownloaderError::Browser(format!("{}: {}", error, message)));
+        }
+        Ok(value)
+    }
+
+    async fn navigate(&self, url: &str) -> Result<(), DownloaderError> {
+        self.request(Method::POST, "/url", Some(json!({ "url": url }))).await.map(|_| ())
+    }
+
+    async fn execute<T: DeserializeOwned>(&self, script: &str) -> Option<T> {
+        let value = self
+            .request(Method::POST, "/execute/sync", Some(json!({ "script": script, "args": [] })))
+            .await
+            .ok()?;
+        serde_json::from_value(value).ok()
+    }
+
+    async fn close(self) {
+        self.request(Method::DELETE, "", None).await.ok();
+    }
+}
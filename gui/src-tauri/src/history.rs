@@ -0,0 +1,183 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+// Maximum number of non-favorite entries kept in history
+pub const MAX_HISTORY_ITEMS: usize = 100;
+
+// Bytes read from each end of a file for its content hash
+const HASH_SAMPLE_BYTES: u64 = 1024 * 1024;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryItem {
+    pub id: String,
+    pub url: String,
+    pub title: String,
+    pub thumbnail: String,
+    pub filename: String,
+    pub quality: String,
+    pub downloaded_at: String,
+    pub file_path: String,
+    pub file_size: Option<u64>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub watched: bool,
+    /// The downloaded file was removed via `history_delete_file`
+    #[serde(default)]
+    pub file_deleted: bool,
+    /// Size before a compression preset was applied
+    #[serde(default)]
+    pub original_size: Option<u64>,
+    /// Id of the compression preset applied to the file, if any
+    #[serde(default)]
+    pub compressed_with: Option<String>,
+    /// `content_hash` of the file when it was added
+    #[serde(default)]
+    pub content_hash: Option<String>,
+}
+
+/// Criteria for `history_filter`. Unset fields match everything.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryFilter {
+    pub tag: Option<String>,
+    pub favorite: Option<bool>,
+    pub watched: Option<bool>,
+    pub query: Option<String>,
+}
+
+impl HistoryFilter {
+    pub fn matches(&self, item: &HistoryItem) -> bool {
+        if let Some(ref tag) = self.tag {
+            if !item.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                return false;
+            }
+        }
+
+        if let Some(favorite) = self.favorite {
+            if item.favorite != favorite {
+                return false;
+            }
+        }
+
+        if let Some(watched) = self.watched {
+            if item.watched != watched {
+                return false;
+            }
+        }
+
+        if let Some(ref query) = self.query {
+            let query = query.to_lowercase();
+            if !item.title.to_lowercase().contains(&query)
+                && !item.filename.to_lowercase().contains(&query)
+            {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+pub fn load_history(path: &Path) -> Result<Vec<HistoryItem>, String> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read history: {}", e))?;
+
+    Ok(serde_json::from_str(&content).unwrap_or_default())
+}
+
+pub fn save_history(path: &Path, history: &[HistoryItem]) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(history)
+        .map_err(|e| format!("Failed to serialize history: {}", e))?;
+
+    fs::write(path, content)
+        .map_err(|e| format!("Failed to write history: {}", e))
+}
+
+/// Keep the newest MAX_HISTORY_ITEMS entries, but never drop favorites
+pub fn truncate_history(history: &mut Vec<HistoryItem>) {
+    let mut kept = 0;
+    history.retain(|item| {
+        if item.favorite {
+            return true;
+        }
+        kept += 1;
+        kept <= MAX_HISTORY_ITEMS
+    });
+}
+
+/// Load history, apply `update` to the entry with `id`, and save it back.
+/// Returns false if no entry matched.
+pub fn update_history_item(
+    path: &Path,
+    id: &str,
+    update: impl FnOnce(&mut HistoryItem),
+) -> Result<bool, String> {
+    let mut history = load_history(path)?;
+
+    let Some(item) = history.iter_mut().find(|item| item.id == id) else {
+        return Ok(false);
+    };
+    update(item);
+
+    save_history(path, &history)?;
+    Ok(true)
+}
+
+/// Fast fingerprint of a file: SHA-256 of its size and the first and last
+/// HASH_SAMPLE_BYTES, so gigabyte videos are compared without reading them
+pub fn content_hash(path: &Path) -> Result<String, String> {
+    let mut file = fs::File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+    let size = file.metadata().map_err(|e| format!("Failed to read file: {}", e))?.len();
+
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    context.update(&size.to_le_bytes());
+
+    let mut sample = Vec::new();
+    (&mut file)
+        .take(HASH_SAMPLE_BYTES)
+        .read_to_end(&mut sample)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    context.update(&sample);
+
+    if size > HASH_SAMPLE_BYTES {
+        sample.clear();
+        file.seek(SeekFrom::Start((size - HASH_SAMPLE_BYTES).max(HASH_SAMPLE_BYTES)))
+            .and_then(|_| file.read_to_end(&mut sample))
+            .map_err(|e| format!("Failed to read file: {}", e))?;
+        context.update(&sample);
+    }
+
+    Ok(hex::encode(context.finish()))
+}
+
+/// An entry with the same content hash whose file is still on disk under
+/// another path
+pub fn find_duplicate<'a>(history: &'a [HistoryItem], hash: &str, file_path: &str) -> Option<&'a HistoryItem> {
+    history.iter().find(|item| {
+        item.content_hash.as_deref() == Some(hash)
+            && !item.file_deleted
+            && item.file_path != file_path
+            && Path::new(&item.file_path).is_file()
+    })
+}
+
+/// Normalize user-entered tags: trimmed, non-empty, deduplicated (case-insensitive)
+pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut result: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().to_string();
+        if !tag.is_empty() && !result.iter().any(|t| t.eq_ignore_ascii_case(&tag)) {
+            result.push(tag);
+        }
+    }
+    result
+}
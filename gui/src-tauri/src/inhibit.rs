@@ -0,0 +1,93 @@
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::queue::QueueItemStatus;
+use crate::AppState;
+
+// How often the queue is checked for active items
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Keeps the system awake while held. The OS tool runs as a child process
+/// and the inhibition ends when it is killed.
+pub struct SleepInhibitor(Child);
+
+impl SleepInhibitor {
+    pub fn acquire() -> Result<Self, String> {
+        platform_command()
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map(SleepInhibitor)
+            .map_err(|e| format!("Failed to inhibit sleep: {}", e))
+    }
+}
+
+impl Drop for SleepInhibitor {
+    fn drop(&mut self) {
+        self.0.kill().ok();
+        self.0.wait().ok();
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn platform_command() -> Command {
+    // ES_CONTINUOUS | ES_SYSTEM_REQUIRED holds for as long as the calling
+    // thread lives, so the script waits, and quits with the app if it dies
+    // without cleanup
+    let script = format!(
+        "Add-Type -Name Power -Namespace Win32 -MemberDefinition '[DllImport(\"kernel32.dll\")] public static extern uint SetThreadExecutionState(uint esFlags);'; \
+        [void][Win32.Power]::SetThreadExecutionState(0x80000001); while (Get-Process -Id {} -ErrorAction SilentlyContinue) {{ Start-Sleep -Seconds 5 }}",
+        std::process::id()
+    );
+    let mut command = crate::background_command("powershell");
+    command.args(["-NoProfile", "-NonInteractive", "-WindowStyle", "Hidden", "-Command", &script]);
+    command
+}
+
+#[cfg(target_os = "macos")]
+fn platform_command() -> Command {
+    // -i: prevent idle sleep; -w: also end if the app dies without cleanup
+    let mut command = Command::new("caffeinate");
+    command.args(["-i", "-w", &std::process::id().to_string()]);
+    command
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn platform_command() -> Command {
+    // tail --pid also ends the lock if the app dies without cleanup
+    let mut command = Command::new("systemd-inhibit");
+    command.args([
+        "--what=sleep:idle",
+        "--who=Thai Video Downloader",
+        "--why=Downloads in progress",
+        "--mode=block",
+        "tail",
+        "-f",
+        "/dev/null",
+        &format!("--pid={}", std::process::id()),
+    ]);
+    command
+}
+
+/// Hold a sleep inhibitor while the queue has downloading or processing
+/// items and release it when idle
+pub async fn watch(state: Arc<AppState>) {
+    let mut inhibitor: Option<SleepInhibitor> = None;
+    loop {
+        let enabled = state.settings.read().await.prevent_sleep;
+        let active = enabled
+            && state.queue.get_items().await.iter().any(|i| {
+                matches!(i.status, QueueItemStatus::Downloading | QueueItemStatus::Processing)
+            });
+
+        match (active, inhibitor.is_some()) {
+            (true, false) => inhibitor = SleepInhibitor::acquire().ok(),
+            (false, true) => inhibitor = None,
+            _ => {}
+        }
+
+        tokio::time::sleep(CHECK_INTERVAL).await;
+    }
+}
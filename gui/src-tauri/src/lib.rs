@@ -1,27 +1,336 @@
-mod downloader;
+mod backup;
+mod checksum;
+mod credentials;
+pub mod downloader;
+mod history;
+mod inhibit;
+mod library;
+mod network;
+mod players;
+mod power;
+mod postprocess;
+mod progress;
 mod queue;
-
+mod recovery;
+mod remote;
+mod remote_output;
+mod season_pack;
+mod site_stats;
+mod trash;
+mod ui_state;
+mod undo;
+mod upload;
+
+use base64::Engine;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tauri::{Emitter, Manager, State};
+use tauri_plugin_dialog::{DialogExt, FilePath};
 use tokio::sync::RwLock;
 
-use queue::{DownloadQueue, QueueItem, QueueItemStatus, QueueProgress};
-
-use downloader::video::VideoDownloader;
+pub use history::{HistoryFilter, HistoryItem};
+use backup::{BackupContents, BackupSummary};
+use checksum::ChecksumReport;
+use credentials::{CredentialSummary, SiteCredential};
+use library::LibraryEntry;
+use postprocess::{CompressionPreset, PostProcessJob, PostProcessQueue, PostProcessSpec, DEFAULT_MAX_CONCURRENT_POSTPROCESS};
+use recovery::RecoveryReport;
+use remote_output::RemoteOutput;
+use site_stats::SiteStats;
+use upload::{UploadDestination, UploadProgress};
+use progress::{ProgressThrottle, SpeedMeter, PROGRESS_INTERVAL, SPEED_SAMPLE_INTERVAL};
+use queue::{
+    DownloadQueue, GroupProgress, MultipartMerge, QueueItem, QueueItemOptions, QueueItemStatus, QueueProgress, QueueSnapshot,
+    QueueSummary, TransferStats,
+};
+use ui_state::UiState;
+use undo::{Removed, UndoBuffer};
+
+use downloader::ads;
+use downloader::aria2::{self, Aria2Client, Aria2Config};
+use downloader::audio;
+use downloader::bandwidth::{self, BandwidthScheduler, SpeedRule};
+use downloader::browser::{self, BrowserPool};
+use downloader::chromium;
+use downloader::diagnostics::ExtractionDiagnostics;
+use downloader::encoders;
+use downloader::explain::{self, ErrorHelp};
+use downloader::ffmpeg;
+use downloader::hls::{DEFAULT_SEGMENT_BUFFER_MB, DEFAULT_SEGMENT_WORKERS};
+use downloader::hooks::{self, SiteHook};
+use downloader::log::{DownloadLog, LogEntry};
+use downloader::dns::{self, NetworkConfig};
+use downloader::http_extractor::{HttpExtractor, RuleMatch};
+use downloader::lan;
+use downloader::lane;
+use downloader::playlist::{self, PlaylistEntry};
+use downloader::probe;
+use downloader::rule_updates::{self, RemoteRuleSet};
+use downloader::rules::{self, ExtractorRule};
+use downloader::scoring::{self, SourcePreferences};
+use downloader::segment_cache::{CacheStats, SegmentCache, DEFAULT_SEGMENT_CACHE_MB};
+use downloader::series::{self, SeriesEpisode, SeriesExtractor};
+use downloader::size::{self, SizeEstimate};
+use downloader::thumbnails;
+use downloader::naming::{self, EpisodeInfo, NfoMetadata};
+use downloader::transliterate;
+use downloader::watchdog::{self, ExtractionWaits, Timeouts};
+use downloader::video::{remap_quality, QualityFallback, SelectedOrigin, VideoDownloader};
+use downloader::webdriver;
+use downloader::{output_file_path, DownloaderError, SourceOrigin, VideoInfo};
+use futures::StreamExt;
 
 // App Settings
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AppSettings {
     pub default_download_dir: String,
     pub default_quality: String,
     pub max_concurrent_downloads: usize,
+    /// Cap on simultaneous downloads from the same site
+    pub max_downloads_per_host: usize,
+    /// Hours after which a queued item's page is extracted again before it
+    /// starts, since its stream links have likely expired; 0 never re-checks
+    pub stale_source_hours: u64,
+    /// Index pages followed when collecting a series' episode links
+    pub series_max_pages: usize,
     pub auto_start_queue: bool,
     pub show_notifications: bool,
     pub minimize_to_tray: bool,
     pub theme: String,
+    pub segment_workers: usize,
+    /// Memory cap (MB) for HLS segments fetched ahead of the disk writer
+    pub segment_buffer_mb: usize,
+    /// Seconds before a page extraction is abandoned; 0 waits forever
+    pub extraction_timeout_secs: u64,
+    /// Seconds one HLS segment may take to download; 0 waits forever
+    pub segment_timeout_secs: u64,
+    /// Seconds without a single byte before a transfer reconnects, and
+    /// fails after a few tries; 0 waits forever
+    pub stall_timeout_secs: u64,
+    /// Milliseconds a browser extraction waits for the page to load
+    pub page_load_wait_ms: u64,
+    /// Milliseconds it waits for each player iframe to load
+    pub iframe_wait_ms: u64,
+    /// Milliseconds it waits after clicking play for the stream request
+    pub playback_wait_ms: u64,
+    /// Keep fetched HLS segments so re-downloading the same video reuses them
+    pub segment_cache_enabled: bool,
+    /// Size limit of the segment cache, MB
+    pub segment_cache_mb: u64,
+    /// Separate audio tracks to keep: "default", "all" or a language code
+    pub audio_tracks: String,
+    /// Save downloads exactly as received (.ts / fragmented .mp4) and skip
+    /// every ffmpeg step, so it also works without ffmpeg
+    pub raw_passthrough: bool,
+    /// fsync finished downloads to disk before reporting completion
+    pub fsync_on_complete: bool,
+    /// Abort downloads bigger than this many MB; 0 means no limit
+    pub max_file_size_mb: u64,
+    /// Write a `<file>.sha256` sidecar next to every finished download
+    pub write_checksums: bool,
+    /// Delete leftover HLS scratch files from the app's scratch dir at
+    /// startup and periodically, instead of asking about them
+    pub auto_clean_temp_files: bool,
+    /// "flat" or "media_server" (Jellyfin/Plex folders, names and .nfo files)
+    pub output_layout: String,
+    /// Output filename pattern: {title}, {series}, {season}, {episode}
+    pub filename_template: String,
+    /// Thai filename handling: "off", "romanize" or "slug" (ASCII slug + id)
+    pub filename_transliteration: String,
+    /// Max output filename length in bytes, to stay under path limits
+    pub max_filename_length: usize,
+    /// Player used by "open with" when none is given ("vlc", "mpv", ...)
+    pub default_player: String,
+    /// Executable paths for players that aren't on PATH (name -> path)
+    pub media_players: std::collections::BTreeMap<String, String>,
+    /// Preferred quality per site (domain -> "1080p"), applied to "auto"
+    pub site_quality: std::collections::BTreeMap<String, String>,
+    /// Token-protected HTTP API for controlling the queue from the LAN
+    pub remote_api_enabled: bool,
+    pub remote_api_port: u16,
+    pub remote_api_token: String,
+    /// Listen on every network interface; otherwise only this machine can connect
+    pub remote_api_lan: bool,
+    /// "builtin" or "aria2" (transfers go to a running aria2c RPC server)
+    pub download_backend: String,
+    pub aria2_rpc_url: String,
+    pub aria2_rpc_secret: String,
+    pub aria2_connections: usize,
+    /// Speed-test mirrors of the same quality and use the fastest
+    pub smart_source_selection: bool,
+    /// Request every source when fetching info: dead links are dropped and
+    /// the rest listed best first with their sizes
+    pub check_sources: bool,
+    /// Download the next lower quality when the chosen one keeps returning
+    /// 404 or stalling, instead of failing
+    pub allow_quality_fallback: bool,
+    /// "auto" quality picks the HLS variant the connection downloads faster
+    /// than real time instead of the highest one
+    pub auto_quality_by_speed: bool,
+    /// "any", "direct" (prefer mp4) or "hls"
+    pub source_type_preference: String,
+    /// Hosts to pick first when several sources are available, best first
+    pub preferred_hosts: Vec<String>,
+    /// Hosts to use only when nothing else is available
+    pub avoided_hosts: Vec<String>,
+    /// Remux direct downloads that aren't MP4 (webm, mkv, ts) to MP4
+    pub remux_to_mp4: bool,
+    /// Web-optimized MP4s (-movflags +faststart) that start playing at once
+    /// over network shares and the preview server
+    pub faststart_mp4: bool,
+    /// Conversions running at once, apart from the download slots
+    pub max_concurrent_postprocess: usize,
+    /// Transcode finished downloads instead of only remuxing them
+    pub reencode_output: bool,
+    /// Encoder family for re-encoding: auto, software, nvenc, qsv,
+    /// videotoolbox or amf. Hardware falls back to software on failure.
+    pub video_encoder: String,
+    /// Hardcode a subtitle saved next to the download into the picture, for
+    /// TVs that can't render external Thai subtitles
+    pub burn_subtitles: bool,
+    /// Font for burned subtitles; empty uses the libass default
+    pub subtitle_font: String,
+    /// Normalize audio that is far too quiet or loud after download
+    pub normalize_loudness: bool,
+    /// Total download speed shared by all active downloads, KB/s (0 = unlimited)
+    pub max_total_speed_kbps: u64,
+    /// Give the download highest in the queue half of the total speed
+    pub prioritize_top_download: bool,
+    /// Time-of-day limits that replace max_total_speed_kbps while they apply
+    pub speed_schedule: Vec<SpeedRule>,
+    /// Pause the queue on metered connections and resume on unmetered ones
+    pub pause_on_metered: bool,
+    /// On battery below battery_threshold percent: "off", "pause" or
+    /// "throttle" to battery_throttle_kbps. Chromium extraction is skipped
+    /// unless it's "off".
+    pub battery_mode: String,
+    pub battery_threshold: u8,
+    pub battery_throttle_kbps: u64,
+    /// Keep the computer awake while the queue is downloading
+    pub prevent_sleep: bool,
+    /// Extract in a visible browser window by default; some CAPTCHA-walled
+    /// sites only work that way
+    pub show_browser: bool,
+    /// Chrome/Chromium/Edge binary used for extraction; empty auto-detects
+    pub browser_executable: String,
+    /// Extra launch flags, e.g. --no-sandbox on some Linux setups
+    pub browser_args: Vec<String>,
+    /// Delete files outright instead of moving them to the trash / recycle
+    /// bin; internal temp files are always deleted outright
+    pub permanent_delete: bool,
+    /// Extraction browser: chromium, or firefox through geckodriver for
+    /// sites that block headless Chromium
+    pub browser_backend: String,
+    /// geckodriver binary for the firefox backend; empty looks it up on PATH
+    pub geckodriver_path: String,
+    /// DNS-over-HTTPS resolver for hosts the ISP's DNS blocks; empty uses
+    /// the system resolver
+    pub dns_over_https: String,
+    /// Host -> IP mappings used instead of any DNS lookup
+    pub dns_overrides: std::collections::BTreeMap<String, String>,
+    /// "any", "ipv4" or "ipv6": address family tried first
+    pub ip_preference: String,
+    /// NAS/media servers on the LAN that may be downloaded from despite the
+    /// private address block: hostnames, IPs or CIDR blocks
+    pub lan_allowlist: Vec<String>,
+    /// Fetch signed ad patterns and extractor rules published between releases
+    pub rule_updates_enabled: bool,
+    pub rule_updates_url: String,
+    /// Publisher's Ed25519 public key (hex) the manifest must be signed with
+    pub rule_updates_public_key: String,
+    /// S3/WebDAV targets finished downloads are copied to when enabled
+    pub upload_destinations: Vec<UploadDestination>,
+    /// Remove the local file once every enabled destination has a verified copy
+    pub upload_delete_local: bool,
+    /// Window placement and last picked folder/quality, see ui_state::UiState
+    pub ui_state: UiState,
+}
+
+impl AppSettings {
+    fn source_preferences(&self) -> SourcePreferences {
+        SourcePreferences {
+            source_type: self.source_type_preference.clone(),
+            preferred_hosts: self.preferred_hosts.clone(),
+            avoided_hosts: self.avoided_hosts.clone(),
+        }
+    }
+
+    /// `bandwidth` gives the speed limit in force right now
+    fn aria2_config(&self, bandwidth: &BandwidthScheduler) -> Option<Aria2Config> {
+        (self.download_backend == aria2::BACKEND_ARIA2).then(|| Aria2Config {
+            rpc_url: self.aria2_rpc_url.clone(),
+            secret: self.aria2_rpc_secret.clone(),
+            connections: self.aria2_connections,
+            max_overall_limit: bandwidth.total(),
+        })
+    }
+
+    /// Whether an item queued this long ago needs its sources checked again
+    fn is_stale(&self, item: &QueueItem) -> bool {
+        let limit = std::time::Duration::from_secs(self.stale_source_hours * 3600);
+        self.stale_source_hours > 0 && item.age().is_some_and(|age| age >= limit)
+    }
+
+    fn timeouts(&self) -> Timeouts {
+        Timeouts::from_secs(self.extraction_timeout_secs, self.segment_timeout_secs, self.stall_timeout_secs)
+    }
+
+    /// Segment cache limit in MB; 0 when the cache is off
+    fn segment_cache_limit(&self) -> u64 {
+        if self.segment_cache_enabled {
+            self.segment_cache_mb
+        } else {
+            0
+        }
+    }
+
+    /// Size cap in bytes for one download, with an item's own MB value
+    /// taking precedence; None when there is no cap
+    fn max_file_size(&self, item_mb: Option<u64>) -> Option<u64> {
+        let mb = item_mb.unwrap_or(self.max_file_size_mb);
+        (mb > 0).then(|| mb.saturating_mul(1024 * 1024))
+    }
+
+    /// Copy without the secrets kept in the clear: the remote API token and
+    /// the aria2 RPC secret. Upload passwords are sealed elsewhere and only
+    /// their references are left in settings.
+    fn without_secrets(&self) -> Self {
+        Self {
+            remote_api_token: String::new(),
+            aria2_rpc_secret: String::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Total speed limit for the current local time, KB/s
+    fn current_speed_limit(&self) -> u64 {
+        use chrono::Timelike;
+        let now = chrono::Local::now();
+        bandwidth::scheduled_limit(&self.speed_schedule, now.hour() * 60 + now.minute())
+            .unwrap_or(self.max_total_speed_kbps)
+    }
+
+    fn postprocess_spec(&self, input: &Path) -> PostProcessSpec {
+        PostProcessSpec {
+            input: input.to_string_lossy().to_string(),
+            faststart: self.faststart_mp4,
+            reencode: self.reencode_output,
+            encoder: self.video_encoder.clone(),
+            preset: None,
+            burn_subtitles: self
+                .burn_subtitles
+                .then(|| postprocess::find_subtitle(input))
+                .flatten()
+                .map(|p| p.to_string_lossy().to_string()),
+            subtitle_font: self.subtitle_font.clone(),
+            extra_args: Vec::new(),
+            loudnorm: self.normalize_loudness,
+        }
+    }
 }
 
 impl Default for AppSettings {
@@ -35,10 +344,83 @@ impl Default for AppSettings {
             default_download_dir: download_dir,
             default_quality: "auto".to_string(),
             max_concurrent_downloads: 2,
+            max_downloads_per_host: queue::DEFAULT_MAX_PER_HOST,
+            stale_source_hours: queue::DEFAULT_STALE_SOURCE_HOURS,
+            series_max_pages: series::DEFAULT_MAX_PAGES,
             auto_start_queue: true,
             show_notifications: true,
             minimize_to_tray: false,
             theme: "dark".to_string(),
+            segment_workers: DEFAULT_SEGMENT_WORKERS,
+            segment_buffer_mb: DEFAULT_SEGMENT_BUFFER_MB,
+            extraction_timeout_secs: watchdog::DEFAULT_EXTRACTION_TIMEOUT_SECS,
+            segment_timeout_secs: watchdog::DEFAULT_SEGMENT_TIMEOUT_SECS,
+            stall_timeout_secs: watchdog::DEFAULT_STALL_TIMEOUT_SECS,
+            page_load_wait_ms: watchdog::DEFAULT_PAGE_LOAD_WAIT_MS,
+            iframe_wait_ms: watchdog::DEFAULT_IFRAME_LOAD_WAIT_MS,
+            playback_wait_ms: watchdog::DEFAULT_PLAYBACK_WAIT_MS,
+            segment_cache_enabled: true,
+            segment_cache_mb: DEFAULT_SEGMENT_CACHE_MB,
+            audio_tracks: audio::AUDIO_DEFAULT.to_string(),
+            raw_passthrough: false,
+            fsync_on_complete: false,
+            max_file_size_mb: 0,
+            write_checksums: false,
+            auto_clean_temp_files: true,
+            output_layout: naming::LAYOUT_FLAT.to_string(),
+            filename_template: naming::DEFAULT_FILENAME_TEMPLATE.to_string(),
+            filename_transliteration: transliterate::MODE_OFF.to_string(),
+            max_filename_length: downloader::DEFAULT_MAX_FILENAME_LENGTH,
+            default_player: players::PLAYER_VLC.to_string(),
+            media_players: std::collections::BTreeMap::new(),
+            site_quality: std::collections::BTreeMap::new(),
+            remote_api_enabled: false,
+            remote_api_port: remote::DEFAULT_PORT,
+            remote_api_token: String::new(),
+            remote_api_lan: false,
+            download_backend: aria2::BACKEND_BUILTIN.to_string(),
+            aria2_rpc_url: aria2::DEFAULT_RPC_URL.to_string(),
+            aria2_rpc_secret: String::new(),
+            aria2_connections: aria2::DEFAULT_CONNECTIONS,
+            smart_source_selection: false,
+            check_sources: false,
+            allow_quality_fallback: true,
+            auto_quality_by_speed: true,
+            source_type_preference: scoring::PREFER_ANY.to_string(),
+            preferred_hosts: Vec::new(),
+            avoided_hosts: Vec::new(),
+            remux_to_mp4: false,
+            faststart_mp4: false,
+            max_concurrent_postprocess: DEFAULT_MAX_CONCURRENT_POSTPROCESS,
+            reencode_output: false,
+            video_encoder: encoders::ENCODER_AUTO.to_string(),
+            burn_subtitles: false,
+            subtitle_font: String::new(),
+            normalize_loudness: false,
+            max_total_speed_kbps: 0,
+            prioritize_top_download: false,
+            speed_schedule: Vec::new(),
+            pause_on_metered: false,
+            battery_mode: power::BATTERY_IGNORE.to_string(),
+            battery_threshold: power::DEFAULT_BATTERY_THRESHOLD,
+            battery_throttle_kbps: power::DEFAULT_BATTERY_THROTTLE_KBPS,
+            prevent_sleep: true,
+            show_browser: false,
+            browser_executable: String::new(),
+            browser_args: Vec::new(),
+            permanent_delete: false,
+            browser_backend: webdriver::BACKEND_CHROMIUM.to_string(),
+            geckodriver_path: String::new(),
+            dns_over_https: String::new(),
+            dns_overrides: std::collections::BTreeMap::new(),
+            ip_preference: dns::IP_ANY.to_string(),
+            lan_allowlist: Vec::new(),
+            rule_updates_enabled: false,
+            rule_updates_url: rule_updates::DEFAULT_MANIFEST_URL.to_string(),
+            rule_updates_public_key: String::new(),
+            upload_destinations: Vec::new(),
+            upload_delete_local: false,
+            ui_state: UiState::default(),
         }
     }
 }
@@ -46,16 +428,168 @@ impl Default for AppSettings {
 // Shared state wrapper
 pub struct AppState {
     pub queue: DownloadQueue,
+    pub postprocess: PostProcessQueue,
+    pub bandwidth: Arc<BandwidthScheduler>,
+    /// HLS segments kept for repeated downloads
+    pub segment_cache: Arc<SegmentCache>,
+    /// Items paused automatically, by reason (PAUSE_*)
+    pub auto_paused: tokio::sync::Mutex<std::collections::BTreeMap<String, Vec<String>>>,
+    /// On battery below the threshold; set by power::watch
+    pub low_battery: AtomicBool,
+    /// Exit was requested and the queue is being saved
+    pub shutdown_started: AtomicBool,
+    /// Set once the queue has been saved and the app may exit
+    pub shutdown_done: AtomicBool,
+    /// Event trail of each queue item's download, by queue id
+    pub download_logs: tokio::sync::Mutex<std::collections::HashMap<String, DownloadLog>>,
+    /// What the last crash left behind, until resumed or cleaned up
+    pub recovery: tokio::sync::Mutex<RecoveryReport>,
     pub settings: RwLock<AppSettings>,
+    pub browser_pool: Arc<BrowserPool>,
+    pub remote_server: tokio::sync::Mutex<Option<tokio::task::JoinHandle<()>>>,
+    /// Mirror of progress events for remote WebSocket subscribers
+    pub events: tokio::sync::broadcast::Sender<RemoteEvent>,
+    /// Recently removed queue items and history entries
+    pub undo: UndoBuffer,
 }
 
 impl AppState {
     pub fn new() -> Self {
         Self {
             queue: DownloadQueue::new(),
+            postprocess: PostProcessQueue::new(),
+            bandwidth: Arc::new(BandwidthScheduler::new()),
+            segment_cache: Arc::new(SegmentCache::in_temp_dir(DEFAULT_SEGMENT_CACHE_MB)),
+            auto_paused: tokio::sync::Mutex::new(std::collections::BTreeMap::new()),
+            low_battery: AtomicBool::new(false),
+            shutdown_started: AtomicBool::new(false),
+            shutdown_done: AtomicBool::new(false),
+            download_logs: tokio::sync::Mutex::new(std::collections::HashMap::new()),
+            recovery: tokio::sync::Mutex::new(RecoveryReport::default()),
             settings: RwLock::new(AppSettings::default()),
+            browser_pool: Arc::new(BrowserPool::new(true, 3)),
+            remote_server: tokio::sync::Mutex::new(None),
+            events: tokio::sync::broadcast::channel(256).0,
+            undo: UndoBuffer::default(),
+        }
+    }
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Version of the event payload contract. Bump it when a payload changes
+/// in a way existing listeners can't read; adding fields doesn't count.
+pub const EVENT_VERSION: u32 = 1;
+
+/// What the frontend receives for every event
+#[derive(Clone, Debug, Serialize)]
+pub struct EventEnvelope<T> {
+    pub event_version: u32,
+    pub payload: T,
+}
+
+/// What remote WebSocket clients receive: the envelope plus the event name
+#[derive(Clone, Debug, Serialize)]
+pub struct RemoteEvent {
+    pub event: String,
+    pub event_version: u32,
+    pub payload: serde_json::Value,
+}
+
+/// Emit an event to the frontend and mirror it to remote WebSocket clients
+pub(crate) fn emit_event<S: Serialize + Clone>(app: &tauri::AppHandle, event: &str, payload: S) {
+    if let Some(state) = app.try_state::<Arc<AppState>>() {
+        if state.events.receiver_count() > 0 {
+            if let Ok(payload) = serde_json::to_value(&payload) {
+                let _ = state.events.send(RemoteEvent {
+                    event: event.to_string(),
+                    event_version: EVENT_VERSION,
+                    payload,
+                });
+            }
+        }
+    }
+
+    let _ = app.emit(event, EventEnvelope { event_version: EVENT_VERSION, payload });
+}
+
+/// Command for a helper tool run in the background. On Windows it gets no
+/// console window, which would otherwise flash up on every call.
+pub(crate) fn background_command(program: &str) -> std::process::Command {
+    #[allow(unused_mut)]
+    let mut command = std::process::Command::new(program);
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+    command
+}
+
+/// Sent as `chromium-download-progress` while the fallback browser downloads
+#[derive(Clone, Serialize)]
+pub struct ChromiumDownloadProgress {
+    pub downloaded: u64,
+    pub total: u64,
+    pub progress: f32,
+    pub message: String,
+}
+
+// Reasons for pausing the queue automatically (AppState::auto_paused)
+pub const PAUSE_METERED: &str = "metered";
+pub const PAUSE_BATTERY: &str = "battery";
+
+/// Sent as `queue-auto-paused` when the queue is held or released
+#[derive(Clone, Serialize)]
+pub struct AutoPauseEvent {
+    pub reason: String,
+    pub paused: bool,
+    pub count: usize,
+}
+
+/// Hold the queue for `reason`: pause what's downloading or waiting and
+/// remember it for auto_resume
+pub async fn auto_pause(app: &tauri::AppHandle, state: &AppState, reason: &str) {
+    let mut held = state.auto_paused.lock().await;
+    if held.contains_key(reason) {
+        return;
+    }
+    let ids = state.queue.pause_unfinished().await;
+    emit_event(app, "queue-auto-paused", AutoPauseEvent {
+        reason: reason.to_string(),
+        paused: true,
+        count: ids.len(),
+    });
+    held.insert(reason.to_string(), ids);
+}
+
+/// Release the hold for `reason` and resume what it paused, unless another
+/// reason still holds the queue
+pub async fn auto_resume(app: &tauri::AppHandle, state: &AppState, reason: &str) {
+    let mut held = state.auto_paused.lock().await;
+    let Some(ids) = held.remove(reason) else {
+        return;
+    };
+    let count = ids.len();
+    match held.values_mut().next() {
+        // The remaining reason resumes them when it clears
+        Some(other) => other.extend(ids),
+        None => {
+            for id in &ids {
+                state.queue.resume_download(id).await;
+            }
         }
     }
+    emit_event(app, "queue-auto-paused", AutoPauseEvent {
+        reason: reason.to_string(),
+        paused: !held.is_empty(),
+        count,
+    });
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -66,6 +600,31 @@ pub struct DownloadProgress {
     pub filename: Option<String>,
 }
 
+/// Progress of a post-processing run on a history entry
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PostProcessProgress {
+    pub id: String,
+    pub progress: f32,
+    pub message: String,
+}
+
+/// A download moved on to a lower quality; `id` is the queue item, None
+/// for a direct download
+#[derive(Clone, Serialize)]
+pub struct QualityFallbackEvent {
+    pub id: Option<String>,
+    pub title: String,
+    pub from: String,
+    pub to: String,
+    pub reason: String,
+}
+
+impl QualityFallbackEvent {
+    fn new(id: Option<String>, title: &str, fallback: QualityFallback) -> Self {
+        Self { id, title: title.to_string(), from: fallback.from, to: fallback.to, reason: fallback.reason }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct VideoInfoResponse {
     pub url: String,
@@ -82,19 +641,112 @@ pub struct VideoSourceResponse {
     pub quality: String,
     #[serde(rename = "type")]
     pub source_type: String,
+    pub codec: Option<String>,
+    pub container: Option<String>,
+    pub bandwidth: Option<u64>,
+    /// Bytes and seconds, filled in when sources are checked
+    pub size: Option<u64>,
+    pub duration: Option<f64>,
+    pub latency_ms: Option<u64>,
+    pub host: Option<String>,
+    /// Page, player embed and extraction time the source came from
+    pub origin: SourceOrigin,
+}
+
+impl From<VideoInfo> for VideoInfoResponse {
+    fn from(info: VideoInfo) -> Self {
+        let sources = info.sources
+            .iter()
+            .map(|s| VideoSourceResponse {
+                url: s.url.clone(),
+                quality: s.quality.clone(),
+                source_type: s.source_type.clone(),
+                codec: s.codec.clone(),
+                container: s.container.clone(),
+                bandwidth: s.bandwidth,
+                size: s.size,
+                duration: s.duration,
+                latency_ms: s.latency_ms,
+                host: s.host(),
+                origin: s.origin(),
+            })
+            .collect();
+
+        Self {
+            url: info.url,
+            title: info.title,
+            thumbnail: info.thumbnail,
+            duration: info.duration,
+            qualities: info.qualities,
+            sources,
+        }
+    }
 }
 
+// video-info-progress statuses
+pub const INFO_STARTED: &str = "started";
+pub const INFO_COMPLETED: &str = "completed";
+pub const INFO_FAILED: &str = "failed";
+
+/// Progress of one get_video_info call. Several can run at once, so each
+/// carries the id the caller passed (or one made up for it).
 #[derive(Clone, Serialize, Deserialize)]
-pub struct HistoryItem {
-    pub id: String,
+pub struct VideoInfoProgress {
+    pub request_id: String,
     pub url: String,
-    pub title: String,
-    pub thumbnail: String,
-    pub filename: String,
-    pub quality: String,
-    pub downloaded_at: String,
-    pub file_path: String,
-    pub file_size: Option<u64>,
+    /// INFO_STARTED, INFO_COMPLETED or INFO_FAILED
+    pub status: String,
+    pub message: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct VideoInfoBatchResult {
+    pub index: usize,
+    pub url: String,
+    pub info: Option<VideoInfoResponse>,
+    pub error: Option<String>,
+    /// Why no sources were found, when extraction came up empty
+    pub diagnostics: Option<ExtractionDiagnostics>,
+}
+
+/// Final output location of a download after applying the filename
+/// template and output layout settings
+struct OutputTarget {
+    dir: String,
+    filename: String,
+    episode: Option<EpisodeInfo>,
+    /// SFTP/FTP folder the finished file is sent to; `dir` is then a
+    /// local staging folder
+    remote: Option<RemoteOutput>,
+}
+
+fn prepare_output(
+    settings: &AppSettings,
+    output_dir: &str,
+    filename: &str,
+    title: &str,
+    episode: Option<EpisodeInfo>,
+) -> Result<OutputTarget, String> {
+    // Fall back to parsing "ตอนที่ 5" / "EP.5" from the page title
+    let episode = episode.or_else(|| naming::parse_episode_title(title));
+
+    let filename = naming::apply_filename_template(&settings.filename_template, filename, episode.as_ref());
+
+    let remote = RemoteOutput::parse(output_dir)?;
+    let local_dir = match &remote {
+        Some(remote) => remote.staging_dir().to_string_lossy().to_string(),
+        None => output_dir.to_string(),
+    };
+
+    let (dir, filename) = naming::resolve_output(
+        &settings.output_layout,
+        &local_dir,
+        &filename,
+        episode.as_ref(),
+    )
+    .map_err(|e| format!("Failed to prepare output folder: {}", e))?;
+
+    Ok(OutputTarget { dir, filename, episode, remote })
 }
 
 fn get_history_path(app: &tauri::AppHandle) -> PathBuf {
@@ -103,73 +755,238 @@ fn get_history_path(app: &tauri::AppHandle) -> PathBuf {
     app_dir.join("download_history.json")
 }
 
-#[tauri::command]
-async fn get_video_info(app: tauri::AppHandle, url: String) -> Result<VideoInfoResponse, String> {
-    let _ = app.emit("download-progress", DownloadProgress {
-        status: "info".to_string(),
-        progress: 0.0,
-        message: "กำลังดึงข้อมูลวิดีโอ...".to_string(),
-        filename: None,
+fn get_site_stats_path(app: &tauri::AppHandle) -> PathBuf {
+    let app_dir = app.path().app_data_dir().unwrap_or_default();
+    fs::create_dir_all(&app_dir).ok();
+    app_dir.join("site_stats.json")
+}
+
+fn get_thumbnails_dir(app: &tauri::AppHandle) -> PathBuf {
+    let app_dir = app.path().app_data_dir().unwrap_or_default();
+    app_dir.join("thumbnails")
+}
+
+/// Download a queued item's thumbnail in the background so history and the
+/// queue still show it once the site's CDN link expires. A failure goes to
+/// the item's download log; get_thumbnail tries again on demand.
+fn prefetch_thumbnail(app: &tauri::AppHandle, log: DownloadLog, thumbnail: &str, page_url: &str) {
+    if thumbnail.is_empty() || thumbnail.starts_with("data:") {
+        return;
+    }
+    let dir = get_thumbnails_dir(app);
+    let (thumbnail, page_url) = (thumbnail.to_string(), page_url.to_string());
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = thumbnails::fetch(&dir, &thumbnail, Some(&page_url)).await {
+            log.warn(format!("Couldn't cache the thumbnail: {}", e));
+        }
     });
+}
 
-    let downloader = VideoDownloader::new(true); // headless mode
+/// Cached thumbnail of a queue or history item as raw image bytes
+#[tauri::command]
+async fn get_thumbnail(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    id: String,
+) -> Result<tauri::ipc::Response, String> {
+    let (thumbnail, page_url) = match state.queue.get_item(&id).await {
+        Some(item) => (item.thumbnail, item.url),
+        None => history::load_history(&get_history_path(&app))?
+            .into_iter()
+            .find(|h| h.id == id)
+            .map(|h| (h.thumbnail, h.url))
+            .ok_or_else(|| "Item not found".to_string())?,
+    };
+    if thumbnail.is_empty() {
+        return Err("Item has no thumbnail".to_string());
+    }
+    let bytes = thumbnails::fetch(&get_thumbnails_dir(&app), &thumbnail, Some(&page_url)).await?;
+    Ok(tauri::ipc::Response::new(bytes))
+}
 
-    let info = downloader
-        .get_info(&url)
-        .await
-        .map_err(|e| format!("Failed to get video info: {}", e))?;
+/// Extract a page's sources on the extraction lane, reporting progress as
+/// "video-info-progress" events tagged with `request_id`
+#[tauri::command]
+async fn get_video_info(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    url: String,
+    show_browser: Option<bool>,
+    request_id: Option<String>,
+) -> Result<VideoInfoResponse, String> {
+    let request_id = request_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let progress = |status: &str, message: String| {
+        emit_event(&app, "video-info-progress", VideoInfoProgress {
+            request_id: request_id.clone(),
+            url: url.clone(),
+            status: status.to_string(),
+            message,
+        });
+    };
+    progress(INFO_STARTED, "กำลังดึงข้อมูลวิดีโอ...".to_string());
+
+    let settings = state.settings.read().await.clone();
+    let downloader = VideoDownloader::new(!show_browser.unwrap_or(settings.show_browser))
+        .with_browser_pool(state.browser_pool.clone())
+        .with_browser_allowed(!state.low_battery.load(Ordering::Relaxed))
+        .with_timeouts(settings.timeouts())
+        .with_source_check(settings.check_sources);
+
+    let page_url = url.clone();
+    let info = match lane::run(async move { downloader.get_info(&page_url).await }).await {
+        Ok(info) => info,
+        Err(e) => {
+            // The UI shows the structured report next to the error message
+            if let DownloaderError::ExtractionFailed(diagnostics) = &e {
+                emit_event(&app, "extraction-diagnostics", diagnostics.as_ref().clone());
+            }
+            let message = format!("Failed to get video info: {}", e);
+            progress(INFO_FAILED, message.clone());
+            return Err(message);
+        }
+    };
+
+    let response = VideoInfoResponse::from(info);
+    progress(INFO_COMPLETED, format!("พบ {} แหล่งวิดีโอ", response.sources.len()));
+
+    Ok(response)
+}
 
-    let sources: Vec<VideoSourceResponse> = info.sources
-        .iter()
-        .map(|s| VideoSourceResponse {
-            url: s.url.clone(),
-            quality: s.quality.clone(),
-            source_type: s.source_type.clone(),
+/// Fetch info for several URLs concurrently through the shared browser pool.
+/// Each result is emitted as a "video-info-batch" event as soon as it's ready.
+#[tauri::command]
+async fn get_video_info_batch(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    urls: Vec<String>,
+    show_browser: Option<bool>,
+) -> Result<Vec<VideoInfoBatchResult>, String> {
+    let settings = state.settings.read().await.clone();
+    let show_browser = show_browser.unwrap_or(settings.show_browser);
+    let timeouts = settings.timeouts();
+    let pool = state.browser_pool.clone();
+    let concurrency = pool.max_tabs();
+    let allow_browser = !state.low_battery.load(Ordering::Relaxed);
+    let check_sources = settings.check_sources;
+
+    let mut results: Vec<VideoInfoBatchResult> = futures::stream::iter(urls.into_iter().enumerate())
+        .map(|(index, url)| {
+            let app = app.clone();
+            let pool = pool.clone();
+            async move {
+                let downloader = VideoDownloader::new(!show_browser)
+                    .with_browser_pool(pool)
+                    .with_browser_allowed(allow_browser)
+                    .with_timeouts(timeouts)
+                    .with_source_check(check_sources);
+                let page_url = url.clone();
+                let result = match lane::run(async move { downloader.get_info(&page_url).await }).await {
+                    Ok(info) => VideoInfoBatchResult {
+                        index,
+                        url,
+                        info: Some(VideoInfoResponse::from(info)),
+                        error: None,
+                        diagnostics: None,
+                    },
+                    Err(e) => VideoInfoBatchResult {
+                        index,
+                        url,
+                        info: None,
+                        error: Some(format!("Failed to get video info: {}", e)),
+                        diagnostics: match e {
+                            DownloaderError::ExtractionFailed(diagnostics) => Some(*diagnostics),
+                            _ => None,
+                        },
+                    },
+                };
+
+                emit_event(&app, "video-info-batch", result.clone());
+                result
+            }
         })
-        .collect();
+        .buffer_unordered(concurrency)
+        .collect()
+        .await;
 
-    let _ = app.emit("download-progress", DownloadProgress {
-        status: "info".to_string(),
-        progress: 100.0,
-        message: format!("พบ {} แหล่งวิดีโอ", sources.len()),
-        filename: None,
-    });
+    results.sort_by_key(|r| r.index);
+    Ok(results)
+}
 
-    Ok(VideoInfoResponse {
-        url: info.url,
-        title: info.title,
-        thumbnail: info.thumbnail,
-        duration: info.duration,
-        qualities: info.qualities,
-        sources,
-    })
+/// Episode links of a series index page, following its pagination up to
+/// the configured number of pages
+#[tauri::command]
+async fn get_series_episodes(state: State<'_, Arc<AppState>>, url: String) -> Result<Vec<SeriesEpisode>, String> {
+    let max_pages = state.settings.read().await.series_max_pages;
+    SeriesExtractor::new(max_pages)
+        .episodes(&url)
+        .await
+        .map_err(|e| format!("Failed to list episodes: {}", e))
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn download_video(
     app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
     url: String,
     output_dir: String,
     output_filename: Option<String>,
     quality: Option<String>,
+    episode: Option<EpisodeInfo>,
+    audio_tracks: Option<String>,
+    show_browser: Option<bool>,
 ) -> Result<String, String> {
     let app_clone = Arc::new(app.clone());
 
-    let _ = app.emit("download-progress", DownloadProgress {
+    emit_event(&app, "download-progress", DownloadProgress {
         status: "starting".to_string(),
         progress: 0.0,
         message: "เริ่มต้นดาวน์โหลด...".to_string(),
         filename: output_filename.clone(),
     });
 
-    let downloader = VideoDownloader::new(true);
+    let settings = state.settings.read().await.clone();
+    let downloader = VideoDownloader::new(!show_browser.unwrap_or(settings.show_browser))
+        .with_browser_pool(state.browser_pool.clone())
+        .with_browser_allowed(!state.low_battery.load(Ordering::Relaxed))
+        .with_segment_workers(settings.segment_workers)
+        .with_segment_buffer_mb(settings.segment_buffer_mb)
+        .with_timeouts(settings.timeouts())
+        .with_fsync(settings.fsync_on_complete)
+        .with_site_qualities(settings.site_quality.clone())
+        .with_aria2(settings.aria2_config(&state.bandwidth))
+        .with_smart_source_selection(settings.smart_source_selection)
+        .with_speed_matched_auto(settings.auto_quality_by_speed)
+        .with_source_preferences(settings.source_preferences())
+        .with_remux_mp4(settings.remux_to_mp4)
+        .with_faststart(settings.faststart_mp4)
+        .with_segment_cache(Some(state.segment_cache.clone()))
+        .with_audio_tracks(audio_tracks.unwrap_or_else(|| settings.audio_tracks.clone()))
+        .with_passthrough(settings.raw_passthrough)
+        .with_max_file_size(settings.max_file_size(None))
+        .with_bandwidth(Some(state.bandwidth.register(0)));
+
+    let title = output_filename.clone().unwrap_or_else(|| "video".to_string());
+    let app_for_fallback = app.clone();
+    let title_for_fallback = title.clone();
+    let downloader = downloader
+        .with_quality_fallback(settings.allow_quality_fallback)
+        .with_fallback_listener(move |fallback| {
+            emit_event(&app_for_fallback, "quality-fallback", QualityFallbackEvent::new(None, &title_for_fallback, fallback));
+        });
+    let target = prepare_output(&settings, &output_dir, &title, &title, episode)?;
 
     let app_for_callback = app_clone.clone();
     let filename_for_callback = output_filename.clone();
+    let throttle = ProgressThrottle::default();
 
     let progress_callback = move |progress: f32, message: String| {
-        let _ = app_for_callback.emit("download-progress", DownloadProgress {
-            status: "downloading".to_string(),
+        if !throttle.should_emit(progress) {
+            return;
+        }
+        let status = if ffmpeg::is_converting_message(&message) { "converting" } else { "downloading" };
+        emit_event(&app_for_callback, "download-progress", DownloadProgress {
+            status: status.to_string(),
             progress,
             message,
             filename: filename_for_callback.clone(),
@@ -179,25 +996,85 @@ async fn download_video(
     let result = downloader
         .download(
             &url,
-            &output_dir,
-            output_filename.as_deref(),
+            &target.dir,
+            Some(&target.filename),
             quality.as_deref(),
             progress_callback,
         )
         .await;
+    // Give the bandwidth share back before post-processing
+    drop(downloader);
+
+    let result = match result {
+        Ok(path) if !settings.raw_passthrough && settings.postprocess_spec(&path).transcodes() => {
+            let app_for_encode = app_clone.clone();
+            let filename_for_encode = output_filename.clone();
+            state.postprocess.run(None, settings.postprocess_spec(&path), move |progress, message| {
+                emit_event(&app_for_encode, "download-progress", DownloadProgress {
+                    status: "converting".to_string(),
+                    progress,
+                    message,
+                    filename: filename_for_encode.clone(),
+                });
+            }).await
+        }
+        result => result,
+    };
+    // Measured before a remote output takes the file away
+    let bytes = result.as_ref().ok().and_then(|path| fs::metadata(path).ok()).map(|m| m.len()).unwrap_or(0);
+
+    let result = match result {
+        Ok(output_path) => {
+            if settings.output_layout == naming::LAYOUT_MEDIA_SERVER {
+                let meta = NfoMetadata {
+                    title: &title,
+                    source_url: &url,
+                    thumbnail: "",
+                };
+                naming::write_nfo(&output_path, target.episode.as_ref(), &meta).ok();
+            }
+
+            match &target.remote {
+                Some(remote) => {
+                    let app_for_send = app_clone.clone();
+                    let filename_for_send = output_filename.clone();
+                    let throttle = ProgressThrottle::default();
+                    let host = remote.host.clone();
+                    remote
+                        .send(&output_path, move |sent, total| {
+                            let progress = if total > 0 { sent as f32 / total as f32 * 100.0 } else { 100.0 };
+                            if throttle.should_emit(progress) {
+                                emit_event(&app_for_send, "download-progress", DownloadProgress {
+                                    status: "sending".to_string(),
+                                    progress,
+                                    message: format!("กำลังส่งไฟล์ไปยัง {} {:.1}%", host, progress),
+                                    filename: filename_for_send.clone(),
+                                });
+                            }
+                        })
+                        .await
+                        .map_err(|e| DownloaderError::DownloadFailed(format!("Failed to send to {}: {}", remote.display(), e)))
+                }
+                None => Ok(output_path.to_string_lossy().to_string()),
+            }
+        }
+        Err(e) => Err(e),
+    };
+
+    site_stats::record(&get_site_stats_path(&app), &url, result.as_ref().map(|_| bytes).map_err(|e| e.to_string())).ok();
 
     match result {
         Ok(output_path) => {
-            let _ = app.emit("download-progress", DownloadProgress {
+            emit_event(&app, "download-progress", DownloadProgress {
                 status: "completed".to_string(),
                 progress: 100.0,
                 message: "ดาวน์โหลดเสร็จสมบูรณ์!".to_string(),
-                filename: Some(output_path.to_string_lossy().to_string()),
+                filename: Some(output_path.clone()),
             });
-            Ok(output_path.to_string_lossy().to_string())
+            Ok(output_path)
         }
         Err(e) => {
-            let _ = app.emit("download-progress", DownloadProgress {
+            emit_event(&app, "download-progress", DownloadProgress {
                 status: "error".to_string(),
                 progress: 0.0,
                 message: format!("ดาวน์โหลดล้มเหลว: {}", e),
@@ -314,110 +1191,549 @@ async fn open_file(path: String) -> Result<(), String> {
     Ok(())
 }
 
+/// Stream a sniffed source directly in VLC (or mpv / another configured
+/// player) with the referer and user agent the CDN expects, without
+/// downloading it first
 #[tauri::command]
-async fn get_download_history(app: tauri::AppHandle) -> Result<Vec<HistoryItem>, String> {
-    let history_path = get_history_path(&app);
-
-    if !history_path.exists() {
-        return Ok(vec![]);
-    }
+async fn play_in_vlc(
+    state: State<'_, Arc<AppState>>,
+    source_url: String,
+    referer: Option<String>,
+    player: Option<String>,
+) -> Result<(), String> {
+    let source_url = downloader::validate_url(&source_url).map_err(|e| e.to_string())?;
+    let referer = referer.filter(|r| !r.is_empty());
 
-    let content = fs::read_to_string(&history_path)
-        .map_err(|e| format!("Failed to read history: {}", e))?;
+    let settings = state.settings.read().await.clone();
+    let player = player.unwrap_or_else(|| players::PLAYER_VLC.to_string());
+    let executable = players::resolve_player(&player, &settings.media_players)?;
 
-    let history: Vec<HistoryItem> = serde_json::from_str(&content)
+    // Site rules may require extra headers on media requests
+    let headers = referer.as_deref()
+        .and_then(rules::rule_for)
+        .map(|r| r.header_list())
         .unwrap_or_default();
 
-    Ok(history)
+    std::process::Command::new(&executable)
+        .args(players::stream_args(&player, &source_url, referer.as_deref(), &headers))
+        .spawn()
+        .map_err(|e| format!("Failed to launch {}: {}", player, e))?;
+
+    Ok(())
+}
+
+/// Open a file in a specific media player instead of the OS default, which
+/// is often a browser or photo viewer for .ts files
+#[tauri::command]
+async fn open_with(
+    state: State<'_, Arc<AppState>>,
+    path: String,
+    player: Option<String>,
+) -> Result<(), String> {
+    let sanitized = sanitize_path(&path)?;
+    let validated = validate_path(&sanitized, true, false)?;
+
+    let settings = state.settings.read().await.clone();
+    let player = player.unwrap_or(settings.default_player);
+    let executable = players::resolve_player(&player, &settings.media_players)?;
+
+    std::process::Command::new(&executable)
+        .arg(&validated)
+        .spawn()
+        .map_err(|e| format!("Failed to launch {}: {}", player, e))?;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_download_history(app: tauri::AppHandle) -> Result<Vec<HistoryItem>, String> {
+    history::load_history(&get_history_path(&app))
+}
+
+/// Downloads, bytes and failure rate per site, least reliable first
+#[tauri::command]
+async fn get_site_stats(app: tauri::AppHandle) -> Result<Vec<SiteStats>, String> {
+    let path = get_site_stats_path(&app);
+    tauri::async_runtime::spawn_blocking(move || site_stats::summarize(&path))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Payload of "duplicate-file"
+#[derive(Clone, Serialize)]
+struct DuplicateFile {
+    file_path: String,
+    existing: HistoryItem,
+}
+
+/// Hash a finished file and emit "duplicate-file" when history already has
+/// the same content under another name. Returns the hash.
+async fn check_duplicate(app: &tauri::AppHandle, path: &Path) -> Option<String> {
+    let file = path.to_path_buf();
+    let hash = tokio::task::spawn_blocking(move || history::content_hash(&file)).await.ok()?.ok()?;
+
+    let history = history::load_history(&get_history_path(app)).unwrap_or_default();
+    let file_path = path.to_string_lossy().to_string();
+    if let Some(existing) = history::find_duplicate(&history, &hash, &file_path) {
+        emit_event(app, "duplicate-file", DuplicateFile {
+            file_path,
+            existing: existing.clone(),
+        });
+    }
+    Some(hash)
 }
 
 #[tauri::command]
-async fn add_to_history(app: tauri::AppHandle, item: HistoryItem) -> Result<(), String> {
+async fn add_to_history(app: tauri::AppHandle, mut item: HistoryItem) -> Result<(), String> {
     let history_path = get_history_path(&app);
 
-    let mut history: Vec<HistoryItem> = if history_path.exists() {
-        let content = fs::read_to_string(&history_path).unwrap_or_default();
-        serde_json::from_str(&content).unwrap_or_default()
-    } else {
-        vec![]
-    };
+    if item.content_hash.is_none() {
+        item.content_hash = check_duplicate(&app, Path::new(&item.file_path)).await;
+    }
+
+    let mut history = history::load_history(&history_path).unwrap_or_default();
 
     // Add new item at the beginning
     history.insert(0, item);
 
-    // Keep only last 100 items
-    history.truncate(100);
-
-    let content = serde_json::to_string_pretty(&history)
-        .map_err(|e| format!("Failed to serialize history: {}", e))?;
+    // Keep only the most recent items (favorites are always kept)
+    history::truncate_history(&mut history);
 
-    fs::write(&history_path, content)
-        .map_err(|e| format!("Failed to write history: {}", e))?;
-
-    Ok(())
+    history::save_history(&history_path, &history)
 }
 
 #[tauri::command]
-async fn clear_history(app: tauri::AppHandle) -> Result<(), String> {
+async fn clear_history(app: tauri::AppHandle, state: State<'_, Arc<AppState>>) -> Result<(), String> {
     let history_path = get_history_path(&app);
 
     if history_path.exists() {
+        let history = history::load_history(&history_path).unwrap_or_default();
         fs::remove_file(&history_path)
             .map_err(|e| format!("Failed to clear history: {}", e))?;
+        state.undo.push(undo::UNDO_HISTORY_CLEAR, Removed::History(history.into_iter().enumerate().collect())).await;
     }
 
     Ok(())
 }
 
 #[tauri::command]
-async fn delete_history_item(app: tauri::AppHandle, id: String) -> Result<(), String> {
+async fn delete_history_item(app: tauri::AppHandle, state: State<'_, Arc<AppState>>, id: String) -> Result<(), String> {
     let history_path = get_history_path(&app);
 
     if !history_path.exists() {
         return Ok(());
     }
 
-    let content = fs::read_to_string(&history_path)
-        .map_err(|e| format!("Failed to read history: {}", e))?;
+    let mut history = history::load_history(&history_path)?;
+
+    let Some(pos) = history.iter().position(|item| item.id == id) else {
+        return Ok(());
+    };
+    let removed = history.remove(pos);
+
+    history::save_history(&history_path, &history)?;
+    state.undo.push(undo::UNDO_HISTORY_DELETE, Removed::History(vec![(pos, removed)])).await;
+    Ok(())
+}
 
-    let mut history: Vec<HistoryItem> = serde_json::from_str(&content).unwrap_or_default();
+/// Put back what the latest queue or history removal took, if it happened
+/// within the last undo::UNDO_GRACE. Returns which action was undone.
+#[tauri::command]
+async fn undo_last_action(app: tauri::AppHandle, state: State<'_, Arc<AppState>>) -> Result<String, String> {
+    let (kind, removed) = state.undo.take().await.ok_or("Nothing to undo")?;
+    match removed {
+        Removed::Queue { removed, logs } => {
+            state.queue.reinsert(removed).await;
+            state.download_logs.lock().await.extend(logs);
+        }
+        Removed::History(entries) => {
+            let history_path = get_history_path(&app);
+            let mut history = history::load_history(&history_path)?;
+            for (pos, item) in entries {
+                if !history.iter().any(|h| h.id == item.id) {
+                    let pos = pos.min(history.len());
+                    history.insert(pos, item);
+                }
+            }
+            history::save_history(&history_path, &history)?;
+        }
+    }
+    Ok(kind.to_string())
+}
 
-    history.retain(|item| item.id != id);
+/// Remove a history entry's file from disk and mark the entry as deleted.
+/// `to_trash` overrides the permanent delete setting for this file.
+#[tauri::command]
+async fn history_delete_file(app: tauri::AppHandle, id: String, to_trash: Option<bool>) -> Result<HistoryItem, String> {
+    let history_path = get_history_path(&app);
+    let mut history = history::load_history(&history_path)?;
+
+    let item = history.iter_mut()
+        .find(|item| item.id == id)
+        .ok_or("History item not found")?;
+
+    let path = PathBuf::from(&item.file_path);
+    if path.exists() {
+        match to_trash {
+            Some(true) => trash::move_to_trash(&path).map_err(|e| format!("Failed to move file to trash: {}", e))?,
+            Some(false) => trash::remove_permanently(&path)?,
+            None => trash::remove(&path)?,
+        }
+    }
 
-    let content = serde_json::to_string_pretty(&history)
-        .map_err(|e| format!("Failed to serialize history: {}", e))?;
+    let sidecar = checksum::sidecar_path(&path);
+    if sidecar.exists() {
+        trash::remove_permanently(&sidecar).ok();
+    }
 
-    fs::write(&history_path, content)
-        .map_err(|e| format!("Failed to write history: {}", e))?;
+    item.file_deleted = true;
+    item.file_size = None;
+    let updated = item.clone();
 
-    Ok(())
+    history::save_history(&history_path, &history)?;
+    Ok(updated)
 }
 
-// ==================== Queue Commands ====================
-
+/// Rename a completed download on disk (keeping its extension) and update
+/// the history entries and queue items that point at it. Returns the new path.
 #[tauri::command]
-async fn queue_add(
+async fn rename_download_file(
+    app: tauri::AppHandle,
     state: State<'_, Arc<AppState>>,
-    url: String,
-    title: String,
-    thumbnail: String,
-    quality: String,
-    output_dir: String,
-    output_filename: String,
+    path: String,
+    new_name: String,
 ) -> Result<String, String> {
-    let id = state.queue.add_item(url, title, thumbnail, quality, output_dir, output_filename).await;
-    Ok(id)
+    let old_path = validate_path(&sanitize_path(&path)?, true, false)?;
+
+    let mut name = downloader::sanitize_filename(&new_name);
+    let extension = old_path.extension().map(|e| e.to_string_lossy().to_string());
+    if let Some(ext) = &extension {
+        // Accept "New name.mp4" as well as "New name"
+        if let Some(stem) = name.strip_suffix(&format!(".{}", ext)) {
+            name = stem.to_string();
+        }
+    }
+    if name.is_empty() {
+        return Err("Invalid file name".to_string());
+    }
+
+    let file_name = match &extension {
+        Some(ext) => format!("{}.{}", name, ext),
+        None => name,
+    };
+    let new_path = old_path.with_file_name(&file_name);
+    if new_path == old_path {
+        return Ok(old_path.to_string_lossy().to_string());
+    }
+    if new_path.exists() {
+        return Err("A file with that name already exists".to_string());
+    }
+
+    fs::rename(downloader::long_path(&old_path), downloader::long_path(&new_path))
+        .map_err(|e| format!("Failed to rename file: {}", e))?;
+
+    // Keep a media server .nfo sidecar next to its video
+    let old_nfo = old_path.with_extension("nfo");
+    if old_nfo.exists() {
+        fs::rename(&old_nfo, new_path.with_extension("nfo")).ok();
+    }
+    checksum::rename_sidecar(&old_path, &new_path);
+
+    let old_str = old_path.to_string_lossy().to_string();
+    let new_str = new_path.to_string_lossy().to_string();
+
+    let history_path = get_history_path(&app);
+    let mut history = history::load_history(&history_path)?;
+    let mut changed = false;
+    // Entries may hold the path as given or in canonical form
+    for item in history.iter_mut().filter(|h| h.file_path == path || h.file_path == old_str || Path::new(&h.file_path) == old_path) {
+        item.file_path = new_str.clone();
+        item.filename = file_name.clone();
+        changed = true;
+    }
+    if changed {
+        history::save_history(&history_path, &history)?;
+    }
+
+    state.queue.update_file_path(&path, &new_str).await;
+    state.queue.update_file_path(&old_str, &new_str).await;
+
+    Ok(new_str)
 }
 
 #[tauri::command]
-async fn queue_get_items(state: State<'_, Arc<AppState>>) -> Result<Vec<QueueItem>, String> {
-    Ok(state.queue.get_items().await)
+async fn get_compression_presets() -> Result<Vec<CompressionPreset>, String> {
+    Ok(postprocess::COMPRESSION_PRESETS.to_vec())
 }
 
+/// Shrink a finished download with a compression preset, replacing the file
+/// and keeping the before/after sizes on its history entry
 #[tauri::command]
-async fn queue_remove(state: State<'_, Arc<AppState>>, id: String) -> Result<(), String> {
-    state.queue.remove_item(&id).await;
-    Ok(())
+async fn compress_history_item(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    id: String,
+    preset: String,
+) -> Result<HistoryItem, String> {
+    postprocess::find_preset(&preset).ok_or("Unknown compression preset")?;
+
+    let preset_for_spec = preset.clone();
+    postprocess_history_item(
+        &app,
+        &state,
+        &id,
+        |spec| {
+            spec.preset = Some(preset_for_spec);
+            Ok(())
+        },
+        |entry| entry.compressed_with = Some(preset),
+    )
+    .await
+}
+
+/// Hardcode a subtitle into a finished download. Without `subtitle_path`
+/// the subtitle saved next to the video is used.
+#[tauri::command]
+async fn burn_subtitles_history_item(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    id: String,
+    subtitle_path: Option<String>,
+) -> Result<HistoryItem, String> {
+    postprocess_history_item(
+        &app,
+        &state,
+        &id,
+        |spec| {
+            let subtitle = match subtitle_path {
+                Some(path) => PathBuf::from(path),
+                None => postprocess::find_subtitle(Path::new(&spec.input)).ok_or("No subtitle file found next to the video")?,
+            };
+            if !subtitle.exists() {
+                return Err("Subtitle file not found".to_string());
+            }
+            spec.burn_subtitles = Some(subtitle.to_string_lossy().to_string());
+            Ok(())
+        },
+        |_| {},
+    )
+    .await
+}
+
+/// Bring a finished download's audio to a standard loudness
+#[tauri::command]
+async fn normalize_history_item(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    id: String,
+) -> Result<HistoryItem, String> {
+    postprocess_history_item(
+        &app,
+        &state,
+        &id,
+        |spec| {
+            spec.loudnorm = true;
+            Ok(())
+        },
+        |_| {},
+    )
+    .await
+}
+
+/// Run a post-processing job on a history entry's file, replacing it and
+/// recording the before/after sizes
+async fn postprocess_history_item(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    id: &str,
+    configure: impl FnOnce(&mut PostProcessSpec) -> Result<(), String>,
+    record: impl FnOnce(&mut HistoryItem),
+) -> Result<HistoryItem, String> {
+    let history_path = get_history_path(app);
+    let item = history::load_history(&history_path)?
+        .into_iter()
+        .find(|item| item.id == id)
+        .ok_or("History item not found")?;
+    let input = PathBuf::from(&item.file_path);
+    if item.file_deleted || !input.exists() {
+        return Err("The downloaded file no longer exists".to_string());
+    }
+
+    let settings = state.settings.read().await.clone();
+    let mut spec = settings.postprocess_spec(&input);
+    // Only what the caller asks for; the download-time options don't apply
+    spec.burn_subtitles = None;
+    spec.loudnorm = false;
+    configure(&mut spec)?;
+
+    let app_for_cb = app.clone();
+    let id_for_cb = id.to_string();
+    let job = state.postprocess.run_job(None, spec, move |progress, message| {
+        emit_event(&app_for_cb, "postprocess-progress", PostProcessProgress {
+            id: id_for_cb.clone(),
+            progress,
+            message,
+        });
+    })
+    .await
+    .map_err(|e| format!("Failed to process file: {}", e))?;
+
+    let output = PathBuf::from(job.output.unwrap_or_default());
+    let mut updated = None;
+    history::update_history_item(&history_path, id, |entry| {
+        // Keep the size of the original download across repeated runs
+        entry.original_size = entry.original_size.or(job.size_before);
+        entry.file_size = job.size_after;
+        entry.file_path = output.to_string_lossy().to_string();
+        entry.filename = output.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        record(entry);
+        updated = Some(entry.clone());
+    })?;
+
+    if input != output {
+        state.queue.update_file_path(&item.file_path, &output.to_string_lossy()).await;
+    }
+
+    // The old checksum would now flag the new content as corrupted
+    if checksum::sidecar_path(&input).exists() {
+        let (input, output) = (input.clone(), output.clone());
+        tokio::task::spawn_blocking(move || {
+            if input != output {
+                fs::remove_file(checksum::sidecar_path(&input)).ok();
+            }
+            checksum::write_sidecar(&output)
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+    }
+
+    updated.ok_or_else(|| "History item not found".to_string())
+}
+
+/// Re-hash files and compare them with their .sha256 sidecars, e.g. after
+/// copying an archive to an external drive
+#[tauri::command]
+async fn verify_checksums(paths: Vec<String>) -> Result<Vec<ChecksumReport>, String> {
+    tokio::task::spawn_blocking(move || paths.iter().map(|p| checksum::verify(Path::new(p))).collect())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn history_set_tags(app: tauri::AppHandle, id: String, tags: Vec<String>) -> Result<bool, String> {
+    let tags = history::normalize_tags(tags);
+    history::update_history_item(&get_history_path(&app), &id, |item| item.tags = tags)
+}
+
+#[tauri::command]
+async fn history_set_favorite(app: tauri::AppHandle, id: String, favorite: bool) -> Result<bool, String> {
+    history::update_history_item(&get_history_path(&app), &id, |item| item.favorite = favorite)
+}
+
+#[tauri::command]
+async fn history_set_watched(app: tauri::AppHandle, id: String, watched: bool) -> Result<bool, String> {
+    history::update_history_item(&get_history_path(&app), &id, |item| item.watched = watched)
+}
+
+#[tauri::command]
+async fn history_filter(app: tauri::AppHandle, filter: HistoryFilter) -> Result<Vec<HistoryItem>, String> {
+    let history = history::load_history(&get_history_path(&app))?;
+    Ok(history.into_iter().filter(|item| filter.matches(item)).collect())
+}
+
+/// All tags in use, sorted, for tag pickers in the UI
+#[tauri::command]
+async fn history_get_tags(app: tauri::AppHandle) -> Result<Vec<String>, String> {
+    let history = history::load_history(&get_history_path(&app))?;
+    let mut tags = history::normalize_tags(history.into_iter().flat_map(|item| item.tags).collect());
+    tags.sort_by_key(|t| t.to_lowercase());
+    Ok(tags)
+}
+
+/// Index video files in `dir` (or the default download directory), matching
+/// them with history entries where possible
+#[tauri::command]
+async fn scan_library(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    dir: Option<String>,
+) -> Result<Vec<LibraryEntry>, String> {
+    let dir = match dir {
+        Some(dir) => dir,
+        None => state.settings.read().await.default_download_dir.clone(),
+    };
+
+    let sanitized = sanitize_path(&dir)?;
+    let validated = validate_path(&sanitized, true, true)?;
+
+    let history = history::load_history(&get_history_path(&app)).unwrap_or_default();
+
+    Ok(library::scan_library(&validated, &history).await)
+}
+
+/// Preview how a page title will be split into series/season/episode
+#[tauri::command]
+async fn parse_episode_title(title: String) -> Result<Option<EpisodeInfo>, String> {
+    Ok(naming::parse_episode_title(&title))
+}
+
+// ==================== Queue Commands ====================
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn queue_add(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    url: String,
+    title: String,
+    thumbnail: String,
+    quality: String,
+    output_dir: String,
+    output_filename: String,
+    options: Option<QueueItemOptions>,
+) -> Result<String, String> {
+    let options = options.unwrap_or_default();
+    postprocess::parse_extra_args(options.extra_ffmpeg_args.as_deref().unwrap_or_default())?;
+    RemoteOutput::parse(&output_dir)?;
+    let (thumbnail_url, page_url) = (thumbnail.clone(), url.clone());
+    let id = state.queue.add_item(url, title, thumbnail, quality, output_dir, output_filename, options).await;
+    prefetch_thumbnail(&app, download_log(&state, &id).await, &thumbnail_url, &page_url);
+    Ok(id)
+}
+
+#[tauri::command]
+async fn queue_set_segment_workers(
+    state: State<'_, Arc<AppState>>,
+    id: String,
+    segment_workers: Option<usize>,
+) -> Result<bool, String> {
+    Ok(state.queue.set_item_segment_workers(&id, segment_workers).await)
+}
+
+#[tauri::command]
+async fn queue_get_items(state: State<'_, Arc<AppState>>) -> Result<Vec<QueueItem>, String> {
+    Ok(state.queue.get_items().await)
+}
+
+/// Counts, remaining work, combined speed and an ETA for the whole queue
+#[tauri::command]
+async fn queue_get_summary(state: State<'_, Arc<AppState>>) -> Result<QueueSummary, String> {
+    Ok(state.queue.summary().await)
+}
+
+#[tauri::command]
+async fn queue_remove(state: State<'_, Arc<AppState>>, id: String) -> Result<(), String> {
+    remove_queue_item(&state, &id).await;
+    Ok(())
+}
+
+/// Remove a queue item, keeping it for undo_last_action
+async fn remove_queue_item(state: &AppState, id: &str) {
+    let removed = state.queue.remove_item(id).await;
+    let logs = state.download_logs.lock().await.remove(id).map(|log| (id.to_string(), log));
+    if !removed.items.is_empty() {
+        state.undo.push(undo::UNDO_QUEUE_REMOVE, Removed::Queue { removed, logs: logs.into_iter().collect() }).await;
+    }
 }
 
 #[tauri::command]
@@ -426,138 +1742,1465 @@ async fn queue_pause(state: State<'_, Arc<AppState>>, id: String) -> Result<bool
 }
 
 #[tauri::command]
-async fn queue_resume(state: State<'_, Arc<AppState>>, id: String) -> Result<bool, String> {
-    Ok(state.queue.resume_download(&id).await)
+async fn queue_resume(state: State<'_, Arc<AppState>>, id: String) -> Result<bool, String> {
+    Ok(state.queue.resume_download(&id).await)
+}
+
+#[tauri::command]
+async fn queue_cancel(state: State<'_, Arc<AppState>>, id: String) -> Result<bool, String> {
+    Ok(cancel_queue_item(&state, &id).await)
+}
+
+/// Cancel a queue item, including its post-processing once the download
+/// itself is over
+pub(crate) async fn cancel_queue_item(state: &AppState, id: &str) -> bool {
+    let cancelled = state.queue.cancel_download(id).await;
+    state.postprocess.cancel(id).await || cancelled
+}
+
+#[tauri::command]
+async fn queue_clear_completed(state: State<'_, Arc<AppState>>) -> Result<(), String> {
+    state.queue.clear_completed().await;
+    Ok(())
+}
+
+#[tauri::command]
+async fn postprocess_get_jobs(state: State<'_, Arc<AppState>>) -> Result<Vec<PostProcessJob>, String> {
+    Ok(state.postprocess.get_jobs().await)
+}
+
+#[tauri::command]
+async fn postprocess_clear_finished(state: State<'_, Arc<AppState>>) -> Result<(), String> {
+    state.postprocess.clear_finished().await;
+    Ok(())
+}
+
+#[tauri::command]
+async fn queue_clear_all(state: State<'_, Arc<AppState>>) -> Result<(), String> {
+    let removed = state.queue.clear_all().await;
+    let logs = state.download_logs.lock().await.drain().collect();
+    if !removed.items.is_empty() {
+        state.undo.push(undo::UNDO_QUEUE_CLEAR, Removed::Queue { removed, logs }).await;
+    }
+    Ok(())
+}
+
+/// Log of a queue item, kept across retries
+async fn download_log(state: &AppState, id: &str) -> DownloadLog {
+    state.download_logs.lock().await.entry(id.to_string()).or_default().clone()
+}
+
+/// Thai explanation and suggested fix for an error a command returned
+#[tauri::command]
+fn explain_error(message: String) -> ErrorHelp {
+    explain::explain(&message)
+}
+
+#[tauri::command]
+async fn get_download_log(state: State<'_, Arc<AppState>>, id: String) -> Result<Vec<LogEntry>, String> {
+    Ok(state
+        .download_logs
+        .lock()
+        .await
+        .get(&id)
+        .map(|log| log.entries())
+        .unwrap_or_default())
+}
+
+#[tauri::command]
+async fn segment_cache_info(state: State<'_, Arc<AppState>>) -> Result<CacheStats, String> {
+    let cache = state.segment_cache.clone();
+    tokio::task::spawn_blocking(move || cache.stats())
+        .await
+        .map_err(|e| format!("Failed to read segment cache: {}", e))
+}
+
+/// Delete all cached segments; returns the bytes freed
+#[tauri::command]
+async fn segment_cache_purge(state: State<'_, Arc<AppState>>) -> Result<u64, String> {
+    let cache = state.segment_cache.clone();
+    tokio::task::spawn_blocking(move || cache.purge())
+        .await
+        .map_err(|e| format!("Failed to purge segment cache: {}", e))?
+}
+
+#[tauri::command]
+async fn queue_move_item(state: State<'_, Arc<AppState>>, id: String, direction: i32) -> Result<(), String> {
+    state.queue.move_item(&id, direction).await;
+    Ok(())
+}
+
+#[tauri::command]
+async fn queue_create_group(
+    state: State<'_, Arc<AppState>>,
+    name: String,
+    sequential: Option<bool>,
+) -> Result<String, String> {
+    Ok(state.queue.create_group(name, sequential.unwrap_or(false)).await)
+}
+
+/// Queue the parts of a video split across pages (part1/part2/...) as one
+/// group; they are joined into `output_filename` once all are downloaded.
+/// Returns the group id.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn queue_add_multipart(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    urls: Vec<String>,
+    title: String,
+    thumbnail: String,
+    quality: String,
+    output_dir: String,
+    output_filename: String,
+    options: Option<QueueItemOptions>,
+) -> Result<String, String> {
+    if urls.len() < 2 {
+        return Err("A multi-part video needs at least two parts".to_string());
+    }
+    RemoteOutput::parse(&output_dir)?;
+    let page_url = urls[0].clone();
+    let options = options.unwrap_or_default();
+    postprocess::parse_extra_args(options.extra_ffmpeg_args.as_deref().unwrap_or_default())?;
+
+    let stem = Path::new(&output_filename)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or(output_filename);
+    let group_id = state.queue.create_group(title.clone(), false).await;
+
+    let mut parts = Vec::new();
+    for (index, url) in urls.into_iter().enumerate() {
+        let part_options = QueueItemOptions {
+            group_id: Some(group_id.clone()),
+            ..options.clone()
+        };
+        let id = state
+            .queue
+            .add_item(
+                url,
+                format!("{} (part {})", title, index + 1),
+                thumbnail.clone(),
+                quality.clone(),
+                output_dir.clone(),
+                format!("{} part{}", stem, index + 1),
+                part_options,
+            )
+            .await;
+        parts.push(id);
+    }
+    // Every part shares the thumbnail; the first part's log gets any failure
+    prefetch_thumbnail(&app, download_log(&state, &parts[0]).await, &thumbnail, &page_url);
+
+    state.queue.set_group_merge(&group_id, MultipartMerge { output_filename: stem, parts }).await;
+    Ok(group_id)
+}
+
+/// Export a whole group (e.g. a season) as one file with a chapter per
+/// episode, in episode order, once every item is downloaded
+#[tauri::command]
+async fn queue_group_merge(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    group_id: String,
+    output_filename: String,
+) -> Result<(), String> {
+    let mut members: Vec<(usize, QueueItem)> = state
+        .queue
+        .get_items()
+        .await
+        .into_iter()
+        .enumerate()
+        .filter(|(_, item)| item.options.group_id.as_deref() == Some(group_id.as_str()))
+        .collect();
+    if members.len() < 2 {
+        return Err("The group needs at least two items to merge".to_string());
+    }
+    // Same order as sequential groups: episode number, then queue position
+    members.sort_by_key(|(position, item)| {
+        let episode = item.options.episode.as_ref().and_then(|e| e.episode);
+        (episode.is_none(), episode, *position)
+    });
+
+    let stem = Path::new(&output_filename)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or(output_filename);
+    let parts = members.into_iter().map(|(_, item)| item.id).collect();
+    state.queue.set_group_merge(&group_id, MultipartMerge { output_filename: stem, parts }).await;
+
+    // Merge now if the group had already finished
+    let progress = state.queue.get_group_progress(&group_id).await.ok_or("Group not found")?;
+    if progress.finished {
+        state.queue.reopen_group(&group_id).await;
+        emit_group_progress(&app, &state, Some(&group_id)).await;
+    }
+    Ok(())
+}
+
+/// Pack a finished group's downloads, with their NFO, subtitle and
+/// thumbnail files, into one ZIP. Progress goes out as
+/// "season-pack-progress". Returns the archive path.
+#[tauri::command]
+async fn queue_group_export_zip(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    group_id: String,
+    output_path: Option<String>,
+) -> Result<String, String> {
+    let progress = state.queue.get_group_progress(&group_id).await.ok_or("Group not found")?;
+    if !progress.finished {
+        return Err("The group is still downloading".to_string());
+    }
+
+    let mut members: Vec<(usize, QueueItem)> = state
+        .queue
+        .get_items()
+        .await
+        .into_iter()
+        .enumerate()
+        .filter(|(_, item)| item.options.group_id.as_deref() == Some(group_id.as_str()))
+        .filter(|(_, item)| item.status == QueueItemStatus::Completed)
+        .collect();
+    members.sort_by_key(|(position, item)| {
+        let episode = item.options.episode.as_ref().and_then(|e| e.episode);
+        (episode.is_none(), episode, *position)
+    });
+
+    let thumbnails_dir = get_thumbnails_dir(&app);
+    let mut videos: Vec<PathBuf> = Vec::new();
+    let mut posters = Vec::new();
+    for (_, item) in &members {
+        let Some(path) = item.file_path.as_deref().map(PathBuf::from).filter(|p| p.is_file()) else {
+            continue;
+        };
+        if videos.contains(&path) {
+            continue;
+        }
+        let cached = thumbnails::cache_path(&thumbnails_dir, &item.thumbnail);
+        if !item.thumbnail.is_empty() && cached.is_file() {
+            posters.push((path.clone(), cached));
+        }
+        videos.push(path);
+    }
+    if videos.is_empty() {
+        return Err("None of the group's files are on disk".to_string());
+    }
+
+    let output = match output_path {
+        Some(path) => output_file_path(Path::new(&path), "zip"),
+        None => {
+            let name = downloader::sanitize_filename(&progress.name);
+            let name = if name.is_empty() { "season".to_string() } else { name };
+            output_file_path(&videos[0].with_file_name(name), "zip")
+        }
+    };
+    let partial = output.with_extension("zip.part");
+
+    let event = {
+        let group_id = group_id.clone();
+        move |written: u64, total: u64, current: &str| season_pack::SeasonPackProgress {
+            group_id: group_id.clone(),
+            progress: if total > 0 { written as f32 / total as f32 * 100.0 } else { 100.0 },
+            written,
+            total,
+            current: current.to_string(),
+            done: false,
+            error: None,
+            file_path: None,
+        }
+    };
+
+    let entries = season_pack::collect_entries(&videos, &posters);
+    let (app_for_cb, event_for_cb, partial_for_task) = (app.clone(), event.clone(), partial.clone());
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let throttle = ProgressThrottle::default();
+        season_pack::write_zip(&entries, &partial_for_task, |written, total, current| {
+            let payload = event_for_cb(written, total, current);
+            if throttle.should_emit(payload.progress) {
+                emit_event(&app_for_cb, "season-pack-progress", payload);
+            }
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|r| r.map_err(|e| e.to_string()))
+    .and_then(|_| fs::rename(&partial, &output).map_err(|e| e.to_string()));
+
+    let output_str = output.to_string_lossy().to_string();
+    match result {
+        Ok(()) => {
+            emit_event(&app, "season-pack-progress", season_pack::SeasonPackProgress {
+                done: true,
+                file_path: Some(output_str.clone()),
+                ..event(1, 1, "")
+            });
+            Ok(output_str)
+        }
+        Err(e) => {
+            fs::remove_file(&partial).ok();
+            let message = format!("Failed to create archive: {}", e);
+            emit_event(&app, "season-pack-progress", season_pack::SeasonPackProgress {
+                done: true,
+                error: Some(message.clone()),
+                ..event(0, 0, "")
+            });
+            Err(message)
+        }
+    }
+}
+
+/// Payload of "queue-multipart-merged"
+#[derive(Clone, Serialize)]
+struct MultipartResult {
+    group_id: String,
+    file_path: Option<String>,
+    error: Option<String>,
+}
+
+/// Join the downloaded parts of a finished multi-part group, then delete
+/// the parts and point their queue items at the joined file
+async fn merge_multipart(state: &AppState, merge: &MultipartMerge) -> Result<PathBuf, String> {
+    let mut inputs = Vec::new();
+    let mut chapters = Vec::new();
+    for id in &merge.parts {
+        let item = state.queue.get_item(id).await.ok_or("Part is no longer in the queue")?;
+        match (&item.status, item.file_path) {
+            (QueueItemStatus::Completed, Some(path)) => inputs.push(PathBuf::from(path)),
+            _ => return Err(format!("Part not downloaded: {}", item.title)),
+        }
+        chapters.push(item.title);
+    }
+
+    let first = &inputs[0];
+    let extension = first.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_else(|| "mp4".to_string());
+    let output = output_file_path(&first.with_file_name(&merge.output_filename), &extension);
+    ffmpeg::concat_files(&inputs, &chapters, &output)
+        .await
+        .map_err(|e| format!("Failed to merge parts: {}", e))?;
+
+    let output_str = output.to_string_lossy().to_string();
+    for (id, input) in merge.parts.iter().zip(&inputs) {
+        trash::remove(input).ok();
+        state.queue.update_item_completed(id, output_str.clone()).await;
+    }
+    Ok(output)
+}
+
+/// Start as many pending items as the scheduler allows. Returns the ids
+/// that were started.
+#[tauri::command]
+async fn queue_process(app: tauri::AppHandle, state: State<'_, Arc<AppState>>) -> Result<Vec<String>, String> {
+    let mut started = Vec::new();
+    for item in state.queue.startable_items().await {
+        start_queue_item(app.clone(), Arc::clone(&*state), item.id.clone()).await?;
+        started.push(item.id);
+    }
+    Ok(started)
+}
+
+#[tauri::command]
+async fn queue_get_groups(state: State<'_, Arc<AppState>>) -> Result<Vec<GroupProgress>, String> {
+    Ok(state.queue.get_groups().await)
+}
+
+#[tauri::command]
+async fn queue_pause_group(state: State<'_, Arc<AppState>>, group_id: String) -> Result<usize, String> {
+    Ok(state.queue.pause_group(&group_id).await)
+}
+
+#[tauri::command]
+async fn queue_resume_group(state: State<'_, Arc<AppState>>, group_id: String) -> Result<usize, String> {
+    Ok(state.queue.resume_group(&group_id).await)
+}
+
+#[tauri::command]
+async fn queue_cancel_group(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    group_id: String,
+) -> Result<usize, String> {
+    let cancelled = state.queue.cancel_group(&group_id).await;
+    for id in &cancelled {
+        state.postprocess.cancel(id).await;
+    }
+    emit_group_progress(&app, &state, Some(&group_id)).await;
+    Ok(cancelled.len())
+}
+
+/// Emit `queue-finished` with the run's totals once the last item is done
+/// and nothing is waiting to start
+async fn emit_queue_finished(app: &tauri::AppHandle, state: &AppState) {
+    if let Some(finished) = state.queue.check_finished().await {
+        emit_event(app, "queue-finished", finished);
+    }
+}
+
+/// Emit aggregate progress for an item's group, plus `queue-group-finished`
+/// once the last item of the group is done
+async fn emit_group_progress(app: &tauri::AppHandle, state: &AppState, group_id: Option<&str>) {
+    let Some(group_id) = group_id else {
+        return;
+    };
+
+    if let Some(progress) = state.queue.get_group_progress(group_id).await {
+        emit_event(app, "queue-group-progress", progress);
+    }
+    if let Some(progress) = state.queue.check_group_finished(group_id).await {
+        let complete = progress.completed == progress.total;
+        emit_event(app, "queue-group-finished", progress);
+
+        if let Some(merge) = state.queue.get_group_merge(group_id).await.filter(|_| complete) {
+            let result = merge_multipart(state, &merge).await;
+            emit_event(app, "queue-multipart-merged", MultipartResult {
+                group_id: group_id.to_string(),
+                file_path: result.as_ref().ok().map(|p| p.to_string_lossy().to_string()),
+                error: result.err(),
+            });
+        }
+    }
+}
+
+#[tauri::command]
+async fn queue_start_download(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    id: String,
+) -> Result<(), String> {
+    start_queue_item(app, Arc::clone(&*state), id).await
+}
+
+/// Start downloading a queue item in the background. Shared by the Tauri
+/// command and the remote API.
+async fn start_queue_item(app: tauri::AppHandle, state: Arc<AppState>, id: String) -> Result<(), String> {
+    let item = state.queue.get_item(&id).await
+        .ok_or("Item not found")?;
+
+    if item.status != QueueItemStatus::Pending && item.status != QueueItemStatus::Paused {
+        return Err("Item is not in a downloadable state".to_string());
+    }
+
+    if let Some(reason) = state.auto_paused.lock().await.keys().next() {
+        return Err(format!("Queue is paused automatically ({})", reason));
+    }
+
+    if state.queue.is_blocked_by_group(&id).await {
+        return Err("Waiting for earlier episodes in this group to finish".to_string());
+    }
+
+    let settings = state.settings.read().await.clone();
+    let target = prepare_output(
+        &settings,
+        &item.output_dir,
+        &item.output_filename,
+        &item.title,
+        item.options.episode.clone(),
+    )?;
+
+    // Queue order decides who gets priority bandwidth
+    let queue_position = state.queue.get_items().await.iter().position(|i| i.id == id).unwrap_or(usize::MAX);
+    let extra_args = postprocess::parse_extra_args(item.options.extra_ffmpeg_args.as_deref().unwrap_or_default())?;
+    let stale = settings.is_stale(&item);
+
+    state.queue.update_item_status(&id, QueueItemStatus::Downloading).await;
+    let log = download_log(&state, &id).await;
+    log.info(format!("Download started ({} quality) to {}", item.quality, target.dir));
+
+    let app_clone = app.clone();
+    let state_clone = state.clone();
+    let id_clone = id.clone();
+
+    tokio::spawn(async move {
+        let cancel_rx = state_clone.queue.register_active_download(&id_clone).await;
+
+        let segment_workers = item.options.segment_workers.unwrap_or(settings.segment_workers);
+        let share = state_clone.bandwidth.register(queue_position);
+        let selected_origin = SelectedOrigin::default();
+        let downloader = VideoDownloader::new(!item.options.show_browser.unwrap_or(settings.show_browser))
+            .with_browser_pool(state_clone.browser_pool.clone())
+            .with_browser_allowed(!state_clone.low_battery.load(Ordering::Relaxed))
+            .with_segment_workers(segment_workers)
+            .with_segment_buffer_mb(settings.segment_buffer_mb)
+            .with_timeouts(settings.timeouts())
+            .with_fsync(settings.fsync_on_complete)
+            .with_site_qualities(settings.site_quality.clone())
+            .with_aria2(settings.aria2_config(&state_clone.bandwidth))
+            .with_smart_source_selection(settings.smart_source_selection)
+            .with_speed_matched_auto(settings.auto_quality_by_speed)
+            .with_source_preferences(settings.source_preferences())
+            .with_remux_mp4(settings.remux_to_mp4)
+            .with_faststart(settings.faststart_mp4)
+            .with_segment_cache(Some(state_clone.segment_cache.clone()))
+            .with_audio_tracks(item.options.audio_tracks.clone().unwrap_or_else(|| settings.audio_tracks.clone()))
+            .with_passthrough(settings.raw_passthrough)
+            .with_max_file_size(settings.max_file_size(item.options.max_file_size_mb))
+            .with_bandwidth(Some(share.clone()))
+            .with_selected_origin(selected_origin.clone())
+            .with_quality_fallback(settings.allow_quality_fallback)
+            .with_fallback_listener({
+                let (app, id, title) = (app_clone.clone(), id_clone.clone(), item.title.clone());
+                move |fallback| emit_event(&app, "quality-fallback", QualityFallbackEvent::new(Some(id.clone()), &title, fallback))
+            })
+            .with_log(log.clone());
+
+        // Progress lands in a watch channel; one writer task applies the
+        // latest value to the queue at most every PROGRESS_INTERVAL instead
+        // of a task and a lock per chunk
+        let (progress_tx, mut progress_rx) = tokio::sync::watch::channel((0.0f32, String::new(), None));
+        let progress_tx = Arc::new(progress_tx);
+        let updater = {
+            let app = app_clone.clone();
+            let state = state_clone.clone();
+            let id = id_clone.clone();
+            let group_id = item.options.group_id.clone();
+            tokio::spawn(async move {
+                let mut meter = SpeedMeter::default();
+                let mut count = None;
+                loop {
+                    // Wake without progress too, so a stall shows as no speed
+                    match tokio::time::timeout(SPEED_SAMPLE_INTERVAL, progress_rx.changed()).await {
+                        Ok(Err(_)) => break,
+                        Ok(Ok(())) => {
+                            let (progress, speed, latest) = progress_rx.borrow_and_update().clone();
+                            count = latest.or(count);
+                            state.queue.update_item_progress(&id, progress, speed, String::new()).await;
+                            emit_group_progress(&app, &state, group_id.as_deref()).await;
+                        }
+                        Err(_) => {}
+                    }
+                    let received = share.received();
+                    let stats = TransferStats { received_bytes: received, bytes_per_sec: meter.sample(received), count };
+                    state.queue.update_item_transfer(&id, stats).await;
+                    tokio::time::sleep(PROGRESS_INTERVAL).await;
+                }
+            })
+        };
+
+        let app_for_cb = app_clone.clone();
+        let id_for_cb = id_clone.clone();
+        let throttle = ProgressThrottle::default();
+
+        let progress_callback = move |progress: f32, message: String| {
+            let speed = if message.contains("KB/s") || message.contains("MB/s") {
+                message.split_whitespace().last().unwrap_or("").to_string()
+            } else {
+                String::new()
+            };
+
+            progress_tx.send_replace((progress, speed.clone(), progress::parse_transfer(&message)));
+
+            if !throttle.should_emit(progress) {
+                return;
+            }
+
+            let status = if ffmpeg::is_converting_message(&message) {
+                QueueItemStatus::Converting
+            } else {
+                QueueItemStatus::Downloading
+            };
+
+            let progress_data = QueueProgress {
+                id: id_for_cb.clone(),
+                status,
+                progress,
+                speed,
+                eta: String::new(),
+                message,
+                file_path: None,
+                error_help: None,
+            };
+
+            emit_event(&app_for_cb, "queue-progress", progress_data);
+        };
+
+        // The downloader (and its bandwidth share) is dropped with this
+        // future, before post-processing starts
+        let download = async {
+            let mut downloader = downloader;
+            let mut quality = item.quality.clone();
+            if stale {
+                // Links extracted long ago have likely expired; check the
+                // page again and keep the closest quality it still offers
+                log.info("Queued long ago, extracting the page again");
+                progress_callback(0.0, "กำลังตรวจสอบลิงก์วิดีโอ...".to_string());
+                let info = downloader.get_info(&item.url).await?;
+                if let Some(closest) = remap_quality(&info.sources, &quality) {
+                    log.warn(format!("{} is no longer offered, using {}", quality, closest));
+                    state_clone.queue.set_item_quality(&id_clone, &closest).await;
+                    quality = closest;
+                }
+                downloader = downloader.with_info(info);
+            }
+            downloader
+                .download_deferred(&item.url, &target.dir, Some(&target.filename), Some(&quality), progress_callback)
+                .await
+        };
+
+        // Use select to handle cancellation
+        tokio::select! {
+            result = download => {
+                state_clone.queue.unregister_active_download(&id_clone).await;
+                // Stop pending progress writes from overwriting the final state
+                updater.abort();
+                if let Some(origin) = selected_origin.get() {
+                    state_clone.queue.set_item_origin(&id_clone, origin).await;
+                }
+
+                let spec_for = |path: &Path| PostProcessSpec {
+                    extra_args: extra_args.clone(),
+                    ..settings.postprocess_spec(path)
+                };
+                let result = match result {
+                    Ok((path, needs_conversion))
+                        if needs_conversion || (!settings.raw_passthrough && spec_for(&path).is_needed()) =>
+                    {
+                        // The download slot is free now; conversion waits for
+                        // a post-processing slot instead
+                        postprocess_queue_item(&app_clone, &state_clone, &item, spec_for(&path)).await
+                    }
+                    result => result.map(|(path, _)| path),
+                };
+
+                finish_queue_item(&app_clone, &state_clone, &settings, &item, &target, result).await;
+            }
+            _ = cancel_rx => {
+                state_clone.queue.unregister_active_download(&id_clone).await;
+                // Stop pending progress writes from overwriting the final state
+                updater.abort();
+                if let Some(origin) = selected_origin.get() {
+                    state_clone.queue.set_item_origin(&id_clone, origin).await;
+                }
+                // Download was cancelled/paused
+                log.info("Paused or cancelled");
+            }
+        }
+
+        emit_group_progress(&app_clone, &state_clone, item.options.group_id.as_deref()).await;
+        emit_queue_finished(&app_clone, &state_clone).await;
+    });
+
+    Ok(())
+}
+
+/// Mark a downloaded queue item as processing and run it through the
+/// post-processing queue
+async fn postprocess_queue_item(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    item: &QueueItem,
+    spec: PostProcessSpec,
+) -> Result<PathBuf, DownloaderError> {
+    state.queue.update_item_status(&item.id, QueueItemStatus::Processing).await;
+    download_log(state, &item.id).await.info(format!("Post-processing {}", spec.input));
+    emit_event(app, "queue-progress", QueueProgress {
+        id: item.id.clone(),
+        status: QueueItemStatus::Processing,
+        progress: 0.0,
+        speed: String::new(),
+        eta: String::new(),
+        message: "รอแปลงไฟล์".to_string(),
+        file_path: None,
+        error_help: None,
+    });
+    emit_group_progress(app, state, item.options.group_id.as_deref()).await;
+
+    let app_for_cb = app.clone();
+    let id_for_cb = item.id.clone();
+    state.postprocess.run(Some(item.id.clone()), spec, move |progress, message| {
+        emit_event(&app_for_cb, "queue-progress", QueueProgress {
+            id: id_for_cb.clone(),
+            status: QueueItemStatus::Processing,
+            progress,
+            speed: String::new(),
+            eta: String::new(),
+            message,
+            file_path: None,
+            error_help: None,
+        });
+    }).await
+}
+
+/// Record the outcome of a queue download and tell the frontend
+async fn finish_queue_item(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    settings: &AppSettings,
+    item: &QueueItem,
+    target: &OutputTarget,
+    result: Result<PathBuf, DownloaderError>,
+) {
+    let log = download_log(state, &item.id).await;
+    if let Err(DownloaderError::Cancelled) = result {
+        // cancel_download already marked the item
+        log.info("Cancelled while post-processing");
+        return;
+    }
+    // Measured now; a remote output or upload may remove the local file
+    let bytes = result.as_ref().ok().and_then(|path| fs::metadata(path).ok()).map(|m| m.len()).unwrap_or(0);
+    let result = match result {
+        Ok(path) => {
+            if settings.output_layout == naming::LAYOUT_MEDIA_SERVER {
+                let meta = NfoMetadata {
+                    title: &item.title,
+                    source_url: &item.url,
+                    thumbnail: &item.thumbnail,
+                };
+                naming::write_nfo(&path, target.episode.as_ref(), &meta).ok();
+            }
+            match &target.remote {
+                Some(remote) => send_queue_item_to_remote(app, state, item, remote, &path).await.map(|url| (path, Some(url))),
+                None => Ok((path, None)),
+            }
+        }
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok((path, remote_url)) => {
+            log.info(format!("Completed: {}", remote_url.clone().unwrap_or_else(|| path.display().to_string())));
+
+            let path_str = remote_url.clone().unwrap_or_else(|| path.to_string_lossy().to_string());
+            state.queue.update_item_completed(&item.id, path_str.clone()).await;
+            state.queue.record_outcome(true, bytes).await;
+            site_stats::record(&get_site_stats_path(app), &item.url, Ok(bytes)).ok();
+            if remote_url.is_none() {
+                check_duplicate(app, &path).await;
+                if settings.write_checksums {
+                    let file = path.clone();
+                    match tokio::task::spawn_blocking(move || checksum::write_sidecar(&file)).await {
+                        Ok(Ok(hash)) => log.info(format!("SHA-256: {}", hash)),
+                        Ok(Err(e)) => log.warn(e),
+                        Err(e) => log.warn(e.to_string()),
+                    }
+                }
+            }
+
+            emit_event(app, "queue-progress", QueueProgress {
+                id: item.id.clone(),
+                status: QueueItemStatus::Completed,
+                progress: 100.0,
+                speed: String::new(),
+                eta: String::new(),
+                message: "ดาวน์โหลดเสร็จสมบูรณ์".to_string(),
+                file_path: Some(path_str),
+                error_help: None,
+            });
+
+            if remote_url.is_none() && settings.upload_destinations.iter().any(|d| d.enabled) {
+                tauri::async_runtime::spawn(upload_completed(app.clone(), settings.clone(), item.id.clone(), path, log));
+            }
+        }
+        Err(e) => {
+            let error_msg = e.to_string();
+            let help = e.help();
+            log.error(format!("Failed: {}", error_msg));
+            state.queue.update_item_error(&item.id, error_msg.clone(), help.clone()).await;
+            state.queue.record_outcome(false, 0).await;
+            site_stats::record(&get_site_stats_path(app), &item.url, Err(error_msg.clone())).ok();
+
+            emit_event(app, "queue-progress", QueueProgress {
+                id: item.id.clone(),
+                status: QueueItemStatus::Failed,
+                progress: 0.0,
+                speed: String::new(),
+                eta: String::new(),
+                message: format!("ดาวน์โหลดล้มเหลว: {}", error_msg),
+                file_path: None,
+                error_help: Some(help),
+            });
+        }
+    }
+}
+
+/// Send a finished download to its SFTP/FTP output folder, reporting
+/// progress like post-processing does
+async fn send_queue_item_to_remote(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    item: &QueueItem,
+    remote: &RemoteOutput,
+    path: &Path,
+) -> Result<String, DownloaderError> {
+    let log = download_log(state, &item.id).await;
+    log.info(format!("Sending to {}", remote.display()));
+    state.queue.update_item_status(&item.id, QueueItemStatus::Processing).await;
+
+    let throttle = ProgressThrottle::default();
+    let app_for_cb = app.clone();
+    let id_for_cb = item.id.clone();
+    let host = remote.host.clone();
+    remote
+        .send(path, move |sent, total| {
+            let progress = if total > 0 { sent as f32 / total as f32 * 100.0 } else { 100.0 };
+            if !throttle.should_emit(progress) {
+                return;
+            }
+            emit_event(&app_for_cb, "queue-progress", QueueProgress {
+                id: id_for_cb.clone(),
+                status: QueueItemStatus::Processing,
+                progress,
+                speed: String::new(),
+                eta: String::new(),
+                message: format!("กำลังส่งไฟล์ไปยัง {} {:.1}%", host, progress),
+                file_path: None,
+                error_help: None,
+            });
+        })
+        .await
+        .map_err(|e| DownloaderError::DownloadFailed(format!("Failed to send to {}: {}", remote.display(), e)))
+}
+
+/// Copy a finished download to every enabled upload destination, then
+/// remove the local file if asked and every copy was verified
+async fn upload_completed(app: tauri::AppHandle, settings: AppSettings, id: String, path: PathBuf, log: DownloadLog) {
+    let (app_dir, secrets_path) = get_upload_secrets_path(&app);
+    let secrets = credentials::load_secrets(&app_dir, &secrets_path).unwrap_or_else(|e| {
+        log.warn(e);
+        Default::default()
+    });
+
+    let mut all_verified = true;
+    for dest in settings.upload_destinations.iter().filter(|d| d.enabled) {
+        let mut dest = dest.clone();
+        if dest.password.is_empty() {
+            dest.password = secrets.get(&dest.password_ref).cloned().unwrap_or_default();
+        }
+        let name = if dest.name.is_empty() { dest.endpoint.clone() } else { dest.name.clone() };
+        let progress_event = {
+            let (id, name) = (id.clone(), name.clone());
+            move |status: &str, progress: f32, uploaded: u64, total: u64, message: String| UploadProgress {
+                id: id.clone(),
+                destination: name.clone(),
+                status: status.to_string(),
+                progress,
+                uploaded,
+                total,
+                message,
+            }
+        };
+
+        log.info(format!("Uploading to {}", name));
+        emit_event(&app, "upload-progress", progress_event(upload::UPLOAD_UPLOADING, 0.0, 0, 0, format!("กำลังอัปโหลดไปยัง {}", name)));
+
+        let throttle = ProgressThrottle::default();
+        let app_for_cb = app.clone();
+        let event_for_cb = progress_event.clone();
+        let name_for_cb = name.clone();
+        let result = upload::upload(&dest, &path, move |uploaded, total| {
+            let progress = if total > 0 { uploaded as f32 / total as f32 * 100.0 } else { 100.0 };
+            if throttle.should_emit(progress) {
+                let message = format!("กำลังอัปโหลดไปยัง {} {:.1}%", name_for_cb, progress);
+                emit_event(&app_for_cb, "upload-progress", event_for_cb(upload::UPLOAD_UPLOADING, progress, uploaded, total, message));
+            }
+        })
+        .await;
+
+        match result {
+            Ok(()) => {
+                log.info(format!("Uploaded to {}", name));
+                emit_event(&app, "upload-progress", progress_event(upload::UPLOAD_DONE, 100.0, 0, 0, format!("อัปโหลดไปยัง {} เสร็จสมบูรณ์", name)));
+            }
+            Err(e) => {
+                all_verified = false;
+                log.error(format!("Upload to {} failed: {}", name, e));
+                emit_event(&app, "upload-progress", progress_event(upload::UPLOAD_FAILED, 0.0, 0, 0, format!("อัปโหลดล้มเหลว: {}", e)));
+            }
+        }
+    }
+
+    if all_verified && settings.upload_delete_local {
+        match trash::remove(&path) {
+            Ok(()) => log.info(format!("Deleted local copy after upload: {}", path.display())),
+            Err(e) => log.warn(format!("Failed to delete local copy: {}", e)),
+        }
+    }
+}
+
+// ==================== Credential Commands ====================
+
+fn get_upload_secrets_path(app: &tauri::AppHandle) -> (PathBuf, PathBuf) {
+    let (app_dir, _) = get_credentials_path(app);
+    let path = app_dir.join("upload_secrets.enc");
+    (app_dir, path)
+}
+
+/// Move upload passwords typed into settings to the sealed secrets file,
+/// leaving a reference in their place, and drop secrets no destination
+/// refers to any more. Returns whether any password moved.
+fn seal_upload_secrets(app: &tauri::AppHandle, settings: &mut AppSettings) -> Result<bool, String> {
+    let (app_dir, path) = get_upload_secrets_path(app);
+    let mut secrets = credentials::load_secrets(&app_dir, &path)?;
+    let before = secrets.clone();
+
+    let mut moved = false;
+    for dest in settings.upload_destinations.iter_mut().filter(|d| !d.password.is_empty()) {
+        if dest.password_ref.is_empty() {
+            dest.password_ref = uuid::Uuid::new_v4().to_string();
+        }
+        secrets.insert(dest.password_ref.clone(), std::mem::take(&mut dest.password));
+        moved = true;
+    }
+    secrets.retain(|key, _| settings.upload_destinations.iter().any(|d| &d.password_ref == key));
+
+    if secrets != before {
+        credentials::save_secrets(&app_dir, &path, &secrets)?;
+    }
+    Ok(moved)
+}
+
+fn get_credentials_path(app: &tauri::AppHandle) -> (PathBuf, PathBuf) {
+    let app_dir = app.path().app_data_dir().unwrap_or_default();
+    fs::create_dir_all(&app_dir).ok();
+    let path = app_dir.join("credentials.enc");
+    (app_dir, path)
+}
+
+fn load_site_credentials(app: &tauri::AppHandle) -> Result<Vec<SiteCredential>, String> {
+    let (app_dir, path) = get_credentials_path(app);
+    credentials::load_credentials(&app_dir, &path)
+}
+
+/// Hand the saved logins to the browser pool for auto-login
+fn apply_credentials(state: &AppState, saved: &[SiteCredential]) {
+    state.browser_pool.set_credentials(saved.iter().map(Into::into).collect());
+}
+
+#[tauri::command]
+async fn credentials_list(app: tauri::AppHandle) -> Result<Vec<CredentialSummary>, String> {
+    Ok(load_site_credentials(&app)?.iter().map(Into::into).collect())
+}
+
+/// Add or update a login. An empty password on update keeps the stored one.
+#[tauri::command]
+async fn credentials_save(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    credential: SiteCredential,
+) -> Result<String, String> {
+    let mut credential = credential;
+    credential.domain = credentials::normalize_domain(&credential.domain);
+    if credential.domain.is_empty() {
+        return Err("Domain is required".to_string());
+    }
+
+    let mut saved = load_site_credentials(&app)?;
+    if let Some(existing) = saved.iter_mut().find(|c| !credential.id.is_empty() && c.id == credential.id) {
+        if credential.password.is_empty() {
+            credential.password = existing.password.clone();
+        }
+        *existing = credential.clone();
+    } else {
+        credential.id = uuid::Uuid::new_v4().to_string();
+        saved.push(credential.clone());
+    }
+
+    let (app_dir, path) = get_credentials_path(&app);
+    credentials::save_credentials(&app_dir, &path, &saved)?;
+    apply_credentials(&state, &saved);
+    Ok(credential.id)
+}
+
+#[tauri::command]
+async fn credentials_delete(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    id: String,
+) -> Result<(), String> {
+    let mut saved = load_site_credentials(&app)?;
+    saved.retain(|c| c.id != id);
+
+    let (app_dir, path) = get_credentials_path(&app);
+    credentials::save_credentials(&app_dir, &path, &saved)?;
+    apply_credentials(&state, &saved);
+    Ok(())
+}
+
+// ==================== Script Hook Commands ====================
+
+fn get_scripts_dir(app: &tauri::AppHandle) -> PathBuf {
+    let dir = app.path().app_data_dir().unwrap_or_default().join("scripts");
+    fs::create_dir_all(&dir).ok();
+    dir
+}
+
+/// Folder holding per-domain hook scripts (`<domain>.page.js`,
+/// `<domain>.transform.js`), so the UI can open it for editing
+#[tauri::command]
+async fn hooks_get_dir(app: tauri::AppHandle) -> Result<String, String> {
+    Ok(get_scripts_dir(&app).to_string_lossy().to_string())
+}
+
+/// Re-read the scripts folder and return the loaded hooks
+#[tauri::command]
+async fn hooks_reload(app: tauri::AppHandle, state: State<'_, Arc<AppState>>) -> Result<Vec<SiteHook>, String> {
+    let hooks = hooks::load_hooks(&get_scripts_dir(&app));
+    state.browser_pool.set_hooks(hooks.clone());
+    Ok(hooks)
+}
+
+// ==================== Extractor Rule Commands ====================
+
+fn get_rules_dir(app: &tauri::AppHandle) -> PathBuf {
+    let dir = app.path().app_data_dir().unwrap_or_default().join("rules");
+    fs::create_dir_all(&dir).ok();
+    dir
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RulesLoadResult {
+    pub rules: Vec<ExtractorRule>,
+    /// Files or rules that were skipped, with the reason
+    pub errors: Vec<String>,
+}
+
+fn reload_rules(app: &tauri::AppHandle) -> RulesLoadResult {
+    let (mut loaded, errors) = rules::load_rules(&get_rules_dir(app));
+    // The user's rules come first so they win over published ones
+    if let Some(remote) = remote_rule_set(app) {
+        loaded.extend(remote.rules);
+    }
+    rules::set_rules(loaded.clone());
+    RulesLoadResult { rules: loaded, errors }
+}
+
+/// Folder holding custom extractor rule files (`*.json`)
+#[tauri::command]
+async fn rules_get_dir(app: tauri::AppHandle) -> Result<String, String> {
+    Ok(get_rules_dir(&app).to_string_lossy().to_string())
+}
+
+#[tauri::command]
+async fn rules_reload(app: tauri::AppHandle) -> Result<RulesLoadResult, String> {
+    Ok(reload_rules(&app))
+}
+
+// ==================== Ad Pattern Commands ====================
+
+fn get_ad_patterns_path(app: &tauri::AppHandle) -> PathBuf {
+    let app_dir = app.path().app_data_dir().unwrap_or_default();
+    fs::create_dir_all(&app_dir).ok();
+    app_dir.join("ad_patterns.txt")
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AdPatternsLoadResult {
+    pub patterns: Vec<String>,
+    /// Lines that were skipped, with the reason
+    pub errors: Vec<String>,
+}
+
+fn reload_ad_patterns(app: &tauri::AppHandle) -> AdPatternsLoadResult {
+    let (mut loaded, errors) = ads::load_patterns(&get_ad_patterns_path(app));
+    if let Some(remote) = remote_rule_set(app) {
+        loaded.extend(remote.ad_patterns.iter().filter_map(|p| ads::AdPattern::parse(p).ok()));
+    }
+    let patterns = loaded.iter().map(|p| p.source.clone()).collect();
+    ads::set_patterns(loaded);
+    AdPatternsLoadResult { patterns, errors }
+}
+
+/// Path of the user-editable ad pattern file
+#[tauri::command]
+async fn ad_patterns_get_path(app: tauri::AppHandle) -> Result<String, String> {
+    Ok(get_ad_patterns_path(&app).to_string_lossy().to_string())
+}
+
+#[tauri::command]
+async fn ad_patterns_reload(app: tauri::AppHandle) -> Result<AdPatternsLoadResult, String> {
+    Ok(reload_ad_patterns(&app))
+}
+
+/// Block sources matching `pattern` from now on (plain text, * wildcards
+/// or re:regex)
+#[tauri::command]
+async fn ad_patterns_add(app: tauri::AppHandle, pattern: String) -> Result<AdPatternsLoadResult, String> {
+    let pattern = ads::AdPattern::parse(&pattern)?.source;
+    let path = get_ad_patterns_path(&app);
+    let mut lines = ads::read_lines(&path)?;
+    if !lines.contains(&pattern) {
+        lines.push(pattern);
+        ads::write_lines(&path, &lines)?;
+    }
+    Ok(reload_ad_patterns(&app))
+}
+
+#[tauri::command]
+async fn ad_patterns_remove(app: tauri::AppHandle, pattern: String) -> Result<AdPatternsLoadResult, String> {
+    let path = get_ad_patterns_path(&app);
+    let mut lines = ads::read_lines(&path)?;
+    let before = lines.len();
+    lines.retain(|line| line != pattern.trim());
+    if lines.len() == before {
+        return Err(format!("Ad pattern not found: {}", pattern));
+    }
+    ads::write_lines(&path, &lines)?;
+    Ok(reload_ad_patterns(&app))
+}
+
+// ==================== Rule Updates ====================
+
+fn get_remote_rules_path(app: &tauri::AppHandle) -> PathBuf {
+    let app_dir = app.path().app_data_dir().unwrap_or_default();
+    fs::create_dir_all(&app_dir).ok();
+    app_dir.join("remote_rules.json")
+}
+
+/// Last verified published rule set, while updates are turned on
+fn remote_rule_set(app: &tauri::AppHandle) -> Option<RemoteRuleSet> {
+    load_settings_file(app).filter(|s| s.rule_updates_enabled)?;
+    rule_updates::load_saved(&get_remote_rules_path(app))
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RuleUpdateStatus {
+    pub version: u64,
+    /// A newer rule set was downloaded and applied
+    pub updated: bool,
+    pub rules: usize,
+    pub ad_patterns: usize,
+}
+
+/// Fetch the published rule set and apply it when it's newer than the one
+/// saved. Emits "rules-updated" when something changed.
+async fn update_rules(app: &tauri::AppHandle, settings: &AppSettings) -> Result<RuleUpdateStatus, String> {
+    if !settings.rule_updates_enabled {
+        return Err("Rule updates are turned off".to_string());
+    }
+    let fetched = rule_updates::fetch(&settings.rule_updates_url, &settings.rule_updates_public_key).await?;
+
+    let path = get_remote_rules_path(app);
+    let current = rule_updates::load_saved(&path).unwrap_or_default();
+    let updated = fetched.version > current.version;
+    let applied = if updated {
+        rule_updates::save(&path, &fetched)?;
+        reload_rules(app);
+        reload_ad_patterns(app);
+        fetched
+    } else {
+        current
+    };
+
+    let status = RuleUpdateStatus {
+        version: applied.version,
+        updated,
+        rules: applied.rules.len(),
+        ad_patterns: applied.ad_patterns.len(),
+    };
+    if updated {
+        emit_event(app, "rules-updated", status.clone());
+    }
+    Ok(status)
+}
+
+#[tauri::command]
+async fn rule_updates_check(app: tauri::AppHandle, state: State<'_, Arc<AppState>>) -> Result<RuleUpdateStatus, String> {
+    let settings = state.settings.read().await.clone();
+    update_rules(&app, &settings).await
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RuleTestResult {
+    /// Whether the rule's url_pattern would select this page
+    pub url_matches: bool,
+    #[serde(flatten)]
+    pub matched: RuleMatch,
+    /// Sources left after the usual ad/segment filtering
+    pub final_sources: Vec<VideoSourceResponse>,
 }
 
+/// Run a candidate rule against a live page without saving it, so custom
+/// rules can be iterated on from the app
 #[tauri::command]
-async fn queue_cancel(state: State<'_, Arc<AppState>>, id: String) -> Result<bool, String> {
-    Ok(state.queue.cancel_download(&id).await)
+async fn test_extraction_rule(url: String, rule: ExtractorRule) -> Result<RuleTestResult, String> {
+    let matched = HttpExtractor::new()
+        .extract_with_rule(&url, &rule)
+        .await
+        .map_err(|e| format!("Failed to run rule: {}", e))?;
+
+    let info = downloader::build_video_info(&url, matched.title.clone(), matched.thumbnail.clone(), &matched.sources);
+    let final_sources = VideoInfoResponse::from(info).sources;
+
+    Ok(RuleTestResult {
+        url_matches: rule.matches(&url),
+        matched,
+        final_sources,
+    })
 }
 
+// ==================== Size Estimates ====================
+
+/// Estimated download size of each source, for the "fit under N GB" mode
 #[tauri::command]
-async fn queue_clear_completed(state: State<'_, Arc<AppState>>) -> Result<(), String> {
-    state.queue.clear_completed().await;
-    Ok(())
+async fn get_size_estimates(
+    state: State<'_, Arc<AppState>>,
+    url: String,
+) -> Result<Vec<SizeEstimate>, String> {
+    let settings = state.settings.read().await.clone();
+    let info = VideoDownloader::new(!settings.show_browser)
+        .with_browser_pool(state.browser_pool.clone())
+        .with_browser_allowed(!state.low_battery.load(Ordering::Relaxed))
+        .with_timeouts(settings.timeouts())
+        .get_info(&url)
+        .await
+        .map_err(|e| format!("Failed to get video info: {}", e))?;
+
+    let headers = rules::rule_for(&url).map(|r| r.header_list()).unwrap_or_default();
+    Ok(size::estimate_sizes(&info.sources, Some(&url), &headers).await)
 }
 
+// ==================== Source Preview ====================
+
+/// A frame of one source as a `data:image/jpeg;base64,...` URL, to check a
+/// mirror is the real video and not an ad before downloading it
 #[tauri::command]
-async fn queue_clear_all(state: State<'_, Arc<AppState>>) -> Result<(), String> {
-    state.queue.clear_all().await;
-    Ok(())
+async fn preview_source(source_url: String, referer: Option<String>) -> Result<String, String> {
+    let source_url = downloader::validate_url(&source_url).map_err(|e| e.to_string())?;
+    let headers = rules::rule_for(referer.as_deref().unwrap_or(&source_url))
+        .map(|r| r.header_list())
+        .unwrap_or_default();
+    let jpeg = probe::preview_frame(&source_url, referer.as_deref(), &headers)
+        .await
+        .map_err(|e| format!("Failed to capture preview: {}", e))?;
+    Ok(format!("data:image/jpeg;base64,{}", base64::engine::general_purpose::STANDARD.encode(jpeg)))
 }
 
+// ==================== aria2 ====================
+
+/// Check that aria2c answers on the configured RPC endpoint; returns its version
 #[tauri::command]
-async fn queue_move_item(state: State<'_, Arc<AppState>>, id: String, direction: i32) -> Result<(), String> {
-    state.queue.move_item(&id, direction).await;
-    Ok(())
+async fn aria2_check(state: State<'_, Arc<AppState>>) -> Result<String, String> {
+    let settings = state.settings.read().await.clone();
+    let config = Aria2Config {
+        rpc_url: settings.aria2_rpc_url,
+        secret: settings.aria2_rpc_secret,
+        connections: settings.aria2_connections,
+        ..Aria2Config::default()
+    };
+    Aria2Client::new(config)
+        .version()
+        .await
+        .map_err(|e| format!("Failed to reach aria2: {}", e))
 }
 
+/// Hardware encoder families the installed ffmpeg offers
 #[tauri::command]
-async fn queue_start_download(
+async fn get_video_encoders() -> Result<Vec<String>, String> {
+    Ok(encoders::available_hw().await.to_vec())
+}
+
+// ==================== Playlist Export ====================
+
+/// Write discovered sources as links instead of files. `m3u` asks where to
+/// save one .m3u/.m3u8 playlist; `strm` asks for a folder and writes one
+/// `<title>.strm` per entry into it. The location always comes from the
+/// save dialog. Returns the paths written, none when the dialog is closed.
+#[tauri::command]
+async fn export_playlist(
     app: tauri::AppHandle,
-    state: State<'_, Arc<AppState>>,
-    id: String,
-) -> Result<(), String> {
-    let item = state.queue.get_item(&id).await
-        .ok_or("Item not found")?;
+    entries: Vec<PlaylistEntry>,
+    format: String,
+) -> Result<Vec<String>, String> {
+    if entries.is_empty() {
+        return Err("Nothing to export".to_string());
+    }
+    for entry in &entries {
+        downloader::validate_url(&entry.source_url).map_err(|e| e.to_string())?;
+    }
 
-    if item.status != QueueItemStatus::Pending && item.status != QueueItemStatus::Paused {
-        return Err("Item is not in a downloadable state".to_string());
+    let (path_tx, path_rx) = tokio::sync::oneshot::channel();
+    let send = move |path: Option<FilePath>| {
+        let _ = path_tx.send(path);
+    };
+    match format.as_str() {
+        playlist::FORMAT_M3U => {
+            app.dialog()
+                .file()
+                .add_filter("M3U playlist", &["m3u", "m3u8"])
+                .set_file_name("playlist.m3u")
+                .save_file(send);
+            let Some(mut path) = dialog_path(path_rx).await? else {
+                return Ok(Vec::new());
+            };
+            let is_playlist = path
+                .extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("m3u") || e.eq_ignore_ascii_case("m3u8"));
+            if !is_playlist {
+                path.as_mut_os_string().push(".m3u");
+            }
+            fs::write(&path, playlist::to_m3u(&entries))
+                .map_err(|e| format!("Failed to write playlist: {}", e))?;
+            Ok(vec![path.to_string_lossy().to_string()])
+        }
+        playlist::FORMAT_STRM => {
+            app.dialog().file().pick_folder(send);
+            let Some(dir) = dialog_path(path_rx).await? else {
+                return Ok(Vec::new());
+            };
+
+            let mut written = Vec::new();
+            for (index, entry) in entries.iter().enumerate() {
+                let name = downloader::sanitize_filename(&entry.title);
+                let name = if name.is_empty() { format!("video-{}", index + 1) } else { name };
+                let path = dir.join(format!("{}.strm", name));
+                fs::write(downloader::long_path(&path), playlist::to_strm(entry))
+                    .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+                written.push(path.to_string_lossy().to_string());
+            }
+            Ok(written)
+        }
+        _ => Err(format!("Unsupported playlist format: {}", format)),
     }
+}
 
-    state.queue.update_item_status(&id, QueueItemStatus::Downloading).await;
+/// Path picked in a file dialog, or None when it was cancelled
+async fn dialog_path(path_rx: tokio::sync::oneshot::Receiver<Option<FilePath>>) -> Result<Option<PathBuf>, String> {
+    match path_rx.await.ok().flatten() {
+        Some(path) => path.into_path().map(Some).map_err(|e| e.to_string()),
+        None => Ok(None),
+    }
+}
 
-    let app_clone = app.clone();
-    let state_clone = Arc::clone(&*state);
-    let id_clone = id.clone();
+// ==================== Cookie Commands ====================
 
-    tokio::spawn(async move {
-        let cancel_rx = state_clone.queue.register_active_download(&id_clone).await;
+/// Write the browser profile's cookies for a domain to a cookies.txt file
+/// (usable with yt-dlp) picked in a save dialog. Returns the number of
+/// cookies exported, or None when the dialog was cancelled.
+#[tauri::command]
+async fn cookies_export(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    domain: String,
+) -> Result<Option<usize>, String> {
+    let domain = credentials::normalize_domain(&domain);
+    let (content, count) = state.browser_pool.export_cookies(&domain).await
+        .map_err(|e| format!("Failed to export cookies: {}", e))?;
+
+    let (path_tx, path_rx) = tokio::sync::oneshot::channel();
+    app.dialog()
+        .file()
+        .add_filter("Cookies", &["txt"])
+        .set_file_name("cookies.txt")
+        .save_file(move |path| {
+            let _ = path_tx.send(path);
+        });
+    let Some(path) = dialog_path(path_rx).await? else {
+        return Ok(None);
+    };
 
-        let downloader = VideoDownloader::new(true);
+    fs::write(&path, content).map_err(|e| format!("Failed to write cookies file: {}", e))?;
+    Ok(Some(count))
+}
 
-        let app_for_cb = app_clone.clone();
-        let state_for_cb = state_clone.clone();
-        let id_for_cb = id_clone.clone();
+/// Import a cookies.txt (e.g. exported from the user's main browser) picked
+/// in an open dialog into the browser profile. Returns the number of
+/// cookies imported, or None when the dialog was cancelled.
+#[tauri::command]
+async fn cookies_import(app: tauri::AppHandle, state: State<'_, Arc<AppState>>) -> Result<Option<usize>, String> {
+    let (path_tx, path_rx) = tokio::sync::oneshot::channel();
+    app.dialog()
+        .file()
+        .add_filter("Cookies", &["txt"])
+        .pick_file(move |path| {
+            let _ = path_tx.send(path);
+        });
+    let Some(path) = dialog_path(path_rx).await? else {
+        return Ok(None);
+    };
 
-        let progress_callback = move |progress: f32, message: String| {
-            let speed = if message.contains("KB/s") || message.contains("MB/s") {
-                message.split_whitespace().last().unwrap_or("").to_string()
-            } else {
-                String::new()
-            };
+    let content = fs::read_to_string(&path).map_err(|e| format!("Failed to read cookies file: {}", e))?;
+    state.browser_pool.import_cookies(&content).await
+        .map(Some)
+        .map_err(|e| format!("Failed to import cookies: {}", e))
+}
 
-            let progress_data = QueueProgress {
-                id: id_for_cb.clone(),
-                status: QueueItemStatus::Downloading,
-                progress,
-                speed: speed.clone(),
-                eta: String::new(),
-                message: message.clone(),
-                file_path: None,
-            };
+// ==================== Backup Commands ====================
 
-            let _ = app_for_cb.emit("queue-progress", progress_data);
+/// Bundle settings, history, queue, extractor rules, hook scripts and ad
+/// patterns into one passphrase-encrypted file. Saved logins, upload
+/// passwords and the secrets inside settings only when
+/// `include_credentials` is set.
+#[tauri::command]
+async fn export_app_data(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    path: String,
+    passphrase: String,
+    include_credentials: bool,
+) -> Result<BackupSummary, String> {
+    let mut contents = BackupContents::new();
+    let settings = state.settings.read().await.clone();
+    contents.settings = Some(if include_credentials { settings } else { settings.without_secrets() });
+    contents.history = history::load_history(&get_history_path(&app))?;
+    contents.queue = state.queue.snapshot().await;
+    contents.rules = backup::read_files(&get_rules_dir(&app));
+    contents.scripts = backup::read_files(&get_scripts_dir(&app));
+    contents.ad_patterns = ads::read_lines(&get_ad_patterns_path(&app)).ok();
+    if include_credentials {
+        contents.credentials = Some(load_site_credentials(&app)?);
+        let (app_dir, secrets_path) = get_upload_secrets_path(&app);
+        contents.upload_secrets = Some(credentials::load_secrets(&app_dir, &secrets_path)?);
+    }
 
-            // Update queue item
-            let state_clone = state_for_cb.clone();
-            let id_clone = id_for_cb.clone();
-            tokio::spawn(async move {
-                state_clone.queue.update_item_progress(&id_clone, progress, speed, String::new()).await;
-            });
-        };
+    let summary = contents.summary();
+    // Key derivation is deliberately slow
+    tauri::async_runtime::spawn_blocking(move || backup::export(Path::new(&path), &contents, &passphrase))
+        .await
+        .map_err(|e| format!("Failed to export app data: {}", e))??;
+    Ok(summary)
+}
 
-        // Use select to handle cancellation
-        tokio::select! {
-            result = downloader.download(
-                &item.url,
-                &item.output_dir,
-                Some(&item.output_filename),
-                Some(&item.quality),
-                progress_callback,
-            ) => {
-                state_clone.queue.unregister_active_download(&id_clone).await;
+/// Replace this machine's data with a backup's. Refused while downloads
+/// run, since the backup's queue replaces the current one.
+#[tauri::command]
+async fn import_app_data(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    path: String,
+    passphrase: String,
+) -> Result<BackupSummary, String> {
+    if state.queue.get_active_count().await > 0 {
+        return Err("Stop running downloads before restoring a backup".to_string());
+    }
+    let contents = tauri::async_runtime::spawn_blocking(move || backup::import(Path::new(&path), &passphrase))
+        .await
+        .map_err(|e| format!("Failed to import app data: {}", e))??;
 
-                match result {
-                    Ok(path) => {
-                        let path_str = path.to_string_lossy().to_string();
-                        state_clone.queue.update_item_completed(&id_clone, path_str.clone()).await;
-
-                        let _ = app_clone.emit("queue-progress", QueueProgress {
-                            id: id_clone,
-                            status: QueueItemStatus::Completed,
-                            progress: 100.0,
-                            speed: String::new(),
-                            eta: String::new(),
-                            message: "ดาวน์โหลดเสร็จสมบูรณ์".to_string(),
-                            file_path: Some(path_str),
-                        });
-                    }
-                    Err(e) => {
-                        let error_msg = e.to_string();
-                        state_clone.queue.update_item_error(&id_clone, error_msg.clone()).await;
-
-                        let _ = app_clone.emit("queue-progress", QueueProgress {
-                            id: id_clone,
-                            status: QueueItemStatus::Failed,
-                            progress: 0.0,
-                            speed: String::new(),
-                            eta: String::new(),
-                            message: format!("ดาวน์โหลดล้มเหลว: {}", error_msg),
-                            file_path: None,
-                        });
-                    }
-                }
-            }
-            _ = cancel_rx => {
-                state_clone.queue.unregister_active_download(&id_clone).await;
-                // Download was cancelled/paused
-            }
+    if let Some(secrets) = &contents.upload_secrets {
+        let (app_dir, secrets_path) = get_upload_secrets_path(&app);
+        let mut saved = credentials::load_secrets(&app_dir, &secrets_path)?;
+        saved.extend(secrets.clone());
+        credentials::save_secrets(&app_dir, &secrets_path, &saved)?;
+    }
+    if let Some(mut settings) = contents.settings.clone() {
+        // A backup made without credentials keeps this machine's secrets
+        let current = state.settings.read().await.clone();
+        if settings.remote_api_token.is_empty() {
+            settings.remote_api_token = current.remote_api_token;
         }
-    });
+        if settings.aria2_rpc_secret.is_empty() {
+            settings.aria2_rpc_secret = current.aria2_rpc_secret;
+        }
+        store_settings(&app, &state, settings).await?;
+    }
+    history::save_history(&get_history_path(&app), &contents.history)?;
+    write_queue_file(&app, &contents.queue)?;
+    state.queue.restore(contents.queue.clone()).await;
+
+    backup::write_files(&get_rules_dir(&app), &contents.rules)?;
+    backup::write_files(&get_scripts_dir(&app), &contents.scripts)?;
+    if let Some(lines) = &contents.ad_patterns {
+        ads::write_lines(&get_ad_patterns_path(&app), lines)?;
+    }
+    if let Some(saved) = &contents.credentials {
+        let (app_dir, path) = get_credentials_path(&app);
+        credentials::save_credentials(&app_dir, &path, saved)?;
+        apply_credentials(&state, saved);
+    }
 
-    Ok(())
+    reload_rules(&app);
+    reload_ad_patterns(&app);
+    state.browser_pool.set_hooks(hooks::load_hooks(&get_scripts_dir(&app)));
+    Ok(contents.summary())
 }
 
 // ==================== Settings Commands ====================
@@ -568,17 +3211,58 @@ fn get_settings_path(app: &tauri::AppHandle) -> PathBuf {
     app_dir.join("settings.json")
 }
 
+// sanitize_filename, validate_url and the HTTP clients read these
+// process-wide, so push them on every load/save
+fn apply_global_settings(settings: &AppSettings) {
+    transliterate::set_mode(&settings.filename_transliteration);
+    downloader::set_max_filename_length(settings.max_filename_length);
+    dns::set_network_config(NetworkConfig::new(
+        &settings.dns_over_https,
+        &settings.dns_overrides,
+        &settings.ip_preference,
+    ));
+    lan::set_lan_allowlist(&settings.lan_allowlist);
+    browser::set_launch_options(&settings.browser_executable, &settings.browser_args);
+    webdriver::set_backend(&settings.browser_backend, &settings.geckodriver_path);
+    trash::set_permanent_delete(settings.permanent_delete);
+    watchdog::set_extraction_waits(ExtractionWaits::from_millis(
+        settings.page_load_wait_ms,
+        settings.iframe_wait_ms,
+        settings.playback_wait_ms,
+    ));
+}
+
+fn load_settings_file(app: &tauri::AppHandle) -> Option<AppSettings> {
+    let settings_path = get_settings_path(app);
+
+    if !settings_path.exists() {
+        return None;
+    }
+
+    let content = fs::read_to_string(&settings_path).unwrap_or_default();
+    let mut settings = serde_json::from_str::<AppSettings>(&content).ok()?;
+    // Files from older versions still carry upload passwords in the clear
+    if seal_upload_secrets(app, &mut settings).unwrap_or(false) {
+        write_settings_file(app, &settings).ok();
+    }
+    Some(settings)
+}
+
+fn write_settings_file(app: &tauri::AppHandle, settings: &AppSettings) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+
+    fs::write(get_settings_path(app), content)
+        .map_err(|e| format!("Failed to save settings: {}", e))
+}
+
 #[tauri::command]
 async fn get_settings(app: tauri::AppHandle, state: State<'_, Arc<AppState>>) -> Result<AppSettings, String> {
-    let settings_path = get_settings_path(&app);
-
-    if settings_path.exists() {
-        let content = fs::read_to_string(&settings_path).unwrap_or_default();
-        if let Ok(settings) = serde_json::from_str::<AppSettings>(&content) {
-            let mut state_settings = state.settings.write().await;
-            *state_settings = settings.clone();
-            return Ok(settings);
-        }
+    if let Some(settings) = load_settings_file(&app) {
+        apply_global_settings(&settings);
+        let mut state_settings = state.settings.write().await;
+        *state_settings = settings.clone();
+        return Ok(settings);
     }
 
     Ok(state.settings.read().await.clone())
@@ -590,27 +3274,351 @@ async fn save_settings(
     state: State<'_, Arc<AppState>>,
     settings: AppSettings,
 ) -> Result<(), String> {
-    let settings_path = get_settings_path(&app);
+    store_settings(&app, &state, settings).await
+}
+
+/// Apply settings to the running app and persist them. Shared by the Tauri
+/// command and the remote API.
+async fn store_settings(app: &tauri::AppHandle, state: &Arc<AppState>, mut settings: AppSettings) -> Result<(), String> {
+    bandwidth::validate_schedule(&settings.speed_schedule)?;
+    dns::validate_settings(&settings.dns_over_https, &settings.dns_overrides)?;
+    lan::validate_allowlist(&settings.lan_allowlist)?;
+    upload::validate_destinations(&settings.upload_destinations)?;
+    browser::validate_launch_options(&settings.browser_executable, &settings.browser_args)?;
+    webdriver::validate_backend(&settings.browser_backend, &settings.geckodriver_path)?;
+    RemoteOutput::parse(&settings.default_download_dir)?;
+    seal_upload_secrets(app, &mut settings)?;
+
+    let (remote_changed, rule_updates_toggled, browser_changed) = {
+        let current = state.settings.read().await;
+        (
+            current.remote_api_enabled != settings.remote_api_enabled
+                || current.remote_api_port != settings.remote_api_port
+                || current.remote_api_token != settings.remote_api_token
+                || current.remote_api_lan != settings.remote_api_lan,
+            current.rule_updates_enabled != settings.rule_updates_enabled,
+            current.browser_executable != settings.browser_executable || current.browser_args != settings.browser_args,
+        )
+    };
 
     // Update state
     {
         let mut state_settings = state.settings.write().await;
+        // A settings form loaded earlier carries an outdated copy
+        settings.ui_state = state_settings.ui_state.clone();
         *state_settings = settings.clone();
     }
 
     // Update queue max concurrent
     state.queue.set_max_concurrent(settings.max_concurrent_downloads).await;
+    state.queue.set_max_per_host(settings.max_downloads_per_host).await;
+    state.postprocess.set_max_concurrent(settings.max_concurrent_postprocess).await;
+    state.bandwidth.configure(settings.current_speed_limit(), settings.prioritize_top_download);
+    state.segment_cache.set_max_mb(settings.segment_cache_limit());
+
+    apply_global_settings(&settings);
 
     // Save to file
-    let content = serde_json::to_string_pretty(&settings)
-        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+    write_settings_file(app, &settings)?;
+
+    // Published rules apply only while updates are on
+    if rule_updates_toggled {
+        reload_rules(app);
+        reload_ad_patterns(app);
+    }
+
+    if remote_changed {
+        remote::restart(app.clone(), state.clone()).await;
+    }
 
-    fs::write(&settings_path, content)
-        .map_err(|e| format!("Failed to save settings: {}", e))?;
+    // The next extraction starts a browser with the new binary and flags
+    if browser_changed {
+        state.browser_pool.shutdown().await;
+    }
 
     Ok(())
 }
 
+#[tauri::command]
+async fn get_ui_state(state: State<'_, Arc<AppState>>) -> Result<UiState, String> {
+    Ok(state.settings.read().await.ui_state.clone())
+}
+
+/// Remember the output folder and quality last picked; None leaves a
+/// value as it was
+#[tauri::command]
+async fn set_ui_state(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    last_output_dir: Option<String>,
+    last_quality: Option<String>,
+) -> Result<(), String> {
+    let settings = {
+        let mut settings = state.settings.write().await;
+        if let Some(dir) = last_output_dir {
+            settings.ui_state.last_output_dir = dir;
+        }
+        if let Some(quality) = last_quality {
+            settings.ui_state.last_quality = quality;
+        }
+        settings.clone()
+    };
+    write_settings_file(&app, &settings)
+}
+
+/// Save the window's placement as it closes. Runs on the event loop
+/// thread, outside the async runtime, so the settings lock is taken
+/// blocking.
+fn remember_window(window: &tauri::WebviewWindow) {
+    let app = window.app_handle();
+    let state = app.state::<Arc<AppState>>();
+    let settings = {
+        let mut settings = state.settings.blocking_write();
+        settings.ui_state.window = ui_state::capture(window, settings.ui_state.window);
+        settings.clone()
+    };
+    write_settings_file(app, &settings).ok();
+}
+
+// How often the speed schedule is re-checked
+const SPEED_SCHEDULE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Switch the total speed limit as schedule rules start and end
+async fn watch_speed_schedule(state: Arc<AppState>) {
+    loop {
+        tokio::time::sleep(SPEED_SCHEDULE_INTERVAL).await;
+        let settings = state.settings.read().await;
+        state.bandwidth.configure(settings.current_speed_limit(), settings.prioritize_top_download);
+    }
+}
+
+// How often the scratch dir is swept for abandoned files
+const TEMP_SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15 * 60);
+
+#[derive(Clone, Serialize)]
+struct TempFilesCleaned {
+    count: usize,
+    bytes: u64,
+}
+
+/// Remove scratch files crashed or cancelled downloads left in the scratch
+/// dir. Files a post-processing job still reads are kept, as is anything
+/// written to recently, which covers every download still running.
+async fn sweep_temp_files(app: tauri::AppHandle, state: Arc<AppState>) {
+    loop {
+        tokio::time::sleep(TEMP_SWEEP_INTERVAL).await;
+        if !state.settings.read().await.auto_clean_temp_files {
+            continue;
+        }
+
+        let in_use: Vec<PathBuf> = state
+            .postprocess
+            .get_jobs()
+            .await
+            .into_iter()
+            .filter(|j| j.status == postprocess::JOB_QUEUED || j.status == postprocess::JOB_RUNNING)
+            .map(|j| PathBuf::from(j.spec.input))
+            .collect();
+        let swept = tokio::task::spawn_blocking(move || {
+            let stale = recovery::stale_temp_files(recovery::TEMP_FILE_GRACE, &in_use);
+            recovery::remove_temp_files(&stale)
+        })
+        .await
+        .unwrap_or_default();
+        if swept.0 > 0 {
+            emit_event(&app, "temp-files-cleaned", TempFilesCleaned { count: swept.0, bytes: swept.1 });
+        }
+    }
+}
+
+// ==================== Queue Persistence ====================
+
+// Grace period for cancelled download tasks to drop their files
+const SHUTDOWN_GRACE: std::time::Duration = std::time::Duration::from_millis(500);
+
+// How often the queue is saved while running, so a crash loses little
+const QUEUE_AUTOSAVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+fn get_queue_path(app: &tauri::AppHandle) -> PathBuf {
+    let app_dir = app.path().app_data_dir().unwrap_or_default();
+    fs::create_dir_all(&app_dir).ok();
+    app_dir.join("queue.json")
+}
+
+fn write_queue_file(app: &tauri::AppHandle, snapshot: &QueueSnapshot) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(snapshot)
+        .map_err(|e| format!("Failed to serialize queue: {}", e))?;
+    // Write then rename so a crash mid-write keeps the previous file
+    let path = get_queue_path(app);
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, content).map_err(|e| format!("Failed to save queue: {}", e))?;
+    fs::rename(&temp, &path).map_err(|e| format!("Failed to save queue: {}", e))
+}
+
+fn load_queue_file(app: &tauri::AppHandle) -> Option<QueueSnapshot> {
+    let content = fs::read_to_string(get_queue_path(app)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Save the queue whenever it changed, until shutdown takes over
+async fn autosave_queue(app: tauri::AppHandle, state: Arc<AppState>) {
+    let mut last_saved = String::new();
+    loop {
+        tokio::time::sleep(QUEUE_AUTOSAVE_INTERVAL).await;
+        if state.shutdown_started.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let snapshot = state.queue.snapshot().await;
+        let Ok(content) = serde_json::to_string(&snapshot) else {
+            continue;
+        };
+        if content != last_saved && write_queue_file(&app, &snapshot).is_ok() {
+            last_saved = content;
+        }
+    }
+}
+
+/// Match downloads the last run left mid-way with what they wrote to disk
+/// and tell the frontend, which offers to resume or clean up
+async fn recover_downloads(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    interrupted: Vec<String>,
+    mut orphaned_files: Vec<recovery::OrphanedFile>,
+) {
+    let settings = state.settings.read().await.clone();
+    if settings.auto_clean_temp_files && !orphaned_files.is_empty() {
+        // Scratch names are never reused, so nothing can resume from these
+        let files = std::mem::take(&mut orphaned_files);
+        tauri::async_runtime::spawn_blocking(move || recovery::remove_temp_files(&files)).await.ok();
+    }
+    let mut downloads = Vec::new();
+    for id in interrupted {
+        let Some(item) = state.queue.get_item(&id).await else {
+            continue;
+        };
+        let Ok(target) = prepare_output(
+            &settings,
+            &item.output_dir,
+            &item.output_filename,
+            &item.title,
+            item.options.episode.clone(),
+        ) else {
+            continue;
+        };
+        downloads.push(recovery::inspect_download(&item.id, &item.title, &target.dir, &target.filename));
+    }
+
+    let report = RecoveryReport {
+        downloads,
+        orphaned_bytes: orphaned_files.iter().map(|f| f.size).sum(),
+        orphaned_files,
+    };
+    if !report.is_empty() {
+        emit_event(app, "recovered-downloads", report.clone());
+    }
+    *state.recovery.lock().await = report;
+}
+
+#[tauri::command]
+async fn get_recovered_downloads(state: State<'_, Arc<AppState>>) -> Result<RecoveryReport, String> {
+    Ok(state.recovery.lock().await.clone())
+}
+
+/// Resume recovered downloads. Ones with a finished TS go straight to
+/// conversion; the rest are queued to download again.
+#[tauri::command]
+async fn recovery_resume(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    ids: Vec<String>,
+) -> Result<usize, String> {
+    let state = state.inner().clone();
+    let recovered: Vec<_> = {
+        let mut report = state.recovery.lock().await;
+        let (picked, rest) = report.downloads.drain(..).partition(|d| ids.contains(&d.id));
+        report.downloads = rest;
+        picked
+    };
+
+    let settings = state.settings.read().await.clone();
+    let mut resumed = 0;
+    for download in recovered {
+        let Some(item) = state.queue.get_item(&download.id).await else {
+            continue;
+        };
+        if item.status != QueueItemStatus::Paused {
+            continue;
+        }
+
+        let Some(checkpoint) = download.checkpoint else {
+            if state.queue.resume_download(&item.id).await {
+                resumed += 1;
+            }
+            continue;
+        };
+
+        let target = prepare_output(
+            &settings,
+            &item.output_dir,
+            &item.output_filename,
+            &item.title,
+            item.options.episode.clone(),
+        )?;
+        let extra_args = postprocess::parse_extra_args(item.options.extra_ffmpeg_args.as_deref().unwrap_or_default())?;
+        let spec = PostProcessSpec {
+            extra_args,
+            ..settings.postprocess_spec(Path::new(&checkpoint))
+        };
+
+        let app = app.clone();
+        let state = state.clone();
+        let settings = settings.clone();
+        tokio::spawn(async move {
+            let result = postprocess_queue_item(&app, &state, &item, spec).await;
+            finish_queue_item(&app, &state, &settings, &item, &target, result).await;
+            emit_group_progress(&app, &state, item.options.group_id.as_deref()).await;
+            emit_queue_finished(&app, &state).await;
+        });
+        resumed += 1;
+    }
+
+    Ok(resumed)
+}
+
+/// Delete orphaned temp files and partial files from the crash. Only
+/// paths from the startup report are touched.
+#[tauri::command]
+async fn recovery_cleanup(state: State<'_, Arc<AppState>>) -> Result<usize, String> {
+    let mut report = state.recovery.lock().await;
+    let removed = recovery::cleanup(report.removable());
+    report.orphaned_files.clear();
+    report.orphaned_bytes = 0;
+    for download in report.downloads.iter_mut() {
+        download.partial_files.clear();
+    }
+    report.downloads.retain(|d| d.checkpoint.is_some());
+    Ok(removed)
+}
+
+/// Pause running downloads, save the queue so the next launch picks up
+/// where this one stopped, then exit
+async fn shutdown(app: tauri::AppHandle, state: Arc<AppState>) {
+    let paused = state.queue.pause_unfinished().await;
+    tokio::time::sleep(SHUTDOWN_GRACE).await;
+
+    // Items paused only for the exit start again next time
+    let mut snapshot = state.queue.snapshot().await;
+    for item in snapshot.items.iter_mut().filter(|i| paused.contains(&i.id)) {
+        item.status = QueueItemStatus::Pending;
+    }
+    write_queue_file(&app, &snapshot).ok();
+
+    state.shutdown_done.store(true, Ordering::SeqCst);
+    app.exit(0);
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
@@ -618,31 +3626,188 @@ pub fn run() {
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_dialog::init())
         .manage(Arc::new(AppState::new()))
+        .setup(|app| {
+            let handle = app.handle().clone();
+            let state = app.state::<Arc<AppState>>().inner().clone();
+
+            if let Ok(app_dir) = app.path().app_data_dir() {
+                state.browser_pool.set_profile_dir(app_dir.join("browser-profile"));
+
+                let progress_handle = handle.clone();
+                let throttle = ProgressThrottle::default();
+                chromium::set_install_dir(app_dir.join("chromium"), move |downloaded, total| {
+                    let progress = if total > 0 { downloaded as f32 / total as f32 * 100.0 } else { 0.0 };
+                    if throttle.should_emit(progress) {
+                        emit_event(&progress_handle, "chromium-download-progress", ChromiumDownloadProgress {
+                            downloaded,
+                            total,
+                            progress,
+                            message: format!("กำลังดาวน์โหลด Chromium {:.1}%", progress),
+                        });
+                    }
+                });
+            }
+            state.browser_pool.set_hooks(hooks::load_hooks(&get_scripts_dir(&handle)));
+            reload_rules(&handle);
+            reload_ad_patterns(&handle);
+
+            // Restore before the frontend asks for the queue
+            let interrupted = load_queue_file(&handle)
+                .map(|snapshot| tauri::async_runtime::block_on(state.queue.restore(snapshot)))
+                .unwrap_or_default();
+            // Nothing has started yet, so every scratch file is left over
+            let orphaned_files = recovery::find_orphaned_temp_files();
+
+            // Load saved settings before the frontend asks, so backend
+            // services (remote API, filename rules) start configured
+            let saved_settings = load_settings_file(&handle);
+            if let (Some(window), Some(geometry)) = (
+                handle.get_webview_window("main"),
+                saved_settings.as_ref().and_then(|s| s.ui_state.window),
+            ) {
+                ui_state::restore(&window, &geometry);
+            }
+            tauri::async_runtime::spawn(async move {
+                if let Some(settings) = saved_settings {
+                    apply_global_settings(&settings);
+                    state.queue.set_max_concurrent(settings.max_concurrent_downloads).await;
+                    state.queue.set_max_per_host(settings.max_downloads_per_host).await;
+                    state.postprocess.set_max_concurrent(settings.max_concurrent_postprocess).await;
+                    state.bandwidth.configure(settings.current_speed_limit(), settings.prioritize_top_download);
+                    state.segment_cache.set_max_mb(settings.segment_cache_limit());
+                    if settings.rule_updates_enabled {
+                        // Offline or unchanged is fine; the saved set stays in use
+                        let (handle, settings) = (handle.clone(), settings.clone());
+                        tauri::async_runtime::spawn(async move {
+                            update_rules(&handle, &settings).await.ok();
+                        });
+                    }
+                    *state.settings.write().await = settings;
+                }
+                if let Ok(saved) = load_site_credentials(&handle) {
+                    apply_credentials(&state, &saved);
+                }
+                recover_downloads(&handle, &state, interrupted, orphaned_files).await;
+                tauri::async_runtime::spawn(autosave_queue(handle.clone(), state.clone()));
+                tauri::async_runtime::spawn(network::watch(handle.clone(), state.clone()));
+                tauri::async_runtime::spawn(power::watch(handle.clone(), state.clone()));
+                tauri::async_runtime::spawn(inhibit::watch(state.clone()));
+                tauri::async_runtime::spawn(watch_speed_schedule(state.clone()));
+                tauri::async_runtime::spawn(sweep_temp_files(handle.clone(), state.clone()));
+                remote::restart(handle, state).await;
+            });
+
+            Ok(())
+        })
+        .on_window_event(|window, event| {
+            if let tauri::WindowEvent::CloseRequested { .. } = event {
+                if let Some(window) = window.app_handle().get_webview_window(window.label()) {
+                    remember_window(&window);
+                }
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             get_video_info,
+            get_video_info_batch,
+            get_series_episodes,
             download_video,
             get_download_dir,
             open_folder,
             open_file,
+            open_with,
+            play_in_vlc,
             get_download_history,
+            get_site_stats,
             add_to_history,
             clear_history,
             delete_history_item,
+            history_delete_file,
+            rename_download_file,
+            verify_checksums,
+            history_set_tags,
+            history_set_favorite,
+            history_set_watched,
+            history_filter,
+            history_get_tags,
+            scan_library,
+            parse_episode_title,
             // Queue commands
             queue_add,
+            get_thumbnail,
+            queue_set_segment_workers,
             queue_get_items,
             queue_remove,
             queue_pause,
             queue_resume,
             queue_cancel,
             queue_clear_completed,
+            postprocess_get_jobs,
+            postprocess_clear_finished,
+            get_download_log,
+            segment_cache_info,
+            segment_cache_purge,
+            explain_error,
+            get_recovered_downloads,
+            recovery_resume,
+            recovery_cleanup,
             queue_clear_all,
             queue_move_item,
+            queue_create_group,
+            queue_add_multipart,
+            queue_group_merge,
+            queue_group_export_zip,
+            queue_get_groups,
+            queue_get_summary,
+            undo_last_action,
+            queue_pause_group,
+            queue_resume_group,
+            queue_cancel_group,
+            queue_process,
             queue_start_download,
             // Settings commands
+            export_app_data,
+            import_app_data,
+            credentials_list,
+            credentials_save,
+            credentials_delete,
+            hooks_get_dir,
+            hooks_reload,
+            rules_get_dir,
+            rules_reload,
+            ad_patterns_get_path,
+            ad_patterns_reload,
+            ad_patterns_add,
+            ad_patterns_remove,
+            rule_updates_check,
+            test_extraction_rule,
+            export_playlist,
+            aria2_check,
+            get_video_encoders,
+            get_compression_presets,
+            compress_history_item,
+            burn_subtitles_history_item,
+            normalize_history_item,
+            get_size_estimates,
+            preview_source,
+            cookies_export,
+            cookies_import,
             get_settings,
-            save_settings
+            save_settings,
+            get_ui_state,
+            set_ui_state
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::ExitRequested { api, .. } = event {
+                let state = app.state::<Arc<AppState>>().inner().clone();
+                // Hold the exit until the queue is saved; shutdown() exits again
+                if !state.shutdown_done.load(Ordering::SeqCst) {
+                    api.prevent_exit();
+                    if !state.shutdown_started.swap(true, Ordering::SeqCst) {
+                        tauri::async_runtime::spawn(shutdown(app.clone(), state));
+                    }
+                }
+            }
+        });
 }
@@ -0,0 +1,92 @@
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::downloader::ffmpeg::{format_duration, probe_duration};
+use crate::downloader::VIDEO_EXTENSIONS;
+use crate::history::HistoryItem;
+
+// Season folders are at most a couple of levels deep
+const MAX_SCAN_DEPTH: usize = 4;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LibraryEntry {
+    pub path: String,
+    pub filename: String,
+    pub title: String,
+    pub size: u64,
+    pub duration: String,
+    pub duration_secs: Option<f64>,
+    pub thumbnail: String,
+    pub modified_at: Option<String>,
+    /// Set when the file matches a download history entry
+    pub history_id: Option<String>,
+}
+
+pub fn is_video_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| VIDEO_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Walk `dir` for video files, probe their duration and match them to
+/// history entries by path (or filename, for files that were moved)
+pub async fn scan_library(dir: &Path, history: &[HistoryItem]) -> Vec<LibraryEntry> {
+    let files: Vec<PathBuf> = WalkDir::new(dir)
+        .max_depth(MAX_SCAN_DEPTH)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && is_video_file(e.path()))
+        .map(|e| e.into_path())
+        .collect();
+
+    let mut entries: Vec<LibraryEntry> = futures::stream::iter(files)
+        .map(|path| async move {
+            let duration_secs = probe_duration(&path).await.ok();
+            (path, duration_secs)
+        })
+        .buffer_unordered(4)
+        .map(|(path, duration_secs)| build_entry(&path, duration_secs, history))
+        .collect()
+        .await;
+
+    entries.sort_by(|a, b| b.modified_at.cmp(&a.modified_at));
+    entries
+}
+
+fn build_entry(path: &Path, duration_secs: Option<f64>, history: &[HistoryItem]) -> LibraryEntry {
+    let metadata = std::fs::metadata(path).ok();
+    let filename = path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let path_str = path.to_string_lossy().to_string();
+
+    let matched = history.iter()
+        .find(|h| Path::new(&h.file_path) == path)
+        .or_else(|| history.iter().find(|h| {
+            Path::new(&h.file_path).file_name().map(|n| n.to_string_lossy() == filename).unwrap_or(false)
+        }));
+
+    let title = match matched {
+        Some(h) if !h.title.is_empty() => h.title.clone(),
+        _ => path.file_stem()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    };
+
+    LibraryEntry {
+        path: path_str,
+        filename,
+        title,
+        size: metadata.as_ref().map(|m| m.len()).unwrap_or(0),
+        duration: duration_secs.map(format_duration).unwrap_or_default(),
+        duration_secs,
+        thumbnail: matched.map(|h| h.thumbnail.clone()).unwrap_or_default(),
+        modified_at: metadata
+            .and_then(|m| m.modified().ok())
+            .map(|t| chrono::DateTime::<chrono::Utc>::from(t).to_rfc3339()),
+        history_id: matched.map(|h| h.id.clone()),
+    }
+}
@@ -0,0 +1,80 @@
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{auto_pause, auto_resume, AppState, PAUSE_METERED};
+
+// How often the connection type is checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Whether the active connection is metered (mobile hotspot, capped plan).
+/// None when the platform gives no hint.
+pub fn is_metered() -> Option<bool> {
+    platform_is_metered()
+}
+
+#[cfg(target_os = "windows")]
+fn platform_is_metered() -> Option<bool> {
+    // NetworkCostType is Unrestricted on normal Wi-Fi/Ethernet and Fixed or
+    // Variable on metered connections
+    let script = "[void][Windows.Networking.Connectivity.NetworkInformation,Windows.Networking.Connectivity,ContentType=WindowsRuntime]; \
+        [Windows.Networking.Connectivity.NetworkInformation]::GetInternetConnectionProfile().GetConnectionCost().NetworkCostType";
+    let output = crate::background_command("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", script])
+        .output()
+        .ok()?;
+    match String::from_utf8_lossy(&output.stdout).trim() {
+        "Unrestricted" => Some(false),
+        "Fixed" | "Variable" => Some(true),
+        _ => None,
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn platform_is_metered() -> Option<bool> {
+    // macOS has no metered flag outside Low Data Mode, which isn't exposed
+    // to command line tools
+    None
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn platform_is_metered() -> Option<bool> {
+    // NetworkManager's NMMetered: 1 yes, 2 no, 3 guess yes, 4 guess no
+    let output = Command::new("busctl")
+        .args([
+            "get-property",
+            "org.freedesktop.NetworkManager",
+            "/org/freedesktop/NetworkManager",
+            "org.freedesktop.NetworkManager",
+            "Metered",
+        ])
+        .output()
+        .ok()?;
+    match String::from_utf8_lossy(&output.stdout).split_whitespace().last()? {
+        "1" | "3" => Some(true),
+        "2" | "4" => Some(false),
+        _ => None,
+    }
+}
+
+/// Pause the queue while on a metered connection (when enabled) and resume
+/// what was paused once back on an unmetered one
+pub async fn watch(app: tauri::AppHandle, state: Arc<AppState>) {
+    loop {
+        tokio::time::sleep(CHECK_INTERVAL).await;
+
+        let enabled = state.settings.read().await.pause_on_metered;
+        let metered = if enabled {
+            tokio::task::spawn_blocking(is_metered).await.ok().flatten()
+        } else {
+            Some(false)
+        };
+
+        match metered {
+            Some(true) => auto_pause(&app, &state, PAUSE_METERED).await,
+            Some(false) => auto_resume(&app, &state, PAUSE_METERED).await,
+            // No hint: leave things as they are
+            None => {}
+        }
+    }
+}
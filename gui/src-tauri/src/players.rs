@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::downloader::USER_AGENT;
+
+// Player names understood without configuration (AppSettings::media_players
+// can add more or point these at a custom install)
+pub const PLAYER_VLC: &str = "vlc";
+pub const PLAYER_MPV: &str = "mpv";
+pub const PLAYER_MPC_HC: &str = "mpc-hc";
+pub const PLAYER_POTPLAYER: &str = "potplayer";
+
+/// Where the known players usually live when they aren't on PATH
+fn default_locations(player: &str) -> &'static [&'static str] {
+    match player {
+        #[cfg(target_os = "windows")]
+        PLAYER_VLC => &[
+            r"C:\Program Files\VideoLAN\VLC\vlc.exe",
+            r"C:\Program Files (x86)\VideoLAN\VLC\vlc.exe",
+        ],
+        #[cfg(target_os = "windows")]
+        PLAYER_MPC_HC => &[
+            r"C:\Program Files\MPC-HC\mpc-hc64.exe",
+            r"C:\Program Files (x86)\MPC-HC\mpc-hc.exe",
+            r"C:\Program Files (x86)\K-Lite Codec Pack\MPC-HC64\mpc-hc64.exe",
+        ],
+        #[cfg(target_os = "windows")]
+        PLAYER_POTPLAYER => &[
+            r"C:\Program Files\DAUM\PotPlayer\PotPlayerMini64.exe",
+            r"C:\Program Files (x86)\DAUM\PotPlayer\PotPlayerMini.exe",
+        ],
+        #[cfg(target_os = "macos")]
+        PLAYER_VLC => &["/Applications/VLC.app/Contents/MacOS/VLC"],
+        #[cfg(target_os = "macos")]
+        PLAYER_MPV => &[
+            "/opt/homebrew/bin/mpv",
+            "/usr/local/bin/mpv",
+            "/Applications/mpv.app/Contents/MacOS/mpv",
+        ],
+        #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+        PLAYER_VLC => &["/usr/bin/vlc", "/snap/bin/vlc"],
+        #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+        PLAYER_MPV => &["/usr/bin/mpv"],
+        _ => &[],
+    }
+}
+
+fn executable_name(player: &str) -> &str {
+    match player {
+        PLAYER_MPC_HC if cfg!(target_os = "windows") => "mpc-hc64.exe",
+        PLAYER_POTPLAYER if cfg!(target_os = "windows") => "PotPlayerMini64.exe",
+        _ => player,
+    }
+}
+
+fn find_on_path(name: &str) -> Option<PathBuf> {
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths).find_map(|dir| {
+        let candidate = dir.join(name);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        let exe = dir.join(format!("{}.exe", name));
+        (cfg!(target_os = "windows") && exe.is_file()).then_some(exe)
+    })
+}
+
+/// Resolve a player name to an executable: a path configured in settings
+/// first, then PATH, then the player's usual install locations
+pub fn resolve_player(player: &str, configured: &BTreeMap<String, String>) -> Result<PathBuf, String> {
+    let key = player.trim().to_lowercase();
+
+    if let Some(path) = configured.iter().find(|(name, _)| name.to_lowercase() == key).map(|(_, p)| p) {
+        let path = Path::new(path);
+        return if path.is_file() {
+            Ok(path.to_path_buf())
+        } else {
+            Err(format!("Player executable not found: {}", path.display()))
+        };
+    }
+
+    if let Some(path) = find_on_path(executable_name(&key)) {
+        return Ok(path);
+    }
+
+    default_locations(&key)
+        .iter()
+        .map(PathBuf::from)
+        .find(|p| p.is_file())
+        .ok_or_else(|| format!("Player '{}' not found; set its path in settings", player))
+}
+
+/// Command line to stream a URL with the referer / user agent the CDN
+/// expects. Only VLC and mpv take header options; other players just get
+/// the URL.
+pub fn stream_args(player: &str, url: &str, referer: Option<&str>, headers: &[(String, String)]) -> Vec<String> {
+    let mut args = Vec::new();
+
+    match player.trim().to_lowercase().as_str() {
+        PLAYER_VLC => {
+            if let Some(referer) = referer {
+                args.push(format!("--http-referrer={}", referer));
+            }
+            args.push(format!("--http-user-agent={}", USER_AGENT));
+        }
+        PLAYER_MPV => {
+            if let Some(referer) = referer {
+                args.push(format!("--referrer={}", referer));
+            }
+            args.push(format!("--user-agent={}", USER_AGENT));
+            if !headers.is_empty() {
+                let fields: Vec<String> = headers
+                    .iter()
+                    // mpv splits the list on commas
+                    .map(|(name, value)| format!("{}: {}", name, value).replace(',', "\\,"))
+                    .collect();
+                args.push(format!("--http-header-fields={}", fields.join(",")));
+            }
+        }
+        _ => {}
+    }
+
+    args.push(url.to_string());
+    args
+}
@@ -0,0 +1,655 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{oneshot, RwLock, Semaphore, SemaphorePermit};
+
+use crate::downloader::encoders::{self, CODEC_H264, CODEC_HEVC, ENCODER_SOFTWARE};
+use crate::downloader::ffmpeg;
+use crate::downloader::{long_path, output_file_path, DownloaderError};
+
+// Conversions running at once; separate from the download slots
+pub const DEFAULT_MAX_CONCURRENT_POSTPROCESS: usize = 1;
+
+// CRF-like quality used when re-encoding without a preset
+const REENCODE_QUALITY: u8 = 23;
+
+// Loudness normalization target (EBU R128 streaming levels)
+const LOUDNORM_TARGET_I: f64 = -16.0;
+const LOUDNORM_TARGET_TP: f64 = -1.5;
+const LOUDNORM_TARGET_LRA: f64 = 11.0;
+// Audio this close to the target with headroom on the peaks is left alone
+const LOUDNORM_TOLERANCE: f64 = 2.0;
+
+/// One-click compression for finished downloads
+#[derive(Clone, Debug, Serialize)]
+pub struct CompressionPreset {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub codec: &'static str,
+    /// CRF-like quality, lower is better
+    pub quality: u8,
+}
+
+pub const COMPRESSION_PRESETS: &[CompressionPreset] = &[
+    CompressionPreset { id: "save-50", name: "Save ~50% space (H.265 CRF 26)", codec: CODEC_HEVC, quality: 26 },
+    CompressionPreset { id: "save-70", name: "Save ~70% space (H.265 CRF 30)", codec: CODEC_HEVC, quality: 30 },
+    CompressionPreset { id: "compatible", name: "Smaller, plays anywhere (H.264 CRF 26)", codec: CODEC_H264, quality: 26 },
+];
+
+pub fn find_preset(id: &str) -> Option<&'static CompressionPreset> {
+    COMPRESSION_PRESETS.iter().find(|p| p.id == id)
+}
+
+// Job states (PostProcessJob::status)
+pub const JOB_QUEUED: &str = "queued";
+pub const JOB_RUNNING: &str = "running";
+pub const JOB_COMPLETED: &str = "completed";
+pub const JOB_FAILED: &str = "failed";
+
+/// What to do with a finished download
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostProcessSpec {
+    pub input: String,
+    /// Put the MP4 index first
+    pub faststart: bool,
+    /// Transcode the video instead of copying the streams
+    pub reencode: bool,
+    /// Encoder family to prefer (encoders::ENCODER_*); empty means auto
+    pub encoder: String,
+    /// Compression preset id; implies re-encoding
+    pub preset: Option<String>,
+    /// Subtitle file to hardcode into the picture; implies re-encoding
+    pub burn_subtitles: Option<String>,
+    /// Font for burned subtitles; must have Thai glyphs. Empty uses the
+    /// libass default.
+    pub subtitle_font: String,
+    /// User flags from parse_extra_args, placed before the output file
+    pub extra_args: Vec<String>,
+    /// Bring too quiet / too loud audio to a standard level (two-pass loudnorm)
+    pub loudnorm: bool,
+}
+
+impl PostProcessSpec {
+    /// Codec and quality to encode with, or None to copy the streams
+    fn video_target(&self) -> Option<(&'static str, u8)> {
+        match self.preset.as_deref().and_then(find_preset) {
+            Some(preset) => Some((preset.codec, preset.quality)),
+            None => (self.reencode || self.burn_subtitles.is_some()).then_some((CODEC_H264, REENCODE_QUALITY)),
+        }
+    }
+
+    /// Whether the video gets re-encoded rather than copied
+    pub fn transcodes(&self) -> bool {
+        self.video_target().is_some()
+    }
+
+    /// Whether a file that's already MP4 still has to go through ffmpeg
+    pub fn is_needed(&self) -> bool {
+        self.transcodes() || self.loudnorm || !self.extra_args.is_empty()
+    }
+}
+
+// Options accepted in extra ffmpeg arguments and whether they take a value.
+// Anything that names an input/output file or reads a script is left out: a
+// stray positional argument would become another ffmpeg output.
+const EXTRA_ARG_OPTIONS: &[(&str, bool)] = &[
+    ("-c", true), ("-codec", true), ("-b", true), ("-q", true), ("-crf", true),
+    ("-preset", true), ("-tune", true), ("-profile", true), ("-level", true),
+    ("-pix_fmt", true), ("-r", true), ("-s", true), ("-aspect", true),
+    ("-ar", true), ("-ac", true), ("-vf", true), ("-af", true), ("-filter", true),
+    ("-map", true), ("-map_metadata", true), ("-map_chapters", true),
+    ("-metadata", true), ("-disposition", true), ("-movflags", true),
+    ("-g", true), ("-maxrate", true), ("-minrate", true), ("-bufsize", true),
+    ("-t", true), ("-ss", true), ("-to", true), ("-threads", true),
+    ("-x264-params", true), ("-x265-params", true), ("-tag", true),
+    ("-bsf", true), ("-strict", true), ("-max_muxing_queue_size", true),
+    ("-an", false), ("-vn", false), ("-sn", false), ("-dn", false),
+    ("-shortest", false),
+];
+
+// Filters allowed in -vf/-af/-filter and their options. None of them reads
+// or writes files, loads libraries or takes commands, so positional values
+// are as safe as named ones.
+const ALLOWED_FILTERS: &[(&str, &[&str])] = &[
+    ("scale", &["w", "h", "width", "height", "flags", "force_original_aspect_ratio", "force_divisible_by"]),
+    ("crop", &["w", "h", "x", "y", "out_w", "out_h", "keep_aspect", "exact"]),
+    ("pad", &["w", "h", "x", "y", "width", "height", "color"]),
+    ("fps", &["fps", "round"]),
+    ("setsar", &["sar", "r", "max"]),
+    ("setdar", &["dar", "r", "max"]),
+    ("format", &["pix_fmts"]),
+    ("transpose", &["dir", "passthrough"]),
+    ("hflip", &[]),
+    ("vflip", &[]),
+    ("yadif", &["mode", "parity", "deint"]),
+    ("bwdif", &["mode", "parity", "deint"]),
+    ("hqdn3d", &["luma_spatial", "chroma_spatial", "luma_tmp", "chroma_tmp"]),
+    ("unsharp", &["lx", "ly", "la", "cx", "cy", "ca"]),
+    ("eq", &["contrast", "brightness", "saturation", "gamma"]),
+    ("setpts", &["expr"]),
+    ("null", &[]),
+    ("volume", &["volume", "precision"]),
+    ("atempo", &["tempo"]),
+    ("asetpts", &["expr"]),
+    ("aresample", &["osr", "async"]),
+    ("loudnorm", &["i", "lra", "tp"]),
+    ("dynaudnorm", &["f", "g", "p", "m"]),
+    ("anull", &[]),
+];
+
+/// Split and check user-supplied ffmpeg flags. Quotes group words; there is
+/// no shell, so nothing else is special.
+pub fn parse_extra_args(input: &str) -> Result<Vec<String>, String> {
+    let tokens = split_args(input)?;
+    let mut args = Vec::new();
+    let mut iter = tokens.into_iter();
+
+    while let Some(option) = iter.next() {
+        // Stream specifiers ("-c:v", "-b:a:0") share the base option's rules
+        let base = option.split(':').next().unwrap_or_default();
+        let Some((_, takes_value)) = EXTRA_ARG_OPTIONS.iter().find(|(name, _)| *name == base) else {
+            return Err(format!("Unsupported ffmpeg option: {}", option));
+        };
+        args.push(option.clone());
+
+        if *takes_value {
+            let value = iter.next().ok_or_else(|| format!("Missing value for {}", option))?;
+            if matches!(base, "-vf" | "-af" | "-filter") {
+                check_filters(&value)?;
+            }
+            args.push(value);
+        }
+    }
+
+    Ok(args)
+}
+
+fn check_filters(graph: &str) -> Result<(), String> {
+    // Quotes and backslashes are ffmpeg's escaping, which could hide a
+    // separator or a second filter inside a value
+    if graph.contains(['\\', '\'', '"']) {
+        return Err("Quotes and escapes aren't allowed in filters".to_string());
+    }
+
+    for filter in graph.split([',', ';']) {
+        let filter = strip_labels(filter.trim())?;
+        let (name, args) = filter.split_once('=').unwrap_or((filter, ""));
+        let name = name.trim();
+        let Some((_, options)) = ALLOWED_FILTERS.iter().find(|(allowed, _)| *allowed == name) else {
+            return Err(format!("Filter not allowed in extra arguments: {}", name));
+        };
+
+        for arg in args.split(':').filter(|a| !a.is_empty()) {
+            let value = match arg.split_once('=') {
+                Some((option, value)) => {
+                    if !options.contains(&option.to_lowercase().as_str()) {
+                        return Err(format!("Option not allowed for {}: {}", name, option));
+                    }
+                    value
+                }
+                None => arg,
+            };
+            let valid = value.chars().all(|c| c.is_ascii_alphanumeric() || "._+-*/()".contains(c));
+            if value.is_empty() || !valid {
+                return Err(format!("Invalid value for {}: {}", name, value));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The filter without its "[in]"/"[out]" link labels
+fn strip_labels(mut filter: &str) -> Result<&str, String> {
+    let is_label = |label: &str| label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':');
+    while let Some(rest) = filter.strip_prefix('[') {
+        let (label, rest) = rest.split_once(']').ok_or("Unclosed filter label")?;
+        if !is_label(label) {
+            return Err(format!("Invalid filter label: {}", label));
+        }
+        filter = rest.trim_start();
+    }
+    while let Some(rest) = filter.strip_suffix(']') {
+        let (rest, label) = rest.rsplit_once('[').ok_or("Unopened filter label")?;
+        if !is_label(label) {
+            return Err(format!("Invalid filter label: {}", label));
+        }
+        filter = rest.trim_end();
+    }
+    Ok(filter)
+}
+
+fn split_args(input: &str) -> Result<Vec<String>, String> {
+    if input.contains('\0') {
+        return Err("Invalid character in ffmpeg arguments".to_string());
+    }
+
+    let mut tokens = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote: Option<char> = None;
+    for c in input.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                current.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => tokens.extend(current.take()),
+            (None, c) => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err("Unbalanced quote in ffmpeg arguments".to_string());
+    }
+    tokens.extend(current);
+    Ok(tokens)
+}
+
+// Sidecar subtitle formats ffmpeg's subtitles filter reads
+pub const SUBTITLE_EXTENSIONS: &[&str] = &["srt", "vtt", "ass", "ssa"];
+
+/// Subtitle file saved next to `video`: "<stem>.srt" or "<stem>.<lang>.srt",
+/// preferring Thai ("th" / "tha")
+pub fn find_subtitle(video: &Path) -> Option<PathBuf> {
+    let stem = video.file_stem()?.to_string_lossy().to_string();
+    let dir = video.parent()?;
+    let mut found: Vec<PathBuf> = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            let ext = p.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+            let sub_stem = p.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+            SUBTITLE_EXTENSIONS.contains(&ext.as_str())
+                && (sub_stem == stem || sub_stem.starts_with(&format!("{}.", stem)))
+        })
+        .collect();
+    found.sort();
+
+    let is_thai = |p: &PathBuf| {
+        let name = p.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+        name.contains(".th.") || name.contains(".tha.")
+    };
+    found.iter().find(|p| is_thai(p)).or(found.first()).cloned()
+}
+
+/// Escape a path for the subtitles filter: once for the option value and
+/// once for the filtergraph
+fn escape_filter_path(path: &Path) -> String {
+    // Forward slashes work on Windows too and need no escaping
+    let path = path.to_string_lossy().replace('\\', "/");
+    let escape = |text: &str, special: &[char]| {
+        text.chars().fold(String::new(), |mut out, c| {
+            if special.contains(&c) {
+                out.push('\\');
+            }
+            out.push(c);
+            out
+        })
+    };
+    let option = escape(&path, &['\\', '\'', ':']);
+    escape(&option, &['\\', '\'', '[', ']', ',', ';'])
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PostProcessJob {
+    pub id: String,
+    /// Queue item waiting on this job, if any
+    pub queue_item_id: Option<String>,
+    pub spec: PostProcessSpec,
+    pub status: String,
+    pub progress: f32,
+    pub output: Option<String>,
+    pub error: Option<String>,
+    /// Input and output sizes in bytes, for before/after reporting
+    pub size_before: Option<u64>,
+    pub size_after: Option<u64>,
+    pub added_at: String,
+}
+
+/// Conversions and transcodes run here, with their own concurrency, so a
+/// download slot is free as soon as the bytes are on disk
+pub struct PostProcessQueue {
+    jobs: Arc<RwLock<Vec<PostProcessJob>>>,
+    slots: Semaphore,
+    max_concurrent: RwLock<usize>,
+    // Permits to retire as running jobs finish, after the limit was lowered
+    // below the number in use
+    surplus: RwLock<usize>,
+    // Cancel signal per queue item whose job is waiting or running
+    cancels: RwLock<HashMap<String, oneshot::Sender<()>>>,
+}
+
+impl PostProcessQueue {
+    pub fn new() -> Self {
+        Self {
+            jobs: Arc::new(RwLock::new(Vec::new())),
+            slots: Semaphore::new(DEFAULT_MAX_CONCURRENT_POSTPROCESS),
+            max_concurrent: RwLock::new(DEFAULT_MAX_CONCURRENT_POSTPROCESS),
+            surplus: RwLock::new(0),
+            cancels: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub async fn set_max_concurrent(&self, max: usize) {
+        let max = max.max(1);
+        let mut current = self.max_concurrent.write().await;
+        let mut surplus = self.surplus.write().await;
+        if max > *current {
+            let owed = (max - *current).min(*surplus);
+            *surplus -= owed;
+            self.slots.add_permits(max - *current - owed);
+        } else {
+            let fewer = *current - max;
+            *surplus += fewer - self.slots.forget_permits(fewer);
+        }
+        *current = max;
+    }
+
+    /// Give a slot back, or retire it if the limit has gone down since
+    async fn release_slot(&self, permit: SemaphorePermit<'_>) {
+        let mut surplus = self.surplus.write().await;
+        if *surplus > 0 {
+            *surplus -= 1;
+            permit.forget();
+        }
+    }
+
+    /// Stop the job of a queue item, whether it waits for a slot or runs.
+    /// Returns whether there was one.
+    pub async fn cancel(&self, queue_item_id: &str) -> bool {
+        match self.cancels.write().await.remove(queue_item_id) {
+            Some(cancel_tx) => cancel_tx.send(()).is_ok(),
+            None => false,
+        }
+    }
+
+    pub async fn get_jobs(&self) -> Vec<PostProcessJob> {
+        self.jobs.read().await.clone()
+    }
+
+    /// Drop finished jobs from the list
+    pub async fn clear_finished(&self) {
+        self.jobs
+            .write()
+            .await
+            .retain(|j| j.status == JOB_QUEUED || j.status == JOB_RUNNING);
+    }
+
+    /// Queue a job and run it when a slot frees up. `on_progress` gets the
+    /// conversion percentage; the result is the final file.
+    pub async fn run(
+        &self,
+        queue_item_id: Option<String>,
+        spec: PostProcessSpec,
+        on_progress: impl Fn(f32, String) + Send + Sync,
+    ) -> Result<PathBuf, DownloaderError> {
+        self.run_job(queue_item_id, spec, on_progress)
+            .await
+            .map(|job| PathBuf::from(job.output.unwrap_or_default()))
+    }
+
+    /// Like `run`, but returns the finished job with its before/after sizes
+    pub async fn run_job(
+        &self,
+        queue_item_id: Option<String>,
+        spec: PostProcessSpec,
+        on_progress: impl Fn(f32, String) + Send + Sync,
+    ) -> Result<PostProcessJob, DownloaderError> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let size_before = tokio::fs::metadata(long_path(Path::new(&spec.input))).await.ok().map(|m| m.len());
+        self.jobs.write().await.push(PostProcessJob {
+            id: id.clone(),
+            queue_item_id: queue_item_id.clone(),
+            spec: spec.clone(),
+            status: JOB_QUEUED.to_string(),
+            progress: 0.0,
+            output: None,
+            error: None,
+            size_before,
+            size_after: None,
+            added_at: chrono::Utc::now().to_rfc3339(),
+        });
+
+        let cancel_rx = match &queue_item_id {
+            Some(item_id) => {
+                let (cancel_tx, cancel_rx) = oneshot::channel();
+                self.cancels.write().await.insert(item_id.clone(), cancel_tx);
+                Some(cancel_rx)
+            }
+            None => None,
+        };
+
+        let jobs = self.jobs.clone();
+        let job_id = id.clone();
+        let track = move |progress: f32, message: String| {
+            if let Ok(mut jobs) = jobs.try_write() {
+                if let Some(job) = jobs.iter_mut().find(|j| j.id == job_id) {
+                    job.progress = progress;
+                }
+            }
+            on_progress(progress, message);
+        };
+        // The permit is dropped with this future, so the slot comes back
+        // on cancellation and on panic as well
+        let work = async {
+            let permit = self
+                .slots
+                .acquire()
+                .await
+                .map_err(|_| DownloaderError::DownloadFailed("Post-processing queue closed".to_string()))?;
+            self.update(&id, |job| job.status = JOB_RUNNING.to_string()).await;
+            let result = process(&spec, &track).await;
+            self.release_slot(permit).await;
+            result
+        };
+        let result = match cancel_rx {
+            Some(cancel_rx) => tokio::select! {
+                result = work => result,
+                // A dropped sender only means the entry was replaced
+                Ok(()) = cancel_rx => {
+                    // ffmpeg is killed with the dropped future; its half file stays
+                    tokio::fs::remove_file(long_path(&temp_output(Path::new(&spec.input)))).await.ok();
+                    Err(DownloaderError::Cancelled)
+                }
+            },
+            None => work.await,
+        };
+        if let Some(item_id) = &queue_item_id {
+            self.cancels.write().await.remove(item_id);
+        }
+
+        let mut finished = None;
+        self.update(&id, |job| {
+            match &result {
+                Ok(path) => {
+                    job.status = JOB_COMPLETED.to_string();
+                    job.progress = 100.0;
+                    job.output = Some(path.to_string_lossy().to_string());
+                    job.size_after = std::fs::metadata(long_path(path)).ok().map(|m| m.len());
+                }
+                Err(e) => {
+                    job.status = JOB_FAILED.to_string();
+                    job.error = Some(e.to_string());
+                }
+            }
+            finished = Some(job.clone());
+        })
+        .await;
+
+        let path = result?;
+        // clear_finished keeps running jobs, but don't bet the task on it
+        finished.ok_or_else(|| DownloaderError::DownloadFailed(format!("Post-processing job for {} went missing", path.display())))
+    }
+
+    async fn update(&self, id: &str, apply: impl FnOnce(&mut PostProcessJob)) {
+        if let Some(job) = self.jobs.write().await.iter_mut().find(|j| j.id == id) {
+            apply(job);
+        }
+    }
+}
+
+impl Default for PostProcessQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Convert the input to MP4 next to it and remove the input on success
+async fn process(spec: &PostProcessSpec, on_progress: &impl Fn(f32, String)) -> Result<PathBuf, DownloaderError> {
+    let input = Path::new(&spec.input);
+    let output = output_file_path(input, "mp4");
+    let temp = temp_output(input);
+
+    let total_seconds = ffmpeg::probe_duration(input).await.unwrap_or(0.0);
+
+    let audio_filter = if spec.loudnorm {
+        on_progress(0.0, "Measuring loudness".to_string());
+        loudnorm_filter(&ffmpeg::measure_loudness(input).await?)
+    } else {
+        None
+    };
+    // Nothing left to do for a file that's already MP4
+    if !spec.transcodes() && audio_filter.is_none() && spec.extra_args.is_empty() && output == input {
+        return Ok(output);
+    }
+
+    let target = spec.video_target();
+    let encoders = if target.is_some() {
+        encoders::candidates(&spec.encoder).await.into_iter().map(Some).collect()
+    } else {
+        vec![None]
+    };
+
+    // Hardware encoders can be listed yet fail without the GPU or driver;
+    // try the next one, ending with software
+    let mut result = Ok(());
+    for encoder in &encoders {
+        let video = encoder.as_deref().zip(target);
+        let args = build_args(spec, input, &temp, video, audio_filter.as_deref());
+        result = ffmpeg::run_with_progress(&args, total_seconds, on_progress).await;
+        if result.is_ok() || encoder.as_deref().is_none_or(|e| e == ENCODER_SOFTWARE) {
+            break;
+        }
+    }
+    if let Err(e) = result {
+        tokio::fs::remove_file(long_path(&temp)).await.ok();
+        return Err(e);
+    }
+
+    tokio::fs::rename(long_path(&temp), long_path(&output)).await?;
+    if output != input {
+        tokio::fs::remove_file(long_path(input)).await.ok();
+    }
+    Ok(output)
+}
+
+/// Where `process` writes, so a failed run never leaves a half file behind
+fn temp_output(input: &Path) -> PathBuf {
+    output_file_path(input, "processing.mp4")
+}
+
+/// Second-pass loudnorm filter from the measured values, or None when the
+/// audio is already close to the target
+fn loudnorm_filter(measured: &ffmpeg::Loudness) -> Option<String> {
+    if (measured.integrated - LOUDNORM_TARGET_I).abs() <= LOUDNORM_TOLERANCE && measured.true_peak <= LOUDNORM_TARGET_TP {
+        return None;
+    }
+    Some(format!(
+        "loudnorm=I={}:TP={}:LRA={}:measured_I={:.2}:measured_TP={:.2}:measured_LRA={:.2}:measured_thresh={:.2}:linear=true",
+        LOUDNORM_TARGET_I, LOUDNORM_TARGET_TP, LOUDNORM_TARGET_LRA,
+        measured.integrated, measured.true_peak, measured.range, measured.threshold,
+    ))
+}
+
+/// `video` is the encoder family with the codec and quality to encode at;
+/// None copies the streams. `audio_filter` re-encodes the audio through it.
+fn build_args(
+    spec: &PostProcessSpec,
+    input: &Path,
+    temp: &Path,
+    video: Option<(&str, (&str, u8))>,
+    audio_filter: Option<&str>,
+) -> Vec<std::ffi::OsString> {
+    let mut args: Vec<std::ffi::OsString> = ["-y", "-i"].iter().map(Into::into).collect();
+    args.push(long_path(input).into());
+    // Keep every audio track (e.g. dub and original), not just the first
+    args.extend(["-map", "0:v?", "-map", "0:a?"].iter().map(Into::into));
+    match video {
+        Some((family, (codec, quality))) => {
+            if let Some(subtitles) = &spec.burn_subtitles {
+                let mut filter = format!("subtitles=filename={}:charenc=UTF-8", escape_filter_path(Path::new(subtitles)));
+                if !spec.subtitle_font.is_empty() {
+                    filter.push_str(&format!(":force_style=FontName={}", spec.subtitle_font.replace([',', ':', '\'', '\\'], "")));
+                }
+                // -sn: the burned track shouldn't also be copied as a stream
+                args.extend(["-vf".into(), filter.into(), "-sn".into()]);
+            }
+            args.extend(encoders::video_args(family, codec, quality).into_iter().map(Into::into));
+            args.extend(["-c:a", "copy"].iter().map(Into::into));
+        }
+        None => args.extend(["-c", "copy"].iter().map(Into::into)),
+    }
+    if let Some(filter) = audio_filter {
+        // Later -c:a overrides the copy above; loudnorm resamples to 192 kHz
+        args.extend(["-af", filter, "-c:a", "aac", "-b:a", "192k", "-ar", "48000"].iter().map(Into::into));
+    } else if is_ts(input) {
+        args.extend(["-bsf:a", "aac_adtstoasc"].iter().map(Into::into));
+    }
+    if spec.faststart {
+        args.extend(["-movflags", "+faststart"].iter().map(Into::into));
+    }
+    args.extend(spec.extra_args.iter().map(Into::into));
+    args.extend(["-f", "mp4"].iter().map(Into::into));
+    args.push(long_path(temp).into());
+    args
+}
+
+fn is_ts(path: &Path) -> bool {
+    path.extension().map(|e| e.eq_ignore_ascii_case("ts")).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn common_filters_pass_the_allowlist() {
+        for graph in [
+            "scale=1280:-2",
+            "scale=w=iw/2:h=ih/2:flags=lanczos,setsar=1",
+            "[0:v]yadif=1[v]",
+            "setpts=0.5*PTS",
+            "volume=1.5,atempo=2.0",
+            "loudnorm=I=-16:TP=-1.5:LRA=11",
+        ] {
+            assert!(check_filters(graph).is_ok(), "{} should be allowed", graph);
+        }
+    }
+
+    #[test]
+    fn filters_that_touch_files_or_libraries_are_refused() {
+        for graph in [
+            "movie@x=/etc/passwd",
+            "movie=in.mp4",
+            "lut3d=/path/to/cube",
+            "ladspa=/lib.so",
+            "frei0r=filter_name=x",
+            "scale=1280:-2,subtitles=f.srt",
+            "scale='1280':-2",
+            "scale=1280\\,movie=x",
+            "scale=eval=/tmp/x",
+            "scale=1280:-2[out];[out]sendcmd=f=cmds",
+        ] {
+            assert!(check_filters(graph).is_err(), "{} should be refused", graph);
+        }
+    }
+
+    #[test]
+    fn extra_args_check_the_filters_they_carry() {
+        assert_eq!(parse_extra_args("-c:a aac -vf \"scale=1280:-2\"").unwrap(), ["-c:a", "aac", "-vf", "scale=1280:-2"]);
+        assert!(parse_extra_args("-af ladspa=/lib.so").is_err());
+        assert!(parse_extra_args("-i other.mp4").is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{auto_pause, auto_resume, AppState, PAUSE_BATTERY};
+
+// What to do on low battery (AppSettings::battery_mode)
+pub const BATTERY_IGNORE: &str = "off";
+pub const BATTERY_PAUSE: &str = "pause";
+pub const BATTERY_THROTTLE: &str = "throttle";
+
+pub const DEFAULT_BATTERY_THRESHOLD: u8 = 20;
+pub const DEFAULT_BATTERY_THROTTLE_KBPS: u64 = 512;
+
+// How often the power source is checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug)]
+pub struct BatteryStatus {
+    /// Running on battery rather than mains power
+    pub discharging: bool,
+    pub percent: u8,
+}
+
+/// Battery state, or None on desktops / when the platform doesn't say
+pub fn battery_status() -> Option<BatteryStatus> {
+    platform_battery_status()
+}
+
+#[cfg(target_os = "windows")]
+fn platform_battery_status() -> Option<BatteryStatus> {
+    // BatteryStatus 1 means discharging
+    let output = crate::background_command("powershell")
+        .args([
+            "-NoProfile",
+            "-NonInteractive",
+            "-Command",
+            "Get-CimInstance Win32_Battery | ForEach-Object { \"$($_.EstimatedChargeRemaining) $($_.BatteryStatus)\" }",
+        ])
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut parts = text.lines().next()?.split_whitespace();
+    let percent = parts.next()?.parse::<u8>().ok()?;
+    let status = parts.next()?.parse::<u32>().ok()?;
+    Some(BatteryStatus {
+        discharging: status == 1,
+        percent,
+    })
+}
+
+#[cfg(target_os = "macos")]
+fn platform_battery_status() -> Option<BatteryStatus> {
+    // "Now drawing from 'Battery Power'" ... "85%; discharging; ..."
+    let output = std::process::Command::new("pmset").args(["-g", "batt"]).output().ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let percent = text
+        .split(|c: char| c.is_whitespace() || c == ';')
+        .find_map(|word| word.strip_suffix('%')?.parse::<u8>().ok())?;
+    Some(BatteryStatus {
+        discharging: text.contains("'Battery Power'"),
+        percent,
+    })
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn platform_battery_status() -> Option<BatteryStatus> {
+    let supplies = std::fs::read_dir("/sys/class/power_supply").ok()?;
+    let read = |path: &std::path::Path, name: &str| {
+        std::fs::read_to_string(path.join(name)).map(|s| s.trim().to_string()).unwrap_or_default()
+    };
+
+    let mut battery = None;
+    let mut on_mains = false;
+    for supply in supplies.filter_map(|e| e.ok()).map(|e| e.path()) {
+        match read(&supply, "type").as_str() {
+            "Battery" if battery.is_none() => {
+                let percent = read(&supply, "capacity").parse::<u8>().ok();
+                let discharging = read(&supply, "status") == "Discharging";
+                battery = percent.map(|percent| (percent, discharging));
+            }
+            "Mains" | "USB" => on_mains |= read(&supply, "online") == "1",
+            _ => {}
+        }
+    }
+
+    battery.map(|(percent, discharging)| BatteryStatus {
+        discharging: discharging && !on_mains,
+        percent,
+    })
+}
+
+/// Pause or throttle downloads and keep Chromium from launching while on
+/// battery below the threshold; undo it once plugged in or charged
+pub async fn watch(app: tauri::AppHandle, state: Arc<AppState>) {
+    loop {
+        let settings = state.settings.read().await.clone();
+        let low = settings.battery_mode != BATTERY_IGNORE
+            && tokio::task::spawn_blocking(battery_status)
+                .await
+                .ok()
+                .flatten()
+                .map(|b| b.discharging && b.percent < settings.battery_threshold)
+                .unwrap_or(false);
+
+        state.low_battery.store(low, Ordering::Relaxed);
+        let throttle = low && settings.battery_mode == BATTERY_THROTTLE;
+        state.bandwidth.set_cap(if throttle { settings.battery_throttle_kbps } else { 0 });
+
+        if low && settings.battery_mode == BATTERY_PAUSE {
+            auto_pause(&app, &state, PAUSE_BATTERY).await;
+        } else {
+            auto_resume(&app, &state, PAUSE_BATTERY).await;
+        }
+
+        tokio::time::sleep(CHECK_INTERVAL).await;
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Max progress events per second per download (4 Hz)
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Rate limiter shared by the clones of a progress callback. Completion
+/// (100%) always goes through so the UI never sticks at 99%.
+#[derive(Clone)]
+pub struct ProgressThrottle {
+    last: Arc<Mutex<Option<Instant>>>,
+    interval: Duration,
+}
+
+impl ProgressThrottle {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            last: Arc::new(Mutex::new(None)),
+            interval,
+        }
+    }
+
+    pub fn should_emit(&self, progress: f32) -> bool {
+        let Ok(mut last) = self.last.lock() else {
+            return true;
+        };
+
+        let now = Instant::now();
+        let due = match *last {
+            Some(at) => now.duration_since(at) >= self.interval,
+            None => true,
+        };
+
+        if due || progress >= 100.0 {
+            *last = Some(now);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Default for ProgressThrottle {
+    fn default() -> Self {
+        Self::new(PROGRESS_INTERVAL)
+    }
+}
+
+/// How far a download is, as its progress messages report it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransferCount {
+    Bytes { done: u64, total: u64 },
+    Segments { done: usize, total: usize },
+}
+
+/// Read "Downloaded 1 / 2 bytes" and "Downloading segment 1/2" messages
+pub fn parse_transfer(message: &str) -> Option<TransferCount> {
+    if let Some(rest) = message.strip_prefix("Downloading segment ") {
+        let (done, total) = rest.trim().split_once('/')?;
+        return Some(TransferCount::Segments {
+            done: done.trim().parse().ok()?,
+            total: total.trim().parse().ok()?,
+        });
+    }
+    let rest = message.strip_prefix("Downloaded ")?.strip_suffix(" bytes")?;
+    let (done, total) = rest.split_once(" / ")?;
+    Some(TransferCount::Bytes {
+        done: done.trim().parse().ok()?,
+        total: total.trim().parse().ok()?,
+    })
+}
+
+// Samples closer together than this are too noisy to measure speed
+const SPEED_WINDOW: Duration = Duration::from_secs(1);
+// How often a running download's speed is measured
+pub const SPEED_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Smoothed bytes per second from a growing byte count
+#[derive(Default)]
+pub struct SpeedMeter {
+    last: Option<(Instant, u64)>,
+    bytes_per_sec: f64,
+}
+
+impl SpeedMeter {
+    pub fn sample(&mut self, received: u64) -> f64 {
+        let now = Instant::now();
+        match self.last {
+            None => self.last = Some((now, received)),
+            Some((at, bytes)) if now.duration_since(at) >= SPEED_WINDOW => {
+                let current = received.saturating_sub(bytes) as f64 / now.duration_since(at).as_secs_f64();
+                self.bytes_per_sec = if self.bytes_per_sec == 0.0 { current } else { self.bytes_per_sec * 0.7 + current * 0.3 };
+                self.last = Some((now, received));
+            }
+            Some(_) => {}
+        }
+        self.bytes_per_sec
+    }
+}
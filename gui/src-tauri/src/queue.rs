@@ -1,16 +1,43 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
+use crate::downloader::explain::ErrorHelp;
+use crate::downloader::SourceOrigin;
+use crate::downloader::ffmpeg::format_duration;
+use crate::downloader::naming::EpisodeInfo;
+use crate::progress::TransferCount;
+
+// Simultaneous downloads allowed against a single host
+pub const DEFAULT_MAX_PER_HOST: usize = 2;
+// Age after which an item's stream links are assumed expired
+pub const DEFAULT_STALE_SOURCE_HOURS: u64 = 6;
+
+/// Wire names are spelled out because events, the remote API and saved
+/// queues all depend on them; renaming a variant must not change them
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum QueueItemStatus {
+    #[serde(rename = "Pending")]
     Pending,
+    #[serde(rename = "Downloading")]
     Downloading,
+    /// Only reported in progress events; the item itself stays Downloading
+    #[serde(rename = "Converting")]
+    Converting,
+    /// Downloaded and waiting on the post-processing queue; holds no
+    /// download slot
+    #[serde(rename = "Processing")]
+    Processing,
+    #[serde(rename = "Paused")]
     Paused,
+    #[serde(rename = "Completed")]
     Completed,
+    #[serde(rename = "Failed")]
     Failed,
+    #[serde(rename = "Cancelled")]
     Cancelled,
 }
 
@@ -28,8 +55,89 @@ pub struct QueueItem {
     pub speed: String,
     pub eta: String,
     pub error: Option<String>,
+    /// Thai explanation of `error` with a suggested fix
+    #[serde(default)]
+    pub error_help: Option<ErrorHelp>,
     pub file_path: Option<String>,
     pub added_at: String,
+    /// Page, embed and extraction time of the source last downloaded from
+    #[serde(default)]
+    pub origin: Option<SourceOrigin>,
+    #[serde(flatten)]
+    pub options: QueueItemOptions,
+}
+
+impl QueueItem {
+    /// Time since its sources were last extracted, or since the item was
+    /// queued when it hasn't started yet; None when neither parses
+    pub fn age(&self) -> Option<Duration> {
+        if let Some(age) = self.origin.as_ref().and_then(SourceOrigin::age) {
+            return Some(age);
+        }
+        let added = chrono::DateTime::parse_from_rfc3339(&self.added_at).ok()?;
+        (chrono::Utc::now() - added.with_timezone(&chrono::Utc)).to_std().ok()
+    }
+}
+
+/// Per-item overrides of the global settings. Unset fields fall back to AppSettings.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueueItemOptions {
+    pub segment_workers: Option<usize>,
+    /// Series/season/episode used by the media server output layout
+    pub episode: Option<EpisodeInfo>,
+    /// Group (e.g. one series batch) this item was enqueued with
+    pub group_id: Option<String>,
+    /// Extra muxing/encoding flags for the conversion, e.g. "-c:a aac -b:a 128k".
+    /// Checked by postprocess::parse_extra_args; never passed through a shell.
+    pub extra_ffmpeg_args: Option<String>,
+    /// Separate audio tracks to keep: "default", "all" or a language code
+    pub audio_tracks: Option<String>,
+    /// Extract in a visible browser window instead of the settings default
+    pub show_browser: Option<bool>,
+    /// Size cap in MB for this item; 0 lifts the global cap
+    pub max_file_size_mb: Option<u64>,
+}
+
+/// A named batch of queue items tracked as one unit
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QueueGroup {
+    pub id: String,
+    pub name: String,
+    pub created_at: String,
+    /// Download items one at a time in episode order
+    #[serde(default)]
+    pub sequential: bool,
+    /// Set once every item has reached a final state
+    pub finished: bool,
+    /// Parts of one video to join into a single file once all are done
+    #[serde(default)]
+    pub merge: Option<MultipartMerge>,
+}
+
+/// Output of a multi-part group and its parts (queue ids) in play order
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MultipartMerge {
+    pub output_filename: String,
+    pub parts: Vec<String>,
+}
+
+/// Aggregate progress of a group, sent with `queue-group-progress`
+/// and `queue-group-finished`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GroupProgress {
+    pub id: String,
+    pub name: String,
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+    pub downloading: usize,
+    pub processing: usize,
+    pub paused: usize,
+    pub progress: f32,
+    pub eta: String,
+    pub finished: bool,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -41,23 +149,108 @@ pub struct QueueProgress {
     pub eta: String,
     pub message: String,
     pub file_path: Option<String>,
+    pub error_help: Option<ErrorHelp>,
+}
+
+/// Live figures for a running download
+#[derive(Clone, Debug, Default)]
+pub struct TransferStats {
+    pub received_bytes: u64,
+    pub bytes_per_sec: f64,
+    pub count: Option<TransferCount>,
+}
+
+/// Whole-queue totals for a status bar or tray tooltip
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct QueueSummary {
+    pub total: usize,
+    pub pending: usize,
+    pub downloading: usize,
+    pub processing: usize,
+    pub paused: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+    /// Bytes left in running downloads; estimated from progress for HLS
+    pub remaining_bytes: u64,
+    /// Segments left in running HLS downloads
+    pub remaining_segments: usize,
+    /// All running downloads together
+    pub bytes_per_sec: u64,
+    /// Time until running and pending items finish, assuming pending ones
+    /// are the size of the running ones; None until there's a speed to go on
+    pub eta_seconds: Option<u64>,
+    pub eta: String,
+}
+
+/// Outcome of everything the queue ran since it last went idle, sent
+/// once as `queue-finished`
+#[derive(Clone, Debug, Serialize)]
+pub struct QueueFinished {
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Size of the files produced
+    pub total_bytes: u64,
+    pub elapsed_secs: u64,
+    pub elapsed: String,
+}
+
+/// Tally of the current busy stretch, from the first download started
+/// until nothing is left to run
+#[derive(Debug)]
+struct QueueRun {
+    started: Instant,
+    succeeded: usize,
+    failed: usize,
+    total_bytes: u64,
+}
+
+impl QueueRun {
+    fn new() -> Self {
+        Self { started: Instant::now(), succeeded: 0, failed: 0, total_bytes: 0 }
+    }
+}
+
+/// Items taken out of the queue, with their positions, and the groups
+/// left empty by it; enough to put them back
+#[derive(Clone, Debug, Default)]
+pub struct RemovedItems {
+    pub items: Vec<(usize, QueueItem)>,
+    pub groups: Vec<QueueGroup>,
+}
+
+/// Queue contents saved on exit and restored on the next launch
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueueSnapshot {
+    pub items: Vec<QueueItem>,
+    pub groups: Vec<QueueGroup>,
 }
 
 pub struct DownloadQueue {
     items: Arc<RwLock<Vec<QueueItem>>>,
+    groups: Arc<RwLock<Vec<QueueGroup>>>,
     active_downloads: Arc<RwLock<HashMap<String, tokio::sync::oneshot::Sender<()>>>>,
     max_concurrent: Arc<RwLock<usize>>,
+    max_per_host: Arc<RwLock<usize>>,
+    transfers: Arc<RwLock<HashMap<String, TransferStats>>>,
+    run: Arc<RwLock<Option<QueueRun>>>,
 }
 
 impl DownloadQueue {
     pub fn new() -> Self {
         Self {
             items: Arc::new(RwLock::new(Vec::new())),
+            groups: Arc::new(RwLock::new(Vec::new())),
             active_downloads: Arc::new(RwLock::new(HashMap::new())),
             max_concurrent: Arc::new(RwLock::new(2)), // Default 2 concurrent downloads
+            max_per_host: Arc::new(RwLock::new(DEFAULT_MAX_PER_HOST)),
+            transfers: Arc::new(RwLock::new(HashMap::new())),
+            run: Arc::new(RwLock::new(None)),
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn add_item(
         &self,
         url: String,
@@ -66,6 +259,7 @@ impl DownloadQueue {
         quality: String,
         output_dir: String,
         output_filename: String,
+        options: QueueItemOptions,
     ) -> String {
         let id = Uuid::new_v4().to_string();
         let item = QueueItem {
@@ -81,8 +275,11 @@ impl DownloadQueue {
             speed: String::new(),
             eta: String::new(),
             error: None,
+            error_help: None,
             file_path: None,
             added_at: chrono::Utc::now().to_rfc3339(),
+            origin: None,
+            options,
         };
 
         let mut items = self.items.write().await;
@@ -90,6 +287,34 @@ impl DownloadQueue {
         id
     }
 
+    pub async fn snapshot(&self) -> QueueSnapshot {
+        QueueSnapshot {
+            items: self.items.read().await.clone(),
+            groups: self.groups.read().await.clone(),
+        }
+    }
+
+    /// Load a saved queue. Nothing is running yet, so items that were
+    /// mid-download come back paused; their ids are returned.
+    pub async fn restore(&self, snapshot: QueueSnapshot) -> Vec<String> {
+        let mut items = snapshot.items;
+        let mut interrupted = Vec::new();
+        for item in items.iter_mut() {
+            if matches!(
+                item.status,
+                QueueItemStatus::Downloading | QueueItemStatus::Converting | QueueItemStatus::Processing
+            ) {
+                item.status = QueueItemStatus::Paused;
+                item.speed.clear();
+                item.eta.clear();
+                interrupted.push(item.id.clone());
+            }
+        }
+        *self.items.write().await = items;
+        *self.groups.write().await = snapshot.groups;
+        interrupted
+    }
+
     pub async fn get_items(&self) -> Vec<QueueItem> {
         let items = self.items.read().await;
         items.clone()
@@ -122,11 +347,36 @@ impl DownloadQueue {
         }
     }
 
-    pub async fn update_item_error(&self, id: &str, error: String) {
+    pub async fn set_item_segment_workers(&self, id: &str, workers: Option<usize>) -> bool {
+        let mut items = self.items.write().await;
+        if let Some(item) = items.iter_mut().find(|i| i.id == id) {
+            item.options.segment_workers = workers;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub async fn set_item_quality(&self, id: &str, quality: &str) {
+        let mut items = self.items.write().await;
+        if let Some(item) = items.iter_mut().find(|i| i.id == id) {
+            item.quality = quality.to_string();
+        }
+    }
+
+    pub async fn set_item_origin(&self, id: &str, origin: SourceOrigin) {
+        let mut items = self.items.write().await;
+        if let Some(item) = items.iter_mut().find(|i| i.id == id) {
+            item.origin = Some(origin);
+        }
+    }
+
+    pub async fn update_item_error(&self, id: &str, error: String, help: ErrorHelp) {
         let mut items = self.items.write().await;
         if let Some(item) = items.iter_mut().find(|i| i.id == id) {
             item.status = QueueItemStatus::Failed;
             item.error = Some(error);
+            item.error_help = Some(help);
         }
     }
 
@@ -139,12 +389,33 @@ impl DownloadQueue {
         }
     }
 
-    pub async fn remove_item(&self, id: &str) {
+    /// Point items at a file that was renamed on disk
+    pub async fn update_file_path(&self, old_path: &str, new_path: &str) -> usize {
+        let mut items = self.items.write().await;
+        let mut count = 0;
+        for item in items.iter_mut().filter(|i| i.file_path.as_deref() == Some(old_path)) {
+            item.file_path = Some(new_path.to_string());
+            count += 1;
+        }
+        count
+    }
+
+    pub async fn remove_item(&self, id: &str) -> RemovedItems {
+        // Keep the state from before the cancel below
+        let removed: Vec<(usize, QueueItem)> = self.items.read().await
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| i.id == id)
+            .map(|(pos, i)| (pos, i.clone()))
+            .collect();
+
         // Cancel if downloading
         self.cancel_download(id).await;
 
         let mut items = self.items.write().await;
         items.retain(|i| i.id != id);
+        drop(items);
+        RemovedItems { items: removed, groups: self.prune_groups().await }
     }
 
     pub async fn clear_completed(&self) {
@@ -152,9 +423,16 @@ impl DownloadQueue {
         items.retain(|i| {
             i.status != QueueItemStatus::Completed && i.status != QueueItemStatus::Failed
         });
+        drop(items);
+        self.prune_groups().await;
     }
 
-    pub async fn clear_all(&self) {
+    pub async fn clear_all(&self) -> RemovedItems {
+        let removed = RemovedItems {
+            items: self.items.read().await.iter().cloned().enumerate().collect(),
+            groups: self.groups.read().await.clone(),
+        };
+
         // Cancel all active downloads
         let active = self.active_downloads.read().await;
         let ids: Vec<String> = active.keys().cloned().collect();
@@ -166,6 +444,62 @@ impl DownloadQueue {
 
         let mut items = self.items.write().await;
         items.clear();
+        self.groups.write().await.clear();
+        removed
+    }
+
+    /// Put removed items back where they were. Anything that was running
+    /// was cancelled by the removal, so it comes back paused.
+    pub async fn reinsert(&self, removed: RemovedItems) {
+        let mut items = self.items.write().await;
+        for (pos, mut item) in removed.items {
+            if items.iter().any(|i| i.id == item.id) {
+                continue;
+            }
+            if matches!(
+                item.status,
+                QueueItemStatus::Downloading | QueueItemStatus::Converting | QueueItemStatus::Processing
+            ) {
+                item.status = QueueItemStatus::Paused;
+            }
+            item.speed.clear();
+            item.eta.clear();
+            let pos = pos.min(items.len());
+            items.insert(pos, item);
+        }
+        drop(items);
+
+        let mut groups = self.groups.write().await;
+        for group in removed.groups {
+            if !groups.iter().any(|g| g.id == group.id) {
+                groups.push(group);
+            }
+        }
+    }
+
+    /// Pause every downloading item and hold pending ones. Returns the ids
+    /// so they can be resumed later.
+    pub async fn pause_unfinished(&self) -> Vec<String> {
+        let candidates: Vec<(String, QueueItemStatus)> = self.items.read().await
+            .iter()
+            .filter(|i| matches!(i.status, QueueItemStatus::Downloading | QueueItemStatus::Pending))
+            .map(|i| (i.id.clone(), i.status.clone()))
+            .collect();
+
+        let mut paused = Vec::new();
+        for (id, status) in candidates {
+            let ok = match status {
+                QueueItemStatus::Downloading => self.pause_download(&id).await,
+                _ => {
+                    self.update_item_status(&id, QueueItemStatus::Paused).await;
+                    true
+                }
+            };
+            if ok {
+                paused.push(id);
+            }
+        }
+        paused
     }
 
     pub async fn pause_download(&self, id: &str) -> bool {
@@ -208,23 +542,91 @@ impl DownloadQueue {
 
     pub async fn set_max_concurrent(&self, max: usize) {
         let mut max_concurrent = self.max_concurrent.write().await;
-        *max_concurrent = max.max(1).min(5); // Between 1 and 5
+        *max_concurrent = max.clamp(1, 5); // Between 1 and 5
     }
 
     pub async fn get_max_concurrent(&self) -> usize {
         *self.max_concurrent.read().await
     }
 
+    pub async fn set_max_per_host(&self, max: usize) {
+        let mut max_per_host = self.max_per_host.write().await;
+        *max_per_host = max.clamp(1, 5);
+    }
+
     pub async fn register_active_download(&self, id: &str) -> tokio::sync::oneshot::Receiver<()> {
         let (tx, rx) = tokio::sync::oneshot::channel();
         let mut active = self.active_downloads.write().await;
         active.insert(id.to_string(), tx);
+        self.run.write().await.get_or_insert_with(QueueRun::new);
         rx
     }
 
     pub async fn unregister_active_download(&self, id: &str) {
         let mut active = self.active_downloads.write().await;
         active.remove(id);
+        self.transfers.write().await.remove(id);
+    }
+
+    pub async fn update_item_transfer(&self, id: &str, stats: TransferStats) {
+        // A late update from a finished download mustn't linger
+        let active = self.active_downloads.read().await;
+        if active.contains_key(id) {
+            self.transfers.write().await.insert(id.to_string(), stats);
+        }
+    }
+
+    pub async fn summary(&self) -> QueueSummary {
+        let items = self.items.read().await;
+        let transfers = self.transfers.read().await;
+        let count = |status: QueueItemStatus| items.iter().filter(|i| i.status == status).count();
+
+        let mut summary = QueueSummary {
+            total: items.len(),
+            pending: count(QueueItemStatus::Pending),
+            downloading: count(QueueItemStatus::Downloading),
+            processing: count(QueueItemStatus::Processing),
+            paused: count(QueueItemStatus::Paused),
+            completed: count(QueueItemStatus::Completed),
+            failed: count(QueueItemStatus::Failed),
+            cancelled: count(QueueItemStatus::Cancelled),
+            ..Default::default()
+        };
+
+        let mut speed = 0.0;
+        let mut sizes: Vec<u64> = Vec::new();
+        for item in items.iter().filter(|i| i.status == QueueItemStatus::Downloading) {
+            let Some(stats) = transfers.get(&item.id) else {
+                continue;
+            };
+            speed += stats.bytes_per_sec;
+            // Plain files report their size; HLS sizes are extrapolated
+            let (done, expected) = match stats.count {
+                Some(TransferCount::Bytes { done, total }) if total > 0 => (done, Some(total)),
+                _ if item.progress > 0.0 => {
+                    let expected = stats.received_bytes as f64 * 100.0 / item.progress.min(100.0) as f64;
+                    (stats.received_bytes, Some(expected as u64))
+                }
+                _ => (stats.received_bytes, None),
+            };
+            if let Some(TransferCount::Segments { done, total }) = stats.count {
+                summary.remaining_segments += total.saturating_sub(done);
+            }
+            if let Some(expected) = expected {
+                sizes.push(expected);
+                summary.remaining_bytes += expected.saturating_sub(done);
+            }
+        }
+
+        summary.bytes_per_sec = speed as u64;
+        if speed > 0.0 && !sizes.is_empty() {
+            let average = sizes.iter().sum::<u64>() / sizes.len() as u64;
+            let queued = summary.remaining_bytes + average * summary.pending as u64;
+            let seconds = queued as f64 / speed;
+            summary.eta_seconds = Some(seconds as u64);
+            summary.eta = format_duration(seconds);
+        }
+        summary
     }
 
     pub async fn get_active_count(&self) -> usize {
@@ -254,6 +656,314 @@ impl DownloadQueue {
             }
         }
     }
+
+    pub async fn create_group(&self, name: String, sequential: bool) -> String {
+        let id = Uuid::new_v4().to_string();
+        let mut groups = self.groups.write().await;
+        groups.push(QueueGroup {
+            id: id.clone(),
+            name,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            sequential,
+            finished: false,
+            merge: None,
+        });
+        id
+    }
+
+    pub async fn set_group_merge(&self, group_id: &str, merge: MultipartMerge) {
+        if let Some(group) = self.groups.write().await.iter_mut().find(|g| g.id == group_id) {
+            group.merge = Some(merge);
+        }
+    }
+
+    /// Let a finished group report completion (and merge) again
+    pub async fn reopen_group(&self, group_id: &str) {
+        if let Some(group) = self.groups.write().await.iter_mut().find(|g| g.id == group_id) {
+            group.finished = false;
+        }
+    }
+
+    pub async fn get_group_merge(&self, group_id: &str) -> Option<MultipartMerge> {
+        self.groups.read().await.iter().find(|g| g.id == group_id)?.merge.clone()
+    }
+
+    /// Whether an item has to wait for earlier episodes of a sequential group
+    pub async fn is_blocked_by_group(&self, id: &str) -> bool {
+        let items = self.items.read().await;
+        let groups = self.groups.read().await;
+        let Some(item) = items.iter().find(|i| i.id == id) else {
+            return false;
+        };
+        sequential_head(&groups, &items, item).map(|head| head != id).unwrap_or(false)
+    }
+
+    /// Pending items that can start now, in queue order: fills the free
+    /// `max_concurrent` slots without exceeding the per-host cap and only
+    /// offers the next unfinished episode of each sequential group
+    pub async fn startable_items(&self) -> Vec<QueueItem> {
+        let max = *self.max_concurrent.read().await;
+        let max_per_host = *self.max_per_host.read().await;
+        let items = self.items.read().await;
+        let groups = self.groups.read().await;
+
+        // Count by status: the active map is only filled once the task runs
+        let mut active = 0;
+        let mut per_host: HashMap<String, usize> = HashMap::new();
+        for item in items.iter().filter(|i| i.status == QueueItemStatus::Downloading) {
+            active += 1;
+            *per_host.entry(host_of(&item.url)).or_default() += 1;
+        }
+
+        let mut startable = Vec::new();
+        for item in items.iter().filter(|i| i.status == QueueItemStatus::Pending) {
+            if active >= max {
+                break;
+            }
+            let blocked = sequential_head(&groups, &items, item)
+                .map(|head| head != item.id)
+                .unwrap_or(false);
+            let host_count = per_host.entry(host_of(&item.url)).or_default();
+            if blocked || *host_count >= max_per_host {
+                continue;
+            }
+
+            *host_count += 1;
+            active += 1;
+            startable.push(item.clone());
+        }
+        startable
+    }
+
+    pub async fn get_groups(&self) -> Vec<GroupProgress> {
+        let items = self.items.read().await;
+        let groups = self.groups.read().await;
+        groups.iter().map(|g| group_progress(g, &items)).collect()
+    }
+
+    pub async fn get_group_progress(&self, group_id: &str) -> Option<GroupProgress> {
+        let items = self.items.read().await;
+        let groups = self.groups.read().await;
+        let group = groups.iter().find(|g| g.id == group_id)?;
+        Some(group_progress(group, &items))
+    }
+
+    async fn group_item_ids(&self, group_id: &str) -> Vec<String> {
+        let items = self.items.read().await;
+        items
+            .iter()
+            .filter(|i| i.options.group_id.as_deref() == Some(group_id))
+            .map(|i| i.id.clone())
+            .collect()
+    }
+
+    /// Pause active items and hold pending ones so they aren't picked up
+    pub async fn pause_group(&self, group_id: &str) -> usize {
+        let mut count = 0;
+        for id in self.group_item_ids(group_id).await {
+            let paused = match self.get_item(&id).await.map(|i| i.status) {
+                Some(QueueItemStatus::Downloading) => self.pause_download(&id).await,
+                Some(QueueItemStatus::Pending) => {
+                    self.update_item_status(&id, QueueItemStatus::Paused).await;
+                    true
+                }
+                _ => false,
+            };
+            if paused {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    pub async fn resume_group(&self, group_id: &str) -> usize {
+        let mut count = 0;
+        for id in self.group_item_ids(group_id).await {
+            if self.resume_download(&id).await {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Cancel every unfinished item in the group
+    /// Cancel the unfinished items of a group; returns their ids
+    pub async fn cancel_group(&self, group_id: &str) -> Vec<String> {
+        let mut cancelled = Vec::new();
+        for id in self.group_item_ids(group_id).await {
+            let unfinished = self.get_item(&id).await
+                .map(|i| !is_final(&i.status))
+                .unwrap_or(false);
+            if unfinished && self.cancel_download(&id).await {
+                cancelled.push(id);
+            }
+        }
+        cancelled
+    }
+
+    /// Count a finished item towards the current run; `bytes` is the
+    /// size of what it produced
+    pub async fn record_outcome(&self, succeeded: bool, bytes: u64) {
+        let mut run = self.run.write().await;
+        let run = run.get_or_insert_with(QueueRun::new);
+        if succeeded {
+            run.succeeded += 1;
+            run.total_bytes += bytes;
+        } else {
+            run.failed += 1;
+        }
+    }
+
+    /// End the current run once nothing is downloading, processing or
+    /// waiting to start. Returns its tally the first time, so the caller
+    /// emits `queue-finished` exactly once per run.
+    pub async fn check_finished(&self) -> Option<QueueFinished> {
+        if !self.active_downloads.read().await.is_empty() {
+            return None;
+        }
+        let items = self.items.read().await;
+        let busy = items.iter().any(|i| {
+            matches!(
+                i.status,
+                QueueItemStatus::Pending | QueueItemStatus::Downloading | QueueItemStatus::Processing
+            )
+        });
+        if busy {
+            return None;
+        }
+
+        let run = self.run.write().await.take()?;
+        // Everything was paused or cancelled; there's nothing to report
+        if run.succeeded + run.failed == 0 {
+            return None;
+        }
+        let elapsed = run.started.elapsed();
+        Some(QueueFinished {
+            succeeded: run.succeeded,
+            failed: run.failed,
+            total_bytes: run.total_bytes,
+            elapsed_secs: elapsed.as_secs(),
+            elapsed: format_duration(elapsed.as_secs_f64()),
+        })
+    }
+
+    /// Mark the group finished if all of its items are done. Returns the
+    /// final progress the first time this happens so the caller can emit
+    /// the completion event exactly once.
+    pub async fn check_group_finished(&self, group_id: &str) -> Option<GroupProgress> {
+        // Items before groups, like everywhere else, so this can't deadlock
+        // against clear_all or prune_groups
+        let items: Vec<QueueItem> = self
+            .items
+            .read()
+            .await
+            .iter()
+            .filter(|i| i.options.group_id.as_deref() == Some(group_id))
+            .cloned()
+            .collect();
+
+        let mut groups = self.groups.write().await;
+        let group = groups.iter_mut().find(|g| g.id == group_id)?;
+        if group.finished {
+            return None;
+        }
+
+        let progress = group_progress(group, &items);
+        if progress.total == 0 || progress.completed + progress.failed + progress.cancelled < progress.total {
+            return None;
+        }
+
+        group.finished = true;
+        Some(GroupProgress { finished: true, ..progress })
+    }
+
+    /// Drop groups with no items left; returns them
+    async fn prune_groups(&self) -> Vec<QueueGroup> {
+        let items = self.items.read().await;
+        let mut groups = self.groups.write().await;
+        let (kept, pruned) = groups
+            .drain(..)
+            .partition(|g| items.iter().any(|i| i.options.group_id.as_deref() == Some(g.id.as_str())));
+        *groups = kept;
+        pruned
+    }
+}
+
+fn host_of(url: &str) -> String {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.to_lowercase()))
+        .unwrap_or_default()
+}
+
+/// The id of the first unfinished item (by episode number, then queue
+/// position) of the item's group, or None if the group isn't sequential
+fn sequential_head<'a>(groups: &[QueueGroup], items: &'a [QueueItem], item: &QueueItem) -> Option<&'a str> {
+    let group_id = item.options.group_id.as_deref()?;
+    if !groups.iter().any(|g| g.id == group_id && g.sequential) {
+        return None;
+    }
+
+    items
+        .iter()
+        .enumerate()
+        .filter(|(_, i)| i.options.group_id.as_deref() == Some(group_id) && !is_final(&i.status))
+        .min_by_key(|(pos, i)| {
+            let episode = i.options.episode.as_ref().and_then(|e| e.episode);
+            (episode.is_none(), episode, *pos)
+        })
+        .map(|(_, i)| i.id.as_str())
+}
+
+fn is_final(status: &QueueItemStatus) -> bool {
+    matches!(
+        status,
+        QueueItemStatus::Completed | QueueItemStatus::Failed | QueueItemStatus::Cancelled
+    )
+}
+
+fn group_progress(group: &QueueGroup, items: &[QueueItem]) -> GroupProgress {
+    let members: Vec<&QueueItem> = items
+        .iter()
+        .filter(|i| i.options.group_id.as_deref() == Some(group.id.as_str()))
+        .collect();
+    let count = |status: QueueItemStatus| members.iter().filter(|i| i.status == status).count();
+
+    let total = members.len();
+    // Cancelled items drop out of the average so they don't hold it back
+    let counted: Vec<f32> = members
+        .iter()
+        .filter(|i| i.status != QueueItemStatus::Cancelled)
+        .map(|i| if is_final(&i.status) { 100.0 } else { i.progress })
+        .collect();
+    let progress = if counted.is_empty() {
+        0.0
+    } else {
+        counted.iter().sum::<f32>() / counted.len() as f32
+    };
+
+    // Extrapolate from the time elapsed since the group was created
+    let eta = chrono::DateTime::parse_from_rfc3339(&group.created_at)
+        .ok()
+        .map(|start| (chrono::Utc::now() - start.with_timezone(&chrono::Utc)).num_seconds() as f64)
+        .filter(|elapsed| *elapsed > 0.0 && progress > 0.0 && progress < 100.0)
+        .map(|elapsed| format_duration(elapsed * (100.0 - progress as f64) / progress as f64))
+        .unwrap_or_default();
+
+    GroupProgress {
+        id: group.id.clone(),
+        name: group.name.clone(),
+        total,
+        completed: count(QueueItemStatus::Completed),
+        failed: count(QueueItemStatus::Failed),
+        cancelled: count(QueueItemStatus::Cancelled),
+        downloading: count(QueueItemStatus::Downloading),
+        processing: count(QueueItemStatus::Processing),
+        paused: count(QueueItemStatus::Paused),
+        progress,
+        eta,
+        finished: group.finished,
+    }
 }
 
 impl Default for DownloadQueue {
@@ -261,3 +971,53 @@ impl Default for DownloadQueue {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn add_group_item(queue: &DownloadQueue) -> String {
+        let group_id = queue.create_group("Series".to_string(), false).await;
+        let options = QueueItemOptions { group_id: Some(group_id.clone()), ..Default::default() };
+        let id = queue
+            .add_item(
+                "https://example.com/ep1".to_string(),
+                "EP1".to_string(),
+                String::new(),
+                "auto".to_string(),
+                String::new(),
+                "ep1.mp4".to_string(),
+                options,
+            )
+            .await;
+        queue.update_item_status(&id, QueueItemStatus::Completed).await;
+        group_id
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn finishing_a_group_while_clearing_does_not_deadlock() {
+        let queue = Arc::new(DownloadQueue::new());
+        let run = async {
+            for _ in 0..200 {
+                let group_id = add_group_item(&queue).await;
+                let finish = tokio::spawn({
+                    let queue = queue.clone();
+                    async move { queue.check_group_finished(&group_id).await }
+                });
+                let prune = tokio::spawn({
+                    let queue = queue.clone();
+                    async move { queue.prune_groups().await }
+                });
+                let clear = tokio::spawn({
+                    let queue = queue.clone();
+                    async move { queue.clear_all().await }
+                });
+                let (finish, prune, clear) = tokio::join!(finish, prune, clear);
+                finish.unwrap();
+                prune.unwrap();
+                clear.unwrap();
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(20), run).await.expect("queue locks deadlocked");
+    }
+}
@@ -0,0 +1,146 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::downloader::{output_file_path, sanitize_filename, scratch_dir};
+use crate::trash;
+
+// Prefix of the HLS downloader's scratch files in the scratch dir
+const TEMP_PREFIX: &str = "video_";
+
+/// Scratch files nothing has written to for this long belong to no running
+/// download. Conversion only reads the TS but keeps writing the MP4 next to
+/// it, so a download is judged by all of its files together.
+pub const TEMP_FILE_GRACE: Duration = Duration::from_secs(30 * 60);
+
+/// A queue item that was mid-download when the app last stopped without
+/// saving, with whatever it left on disk
+#[derive(Clone, Debug, Serialize)]
+pub struct RecoveredDownload {
+    pub id: String,
+    pub title: String,
+    /// Fully downloaded TS still waiting for conversion; resuming converts
+    /// it instead of downloading again
+    pub checkpoint: Option<String>,
+    /// Half-written files that can't be resumed
+    pub partial_files: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct OrphanedFile {
+    pub path: String,
+    pub size: u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct RecoveryReport {
+    pub downloads: Vec<RecoveredDownload>,
+    /// Temp files no queue item owns any more
+    pub orphaned_files: Vec<OrphanedFile>,
+    pub orphaned_bytes: u64,
+}
+
+impl RecoveryReport {
+    pub fn is_empty(&self) -> bool {
+        self.downloads.is_empty() && self.orphaned_files.is_empty()
+    }
+
+    /// Files `cleanup` may delete: orphans and partial files, never
+    /// checkpoints
+    pub fn removable(&self) -> impl Iterator<Item = &str> {
+        self.orphaned_files
+            .iter()
+            .map(|f| f.path.as_str())
+            .chain(self.downloads.iter().flat_map(|d| d.partial_files.iter().map(String::as_str)))
+    }
+}
+
+/// Check what a download writing to `dir`/`filename` left behind
+pub fn inspect_download(id: &str, title: &str, dir: &str, filename: &str) -> RecoveredDownload {
+    let base = Path::new(dir).join(sanitize_filename(filename));
+    let existing = |ext: &str| Some(output_file_path(&base, ext)).filter(|p| p.is_file());
+
+    RecoveredDownload {
+        id: id.to_string(),
+        title: title.to_string(),
+        checkpoint: existing("ts").map(|p| p.to_string_lossy().to_string()),
+        partial_files: ["part", "processing.mp4"]
+            .into_iter()
+            .filter_map(existing)
+            .map(|p| p.to_string_lossy().to_string())
+            .collect(),
+    }
+}
+
+/// HLS scratch files in the app's scratch dir. Their names are unique per
+/// download, so anything found before this run started any download is
+/// orphaned.
+pub fn find_orphaned_temp_files() -> Vec<OrphanedFile> {
+    let Ok(entries) = std::fs::read_dir(scratch_dir()) else {
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| {
+            let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            name.starts_with(TEMP_PREFIX)
+                && (name.ends_with(".ts") || name.ends_with(".mp4") || name.ends_with("_segments"))
+        })
+        .map(|path| OrphanedFile {
+            size: disk_size(&path),
+            path: path.to_string_lossy().to_string(),
+        })
+        .collect()
+}
+
+/// Orphaned scratch files of downloads that are no longer running: no file
+/// of the same download was written within `grace` and none is in `in_use`
+pub fn stale_temp_files(grace: Duration, in_use: &[PathBuf]) -> Vec<OrphanedFile> {
+    let files = find_orphaned_temp_files();
+    let recent = |f: &OrphanedFile| {
+        let path = Path::new(&f.path);
+        let modified = path.metadata().and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+        SystemTime::now().duration_since(modified).map(|age| age < grace).unwrap_or(true)
+            || in_use.iter().any(|p| p == path)
+    };
+    let busy: Vec<String> = files.iter().filter(|f| recent(f)).map(|f| scratch_id(&f.path)).collect();
+
+    files.into_iter().filter(|f| !busy.contains(&scratch_id(&f.path))).collect()
+}
+
+/// "video_<uuid>" for every file one download writes (.ts, .mp4, _segments)
+fn scratch_id(path: &str) -> String {
+    let name = Path::new(path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let stem = name.split('.').next().unwrap_or_default();
+    stem.trim_end_matches("_segments").to_string()
+}
+
+/// Delete scratch files for good; they are internal, so the trash setting
+/// doesn't apply. Returns how many went and the bytes freed.
+pub fn remove_temp_files(files: &[OrphanedFile]) -> (usize, u64) {
+    files
+        .iter()
+        .filter(|f| trash::remove_permanently(Path::new(&f.path)).is_ok())
+        .fold((0, 0), |(count, bytes), f| (count + 1, bytes + f.size))
+}
+
+fn disk_size(path: &Path) -> u64 {
+    if path.is_dir() {
+        std::fs::read_dir(path)
+            .map(|entries| entries.filter_map(|e| e.ok()).map(|e| disk_size(&e.path())).sum())
+            .unwrap_or(0)
+    } else {
+        path.metadata().map(|m| m.len()).unwrap_or(0)
+    }
+}
+
+/// Delete files or segment folders (into the trash unless permanent
+/// delete is on); returns how many went
+pub fn cleanup<'a>(paths: impl Iterator<Item = &'a str>) -> usize {
+    paths
+        .map(PathBuf::from)
+        .filter(|path| trash::remove(path).is_ok())
+        .count()
+}
@@ -0,0 +1,408 @@
+//! Optional HTTP API for driving the app remotely (a phone on the LAN,
+//! scripts). It only listens on localhost unless `remote_api_lan` is set.
+//! Every request must carry the configured token, either as
+//! `Authorization: Bearer <token>` or `X-Api-Token: <token>`.
+//!
+//! Routes:
+//! - `GET    /api/queue`              list queue items
+//! - `POST   /api/queue`              add an item (`start: true` to begin immediately)
+//! - `POST   /api/queue/{id}/start`   start / pause / resume / cancel an item
+//! - `DELETE /api/queue/{id}`         remove an item
+//! - `GET    /api/settings`           queue and transfer settings (RemoteSettings)
+//! - `PUT    /api/settings`           change some of them; other keys are refused
+//! - `GET    /api/events`             WebSocket stream of progress events
+//!
+//! Browsers can't set headers on WebSocket connections, so `/api/events`
+//! also accepts the token as a `?token=` query parameter. Each message is
+//! `{"event", "event_version", "payload"}`; `event_version` only changes
+//! when a payload stops being readable by existing clients.
+
+use axum::body::Bytes;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{DefaultBodyLimit, Path, Request, State};
+use axum::http::{header, HeaderMap, Method, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+use crate::queue::QueueItemOptions;
+use crate::{AppSettings, AppState};
+
+pub const DEFAULT_PORT: u16 = 8787;
+
+// Request bodies are small JSON documents
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+type ApiResult = Result<Response, ApiError>;
+
+/// Error status and message, sent as `{"error": "..."}`
+struct ApiError(StatusCode, String);
+
+impl From<(StatusCode, String)> for ApiError {
+    fn from((status, message): (StatusCode, String)) -> Self {
+        Self(status, message)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct RemoteApiStatus {
+    pub running: bool,
+    pub port: u16,
+    pub error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AddItemRequest {
+    url: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    thumbnail: String,
+    #[serde(default)]
+    quality: Option<String>,
+    #[serde(default)]
+    output_dir: Option<String>,
+    #[serde(default)]
+    output_filename: Option<String>,
+    #[serde(default)]
+    options: QueueItemOptions,
+    #[serde(default)]
+    start: bool,
+}
+
+/// The part of AppSettings the API may read and change: queue and
+/// transfer behaviour. Executables and their arguments, folders, network
+/// filtering, the API's own settings and every secret stay local-only.
+#[derive(Serialize, Deserialize)]
+struct RemoteSettings {
+    default_quality: String,
+    max_concurrent_downloads: usize,
+    max_downloads_per_host: usize,
+    stale_source_hours: u64,
+    auto_start_queue: bool,
+    show_notifications: bool,
+    segment_workers: usize,
+    audio_tracks: String,
+    max_file_size_mb: u64,
+    check_sources: bool,
+    allow_quality_fallback: bool,
+    auto_quality_by_speed: bool,
+    max_concurrent_postprocess: usize,
+    max_total_speed_kbps: u64,
+    prioritize_top_download: bool,
+    pause_on_metered: bool,
+}
+
+impl RemoteSettings {
+    fn from_settings(settings: &AppSettings) -> Self {
+        Self {
+            default_quality: settings.default_quality.clone(),
+            max_concurrent_downloads: settings.max_concurrent_downloads,
+            max_downloads_per_host: settings.max_downloads_per_host,
+            stale_source_hours: settings.stale_source_hours,
+            auto_start_queue: settings.auto_start_queue,
+            show_notifications: settings.show_notifications,
+            segment_workers: settings.segment_workers,
+            audio_tracks: settings.audio_tracks.clone(),
+            max_file_size_mb: settings.max_file_size_mb,
+            check_sources: settings.check_sources,
+            allow_quality_fallback: settings.allow_quality_fallback,
+            auto_quality_by_speed: settings.auto_quality_by_speed,
+            max_concurrent_postprocess: settings.max_concurrent_postprocess,
+            max_total_speed_kbps: settings.max_total_speed_kbps,
+            prioritize_top_download: settings.prioritize_top_download,
+            pause_on_metered: settings.pause_on_metered,
+        }
+    }
+
+    fn apply_to(self, settings: &mut AppSettings) {
+        settings.default_quality = self.default_quality;
+        settings.max_concurrent_downloads = self.max_concurrent_downloads;
+        settings.max_downloads_per_host = self.max_downloads_per_host;
+        settings.stale_source_hours = self.stale_source_hours;
+        settings.auto_start_queue = self.auto_start_queue;
+        settings.show_notifications = self.show_notifications;
+        settings.segment_workers = self.segment_workers;
+        settings.audio_tracks = self.audio_tracks;
+        settings.max_file_size_mb = self.max_file_size_mb;
+        settings.check_sources = self.check_sources;
+        settings.allow_quality_fallback = self.allow_quality_fallback;
+        settings.auto_quality_by_speed = self.auto_quality_by_speed;
+        settings.max_concurrent_postprocess = self.max_concurrent_postprocess;
+        settings.max_total_speed_kbps = self.max_total_speed_kbps;
+        settings.prioritize_top_download = self.prioritize_top_download;
+        settings.pause_on_metered = self.pause_on_metered;
+    }
+}
+
+#[derive(Clone)]
+struct Context {
+    app: tauri::AppHandle,
+    state: Arc<AppState>,
+    token: String,
+}
+
+/// Stop the running server (if any) and start a new one when enabled in
+/// settings. A token is generated on first enable.
+pub async fn restart(app: tauri::AppHandle, state: Arc<AppState>) {
+    if let Some(handle) = state.remote_server.lock().await.take() {
+        handle.abort();
+    }
+
+    let mut settings = state.settings.read().await.clone();
+    if !settings.remote_api_enabled {
+        crate::emit_event(&app, "remote-api-status", RemoteApiStatus {
+            running: false,
+            port: settings.remote_api_port,
+            error: None,
+        });
+        return;
+    }
+
+    if settings.remote_api_token.trim().is_empty() {
+        settings.remote_api_token = uuid::Uuid::new_v4().simple().to_string();
+        state.settings.write().await.remote_api_token = settings.remote_api_token.clone();
+        crate::write_settings_file(&app, &settings).ok();
+    }
+
+    // Other devices can only connect once LAN access is turned on
+    let ip = if settings.remote_api_lan { Ipv4Addr::UNSPECIFIED } else { Ipv4Addr::LOCALHOST };
+    let listener = match TcpListener::bind(SocketAddr::from((ip, settings.remote_api_port))).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            crate::emit_event(&app, "remote-api-status", RemoteApiStatus {
+                running: false,
+                port: settings.remote_api_port,
+                error: Some(format!("Failed to bind port {}: {}", settings.remote_api_port, e)),
+            });
+            return;
+        }
+    };
+
+    crate::emit_event(&app, "remote-api-status", RemoteApiStatus {
+        running: true,
+        port: settings.remote_api_port,
+        error: None,
+    });
+
+    let router = router(Context {
+        app,
+        state: state.clone(),
+        token: settings.remote_api_token,
+    });
+
+    // Connections run on their own tasks, so a settings update that
+    // restarts the server still gets its response out
+    let handle = tokio::spawn(async move {
+        axum::serve(listener, router).await.ok();
+    });
+
+    *state.remote_server.lock().await = Some(handle);
+}
+
+fn router(ctx: Context) -> Router {
+    Router::new()
+        .route("/api/queue", get(list_queue).post(add_item))
+        .route("/api/queue/summary", get(queue_summary))
+        .route("/api/queue/{id}", delete(remove_item))
+        .route("/api/queue/{id}/{action}", post(queue_action))
+        .route("/api/settings", get(get_settings).put(update_settings))
+        .route("/api/events", get(events))
+        .fallback(not_found)
+        .layer(middleware::from_fn_with_state(ctx.clone(), require_token))
+        .layer(DefaultBodyLimit::max(MAX_BODY_SIZE))
+        .with_state(ctx)
+}
+
+async fn require_token(State(ctx): State<Context>, req: Request, next: Next) -> Response {
+    let allow_query = is_websocket_upgrade(&req);
+    if !is_authorized(req.headers(), req.uri().query(), &ctx.token, allow_query) {
+        return ApiError(StatusCode::UNAUTHORIZED, "Invalid or missing API token".to_string()).into_response();
+    }
+    next.run(req).await
+}
+
+async fn not_found() -> ApiError {
+    ApiError(StatusCode::NOT_FOUND, "Not found".to_string())
+}
+
+async fn list_queue(State(ctx): State<Context>) -> ApiResult {
+    json(ctx.state.queue.get_items().await)
+}
+
+async fn queue_summary(State(ctx): State<Context>) -> ApiResult {
+    json(ctx.state.queue.summary().await)
+}
+
+async fn remove_item(State(ctx): State<Context>, Path(id): Path<String>) -> ApiResult {
+    crate::remove_queue_item(&ctx.state, &id).await;
+    json(serde_json::json!({ "ok": true }))
+}
+
+async fn get_settings(State(ctx): State<Context>) -> ApiResult {
+    json(RemoteSettings::from_settings(&ctx.state.settings.read().await))
+}
+
+/// Merge the given RemoteSettings keys into the current settings
+async fn update_settings(State(ctx): State<Context>, body: Bytes) -> ApiResult {
+    let changes: serde_json::Map<String, serde_json::Value> = parse_json(&body)?;
+    let mut settings = ctx.state.settings.read().await.clone();
+
+    let mut merged = serde_json::to_value(RemoteSettings::from_settings(&settings))
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    for (key, value) in changes {
+        let Some(slot) = merged.get_mut(&key) else {
+            return Err(ApiError(StatusCode::FORBIDDEN, format!("Setting can't be changed remotely: {}", key)));
+        };
+        *slot = value;
+    }
+    let update: RemoteSettings = serde_json::from_value(merged)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid settings: {}", e)))?;
+    update.apply_to(&mut settings);
+
+    crate::store_settings(&ctx.app, &ctx.state, settings)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    json(serde_json::json!({ "ok": true }))
+}
+
+async fn add_item(State(ctx): State<Context>, body: Bytes) -> ApiResult {
+    let body: AddItemRequest = parse_json(&body)?;
+    crate::postprocess::parse_extra_args(body.options.extra_ffmpeg_args.as_deref().unwrap_or_default())
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let settings = ctx.state.settings.read().await.clone();
+    let output_dir = body.output_dir.unwrap_or(settings.default_download_dir);
+    crate::remote_output::RemoteOutput::parse(&output_dir).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let title = if body.title.is_empty() { body.url.clone() } else { body.title };
+    let (thumbnail, page_url) = (body.thumbnail.clone(), body.url.clone());
+    let id = ctx.state.queue.add_item(
+        body.url,
+        title.clone(),
+        body.thumbnail,
+        body.quality.unwrap_or(settings.default_quality),
+        output_dir,
+        body.output_filename.unwrap_or(title),
+        body.options,
+    ).await;
+    crate::prefetch_thumbnail(&ctx.app, crate::download_log(&ctx.state, &id).await, &thumbnail, &page_url);
+
+    if body.start {
+        crate::start_queue_item(ctx.app.clone(), ctx.state.clone(), id.clone())
+            .await
+            .map_err(|e| (StatusCode::CONFLICT, e))?;
+    }
+
+    json(serde_json::json!({ "id": id }))
+}
+
+async fn queue_action(State(ctx): State<Context>, Path((id, action)): Path<(String, String)>) -> ApiResult {
+    let ok = match action.as_str() {
+        "start" => {
+            crate::start_queue_item(ctx.app.clone(), ctx.state.clone(), id.clone())
+                .await
+                .map_err(|e| (StatusCode::CONFLICT, e))?;
+            true
+        }
+        "pause" => ctx.state.queue.pause_download(&id).await,
+        "resume" => ctx.state.queue.resume_download(&id).await,
+        "cancel" => crate::cancel_queue_item(&ctx.state, &id).await,
+        _ => return Err(ApiError(StatusCode::NOT_FOUND, format!("Unknown action: {}", action))),
+    };
+
+    json(serde_json::json!({ "ok": ok }))
+}
+
+fn is_websocket_upgrade(req: &Request) -> bool {
+    req.method() == Method::GET
+        && req.uri().path() == "/api/events"
+        && req
+            .headers()
+            .get(header::UPGRADE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.eq_ignore_ascii_case("websocket"))
+            .unwrap_or(false)
+}
+
+async fn events(State(ctx): State<Context>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| serve_events(ctx, socket))
+}
+
+/// Forward every mirrored app event as a JSON text frame
+/// (`{"event": "...", "payload": {...}}`)
+async fn serve_events(ctx: Context, socket: WebSocket) {
+    let (mut sink, mut source) = socket.split();
+    let mut events = ctx.state.events.subscribe();
+
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let Ok(text) = serde_json::to_string(&event) else {
+                        continue;
+                    };
+                    if sink.send(Message::Text(text.into())).await.is_err() {
+                        break;
+                    }
+                }
+                // A slow client just misses some progress ticks
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            },
+            message = source.next() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                _ => {}
+            },
+        }
+    }
+}
+
+/// Check the API token. `allow_query` lets `?token=` stand in for the
+/// headers; only the WebSocket upgrade needs it, since a query string ends
+/// up in browser history, proxy logs and Referer headers.
+fn is_authorized(headers: &HeaderMap, query: Option<&str>, token: &str, allow_query: bool) -> bool {
+    let value = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let query_token = query.filter(|_| allow_query).and_then(|query| {
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(k, _)| k == "token")
+            .map(|(_, v)| v.to_string())
+    });
+
+    let provided = value("authorization")
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| value("x-api-token"))
+        .map(|v| v.to_string())
+        .or(query_token);
+
+    match provided {
+        Some(provided) => !token.is_empty() && constant_time_eq(provided.trim().as_bytes(), token.as_bytes()),
+        None => false,
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn parse_json<T: serde::de::DeserializeOwned>(body: &[u8]) -> Result<T, ApiError> {
+    serde_json::from_slice(body).map_err(|e| ApiError(StatusCode::BAD_REQUEST, format!("Invalid JSON: {}", e)))
+}
+
+fn json<T: Serialize>(value: T) -> ApiResult {
+    Ok(Json(value).into_response())
+}
@@ -0,0 +1,183 @@
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::postprocess::SUBTITLE_EXTENSIONS;
+
+// Sidecars packed along with each video, besides subtitles
+const SIDECAR_EXTENSIONS: &[&str] = &["nfo", "jpg", "png"];
+const COPY_BUFFER: usize = 256 * 1024;
+
+/// Sent as `season-pack-progress` while a group is archived
+#[derive(Clone, Debug, Serialize)]
+pub struct SeasonPackProgress {
+    pub group_id: String,
+    pub progress: f32,
+    pub written: u64,
+    pub total: u64,
+    /// Name of the file being added
+    pub current: String,
+    pub done: bool,
+    pub error: Option<String>,
+    /// The archive, once written
+    pub file_path: Option<String>,
+}
+
+/// A file on disk and its name inside the archive
+#[derive(Clone, Debug)]
+pub struct PackEntry {
+    pub path: PathBuf,
+    pub name: String,
+}
+
+/// The videos plus the NFO, subtitle and image files saved next to them,
+/// and tvshow.nfo when the media server layout put one above the season
+/// folder. `thumbnails` are cached posters added as `<video>-thumb.jpg`.
+pub fn collect_entries(videos: &[PathBuf], thumbnails: &[(PathBuf, PathBuf)]) -> Vec<PackEntry> {
+    let mut entries: Vec<PackEntry> = Vec::new();
+    let mut add = |path: PathBuf, name: String| {
+        if entries.iter().any(|e| e.path == path) {
+            return;
+        }
+        let name = unique_name(&entries, &name);
+        entries.push(PackEntry { path, name });
+    };
+
+    for video in videos {
+        let Some(name) = video.file_name().map(|n| n.to_string_lossy().to_string()) else {
+            continue;
+        };
+        add(video.clone(), name);
+        for sidecar in sidecars(video) {
+            let name = sidecar.file_name().unwrap_or_default().to_string_lossy().to_string();
+            add(sidecar, name);
+        }
+        if let Some((_, thumbnail)) = thumbnails.iter().find(|(v, _)| v == video) {
+            let stem = video.file_stem().unwrap_or_default().to_string_lossy().to_string();
+            add(thumbnail.clone(), format!("{}-thumb.jpg", stem));
+        }
+    }
+
+    let tvshow = videos
+        .first()
+        .and_then(|v| v.parent()?.parent())
+        .map(|series_dir| series_dir.join("tvshow.nfo"))
+        .filter(|p| p.is_file());
+    if let Some(tvshow) = tvshow {
+        add(tvshow, "tvshow.nfo".to_string());
+    }
+
+    entries
+}
+
+/// Files beside `video` named `<stem>.<ext>`, `<stem>.<lang>.<ext>` or
+/// `<stem>-thumb.<ext>`
+fn sidecars(video: &Path) -> Vec<PathBuf> {
+    let (Some(stem), Some(dir)) = (video.file_stem().map(|s| s.to_string_lossy().to_string()), video.parent()) else {
+        return Vec::new();
+    };
+    let Ok(read_dir) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut found: Vec<PathBuf> = read_dir
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p != video && p.is_file())
+        .filter(|p| {
+            let ext = p.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+            let sub_stem = p.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+            (SUBTITLE_EXTENSIONS.contains(&ext.as_str()) || SIDECAR_EXTENSIONS.contains(&ext.as_str()))
+                && (sub_stem == stem || sub_stem.starts_with(&format!("{}.", stem)) || sub_stem == format!("{}-thumb", stem))
+        })
+        .collect();
+    found.sort();
+    found
+}
+
+/// `name`, or `name (2)`... when another entry already uses it
+fn unique_name(entries: &[PackEntry], name: &str) -> String {
+    let taken = |candidate: &str| entries.iter().any(|e| e.name.eq_ignore_ascii_case(candidate));
+    if !taken(name) {
+        return name.to_string();
+    }
+    let path = Path::new(name);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let ext = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    (2..)
+        .map(|n| format!("{} ({}){}", stem, n, ext))
+        .find(|candidate| !taken(candidate))
+        .unwrap()
+}
+
+/// Write `entries` as a stored (uncompressed) ZIP; videos don't shrink, so
+/// compressing would only cost time. Files over 4 GB get ZIP64 records.
+/// `on_progress` gets bytes written so far, the total and the entry name.
+pub fn write_zip(
+    entries: &[PackEntry],
+    output: &Path,
+    mut on_progress: impl FnMut(u64, u64, &str),
+) -> io::Result<u64> {
+    let mut sizes = Vec::with_capacity(entries.len());
+    for entry in entries {
+        sizes.push(std::fs::metadata(&entry.path)?.len());
+    }
+    let total: u64 = sizes.iter().sum();
+
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(output)?));
+    let mut done = 0u64;
+    let mut buf = vec![0u8; COPY_BUFFER];
+
+    for (entry, &size) in entries.iter().zip(&sizes) {
+        on_progress(done, total, &entry.name);
+        let mut options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .unix_permissions(0o644)
+            .large_file(size >= u32::MAX as u64);
+        if let Some(modified) = std::fs::metadata(&entry.path)?.modified().ok().and_then(zip_datetime) {
+            options = options.last_modified_time(modified);
+        }
+        zip.start_file(entry.name.as_str(), options).map_err(io::Error::other)?;
+
+        let mut input = File::open(&entry.path)?;
+        let mut copied = 0u64;
+        loop {
+            let n = input.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            zip.write_all(&buf[..n])?;
+            copied += n as u64;
+            on_progress(done + copied, total, &entry.name);
+        }
+        if copied != size {
+            return Err(io::Error::other(format!("{} changed while being archived", entry.name)));
+        }
+        done += size;
+    }
+
+    let mut out = zip.finish().map_err(io::Error::other)?;
+    out.flush()?;
+    out.get_ref().sync_all()?;
+    on_progress(total, total, "");
+    Ok(total)
+}
+
+/// Modification time in local time, as ZIP headers store it; None before
+/// 1980, which ZIP can't represent
+fn zip_datetime(time: std::time::SystemTime) -> Option<zip::DateTime> {
+    use chrono::{Datelike, Timelike};
+    let local: chrono::DateTime<chrono::Local> = time.into();
+    let year = u16::try_from(local.year()).ok()?;
+    zip::DateTime::from_date_and_time(
+        year,
+        local.month() as u8,
+        local.day() as u8,
+        local.hour() as u8,
+        local.minute() as u8,
+        local.second() as u8,
+    )
+    .ok()
+}
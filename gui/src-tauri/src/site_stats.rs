@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+// Downloads finish concurrently; each update is a read-modify-write of the file
+static FILE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Running totals for one site. Kept apart from history, which is
+/// truncated and only holds successful downloads.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SiteCounts {
+    pub succeeded: u64,
+    pub failed: u64,
+    /// Size of the files downloaded successfully
+    pub bytes: u64,
+    pub last_error: Option<String>,
+    pub last_failed_at: Option<String>,
+}
+
+/// One row of `get_site_stats`
+#[derive(Clone, Debug, Serialize)]
+pub struct SiteStats {
+    pub domain: String,
+    pub count: u64,
+    pub succeeded: u64,
+    pub failed: u64,
+    pub bytes: u64,
+    /// Share of downloads that failed, 0.0 to 1.0
+    pub failure_rate: f64,
+    pub last_error: Option<String>,
+    pub last_failed_at: Option<String>,
+}
+
+/// Host of `url` without "www.", the key sites are counted under
+pub fn site_of(url: &str) -> Option<String> {
+    let parsed = url::Url::parse(url).ok()?;
+    let host = parsed.host_str()?.to_lowercase();
+    Some(host.trim_start_matches("www.").to_string())
+}
+
+fn load(path: &Path) -> BTreeMap<String, SiteCounts> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Count one finished download of `url`: Ok with the file size, or Err
+/// with why it failed
+pub fn record(path: &Path, url: &str, outcome: Result<u64, String>) -> Result<(), String> {
+    let Some(site) = site_of(url) else {
+        return Ok(());
+    };
+    let _guard = FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut sites = load(path);
+    let counts = sites.entry(site).or_default();
+    match outcome {
+        Ok(bytes) => {
+            counts.succeeded += 1;
+            counts.bytes += bytes;
+        }
+        Err(error) => {
+            counts.failed += 1;
+            counts.last_error = Some(error);
+            counts.last_failed_at = Some(chrono::Utc::now().to_rfc3339());
+        }
+    }
+
+    let content = serde_json::to_string_pretty(&sites).map_err(|e| format!("Failed to serialize site stats: {}", e))?;
+    fs::write(path, content).map_err(|e| format!("Failed to write site stats: {}", e))
+}
+
+/// Per-site breakdown, least reliable sites first
+pub fn summarize(path: &Path) -> Vec<SiteStats> {
+    let mut stats: Vec<SiteStats> = load(path)
+        .into_iter()
+        .map(|(domain, counts)| {
+            let count = counts.succeeded + counts.failed;
+            SiteStats {
+                domain,
+                count,
+                succeeded: counts.succeeded,
+                failed: counts.failed,
+                bytes: counts.bytes,
+                failure_rate: if count > 0 { counts.failed as f64 / count as f64 } else { 0.0 },
+                last_error: counts.last_error,
+                last_failed_at: counts.last_failed_at,
+            }
+        })
+        .collect();
+    stats.sort_by(|a, b| b.failure_rate.total_cmp(&a.failure_rate).then(b.count.cmp(&a.count)));
+    stats
+}
@@ -0,0 +1,40 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Skip the trash and delete outright; set from the settings
+static PERMANENT_DELETE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_permanent_delete(permanent: bool) {
+    PERMANENT_DELETE.store(permanent, Ordering::Relaxed);
+}
+
+/// Remove a file or folder the way the user chose: into the trash by
+/// default, or for good with the permanent delete setting on
+pub fn remove(path: &Path) -> Result<(), String> {
+    if PERMANENT_DELETE.load(Ordering::Relaxed) {
+        remove_permanently(path)
+    } else {
+        move_to_trash(path).map_err(|e| format!("Failed to move file to trash: {}", e))
+    }
+}
+
+pub fn remove_permanently(path: &Path) -> Result<(), String> {
+    let path = crate::downloader::long_path(path);
+    let result = if path.is_dir() {
+        std::fs::remove_dir_all(&path)
+    } else {
+        std::fs::remove_file(&path)
+    };
+    result.map_err(|e| format!("Failed to delete file: {}", e))
+}
+
+/// Move a file to the OS trash / recycle bin so the deletion can be undone
+/// from the file manager. On Linux this follows the FreeDesktop.org spec,
+/// including the per-volume trash for files on other drives.
+pub fn move_to_trash(path: &Path) -> Result<(), String> {
+    if !path.exists() {
+        return Err("File not found".to_string());
+    }
+
+    ::trash::delete(path).map_err(|e| e.to_string())
+}
@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+use tauri::{PhysicalPosition, PhysicalSize, WebviewWindow};
+
+// Smaller than this is a broken window, not something to restore
+const MIN_SIZE: u32 = 200;
+// How far into the window (from its top-left corner) must be on a monitor
+// for the saved position to count as visible
+const VISIBLE_MARGIN: i32 = 40;
+
+/// Where the app was left: window placement and the last output folder
+/// and quality picked. Owned by get_ui_state/set_ui_state and the window
+/// close handler; save_settings keeps whatever is stored.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiState {
+    pub window: Option<WindowGeometry>,
+    pub last_output_dir: String,
+    pub last_quality: String,
+}
+
+/// Outer position and inner size in physical pixels. While maximized the
+/// size and position are the ones to go back to when unmaximized.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub maximized: bool,
+}
+
+/// Current placement of `window`; `previous` supplies the normal size and
+/// position while it's maximized. None when minimized.
+pub fn capture(window: &WebviewWindow, previous: Option<WindowGeometry>) -> Option<WindowGeometry> {
+    if window.is_minimized().unwrap_or(false) {
+        return previous;
+    }
+    if window.is_maximized().unwrap_or(false) {
+        return previous.map(|geometry| WindowGeometry { maximized: true, ..geometry });
+    }
+
+    let position = window.outer_position().ok()?;
+    let size = window.inner_size().ok()?;
+    Some(WindowGeometry {
+        x: position.x,
+        y: position.y,
+        width: size.width,
+        height: size.height,
+        maximized: false,
+    })
+}
+
+/// Put `window` back where it was. The position is skipped when no
+/// current monitor shows it, e.g. after unplugging a second screen.
+pub fn restore(window: &WebviewWindow, geometry: &WindowGeometry) {
+    if geometry.width >= MIN_SIZE && geometry.height >= MIN_SIZE {
+        window.set_size(PhysicalSize::new(geometry.width, geometry.height)).ok();
+    }
+    if is_visible(window, geometry) {
+        window.set_position(PhysicalPosition::new(geometry.x, geometry.y)).ok();
+    }
+    if geometry.maximized {
+        window.maximize().ok();
+    }
+}
+
+fn is_visible(window: &WebviewWindow, geometry: &WindowGeometry) -> bool {
+    let (x, y) = (geometry.x + VISIBLE_MARGIN, geometry.y + VISIBLE_MARGIN);
+    window.available_monitors().unwrap_or_default().iter().any(|monitor| {
+        let (position, size) = (monitor.position(), monitor.size());
+        x >= position.x && y >= position.y && x < position.x + size.width as i32 && y < position.y + size.height as i32
+    })
+}
@@ -0,0 +1,57 @@
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::downloader::log::DownloadLog;
+use crate::history::HistoryItem;
+use crate::queue::RemovedItems;
+
+// How long after a destructive action it can still be taken back
+pub const UNDO_GRACE: Duration = Duration::from_secs(30);
+// Actions kept at once; older ones fall off
+const MAX_ACTIONS: usize = 10;
+
+// What was undone, for the UI's message
+pub const UNDO_QUEUE_REMOVE: &str = "queue_remove";
+pub const UNDO_QUEUE_CLEAR: &str = "queue_clear_all";
+pub const UNDO_HISTORY_DELETE: &str = "delete_history_item";
+pub const UNDO_HISTORY_CLEAR: &str = "clear_history";
+
+/// What a destructive action took away
+pub enum Removed {
+    Queue {
+        removed: RemovedItems,
+        logs: Vec<(String, DownloadLog)>,
+    },
+    /// History entries with their positions in the list
+    History(Vec<(usize, HistoryItem)>),
+}
+
+struct Action {
+    kind: &'static str,
+    at: Instant,
+    removed: Removed,
+}
+
+/// Recent destructive queue and history actions, newest last
+#[derive(Default)]
+pub struct UndoBuffer {
+    actions: Mutex<Vec<Action>>,
+}
+
+impl UndoBuffer {
+    pub async fn push(&self, kind: &'static str, removed: Removed) {
+        let mut actions = self.actions.lock().await;
+        actions.push(Action { kind, at: Instant::now(), removed });
+        if actions.len() > MAX_ACTIONS {
+            actions.remove(0);
+        }
+    }
+
+    /// The newest action still inside the grace period; expired ones are
+    /// dropped
+    pub async fn take(&self) -> Option<(&'static str, Removed)> {
+        let mut actions = self.actions.lock().await;
+        actions.retain(|a| a.at.elapsed() <= UNDO_GRACE);
+        actions.pop().map(|a| (a.kind, a.removed))
+    }
+}
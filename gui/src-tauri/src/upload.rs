@@ -0,0 +1,282 @@
+use futures::StreamExt;
+use reqwest::{Client, Method, RequestBuilder};
+use ring::{digest, hmac};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use url::Url;
+
+use crate::downloader::USER_AGENT;
+
+pub const KIND_S3: &str = "s3";
+pub const KIND_WEBDAV: &str = "webdav";
+
+pub const UPLOAD_UPLOADING: &str = "uploading";
+pub const UPLOAD_DONE: &str = "done";
+pub const UPLOAD_FAILED: &str = "failed";
+
+const CHUNK_SIZE: usize = 256 * 1024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(20);
+// Largest object a single S3 PUT accepts
+const S3_MAX_PUT_BYTES: u64 = 5 * 1024 * 1024 * 1024;
+const S3_DEFAULT_REGION: &str = "us-east-1";
+const S3_UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// Where finished downloads are copied: an S3-compatible bucket (AWS, R2,
+/// MinIO, Wasabi...) or a WebDAV folder on a NAS
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UploadDestination {
+    pub id: String,
+    pub name: String,
+    pub enabled: bool,
+    /// KIND_S3 or KIND_WEBDAV
+    pub kind: String,
+    /// S3: service endpoint ("https://s3.ap-southeast-1.amazonaws.com");
+    /// WebDAV: folder URL
+    pub endpoint: String,
+    /// S3 only; objects are addressed path-style (endpoint/bucket/key)
+    pub bucket: String,
+    /// S3 signing region; us-east-1 when empty
+    pub region: String,
+    /// Folder inside the bucket or WebDAV folder ("videos/series")
+    pub prefix: String,
+    /// S3 access key or WebDAV user
+    pub username: String,
+    /// S3 secret key or WebDAV password, only as typed into the settings.
+    /// Saving moves it to the sealed secrets file and it is never written
+    /// back out; uploads look it up through `password_ref`.
+    #[serde(skip_serializing)]
+    pub password: String,
+    /// Key of the stored password in the secrets file; empty when none
+    pub password_ref: String,
+}
+
+/// Sent as `upload-progress` for each queue item and destination
+#[derive(Clone, Debug, Serialize)]
+pub struct UploadProgress {
+    /// Queue item id
+    pub id: String,
+    pub destination: String,
+    /// UPLOAD_UPLOADING, UPLOAD_DONE or UPLOAD_FAILED
+    pub status: String,
+    pub progress: f32,
+    pub uploaded: u64,
+    pub total: u64,
+    pub message: String,
+}
+
+pub fn validate_destinations(destinations: &[UploadDestination]) -> Result<(), String> {
+    for dest in destinations {
+        let label = if dest.name.is_empty() { &dest.endpoint } else { &dest.name };
+        let endpoint = Url::parse(&dest.endpoint).map_err(|_| format!("Invalid upload endpoint for {}", label))?;
+        if !matches!(endpoint.scheme(), "http" | "https") {
+            return Err(format!("Upload endpoint for {} must be http or https", label));
+        }
+        match dest.kind.as_str() {
+            KIND_S3 if dest.bucket.is_empty() => return Err(format!("S3 destination {} needs a bucket", label)),
+            KIND_S3 if dest.username.is_empty() || (dest.password.is_empty() && dest.password_ref.is_empty()) => {
+                return Err(format!("S3 destination {} needs an access key and secret", label))
+            }
+            KIND_S3 | KIND_WEBDAV => {}
+            other => return Err(format!("Unknown upload destination type: {}", other)),
+        }
+    }
+    Ok(())
+}
+
+/// Upload `path` and confirm the stored size matches before returning Ok.
+/// `on_progress` gets (bytes sent, total bytes).
+pub async fn upload<F>(dest: &UploadDestination, path: &Path, on_progress: F) -> Result<(), String>
+where
+    F: Fn(u64, u64) + Send + Sync + 'static,
+{
+    let filename = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or_else(|| "Nothing to upload".to_string())?;
+    let total = tokio::fs::metadata(path)
+        .await
+        .map_err(|e| format!("Failed to read file: {}", e))?
+        .len();
+    if dest.kind == KIND_S3 && total > S3_MAX_PUT_BYTES {
+        return Err("File is larger than 5 GB, the S3 single upload limit".to_string());
+    }
+
+    // Destinations are configured by the user, often on the LAN, so they
+    // skip the download-side DNS and private address rules
+    let client = Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to upload: {}", e))?;
+    let url = object_url(dest, &filename)?;
+
+    if dest.kind == KIND_WEBDAV {
+        create_webdav_folders(&client, dest).await;
+    }
+
+    let file = tokio::fs::File::open(path).await.map_err(|e| format!("Failed to open file: {}", e))?;
+    let sent = Arc::new(AtomicU64::new(0));
+    let on_progress = Arc::new(on_progress);
+    let body = futures::stream::unfold(file, |mut file| async move {
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        match file.read(&mut buf).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buf.truncate(n);
+                Some((Ok::<_, std::io::Error>(bytes::Bytes::from(buf)), file))
+            }
+            Err(e) => Some((Err(e), file)),
+        }
+    })
+    .inspect(move |chunk| {
+        if let Ok(chunk) = chunk {
+            let done = sent.fetch_add(chunk.len() as u64, Ordering::Relaxed) + chunk.len() as u64;
+            on_progress(done, total);
+        }
+    });
+
+    request(&client, dest, Method::PUT, &url)
+        .header(reqwest::header::CONTENT_LENGTH, total)
+        .body(reqwest::Body::wrap_stream(body))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("Failed to upload: {}", e))?;
+
+    verify(&client, dest, &url, total).await
+}
+
+/// Ask the server for the stored object's size
+async fn verify(client: &Client, dest: &UploadDestination, url: &Url, expected: u64) -> Result<(), String> {
+    let response = request(client, dest, Method::HEAD, url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("Failed to verify upload: {}", e))?;
+    let stored = response
+        .headers()
+        .get(reqwest::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    match stored {
+        Some(size) if size == expected => Ok(()),
+        Some(size) => Err(format!("Uploaded size {} doesn't match the local {} bytes", size, expected)),
+        None => Err("Server didn't report the uploaded size".to_string()),
+    }
+}
+
+/// Authenticated request for a destination: SigV4 for S3, basic auth for
+/// WebDAV
+fn request(client: &Client, dest: &UploadDestination, method: Method, url: &Url) -> RequestBuilder {
+    let builder = client.request(method.clone(), url.clone());
+    if dest.kind == KIND_S3 {
+        s3_sign_headers(dest, method.as_str(), url, chrono::Utc::now())
+            .into_iter()
+            .fold(builder, |builder, (name, value)| builder.header(name, value))
+    } else if dest.username.is_empty() {
+        builder
+    } else {
+        builder.basic_auth(&dest.username, Some(&dest.password))
+    }
+}
+
+fn object_url(dest: &UploadDestination, filename: &str) -> Result<Url, String> {
+    let mut segments: Vec<&str> = Vec::new();
+    if dest.kind == KIND_S3 {
+        segments.push(&dest.bucket);
+    }
+    segments.extend(dest.prefix.split('/').filter(|s| !s.is_empty()));
+    segments.push(filename);
+
+    let path: Vec<String> = segments.iter().map(|s| uri_encode(s)).collect();
+    let base = dest.endpoint.trim_end_matches('/');
+    Url::parse(&format!("{}/{}", base, path.join("/"))).map_err(|_| "Invalid upload endpoint".to_string())
+}
+
+/// MKCOL each folder of the prefix; existing ones answer 405, which is fine
+async fn create_webdav_folders(client: &Client, dest: &UploadDestination) {
+    let Ok(mkcol) = Method::from_bytes(b"MKCOL") else {
+        return;
+    };
+    let mut folder = dest.endpoint.trim_end_matches('/').to_string();
+    for segment in dest.prefix.split('/').filter(|s| !s.is_empty()) {
+        folder = format!("{}/{}", folder, uri_encode(segment));
+        if let Ok(url) = Url::parse(&format!("{}/", folder)) {
+            request(client, dest, mkcol.clone(), &url).send().await.ok();
+        }
+    }
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters, as SigV4
+/// requires for path segments
+fn uri_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes()).as_ref().to_vec()
+}
+
+/// AWS Signature Version 4 headers for a request without a query string.
+/// The body is sent as UNSIGNED-PAYLOAD so large files can stream.
+fn s3_sign_headers(
+    dest: &UploadDestination,
+    method: &str,
+    url: &Url,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Vec<(String, String)> {
+    let region = if dest.region.is_empty() { S3_DEFAULT_REGION } else { &dest.region };
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+
+    let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+    let canonical_request = format!(
+        "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+        method,
+        url.path(),
+        host,
+        S3_UNSIGNED_PAYLOAD,
+        amz_date,
+        signed_headers,
+        S3_UNSIGNED_PAYLOAD
+    );
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(digest::digest(&digest::SHA256, canonical_request.as_bytes()))
+    );
+
+    let key = [date.as_str(), region, "s3", "aws4_request"]
+        .iter()
+        .fold(format!("AWS4{}", dest.password).into_bytes(), |key, part| hmac_sha256(&key, part));
+    let signature = hex::encode(hmac_sha256(&key, &string_to_sign));
+
+    vec![
+        ("x-amz-date".to_string(), amz_date),
+        ("x-amz-content-sha256".to_string(), S3_UNSIGNED_PAYLOAD.to_string()),
+        (
+            "authorization".to_string(),
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                dest.username, scope, signed_headers, signature
+            ),
+        ),
+    ]
+}
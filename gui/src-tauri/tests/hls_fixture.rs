@@ -0,0 +1,615 @@
+//! Downloaders against local fixture servers: the joined .ts must match
+//! the source segments byte for byte.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use cbc::cipher::{block_padding::Pkcs7, BlockEncryptMut, KeyIvInit};
+use gui_lib::downloader::dns::{self, NetworkConfig};
+use gui_lib::downloader::probe;
+use gui_lib::downloader::hls::{DirectDownloader, HlsDownloader};
+use gui_lib::downloader::segment_cache::SegmentCache;
+use gui_lib::downloader::watchdog::Timeouts;
+use gui_lib::downloader::{build_video_info, is_ad_url, DownloaderError, VideoSource};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+const KEY: [u8; 16] = *b"0123456789abcdef";
+const OTHER_KEY: [u8; 16] = *b"fedcba9876543210";
+
+/// AES-128-CBC with PKCS#7 padding, as HLS encrypts segments
+fn encrypt_cbc(key: &[u8; 16], iv: &[u8; 16], data: &[u8]) -> Vec<u8> {
+    cbc::Encryptor::<aes::Aes128>::new(key.into(), iv.into()).encrypt_padded_vec_mut::<Pkcs7>(data)
+}
+
+// Private addresses are refused in URLs, so fixtures are reached through a
+// public-looking name pinned to loopback
+const FIXTURE_HOST: &str = "fixture.test";
+
+async fn listen() -> (TcpListener, String) {
+    let overrides = BTreeMap::from([(FIXTURE_HOST.to_string(), "127.0.0.1".to_string())]);
+    dns::set_network_config(NetworkConfig::new("", &overrides, dns::IP_ANY));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}:{}", FIXTURE_HOST, listener.local_addr().unwrap().port());
+    (listener, base)
+}
+
+/// Minimal HTTP/1.1 server for fixed paths, with optional Range support
+struct FixtureServer {
+    base: String,
+    files: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+}
+
+impl FixtureServer {
+    async fn start(files: HashMap<String, Vec<u8>>, honor_ranges: bool) -> Self {
+        let (listener, base) = listen().await;
+        let files = Arc::new(Mutex::new(files));
+        let served = files.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    return;
+                };
+                let files = served.clone();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0u8; 1024];
+                    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match socket.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+
+                    let request = String::from_utf8_lossy(&request).to_string();
+                    let path = request.split_whitespace().nth(1).unwrap_or("/").to_string();
+                    let range = request
+                        .lines()
+                        .find_map(|line| line.to_lowercase().strip_prefix("range: bytes=").map(str::to_string))
+                        .and_then(|r| {
+                            let (start, end) = r.trim().split_once('-')?;
+                            Some((start.parse::<usize>().ok()?, end.parse::<usize>().ok()?))
+                        });
+
+                    let file = files.lock().unwrap().get(path.split('?').next().unwrap_or("/")).cloned();
+                    let (status, body) = match file {
+                        None => ("404 Not Found", Vec::new()),
+                        Some(data) => match range {
+                            Some((start, end)) if honor_ranges => {
+                                ("206 Partial Content", data[start..=end.min(data.len() - 1)].to_vec())
+                            }
+                            _ => ("200 OK", data),
+                        },
+                    };
+
+                    let head = format!(
+                        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        status,
+                        body.len()
+                    );
+                    socket.write_all(head.as_bytes()).await.ok();
+                    socket.write_all(&body).await.ok();
+                    socket.shutdown().await.ok();
+                });
+            }
+        });
+
+        Self { base, files }
+    }
+
+    fn insert(&self, path: &str, data: Vec<u8>) {
+        self.files.lock().unwrap().insert(path.to_string(), data);
+    }
+
+    fn remove(&self, path: &str) {
+        self.files.lock().unwrap().remove(path);
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base, path)
+    }
+}
+
+/// Distinct, recognisable segment payloads (not block aligned on purpose)
+fn segment(index: usize, len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + index * 31) as u8).collect()
+}
+
+fn media_playlist(lines: &[String]) -> Vec<u8> {
+    let mut playlist = String::from("#EXTM3U\n#EXT-X-VERSION:4\n#EXT-X-TARGETDURATION:4\n");
+    for line in lines {
+        playlist.push_str(line);
+        playlist.push('\n');
+    }
+    playlist.push_str("#EXT-X-ENDLIST\n");
+    playlist.into_bytes()
+}
+
+async fn download(server: &FixtureServer, playlist: &str) -> (tempfile::TempDir, Result<PathBuf, DownloaderError>) {
+    let dir = tempfile::tempdir().unwrap();
+    let result = HlsDownloader::new(None)
+        .with_deferred_conversion(true)
+        .download(&server.url(playlist), &dir.path().join("video"), |_, _| {})
+        .await;
+    (dir, result)
+}
+
+async fn download_bytes(server: &FixtureServer, playlist: &str) -> Vec<u8> {
+    let (_dir, result) = download(server, playlist).await;
+    let path = result.expect("download failed");
+    assert_eq!(path.extension().unwrap(), "ts");
+    std::fs::read(path).unwrap()
+}
+
+#[tokio::test]
+async fn media_playlist_is_joined_in_order() {
+    let segments: Vec<Vec<u8>> = (0..12).map(|i| segment(i, 1000 + i * 97)).collect();
+    let mut files = HashMap::new();
+    let mut lines = Vec::new();
+    for (i, data) in segments.iter().enumerate() {
+        files.insert(format!("/seg{}.ts", i), data.clone());
+        lines.push("#EXTINF:4.0,".to_string());
+        lines.push(format!("seg{}.ts", i));
+    }
+    files.insert("/media.m3u8".to_string(), media_playlist(&lines));
+
+    let server = FixtureServer::start(files, true).await;
+    assert_eq!(download_bytes(&server, "/media.m3u8").await, segments.concat());
+}
+
+#[tokio::test]
+async fn master_playlist_uses_the_highest_bandwidth_variant() {
+    let low = segment(1, 500);
+    let high = segment(2, 900);
+    let mut files = HashMap::new();
+    files.insert("/low/0.ts".to_string(), low);
+    files.insert("/high/0.ts".to_string(), high.clone());
+    files.insert("/low/index.m3u8".to_string(), media_playlist(&["#EXTINF:4.0,".into(), "0.ts".into()]));
+    files.insert("/high/index.m3u8".to_string(), media_playlist(&["#EXTINF:4.0,".into(), "0.ts".into()]));
+    files.insert(
+        "/master.m3u8".to_string(),
+        b"#EXTM3U\n\
+          #EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360\nlow/index.m3u8\n\
+          #EXT-X-STREAM-INF:BANDWIDTH=2800000,RESOLUTION=1280x720\nhigh/index.m3u8\n"
+            .to_vec(),
+    );
+
+    let server = FixtureServer::start(files, true).await;
+    assert_eq!(download_bytes(&server, "/master.m3u8").await, high);
+}
+
+#[tokio::test]
+async fn aes128_segments_are_decrypted() {
+    let segments: Vec<Vec<u8>> = (0..5).map(|i| segment(i, 700 + i * 13)).collect();
+    let media_sequence = 40u64;
+    let explicit_iv: [u8; 16] = *b"ivivivivivivivi!";
+    let sequence_iv = |index: usize| ((media_sequence + index as u64) as u128).to_be_bytes();
+
+    let mut files = HashMap::new();
+    files.insert("/key.bin".to_string(), KEY.to_vec());
+    files.insert("/key2.bin".to_string(), OTHER_KEY.to_vec());
+    // 0-1: explicit IV, 2: IV from the media sequence, 3: rotated key,
+    // 4: unencrypted after METHOD=NONE
+    files.insert("/0.ts".to_string(), encrypt_cbc(&KEY, &explicit_iv, &segments[0]));
+    files.insert("/1.ts".to_string(), encrypt_cbc(&KEY, &explicit_iv, &segments[1]));
+    files.insert("/2.ts".to_string(), encrypt_cbc(&KEY, &sequence_iv(2), &segments[2]));
+    files.insert("/3.ts".to_string(), encrypt_cbc(&OTHER_KEY, &sequence_iv(3), &segments[3]));
+    files.insert("/4.ts".to_string(), segments[4].clone());
+
+    let lines: Vec<String> = vec![
+        format!("#EXT-X-MEDIA-SEQUENCE:{}", media_sequence),
+        format!("#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\",IV=0x{}", hex::encode(explicit_iv)),
+        "#EXTINF:4.0,".into(),
+        "0.ts".into(),
+        "#EXTINF:4.0,".into(),
+        "1.ts".into(),
+        "#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\"".into(),
+        "#EXTINF:4.0,".into(),
+        "2.ts".into(),
+        "#EXT-X-KEY:METHOD=AES-128,URI=\"key2.bin\"".into(),
+        "#EXTINF:4.0,".into(),
+        "3.ts".into(),
+        "#EXT-X-KEY:METHOD=NONE".into(),
+        "#EXTINF:4.0,".into(),
+        "4.ts".into(),
+    ];
+    files.insert("/media.m3u8".to_string(), media_playlist(&lines));
+
+    let server = FixtureServer::start(files, true).await;
+    assert_eq!(download_bytes(&server, "/media.m3u8").await, segments.concat());
+}
+
+fn byte_range_fixture() -> (HashMap<String, Vec<u8>>, Vec<u8>) {
+    let whole = segment(9, 6000);
+    let mut files = HashMap::new();
+    files.insert("/all.ts".to_string(), whole.clone());
+    // Explicit offsets, then ranges continuing from the previous one
+    let lines: Vec<String> = vec![
+        "#EXTINF:4.0,".into(),
+        "#EXT-X-BYTERANGE:1500@0".into(),
+        "all.ts".into(),
+        "#EXTINF:4.0,".into(),
+        "#EXT-X-BYTERANGE:2000@1500".into(),
+        "all.ts".into(),
+        "#EXTINF:4.0,".into(),
+        "#EXT-X-BYTERANGE:1000".into(),
+        "all.ts".into(),
+        "#EXTINF:4.0,".into(),
+        "#EXT-X-BYTERANGE:1500".into(),
+        "all.ts".into(),
+    ];
+    files.insert("/media.m3u8".to_string(), media_playlist(&lines));
+    (files, whole)
+}
+
+#[tokio::test]
+async fn byte_range_segments_are_fetched_by_range() {
+    let (files, whole) = byte_range_fixture();
+    let server = FixtureServer::start(files, true).await;
+    assert_eq!(download_bytes(&server, "/media.m3u8").await, whole);
+}
+
+#[tokio::test]
+async fn byte_ranges_work_when_the_server_ignores_range() {
+    let (files, whole) = byte_range_fixture();
+    let server = FixtureServer::start(files, false).await;
+    assert_eq!(download_bytes(&server, "/media.m3u8").await, whole);
+}
+
+#[tokio::test]
+async fn repeated_download_reuses_cached_segments() {
+    let segments: Vec<Vec<u8>> = (0..4).map(|i| segment(i, 800 + i * 11)).collect();
+    let playlist = |token: &str| {
+        let lines: Vec<String> = (0..segments.len())
+            .flat_map(|i| ["#EXTINF:4.0,".to_string(), format!("seg{}.ts?token={}", i, token)])
+            .collect();
+        media_playlist(&lines)
+    };
+    let server = FixtureServer::start(HashMap::new(), true).await;
+    for (i, data) in segments.iter().enumerate() {
+        server.insert(&format!("/seg{}.ts", i), data.clone());
+    }
+    server.insert("/media.m3u8", playlist("first"));
+
+    let cache_dir = tempfile::tempdir().unwrap();
+    let cache = Arc::new(SegmentCache::new(cache_dir.path().to_path_buf(), 64));
+    let fetch = || async {
+        let dir = tempfile::tempdir().unwrap();
+        let path = HlsDownloader::new(None)
+            .with_deferred_conversion(true)
+            .with_segment_cache(Some(cache.clone()))
+            .download(&server.url("/media.m3u8"), &dir.path().join("video"), |_, _| {})
+            .await
+            .expect("download failed");
+        std::fs::read(path).unwrap()
+    };
+    assert_eq!(fetch().await, segments.concat());
+    assert_eq!(cache.stats().segments, segments.len());
+
+    // Segments are gone from the server and the auth token changed
+    for i in 0..segments.len() {
+        server.remove(&format!("/seg{}.ts", i));
+    }
+    server.insert("/media.m3u8", playlist("second"));
+    assert_eq!(fetch().await, segments.concat());
+
+    assert_eq!(cache.purge().unwrap(), segments.iter().map(|s| s.len() as u64).sum::<u64>());
+    assert_eq!(cache.stats().segments, 0);
+}
+
+#[tokio::test]
+async fn passthrough_keeps_fragmented_mp4_with_its_init_section() {
+    let mut init = b"\0\0\0\x18ftypiso6\0\0\0\0iso6dash".to_vec();
+    init.extend(segment(7, 200));
+    let segments: Vec<Vec<u8>> = (0..3).map(|i| segment(i, 500 + i * 3)).collect();
+    let mut files = HashMap::new();
+    files.insert("/init.mp4".to_string(), init.clone());
+    let mut lines = vec!["#EXT-X-MAP:URI=\"init.mp4\"".to_string()];
+    for (i, data) in segments.iter().enumerate() {
+        files.insert(format!("/{}.m4s", i), data.clone());
+        lines.push("#EXTINF:4.0,".to_string());
+        lines.push(format!("{}.m4s", i));
+    }
+    files.insert("/media.m3u8".to_string(), media_playlist(&lines));
+
+    let server = FixtureServer::start(files, true).await;
+    let dir = tempfile::tempdir().unwrap();
+    let path = HlsDownloader::new(None)
+        .with_passthrough(true)
+        .download(&server.url("/media.m3u8"), &dir.path().join("video"), |_, _| {})
+        .await
+        .expect("download failed");
+
+    assert_eq!(path.extension().unwrap(), "mp4");
+    assert_eq!(std::fs::read(path).unwrap(), [init, segments.concat()].concat());
+}
+
+#[tokio::test]
+async fn missing_segment_fails_the_download() {
+    let mut files = HashMap::new();
+    files.insert("/0.ts".to_string(), segment(0, 100));
+    files.insert(
+        "/media.m3u8".to_string(),
+        media_playlist(&["#EXTINF:4.0,".into(), "0.ts".into(), "#EXTINF:4.0,".into(), "gone.ts".into()]),
+    );
+
+    let server = FixtureServer::start(files, true).await;
+    let (_dir, result) = download(&server, "/media.m3u8").await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn html_error_page_segment_is_rejected() {
+    let mut files = HashMap::new();
+    files.insert("/0.ts".to_string(), segment(0, 100));
+    files.insert("/1.ts".to_string(), b"\n<!DOCTYPE html><html><body>Link expired</body></html>".to_vec());
+    files.insert(
+        "/media.m3u8".to_string(),
+        media_playlist(&["#EXTINF:4.0,".into(), "0.ts".into(), "#EXTINF:4.0,".into(), "1.ts".into()]),
+    );
+
+    let server = FixtureServer::start(files, true).await;
+    let (_dir, result) = download(&server, "/media.m3u8").await;
+    assert!(matches!(result, Err(DownloaderError::SegmentExpired(_))));
+}
+
+/// Answers every request with headers promising 1000 bytes, then goes quiet
+async fn start_stalling_server() -> String {
+    let (listener, base) = listen().await;
+    tokio::spawn(async move {
+        loop {
+            let Ok((mut socket, _)) = listener.accept().await else {
+                return;
+            };
+            tokio::spawn(async move {
+                let mut buf = [0u8; 1024];
+                let _ = socket.read(&mut buf).await;
+                socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 1000\r\n\r\n0123").await.ok();
+                tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+            });
+        }
+    });
+    base
+}
+
+#[tokio::test]
+async fn stalled_segment_fails_with_a_timeout() {
+    let stalling = start_stalling_server().await;
+    let mut files = HashMap::new();
+    files.insert("/0.ts".to_string(), segment(0, 100));
+    files.insert(
+        "/media.m3u8".to_string(),
+        media_playlist(&["#EXTINF:4.0,".into(), "0.ts".into(), "#EXTINF:4.0,".into(), format!("{}/1.ts", stalling)]),
+    );
+    let server = FixtureServer::start(files, true).await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let started = std::time::Instant::now();
+    let result = HlsDownloader::new(None)
+        .with_deferred_conversion(true)
+        .with_timeouts(Timeouts::from_secs(0, 0, 1))
+        .download(&server.url("/media.m3u8"), &dir.path().join("video"), |_, _| {})
+        .await;
+    assert!(matches!(result, Err(DownloaderError::Timeout(_))));
+    // Three attempts, each stopped by the one-second stall limit
+    assert!(started.elapsed() < std::time::Duration::from_secs(20));
+}
+
+#[tokio::test]
+async fn stalled_direct_download_fails_with_a_timeout() {
+    let stalling = start_stalling_server().await;
+    let dir = tempfile::tempdir().unwrap();
+    let result = DirectDownloader::new(None)
+        .with_timeouts(Timeouts::from_secs(0, 0, 1))
+        .download(&format!("{}/video.mp4", stalling), &dir.path().join("video"), |_, _| {})
+        .await;
+    assert!(matches!(result, Err(DownloaderError::Timeout(_))));
+}
+
+/// Sends the first `cut` bytes of `video` and stalls; a Range request
+/// gets the rest
+async fn start_flaky_server(video: Vec<u8>, cut: usize) -> String {
+    let (listener, base) = listen().await;
+    tokio::spawn(async move {
+        loop {
+            let Ok((mut socket, _)) = listener.accept().await else {
+                return;
+            };
+            let video = video.clone();
+            tokio::spawn(async move {
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match socket.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let request = String::from_utf8_lossy(&request).to_lowercase();
+                let start = request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("range: bytes="))
+                    .and_then(|r| r.trim().trim_end_matches('-').parse::<usize>().ok());
+                match start {
+                    None => {
+                        let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", video.len());
+                        socket.write_all(head.as_bytes()).await.ok();
+                        socket.write_all(&video[..cut]).await.ok();
+                        tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+                    }
+                    Some(start) => {
+                        let head = format!(
+                            "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\nConnection: close\r\n\r\n",
+                            video.len() - start,
+                            start,
+                            video.len() - 1,
+                            video.len()
+                        );
+                        socket.write_all(head.as_bytes()).await.ok();
+                        socket.write_all(&video[start..]).await.ok();
+                        socket.shutdown().await.ok();
+                    }
+                }
+            });
+        }
+    });
+    base
+}
+
+#[tokio::test]
+async fn stalled_direct_download_reconnects_with_range() {
+    let video = segment(3, 5000);
+    let server = start_flaky_server(video.clone(), 1200).await;
+    let dir = tempfile::tempdir().unwrap();
+    let messages = Arc::new(Mutex::new(Vec::new()));
+    let seen = messages.clone();
+
+    let path = DirectDownloader::new(None)
+        .with_timeouts(Timeouts::from_secs(0, 0, 1))
+        .download(&format!("{}/video.mp4", server), &dir.path().join("video"), move |_, message| {
+            seen.lock().unwrap().push(message)
+        })
+        .await
+        .expect("download failed");
+
+    assert_eq!(std::fs::read(path).unwrap(), video);
+    assert!(messages.lock().unwrap().iter().any(|m| m == "กำลังเชื่อมต่อใหม่..."));
+}
+
+/// A token gateway in front of a hotlink-protected file: /watch sets a
+/// session cookie and redirects, /cdn/video.mp4 answers 403 unless the
+/// cookie and the embed page's Referer/Origin pair come along
+async fn start_gateway(video: Vec<u8>, embed: &'static str) -> String {
+    let (listener, base) = listen().await;
+
+    tokio::spawn(async move {
+        loop {
+            let Ok((mut socket, _)) = listener.accept().await else {
+                return;
+            };
+            let video = video.clone();
+            tokio::spawn(async move {
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match socket.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let request = String::from_utf8_lossy(&request).to_lowercase();
+                let path = request.split_whitespace().nth(1).unwrap_or("/").to_string();
+                let has = |header: &str| request.lines().any(|line| line.trim() == header);
+
+                let (head, body) = if path == "/watch" {
+                    ("302 Found\r\nSet-Cookie: token=abc; Path=/\r\nLocation: /cdn/video.mp4".to_string(), Vec::new())
+                } else if path == "/cdn/video.mp4"
+                    && has("cookie: token=abc")
+                    && has(&format!("referer: {}", embed))
+                    && has("origin: https://player.example")
+                {
+                    ("200 OK".to_string(), video)
+                } else {
+                    ("403 Forbidden".to_string(), Vec::new())
+                };
+
+                let head = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", head, body.len());
+                socket.write_all(head.as_bytes()).await.ok();
+                socket.write_all(&body).await.ok();
+                socket.shutdown().await.ok();
+            });
+        }
+    });
+
+    base
+}
+
+#[tokio::test]
+async fn direct_download_follows_token_gateway_and_retries_with_embed_referer() {
+    let embed = "https://player.example/embed/1";
+    let mut video = b"\0\0\0\x18ftypisom\0\0\0\0isomiso2".to_vec();
+    video.extend(segment(0, 5000));
+    let base = start_gateway(video.clone(), embed).await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = DirectDownloader::new(Some("https://site.example/episode-1".to_string()))
+        .with_fallback_referer(Some(embed.to_string()))
+        .download(&format!("{}/watch", base), &dir.path().join("video"), |_, _| {})
+        .await
+        .unwrap();
+
+    assert_eq!(path.extension().unwrap(), "mp4");
+    assert_eq!(std::fs::read(path).unwrap(), video);
+}
+
+fn hls_source(server: &FixtureServer, path: &str) -> VideoSource {
+    VideoSource { url: server.url(path), quality: "auto".to_string(), source_type: "hls".to_string(), ..Default::default() }
+}
+
+#[tokio::test]
+async fn short_preroll_playlists_are_dropped_but_never_every_source() {
+    let episode: Vec<String> = (0..20).flat_map(|i| ["#EXTINF:4.0,".to_string(), format!("{}.ts", i)]).collect();
+    let preroll: Vec<String> = (0..4).flat_map(|i| ["#EXTINF:4.0,".to_string(), format!("ad{}.ts", i)]).collect();
+    let mut files = HashMap::new();
+    files.insert("/episode.m3u8".to_string(), media_playlist(&episode));
+    files.insert("/preroll.m3u8".to_string(), media_playlist(&preroll));
+    files.insert("/preroll2.m3u8".to_string(), media_playlist(&preroll));
+    let server = FixtureServer::start(files, true).await;
+
+    let sources = [hls_source(&server, "/preroll.m3u8"), hls_source(&server, "/episode.m3u8")];
+    let mut info = build_video_info("https://example.com/ep1", String::new(), String::new(), &sources);
+    probe::drop_ad_streams(&mut info, None, &[]).await;
+    assert_eq!(info.sources.len(), 1);
+    assert!(info.sources[0].url.ends_with("/episode.m3u8"));
+
+    let sources = [hls_source(&server, "/preroll.m3u8"), hls_source(&server, "/preroll2.m3u8")];
+    let mut info = build_video_info("https://example.com/ep1", String::new(), String::new(), &sources);
+    probe::drop_ad_streams(&mut info, None, &[]).await;
+    assert_eq!(info.sources.len(), 2);
+
+    assert!(is_ad_url("https://pubads.g.doubleclick.net/vast/preroll.m3u8"));
+    assert!(!is_ad_url("https://notdoubleclick.net.example/video.m3u8"));
+}
+
+#[tokio::test]
+async fn sources_are_labelled_with_codec_container_and_bitrate() {
+    let mut files = HashMap::new();
+    files.insert("/high/index.m3u8".to_string(), media_playlist(&["#EXTINF:4.0,".into(), "0.ts".into()]));
+    files.insert(
+        "/master.m3u8".to_string(),
+        b"#EXTM3U\n\
+          #EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,CODECS=\"avc1.4d401e,mp4a.40.2\"\nlow/index.m3u8\n\
+          #EXT-X-STREAM-INF:BANDWIDTH=2800000,RESOLUTION=1280x720,CODECS=\"mp4a.40.2,hvc1.1.6.L93.B0\"\nhigh/index.m3u8\n"
+            .to_vec(),
+    );
+    let server = FixtureServer::start(files, true).await;
+
+    let mut info = build_video_info("https://example.com/ep1", String::new(), String::new(), &[hls_source(&server, "/master.m3u8")]);
+    probe::label_sources(&mut info, None, &[]).await;
+
+    let source = &info.sources[0];
+    assert_eq!(source.quality, "720p");
+    assert_eq!(source.codec.as_deref(), Some("h265"));
+    assert_eq!(source.container.as_deref(), Some("ts"));
+    assert_eq!(source.bandwidth, Some(2_800_000));
+    assert_eq!(probe::normalize_codec("mp4a.40.2"), None);
+}
+
+#[tokio::test]
+async fn drm_playlist_is_rejected() {
+    let mut files = HashMap::new();
+    files.insert(
+        "/media.m3u8".to_string(),
+        media_playlist(&[
+            "#EXT-X-KEY:METHOD=SAMPLE-AES,URI=\"skd://key\",KEYFORMAT=\"com.apple.streamingkeydelivery\"".into(),
+            "#EXTINF:4.0,".into(),
+            "0.ts".into(),
+        ]),
+    );
+
+    let server = FixtureServer::start(files, true).await;
+    let (_dir, result) = download(&server, "/media.m3u8").await;
+    assert!(matches!(result, Err(DownloaderError::DrmProtected(_))));
+}
@@ -23,7 +23,11 @@ import {
   List,
   ChevronUp,
   RotateCcw,
+  Minimize2,
+  Subtitles,
+  Undo2,
 } from "lucide-react";
+import "./App.css";
 
 // Supported site patterns for URL validation
 const SUPPORTED_SITES = [
@@ -85,7 +89,22 @@ const formatEta = (seconds: number | null): string => {
   return `${minutes}:${secs.toString().padStart(2, "0")}`;
 };
 
-import "./App.css";
+// Events come wrapped as { event_version, payload }; a different version
+// means the backend changed a payload shape this build doesn't know
+const EVENT_VERSION = 1;
+
+interface EventEnvelope<T> {
+  event_version: number;
+  payload: T;
+}
+
+const listenEvent = <T,>(name: string, handler: (event: { payload: T }) => void) =>
+  listen<EventEnvelope<T>>(name, (event) => {
+    if (event.payload.event_version !== EVENT_VERSION) {
+      console.warn(`${name}: event version ${event.payload.event_version}, expected ${EVENT_VERSION}`);
+    }
+    handler({ payload: event.payload.payload });
+  });
 
 interface DownloadProgress {
   status: string;
@@ -103,7 +122,33 @@ interface VideoInfo {
   thumbnail: string;
   duration: string;
   qualities: string[];
-  sources: { url: string; quality: string; type: string }[];
+  sources: {
+    url: string;
+    quality: string;
+    type: string;
+    codec: string | null;
+    container: string | null;
+    bandwidth: number | null;
+    size: number | null;
+    duration: number | null;
+    latency_ms: number | null;
+    host: string | null;
+    origin: SourceOrigin;
+  }[];
+  audio_tracks?: AudioTrack[];
+}
+
+interface SourceOrigin {
+  page_url: string | null;
+  embed_url: string | null;
+  embed_host: string | null;
+  extracted_at: string | null;
+}
+
+interface AudioTrack {
+  language: string;
+  name: string;
+  default: boolean;
 }
 
 interface HistoryItem {
@@ -116,6 +161,9 @@ interface HistoryItem {
   downloaded_at: string;
   file_path: string;
   file_size: number | null;
+  original_size?: number | null;
+  compressed_with?: string | null;
+  content_hash?: string | null;
 }
 
 interface LogEntry {
@@ -125,6 +173,18 @@ interface LogEntry {
 }
 
 // Queue types
+interface ErrorHelp {
+  code: string;
+  message: string;
+  hint: string;
+}
+
+interface RecoveryReport {
+  downloads: { id: string; title: string; checkpoint: string | null; partial_files: string[] }[];
+  orphaned_files: { path: string; size: number }[];
+  orphaned_bytes: number;
+}
+
 interface QueueItem {
   id: string;
   url: string;
@@ -133,18 +193,20 @@ interface QueueItem {
   quality: string;
   output_dir: string;
   output_filename: string;
-  status: "Pending" | "Downloading" | "Paused" | "Completed" | "Failed" | "Cancelled";
+  status: "Pending" | "Downloading" | "Converting" | "Processing" | "Paused" | "Completed" | "Failed" | "Cancelled";
   progress: number;
   speed: string;
   eta: string;
   error: string | null;
+  error_help?: ErrorHelp | null;
   file_path: string | null;
   added_at: string;
+  origin?: SourceOrigin | null;
 }
 
 interface QueueProgress {
   id: string;
-  status: "Pending" | "Downloading" | "Paused" | "Completed" | "Failed" | "Cancelled";
+  status: "Pending" | "Downloading" | "Converting" | "Processing" | "Paused" | "Completed" | "Failed" | "Cancelled";
   progress: number;
   speed: string;
   eta: string;
@@ -156,15 +218,52 @@ interface QueueProgress {
 interface AppSettings {
   default_download_dir: string;
   default_quality: string;
+  auto_quality_by_speed: boolean;
+  check_sources: boolean;
+  allow_quality_fallback: boolean;
   max_concurrent_downloads: number;
   auto_start_queue: boolean;
   show_notifications: boolean;
   minimize_to_tray: boolean;
   theme: string;
+  show_browser: boolean;
+  browser_backend: string;
+  page_load_wait_ms: number;
+  iframe_wait_ms: number;
+  playback_wait_ms: number;
+  permanent_delete: boolean;
+  max_file_size_mb: number;
+  write_checksums: boolean;
+  auto_clean_temp_files: boolean;
+  stale_source_hours: number;
+  series_max_pages: number;
 }
 
 type TabType = "download" | "queue" | "history" | "settings";
 
+// Thumbnail from the local cache, falling back to the remote URL
+function CachedThumbnail({ id, src, alt }: { id: string; src: string; alt: string }) {
+  const [url, setUrl] = useState(src);
+
+  useEffect(() => {
+    let objectUrl: string | null = null;
+    let cancelled = false;
+    invoke<ArrayBuffer>("get_thumbnail", { id })
+      .then((bytes) => {
+        if (cancelled) return;
+        objectUrl = URL.createObjectURL(new Blob([bytes]));
+        setUrl(objectUrl);
+      })
+      .catch(() => setUrl(src));
+    return () => {
+      cancelled = true;
+      if (objectUrl) URL.revokeObjectURL(objectUrl);
+    };
+  }, [id, src]);
+
+  return <img src={url} alt={alt} />;
+}
+
 function App() {
   const [activeTab, setActiveTab] = useState<TabType>("download");
   const [url, setUrl] = useState("");
@@ -172,12 +271,15 @@ function App() {
   const [filename, setFilename] = useState("");
   const [quality, setQuality] = useState("auto");
   const [availableQualities, setAvailableQualities] = useState<string[]>(["auto"]);
+  const [audioTracks, setAudioTracks] = useState("default");
   const [isDownloading, setIsDownloading] = useState(false);
   const [isFetchingInfo, setIsFetchingInfo] = useState(false);
   const [progress, setProgress] = useState(0);
   const [status, setStatus] = useState<"idle" | "downloading" | "completed" | "error">("idle");
   const [logs, setLogs] = useState<LogEntry[]>([]);
   const [videoInfo, setVideoInfo] = useState<VideoInfo | null>(null);
+  // Frame captured from each source (by URL), to spot ad mirrors
+  const [sourcePreviews, setSourcePreviews] = useState<Record<string, string>>({});
   const [history, setHistory] = useState<HistoryItem[]>([]);
 
   // Queue state
@@ -188,14 +290,31 @@ function App() {
   const [settings, setSettings] = useState<AppSettings>({
     default_download_dir: "",
     default_quality: "auto",
+    auto_quality_by_speed: true,
+    check_sources: false,
+    allow_quality_fallback: true,
     max_concurrent_downloads: 2,
     auto_start_queue: true,
     show_notifications: true,
     minimize_to_tray: false,
     theme: "dark",
+    show_browser: false,
+    browser_backend: "chromium",
+    page_load_wait_ms: 3000,
+    iframe_wait_ms: 5000,
+    playback_wait_ms: 5000,
+    permanent_delete: false,
+    max_file_size_mb: 0,
+    write_checksums: false,
+    auto_clean_temp_files: true,
+    stale_source_hours: 6,
+    series_max_pages: 5,
   });
   const [showQualityDropdown, setShowQualityDropdown] = useState(false);
   const [clipboardDetected, setClipboardDetected] = useState(false);
+  // Shown for as long as the backend keeps the removal (UNDO_GRACE)
+  const [undoMessage, setUndoMessage] = useState<string | null>(null);
+  const undoTimer = useRef<number | null>(null);
   const [, setUrlSource] = useState<"manual" | "clipboard" | null>(null);
 
   // Speed & ETA tracking
@@ -205,6 +324,10 @@ function App() {
   const [eta, setEta] = useState<number | null>(null); // seconds remaining
   const downloadStartTime = useRef<number | null>(null);
   const lastProgressUpdate = useRef<{ time: number; bytes: number } | null>(null);
+  // Id of the newest get_video_info call; older ones finishing late are ignored
+  const latestInfoRequest = useRef<string | null>(null);
+  // Quality picked last time, preselected when a new video offers it
+  const lastQuality = useRef<string | null>(null);
 
   const logEndRef = useRef<HTMLDivElement>(null);
   const urlInputRef = useRef<HTMLInputElement>(null);
@@ -308,13 +431,17 @@ function App() {
   }, [url, videoInfo, isDownloading, isFetchingInfo, showQualityDropdown, outputDir, checkClipboard]);
 
   useEffect(() => {
-    invoke<string>("get_download_dir").then(setOutputDir).catch(console.error);
+    // The last picked folder wins over the configured default
+    invoke<string>("get_download_dir")
+      .then(setOutputDir)
+      .catch(console.error)
+      .then(loadSettings)
+      .then(loadUiState);
     loadHistory();
-    loadSettings();
     loadQueue();
 
     // Listen for queue progress updates
-    const unlistenQueue = listen<QueueProgress>("queue-progress", (event) => {
+    const unlistenQueue = listenEvent<QueueProgress>("queue-progress", (event) => {
       const data = event.payload;
       setQueue(prev => prev.map(item =>
         item.id === data.id
@@ -336,7 +463,7 @@ function App() {
       }
     });
 
-    const unlisten = listen<DownloadProgress>("download-progress", (event) => {
+    const unlisten = listenEvent<DownloadProgress>("download-progress", (event) => {
       const data = event.payload;
       const now = Date.now();
 
@@ -381,6 +508,13 @@ function App() {
         if (Math.floor(data.progress) % 10 === 0) {
           addLog("progress", data.message);
         }
+      } else if (data.status === "converting") {
+        setProgress(data.progress);
+        setDownloadSpeed(0);
+        setEta(null);
+        if (Math.floor(data.progress) % 25 === 0) {
+          addLog("progress", data.message);
+        }
       } else if (data.status === "completed") {
         setProgress(100);
         setStatus("completed");
@@ -409,12 +543,105 @@ function App() {
         setDownloadSpeed(0);
         setEta(null);
         addLog("error", data.message);
+        invoke<ErrorHelp>("explain_error", { message: data.message }).then((help) => {
+          if (help.code !== "unknown") addLog("info", `${help.message} — ${help.hint}`);
+        });
+      }
+    });
+
+    // The backend paused or resumed the queue (metered connection, ...)
+    const unlistenAutoPause = listenEvent<{ reason: string; paused: boolean; count: number }>("queue-auto-paused", (event) => {
+      const { reason, paused, count } = event.payload;
+      addLog("info", paused ? `Queue paused (${reason}): ${count} item(s)` : `Queue resumed after ${reason}: ${count} item(s)`);
+      loadQueue();
+    });
+
+    // A download gave up on its quality and moved on to a lower one
+    const unlistenQualityFallback = listenEvent<{ id: string | null; title: string; from: string; to: string; reason: string }>("quality-fallback", (event) => {
+      const { title, from, to, reason } = event.payload;
+      addLog("info", `${title}: ${from} failed (${reason}), downloading ${to} instead`);
+      showNotification("Quality Lowered", `${title}: ${from} → ${to}`);
+    });
+
+    // The last queued download ended and nothing is waiting to start
+    const unlistenQueueFinished = listenEvent<{ succeeded: number; failed: number; total_bytes: number; elapsed: string }>("queue-finished", (event) => {
+      const { succeeded, failed, total_bytes, elapsed } = event.payload;
+      const summary = `${succeeded} succeeded, ${failed} failed, ${formatBytes(total_bytes)} in ${elapsed}`;
+      addLog(failed > 0 ? "error" : "success", `Queue finished: ${summary}`);
+      showNotification("Queue Finished", summary);
+    });
+
+    // The periodic sweep removed scratch files of abandoned downloads
+    const unlistenTempCleaned = listenEvent<{ count: number; bytes: number }>("temp-files-cleaned", (event) => {
+      addLog("info", `Removed ${event.payload.count} abandoned temp file(s), ${formatBytes(event.payload.bytes)}`);
+    });
+
+    // Info fetches run beside downloads and report under their own id
+    const unlistenInfoProgress = listenEvent<{ request_id: string; url: string; status: string; message: string }>("video-info-progress", (event) => {
+      const { request_id, status, message } = event.payload;
+      if (request_id === latestInfoRequest.current && status !== "failed") {
+        addLog("info", message);
       }
     });
 
+    // A finished file has the same content as one already in history
+    const unlistenDuplicate = listenEvent<{ file_path: string; existing: HistoryItem }>("duplicate-file", (event) => {
+      const { file_path, existing } = event.payload;
+      addLog("info", `ไฟล์ซ้ำ: ${file_path} มีเนื้อหาเหมือนกับ ${existing.file_path} ที่ดาวน์โหลดไว้แล้ว`);
+    });
+
+    // Parts of a multi-part video were joined into one file
+    const unlistenMerged = listenEvent<{ group_id: string; file_path: string | null; error: string | null }>("queue-multipart-merged", (event) => {
+      const { file_path, error } = event.payload;
+      if (file_path) addLog("success", `รวมไฟล์ทุกตอนแล้ว: ${file_path}`);
+      else addLog("error", `รวมไฟล์ไม่สำเร็จ: ${error}`);
+      loadQueue();
+    });
+
+    // A finished group was packed into one archive
+    const unlistenSeasonPack = listenEvent<{ done: boolean; file_path: string | null; error: string | null }>("season-pack-progress", (event) => {
+      const { done, file_path, error } = event.payload;
+      if (!done) return;
+      if (file_path) addLog("success", `สร้างไฟล์ ZIP แล้ว: ${file_path}`);
+      else addLog("error", `สร้างไฟล์ ZIP ไม่สำเร็จ: ${error}`);
+    });
+
+    // Downloads left mid-way by a crash; the event can fire before this
+    // listener exists, so the report is also fetched once
+    const handleRecovery = async (report: RecoveryReport) => {
+      if (report.downloads.length === 0 && report.orphaned_files.length === 0) return;
+      addLog("info", `Recovered ${report.downloads.length} interrupted download(s), ${report.orphaned_files.length} leftover temp file(s)`);
+      if (report.downloads.length > 0 && window.confirm(`ดาวน์โหลดค้างจากครั้งก่อน ${report.downloads.length} รายการ ต้องการดาวน์โหลดต่อหรือไม่?`)) {
+        await invoke("recovery_resume", { ids: report.downloads.map((d) => d.id) });
+      }
+      if (report.orphaned_bytes > 0 && window.confirm(`ลบไฟล์ชั่วคราวที่ค้างอยู่ (${(report.orphaned_bytes / 1024 / 1024).toFixed(1)} MB) หรือไม่?`)) {
+        await invoke("recovery_cleanup");
+      }
+      loadQueue();
+    };
+    let recoveryHandled = false;
+    const onRecovery = (report: RecoveryReport) => {
+      if (recoveryHandled) return;
+      recoveryHandled = true;
+      handleRecovery(report);
+    };
+    const unlistenRecovery = listenEvent<RecoveryReport>("recovered-downloads", (event) => onRecovery(event.payload));
+    invoke<RecoveryReport>("get_recovered_downloads").then((report) => {
+      if (report.downloads.length > 0 || report.orphaned_files.length > 0) onRecovery(report);
+    });
+
     return () => {
       unlisten.then((fn) => fn());
       unlistenQueue.then((fn) => fn());
+      unlistenAutoPause.then((fn) => fn());
+      unlistenQualityFallback.then((fn) => fn());
+      unlistenInfoProgress.then((fn) => fn());
+      unlistenQueueFinished.then((fn) => fn());
+      unlistenTempCleaned.then((fn) => fn());
+      unlistenRecovery.then((fn) => fn());
+      unlistenDuplicate.then((fn) => fn());
+      unlistenMerged.then((fn) => fn());
+      unlistenSeasonPack.then((fn) => fn());
     };
   }, []);
 
@@ -435,6 +662,24 @@ function App() {
     }
   };
 
+  // Restore the folder and quality picked last time
+  const loadUiState = async () => {
+    try {
+      const uiState = await invoke<{ last_output_dir: string; last_quality: string }>("get_ui_state");
+      if (uiState.last_output_dir) setOutputDir(uiState.last_output_dir);
+      if (uiState.last_quality) {
+        lastQuality.current = uiState.last_quality;
+        setQuality(uiState.last_quality);
+      }
+    } catch (error) {
+      console.error("Failed to load UI state:", error);
+    }
+  };
+
+  const rememberUiState = (update: { lastOutputDir?: string; lastQuality?: string }) => {
+    invoke("set_ui_state", update).catch(console.error);
+  };
+
   // Save settings to backend
   const saveSettings = async (newSettings: AppSettings) => {
     try {
@@ -471,6 +716,7 @@ function App() {
         quality: quality,
         outputDir: outputDir,
         outputFilename: filename || videoInfo.title?.replace(/[<>:"/\\|?*]/g, "_") + ".mp4" || "video.mp4",
+        options: { audio_tracks: audioTracks },
       });
 
       addLog("success", `Added to queue: ${videoInfo.title}`);
@@ -490,9 +736,28 @@ function App() {
     }
   };
 
+  const offerUndo = (message: string) => {
+    if (undoTimer.current) window.clearTimeout(undoTimer.current);
+    setUndoMessage(message);
+    undoTimer.current = window.setTimeout(() => setUndoMessage(null), 30000);
+  };
+
+  const undoLastAction = async () => {
+    if (undoTimer.current) window.clearTimeout(undoTimer.current);
+    setUndoMessage(null);
+    try {
+      await invoke<string>("undo_last_action");
+      loadQueue();
+      loadHistory();
+    } catch (error) {
+      addLog("error", `Failed to undo: ${error}`);
+    }
+  };
+
   const removeFromQueue = async (id: string) => {
     try {
       await invoke("queue_remove", { id });
+      offerUndo("ลบออกจากคิวแล้ว");
       loadQueue();
     } catch (error) {
       addLog("error", `Failed to remove from queue: ${error}`);
@@ -552,16 +817,8 @@ function App() {
     setIsProcessingQueue(true);
 
     try {
-      // Find pending items
-      const pendingItems = queue.filter(item => item.status === "Pending");
-      const activeCount = queue.filter(item => item.status === "Downloading").length;
-      const availableSlots = settings.max_concurrent_downloads - activeCount;
-
-      // Start downloads for available slots
-      for (let i = 0; i < Math.min(pendingItems.length, availableSlots); i++) {
-        const item = pendingItems[i];
-        await invoke("queue_start_download", { id: item.id });
-      }
+      // The backend scheduler fills free slots and respects group ordering
+      await invoke<string[]>("queue_process");
 
       loadQueue();
     } catch (error) {
@@ -574,7 +831,7 @@ function App() {
   // Auto-process queue when items are added or status changes
   useEffect(() => {
     const pendingCount = queue.filter(item => item.status === "Pending").length;
-    const activeCount = queue.filter(item => item.status === "Downloading").length;
+    const activeCount = queue.filter(item => item.status === "Downloading" || item.status === "Converting").length;
 
     if (pendingCount > 0 && activeCount < settings.max_concurrent_downloads && settings.auto_start_queue) {
       processQueue();
@@ -594,6 +851,18 @@ function App() {
     setLogs((prev) => [...prev, { type, message, timestamp: new Date() }]);
   };
 
+  const previewSource = async (source: VideoInfo["sources"][number]) => {
+    try {
+      const image = await invoke<string>("preview_source", {
+        sourceUrl: source.url,
+        referer: source.origin.page_url ?? videoInfo?.url ?? null,
+      });
+      setSourcePreviews((prev) => ({ ...prev, [source.url]: image }));
+    } catch (error) {
+      addLog("error", `Preview failed (${source.quality}): ${error}`);
+    }
+  };
+
   // Show desktop notification
   const showNotification = async (title: string, body: string) => {
     try {
@@ -640,6 +909,7 @@ function App() {
     });
     if (selected) {
       setOutputDir(selected as string);
+      rememberUiState({ lastOutputDir: selected as string });
     }
   };
 
@@ -649,23 +919,32 @@ function App() {
       return;
     }
 
+    const requestId = crypto.randomUUID();
+    latestInfoRequest.current = requestId;
     setIsFetchingInfo(true);
     setVideoInfo(null);
     addLog("info", `Fetching video info: ${url}`);
 
     try {
-      const info = await invoke<VideoInfo>("get_video_info", { url: url.trim() });
+      const info = await invoke<VideoInfo>("get_video_info", { url: url.trim(), requestId });
+      if (latestInfoRequest.current !== requestId) return;
       setVideoInfo(info);
+      setSourcePreviews({});
       setAvailableQualities(info.qualities.length > 0 ? info.qualities : ["auto"]);
-      setQuality(info.qualities[0] || "auto");
+      setQuality(
+        lastQuality.current && info.qualities.includes(lastQuality.current)
+          ? lastQuality.current
+          : info.qualities[0] || "auto"
+      );
+      setAudioTracks("default");
       if (info.title) {
         setFilename(info.title.replace(/[<>:"/\\|?*]/g, "_") + ".mp4");
       }
       addLog("success", `Found: ${info.title}`);
     } catch (error) {
-      addLog("error", `Error: ${error}`);
+      if (latestInfoRequest.current === requestId) addLog("error", `Error: ${error}`);
     } finally {
-      setIsFetchingInfo(false);
+      if (latestInfoRequest.current === requestId) setIsFetchingInfo(false);
     }
   };
 
@@ -688,6 +967,7 @@ function App() {
     lastProgressUpdate.current = null;
 
     addLog("info", `Starting download: ${url}`);
+    rememberUiState({ lastOutputDir: outputDir });
 
     try {
       const result = await invoke<string>("download_video", {
@@ -695,6 +975,7 @@ function App() {
         outputDir: outputDir,
         outputFilename: filename.trim() || null,
         quality: quality,
+        audioTracks: audioTracks,
       });
 
       // Add to history
@@ -736,9 +1017,36 @@ function App() {
     }
   };
 
+  // One-click "save 50% space" (H.265 CRF 26) on a finished download
+  const handleCompressHistoryItem = async (item: HistoryItem) => {
+    addLog("info", `Compressing ${item.filename}...`);
+    try {
+      const updated = await invoke<HistoryItem>("compress_history_item", { id: item.id, preset: "save-50" });
+      if (updated.original_size && updated.file_size) {
+        addLog("success", `Compressed ${updated.filename}: ${formatBytes(updated.original_size)} → ${formatBytes(updated.file_size)}`);
+      }
+      loadHistory();
+    } catch (error) {
+      addLog("error", `Failed to compress: ${error}`);
+    }
+  };
+
+  // Hardcode the subtitle saved next to the video for TVs that can't render Thai subtitles
+  const handleBurnSubtitles = async (item: HistoryItem) => {
+    addLog("info", `Burning subtitles into ${item.filename}...`);
+    try {
+      await invoke<HistoryItem>("burn_subtitles_history_item", { id: item.id, subtitlePath: null });
+      addLog("success", `Subtitles burned into ${item.filename}`);
+      loadHistory();
+    } catch (error) {
+      addLog("error", `Failed to burn subtitles: ${error}`);
+    }
+  };
+
   const handleDeleteHistoryItem = async (id: string) => {
     try {
       await invoke("delete_history_item", { id });
+      offerUndo("ลบประวัติแล้ว");
       loadHistory();
     } catch (error) {
       console.error("Failed to delete history item:", error);
@@ -748,6 +1056,7 @@ function App() {
   const handleClearHistory = async () => {
     try {
       await invoke("clear_history");
+      offerUndo("ล้างประวัติแล้ว");
       setHistory([]);
     } catch (error) {
       console.error("Failed to clear history:", error);
@@ -813,8 +1122,8 @@ function App() {
         >
           <List size={18} />
           Queue
-          {queue.filter(i => i.status === "Pending" || i.status === "Downloading").length > 0 && (
-            <span className="badge">{queue.filter(i => i.status === "Pending" || i.status === "Downloading").length}</span>
+          {queue.filter(i => i.status === "Pending" || i.status === "Downloading" || i.status === "Converting" || i.status === "Processing").length > 0 && (
+            <span className="badge">{queue.filter(i => i.status === "Pending" || i.status === "Downloading" || i.status === "Converting" || i.status === "Processing").length}</span>
           )}
         </button>
         <button
@@ -835,6 +1144,15 @@ function App() {
       </div>
 
       <main className="main-content">
+        {undoMessage && (
+          <div className="undo-banner">
+            <span>{undoMessage}</span>
+            <button onClick={undoLastAction}>
+              <Undo2 size={14} />
+              เลิกทำ
+            </button>
+          </div>
+        )}
         {activeTab === "download" && (
           <>
             {/* URL Input Section */}
@@ -878,7 +1196,7 @@ function App() {
                   )}
                   <button
                     onClick={handleFetchInfo}
-                    disabled={isDownloading || isFetchingInfo || !url.trim()}
+                    disabled={isFetchingInfo || !url.trim()}
                     className="fetch-btn"
                     title="Fetch video info"
                   >
@@ -936,7 +1254,30 @@ function App() {
                           {videoInfo.qualities.join(", ")}
                         </span>
                       )}
+                      {videoInfo.sources.some((s) => s.codec) && (
+                        <span className="meta-item">
+                          {[...new Set(videoInfo.sources.map((s) => s.codec).filter(Boolean))]
+                            .map((c) => c!.toUpperCase())
+                            .join(", ")}
+                        </span>
+                      )}
                     </div>
+                    {videoInfo.sources.length > 1 && (
+                      <div className="source-previews">
+                        {videoInfo.sources.map((source) => (
+                          <div key={source.url} className="source-preview">
+                            {sourcePreviews[source.url] ? (
+                              <img src={sourcePreviews[source.url]} alt={source.quality} />
+                            ) : (
+                              <button onClick={() => previewSource(source)}>
+                                <Image size={14} /> {source.quality} {source.host ?? ""}
+                                {source.size ? ` · ${formatBytes(source.size)}` : ""}
+                              </button>
+                            )}
+                          </div>
+                        ))}
+                      </div>
+                    )}
                   </div>
                   <button className="close-preview" onClick={() => setVideoInfo(null)}>
                     <X size={16} />
@@ -976,6 +1317,8 @@ function App() {
                             onClick={(e) => {
                               e.stopPropagation();
                               setQuality(q);
+                              lastQuality.current = q;
+                              rememberUiState({ lastQuality: q });
                               setShowQualityDropdown(false);
                             }}
                           >
@@ -986,6 +1329,21 @@ function App() {
                     )}
                   </div>
                 </div>
+
+                {videoInfo?.audio_tracks && videoInfo.audio_tracks.length > 1 && (
+                  <div className="input-group">
+                    <label>เสียง</label>
+                    <select value={audioTracks} onChange={(e) => setAudioTracks(e.target.value)} disabled={isDownloading}>
+                      <option value="default">ค่าเริ่มต้น</option>
+                      {videoInfo.audio_tracks.map((track) => (
+                        <option key={`${track.language}-${track.name}`} value={track.language}>
+                          {track.name} ({track.language})
+                        </option>
+                      ))}
+                      <option value="all">ทุกภาษา</option>
+                    </select>
+                  </div>
+                )}
               </div>
 
               <div className="input-group">
@@ -1135,7 +1493,7 @@ function App() {
                   <div key={item.id} className="history-item">
                     <div className="history-thumbnail">
                       {item.thumbnail ? (
-                        <img src={item.thumbnail} alt={item.title} />
+                        <CachedThumbnail id={item.id} src={item.thumbnail} alt={item.title} />
                       ) : (
                         <div className="no-thumbnail">
                           <Film size={24} />
@@ -1147,6 +1505,9 @@ function App() {
                       <div className="history-meta">
                         <span className="quality-badge">{item.quality}</span>
                         <span className="date">{formatDate(item.downloaded_at)}</span>
+                        {item.original_size && item.file_size && (
+                          <span className="size">{formatBytes(item.original_size)} → {formatBytes(item.file_size)}</span>
+                        )}
                       </div>
                       <p className="filename">{item.filename}</p>
                     </div>
@@ -1165,6 +1526,20 @@ function App() {
                       >
                         <FolderOpen size={16} />
                       </button>
+                      <button
+                        className="action-btn compress"
+                        onClick={() => handleCompressHistoryItem(item)}
+                        title="Save ~50% space (H.265)"
+                      >
+                        <Minimize2 size={16} />
+                      </button>
+                      <button
+                        className="action-btn compress"
+                        onClick={() => handleBurnSubtitles(item)}
+                        title="Burn in subtitles"
+                      >
+                        <Subtitles size={16} />
+                      </button>
                       <button
                         className="action-btn delete"
                         onClick={() => handleDeleteHistoryItem(item.id)}
@@ -1216,13 +1591,13 @@ function App() {
                   <div key={item.id} className={`queue-item status-${item.status.toLowerCase()}`}>
                     <div className="queue-thumbnail">
                       {item.thumbnail ? (
-                        <img src={item.thumbnail} alt={item.title} />
+                        <CachedThumbnail id={item.id} src={item.thumbnail} alt={item.title} />
                       ) : (
                         <div className="no-thumbnail">
                           <Film size={24} />
                         </div>
                       )}
-                      {item.status === "Downloading" && (
+                      {(item.status === "Downloading" || item.status === "Converting" || item.status === "Processing") && (
                         <div className="thumbnail-overlay">
                           <Loader2 className="animate-spin" size={20} />
                         </div>
@@ -1232,7 +1607,7 @@ function App() {
                       <h4>{item.title}</h4>
                       <div className="queue-meta">
                         <span className={`status-badge ${item.status.toLowerCase()}`}>
-                          {item.status === "Downloading" && <Loader2 className="animate-spin" size={12} />}
+                          {(item.status === "Downloading" || item.status === "Converting" || item.status === "Processing") && <Loader2 className="animate-spin" size={12} />}
                           {item.status === "Completed" && <CheckCircle size={12} />}
                           {item.status === "Failed" && <XCircle size={12} />}
                           {item.status === "Paused" && <Pause size={12} />}
@@ -1242,7 +1617,7 @@ function App() {
                         {item.speed && <span className="speed">{item.speed}</span>}
                         {item.eta && <span className="eta">{item.eta}</span>}
                       </div>
-                      {(item.status === "Downloading" || item.status === "Paused") && (
+                      {(item.status === "Downloading" || item.status === "Converting" || item.status === "Processing" || item.status === "Paused") && (
                         <div className="queue-progress">
                           <div className="progress-bar-container">
                             <div className="progress-bar" style={{ width: `${item.progress}%` }} />
@@ -1251,7 +1626,13 @@ function App() {
                         </div>
                       )}
                       {item.error && (
-                        <p className="error-text">{item.error}</p>
+                        <p className="error-text" title={item.error}>{item.error_help?.message ?? item.error}</p>
+                      )}
+                      {item.error_help && (
+                        <p className="error-hint">{item.error_help.hint}</p>
+                      )}
+                      {item.error && item.origin?.embed_host && (
+                        <p className="error-hint">แหล่งที่มา: {item.origin.embed_host}</p>
                       )}
                     </div>
                     <div className="queue-item-actions">
@@ -1390,6 +1771,39 @@ function App() {
                   </select>
                 </div>
 
+                <div className="setting-item checkbox">
+                  <label>
+                    <input
+                      type="checkbox"
+                      checked={settings.auto_quality_by_speed}
+                      onChange={(e) => setSettings({ ...settings, auto_quality_by_speed: e.target.checked })}
+                    />
+                    Auto quality follows connection speed (HLS)
+                  </label>
+                </div>
+
+                <div className="setting-item checkbox">
+                  <label>
+                    <input
+                      type="checkbox"
+                      checked={settings.check_sources}
+                      onChange={(e) => setSettings({ ...settings, check_sources: e.target.checked })}
+                    />
+                    Check every source when fetching info (drop dead links, sort best first)
+                  </label>
+                </div>
+
+                <div className="setting-item checkbox">
+                  <label>
+                    <input
+                      type="checkbox"
+                      checked={settings.allow_quality_fallback}
+                      onChange={(e) => setSettings({ ...settings, allow_quality_fallback: e.target.checked })}
+                    />
+                    Allow quality fallback when a quality keeps failing
+                  </label>
+                </div>
+
                 <div className="setting-item">
                   <label>Max Concurrent Downloads</label>
                   <select
@@ -1403,11 +1817,137 @@ function App() {
                     <option value={5}>5</option>
                   </select>
                 </div>
+
+                <div className="setting-item checkbox">
+                  <label>
+                    <input
+                      type="checkbox"
+                      checked={settings.show_browser}
+                      onChange={(e) => setSettings({ ...settings, show_browser: e.target.checked })}
+                    />
+                    Show the browser window while fetching video info (for CAPTCHA sites)
+                  </label>
+                </div>
+
+                <div className="setting-item">
+                  <label>Extraction Browser</label>
+                  <select
+                    value={settings.browser_backend}
+                    onChange={(e) => setSettings({ ...settings, browser_backend: e.target.value })}
+                  >
+                    <option value="chromium">Chromium</option>
+                    <option value="firefox">Firefox (geckodriver)</option>
+                  </select>
+                </div>
+
+                <div className="setting-item">
+                  <label>Page Load Wait (ms)</label>
+                  <input
+                    type="number"
+                    min={0}
+                    max={60000}
+                    step={500}
+                    value={settings.page_load_wait_ms}
+                    onChange={(e) => setSettings({ ...settings, page_load_wait_ms: Math.max(0, parseInt(e.target.value) || 0) })}
+                  />
+                </div>
+
+                <div className="setting-item">
+                  <label>Player Iframe Wait (ms)</label>
+                  <input
+                    type="number"
+                    min={0}
+                    max={60000}
+                    step={500}
+                    value={settings.iframe_wait_ms}
+                    onChange={(e) => setSettings({ ...settings, iframe_wait_ms: Math.max(0, parseInt(e.target.value) || 0) })}
+                  />
+                </div>
+
+                <div className="setting-item">
+                  <label>Wait After Clicking Play (ms)</label>
+                  <input
+                    type="number"
+                    min={0}
+                    max={60000}
+                    step={500}
+                    value={settings.playback_wait_ms}
+                    onChange={(e) => setSettings({ ...settings, playback_wait_ms: Math.max(0, parseInt(e.target.value) || 0) })}
+                  />
+                </div>
               </div>
 
               <div className="settings-group">
                 <h4>Queue Settings</h4>
 
+                <div className="setting-item">
+                  <label>Re-check Links of Items Queued Longer Than</label>
+                  <select
+                    value={settings.stale_source_hours}
+                    onChange={(e) => setSettings({ ...settings, stale_source_hours: parseInt(e.target.value) })}
+                  >
+                    <option value={0}>Never</option>
+                    <option value={1}>1 hour</option>
+                    <option value={6}>6 hours</option>
+                    <option value={24}>1 day</option>
+                  </select>
+                </div>
+
+                <div className="setting-item">
+                  <label>Series Index Pages to Follow</label>
+                  <input
+                    type="number"
+                    min={1}
+                    max={50}
+                    value={settings.series_max_pages}
+                    onChange={(e) => setSettings({ ...settings, series_max_pages: parseInt(e.target.value) || 1 })}
+                  />
+                </div>
+
+                <div className="setting-item checkbox">
+                  <label>
+                    <input
+                      type="checkbox"
+                      checked={settings.permanent_delete}
+                      onChange={(e) => setSettings({ ...settings, permanent_delete: e.target.checked })}
+                    />
+                    Delete files permanently instead of moving them to the trash
+                  </label>
+                </div>
+
+                <div className="setting-item">
+                  <label>Max File Size (MB, 0 = no limit)</label>
+                  <input
+                    type="number"
+                    min={0}
+                    step={100}
+                    value={settings.max_file_size_mb}
+                    onChange={(e) => setSettings({ ...settings, max_file_size_mb: Math.max(0, parseInt(e.target.value) || 0) })}
+                  />
+                </div>
+
+                <div className="setting-item checkbox">
+                  <label>
+                    <input
+                      type="checkbox"
+                      checked={settings.write_checksums}
+                      onChange={(e) => setSettings({ ...settings, write_checksums: e.target.checked })}
+                    />
+                    Write a .sha256 checksum file next to each download
+                  </label>
+                </div>
+
+                <div className="setting-item checkbox">
+                  <label>
+                    <input
+                      type="checkbox"
+                      checked={settings.auto_clean_temp_files}
+                      onChange={(e) => setSettings({ ...settings, auto_clean_temp_files: e.target.checked })}
+                    />
+                    Clean up temp files left by crashed or cancelled downloads
+                  </label>
+                </div>
+
                 <div className="setting-item checkbox">
                   <label>
                     <input